@@ -1,23 +1,36 @@
 use std::collections::HashMap;
+use std::io::SeekFrom;
 use std::path::{Path, PathBuf};
 
 use anyhow::{ensure, Context as _, Result};
+use bytes::Bytes;
+use cid::multihash::{Code, MultihashDigest};
 use cid::Cid;
 use futures::stream::LocalBoxStream;
 use futures::{StreamExt, TryStreamExt};
-use iroh_resolver::unixfs_builder;
+use iroh_resolver::resolver::{parse_links, Resolver, ResponseClip};
+use iroh_resolver::unixfs::Link;
+use iroh_resolver::unixfs_builder::{self, Directory};
 use iroh_rpc_client::Client;
 use iroh_rpc_client::StatusTable;
 use iroh_util::{iroh_config_path, make_config};
 #[cfg(feature = "testing")]
 use mockall::automock;
 use relative_path::RelativePathBuf;
-use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWrite};
 
+use crate::block::BlockStat;
+use crate::car::{self, ImportEvent};
 use crate::config::{Config, CONFIG_FILE_NAME, ENV_PREFIX};
+use crate::content::{self, EntryType, LsEntry, Stat};
+use crate::dag::{self, DagCodec};
+use crate::error::map_service_error;
 use crate::P2pApi;
 use crate::{AddEvent, IpfsPath};
 
+/// Number of directory entries resolved concurrently by `ls`.
+const LS_CONCURRENCY: usize = 16;
+
 pub struct Api {
     client: Client,
 }
@@ -111,6 +124,183 @@ impl Api {
         Ok(stream.boxed_local())
     }
 
+    /// Reads the file at `ipfs_path`, starting at `offset` and reading at most `length` bytes.
+    pub async fn cat(
+        &self,
+        ipfs_path: &IpfsPath,
+        offset: u64,
+        length: Option<u64>,
+    ) -> Result<Box<dyn AsyncRead + Unpin>> {
+        let resolver = Resolver::new(self.client.clone());
+        let out = resolver.resolve(ipfs_path.clone()).await?;
+        ensure!(!out.is_dir(), "{} is a directory", ipfs_path);
+
+        let mut reader = out.pretty(resolver, Default::default(), ResponseClip::NoClip)?;
+        if offset > 0 {
+            if reader.size().map(|size| offset >= size).unwrap_or_default() {
+                return Ok(Box::new(tokio::io::empty()));
+            }
+            reader.seek(SeekFrom::Start(offset)).await?;
+        }
+        match length {
+            Some(length) => Ok(Box::new(reader.take(length))),
+            None => Ok(Box::new(reader)),
+        }
+    }
+
+    /// Lists the entries of the directory at `ipfs_path`, including HAMT sharded directories.
+    pub async fn ls(
+        &self,
+        ipfs_path: &IpfsPath,
+    ) -> Result<LocalBoxStream<'static, Result<LsEntry>>> {
+        let resolver = Resolver::new(self.client.clone());
+        let out = resolver.resolve(ipfs_path.clone()).await?;
+        ensure!(out.is_dir(), "{} is not a directory", ipfs_path);
+
+        let stream = async_stream::try_stream! {
+            let links: Vec<Link> = out
+                .unixfs_read_dir(&resolver, Default::default())?
+                .expect("already known this is a directory")
+                .try_collect()
+                .await?;
+            let entries = futures::stream::iter(links)
+                .map(|link| content::ls_entry(&resolver, link))
+                .buffered(LS_CONCURRENCY);
+            tokio::pin!(entries);
+            while let Some(entry) = entries.next().await {
+                yield entry?;
+            }
+        };
+
+        Ok(stream.boxed_local())
+    }
+
+    /// Reports the cid, codec, type and size of the content at `ipfs_path`, and how much of
+    /// its DAG is available locally.
+    pub async fn stat(&self, ipfs_path: &IpfsPath) -> Result<Stat> {
+        let resolver = Resolver::new(self.client.clone());
+        let out = resolver.resolve(ipfs_path.clone()).await?;
+        let metadata = out.metadata();
+        let cid = *metadata
+            .resolved_path
+            .last()
+            .context("path did not resolve to a cid")?;
+        let (blocks, missing_blocks) =
+            content::count_local_blocks(&self.client.try_store()?, cid).await?;
+
+        Ok(Stat {
+            cid,
+            codec: content::codec_name(cid.codec()),
+            typ: EntryType::from_out(&out),
+            size: metadata.size,
+            blocks,
+            missing_blocks,
+        })
+    }
+
+    /// Stores the dag-json document `json` as a single block encoded with `codec`.
+    pub async fn dag_put(&self, json: Bytes, codec: DagCodec) -> Result<Cid> {
+        let (cid, bytes, links) = dag::encode_json(&json, codec)?;
+        self.client.try_store()?.put(cid, bytes, links).await?;
+        Ok(cid)
+    }
+
+    /// Resolves `ipfs_path` through IPLD links and returns the content it points to as dag-json.
+    pub async fn dag_get(&self, ipfs_path: &IpfsPath) -> Result<Bytes> {
+        let resolver = Resolver::new(self.client.clone());
+        let out = resolver.resolve(ipfs_path.clone()).await?;
+        dag::render_json(&resolver, &out).await
+    }
+
+    /// Writes the DAG below `root` to `writer` as a CAR file, returning the number of blocks.
+    pub async fn dag_export<W: AsyncWrite + Send + Unpin + 'static>(
+        &self,
+        root: Cid,
+        writer: W,
+    ) -> Result<u64> {
+        let resolver = Resolver::new(self.client.clone());
+        dag::export_car(&resolver, root, writer).await
+    }
+
+    /// Imports all blocks of the CAR file read from `reader` into the store, returning the
+    /// roots of the CAR file.
+    pub async fn dag_import<R: AsyncRead + Send + Unpin + 'static>(
+        &self,
+        reader: R,
+    ) -> Result<Vec<Cid>> {
+        car::import_car(self.client.clone(), reader, false)
+            .try_filter_map(|event| async move {
+                match event {
+                    ImportEvent::Root { cid, .. } => Ok(Some(cid)),
+                    ImportEvent::ProgressDelta { .. } => Ok(None),
+                }
+            })
+            .try_collect()
+            .await
+    }
+
+    /// Imports all blocks of the CAR file read from `reader` into the store, reporting each
+    /// stored block and then the roots of the CAR file.
+    ///
+    /// If `provide` is set the roots are announced to the network.
+    pub async fn import_car<R: AsyncRead + Send + Unpin + 'static>(
+        &self,
+        reader: R,
+        provide: bool,
+    ) -> Result<LocalBoxStream<'static, Result<ImportEvent>>> {
+        Ok(car::import_car(self.client.clone(), reader, provide).boxed_local())
+    }
+
+    /// Returns the raw bytes of the block `cid` from the store.
+    pub async fn block_get(&self, cid: Cid) -> Result<Bytes> {
+        self.client
+            .try_store()?
+            .get(cid)
+            .await
+            .map_err(|e| map_service_error("store", e))?
+            .with_context(|| format!("block {} not found in the store", cid))
+    }
+
+    /// Stores `data` as a block with the given codec, hashed with `mhtype`.
+    ///
+    /// The data is decoded to record the links of the block, so it has to be valid for `codec`.
+    pub async fn block_put(&self, data: Bytes, codec: u64, mhtype: Code) -> Result<Cid> {
+        let cid = Cid::new_v1(codec, mhtype.digest(&data));
+        let links = parse_links(&cid, &data)
+            .with_context(|| format!("invalid {} block", content::codec_name(codec)))?;
+        self.client
+            .try_store()?
+            .put(cid, data, links)
+            .await
+            .map_err(|e| map_service_error("store", e))?;
+        Ok(cid)
+    }
+
+    /// Reports the size and links of the block `cid` in the store.
+    pub async fn block_stat(&self, cid: Cid) -> Result<BlockStat> {
+        let store = self.client.try_store()?;
+        let size = store
+            .get_size(cid)
+            .await
+            .map_err(|e| map_service_error("store", e))?
+            .with_context(|| format!("block {} not found in the store", cid))?;
+        let links = store
+            .get_links(cid)
+            .await
+            .map_err(|e| map_service_error("store", e))?
+            .unwrap_or_default();
+        Ok(BlockStat { cid, size, links })
+    }
+
+    /// Removes the block `cid` from the store, returning whether it was present.
+    pub async fn block_rm(&self, cid: Cid) -> Result<bool> {
+        self.client
+            .try_store()?
+            .delete(cid)
+            .await
+            .map_err(|e| map_service_error("store", e))
+    }
+
     pub async fn add_file(
         &self,
         path: &Path,
@@ -153,6 +343,46 @@ impl Api {
         Ok(stream.boxed_local())
     }
 
+    /// Adds a file read from `reader`, named `name` if it is wrapped in a directory.
+    pub async fn add_reader<R: AsyncRead + Send + 'static>(
+        &self,
+        reader: R,
+        name: &str,
+        wrap: bool,
+    ) -> Result<LocalBoxStream<'static, Result<AddEvent>>> {
+        let providing_client = iroh_resolver::unixfs_builder::StoreAndProvideClient {
+            client: self.client.clone(),
+        };
+        let stream = unixfs_builder::add_reader(Some(providing_client), name, reader, wrap).await?;
+
+        Ok(stream.boxed_local())
+    }
+
+    /// Adds a file with the given content, named `name` if it is wrapped in a directory.
+    pub async fn add_bytes(
+        &self,
+        content: Bytes,
+        name: &str,
+        wrap: bool,
+    ) -> Result<LocalBoxStream<'static, Result<AddEvent>>> {
+        self.add_reader(std::io::Cursor::new(content), name, wrap)
+            .await
+    }
+
+    /// Adds a directory tree built in memory with a `DirectoryBuilder`.
+    pub async fn add_directory(
+        &self,
+        dir: Directory,
+        wrap: bool,
+    ) -> Result<LocalBoxStream<'static, Result<AddEvent>>> {
+        let providing_client = iroh_resolver::unixfs_builder::StoreAndProvideClient {
+            client: self.client.clone(),
+        };
+        let stream = unixfs_builder::add_directory(Some(providing_client), dir, wrap).await?;
+
+        Ok(stream.boxed_local())
+    }
+
     pub async fn check(&self) -> StatusTable {
         self.client.check().await
     }
@@ -0,0 +1,62 @@
+use anyhow::{bail, Result};
+use cid::multihash::Code;
+use cid::Cid;
+
+/// Summary of a block in the store.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockStat {
+    pub cid: Cid,
+    /// Size of the block in bytes.
+    pub size: u64,
+    /// Cids the block links to, as recorded by the store.
+    pub links: Vec<Cid>,
+}
+
+/// Parses the name of a codec blocks can be stored with, e.g. `dag-cbor`.
+pub fn codec_from_name(name: &str) -> Result<u64> {
+    match name {
+        "raw" => Ok(0x55),
+        "dag-pb" => Ok(0x70),
+        "dag-cbor" => Ok(0x71),
+        "dag-json" => Ok(0x0129),
+        _ => bail!(
+            "unsupported codec '{}', expected raw, dag-pb, dag-cbor or dag-json",
+            name
+        ),
+    }
+}
+
+/// Parses the name of a multihash function, e.g. `sha2-256`.
+pub fn multihash_from_name(name: &str) -> Result<Code> {
+    match name {
+        "sha2-256" => Ok(Code::Sha2_256),
+        "sha2-512" => Ok(Code::Sha2_512),
+        "sha3-256" => Ok(Code::Sha3_256),
+        "sha3-512" => Ok(Code::Sha3_512),
+        "blake2b-256" => Ok(Code::Blake2b256),
+        "blake2b-512" => Ok(Code::Blake2b512),
+        "blake3" => Ok(Code::Blake3_256),
+        _ => bail!("unsupported multihash '{}'", name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::content::codec_name;
+
+    #[test]
+    fn codec_names_roundtrip() {
+        for name in ["raw", "dag-pb", "dag-cbor", "dag-json"] {
+            assert_eq!(codec_name(codec_from_name(name).unwrap()), name);
+        }
+        assert!(codec_from_name("git-raw").is_err());
+    }
+
+    #[test]
+    fn multihash_names() {
+        assert_eq!(multihash_from_name("sha2-256").unwrap(), Code::Sha2_256);
+        assert_eq!(multihash_from_name("blake3").unwrap(), Code::Blake3_256);
+        assert!(multihash_from_name("md5").is_err());
+    }
+}
@@ -0,0 +1,263 @@
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use cid::Cid;
+use futures::{Stream, StreamExt, TryStreamExt};
+use iroh_car::CarReader;
+use iroh_resolver::resolver::parse_links;
+use iroh_rpc_client::{Client, StoreClient};
+use libipld::IpldCodec;
+use tokio::io::AsyncRead;
+
+/// Maximum number of blocks written to the store in a single request during a CAR import.
+const IMPORT_BATCH_BLOCKS: usize = 256;
+/// Maximum number of bytes written to the store in a single request during a CAR import.
+const IMPORT_BATCH_BYTES: usize = 4 * 1024 * 1024;
+/// Number of blocks whose links are parsed concurrently during a CAR import.
+const IMPORT_PARSE_CONCURRENCY: usize = 16;
+
+/// An event on the stream of a CAR import.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImportEvent {
+    /// A block was written to the store.
+    ProgressDelta {
+        cid: Cid,
+        /// Size of the block in bytes.
+        size: u64,
+    },
+    /// A root of the CAR file, sent once all blocks are stored.
+    Root {
+        cid: Cid,
+        /// Whether the root is announced to the network.
+        provided: bool,
+    },
+}
+
+/// Reads the blocks of the CAR file from `reader` into the store.
+///
+/// Blocks are checked against their cids, their links are parsed concurrently and they are
+/// written in batches. The import fails on dag-pb, dag-cbor, dag-json or raw blocks that can
+/// not be decoded, as their links could not be recorded in the store. Blocks of other codecs
+/// are stored without links. If `provide` is set the roots of the file are provided once all
+/// blocks are stored.
+pub(crate) fn import_car<R>(
+    client: Client,
+    reader: R,
+    provide: bool,
+) -> impl Stream<Item = Result<ImportEvent>>
+where
+    R: AsyncRead + Send + Unpin + 'static,
+{
+    async_stream::try_stream! {
+        let car = CarReader::new_verifying(reader).await?;
+        let roots = car.header().roots().to_vec();
+        let store = client.try_store()?;
+
+        let blocks = car
+            .stream()
+            .map_err(anyhow::Error::from)
+            .map_ok(|(cid, data)| async move {
+                let (data, links) = tokio::task::spawn_blocking(move || {
+                    let links = match IpldCodec::try_from(cid.codec()) {
+                        Ok(_) => parse_links(&cid, &data).with_context(|| {
+                            format!("failed to decode the links of block {}", cid)
+                        }),
+                        // there is no decoder for the codec, so no links can be recorded
+                        Err(_) => Ok(Vec::new()),
+                    };
+                    (data, links)
+                })
+                .await?;
+                Ok::<_, anyhow::Error>((cid, Bytes::from(data), links?))
+            })
+            .try_buffered(IMPORT_PARSE_CONCURRENCY);
+        tokio::pin!(blocks);
+
+        let mut batch = Vec::new();
+        let mut batch_bytes = 0;
+        while let Some(block) = blocks.next().await {
+            let block = block?;
+            batch_bytes += block.1.len();
+            batch.push(block);
+            if batch.len() >= IMPORT_BATCH_BLOCKS || batch_bytes >= IMPORT_BATCH_BYTES {
+                for event in store_batch(&store, &mut batch).await? {
+                    yield event;
+                }
+                batch_bytes = 0;
+            }
+        }
+        for event in store_batch(&store, &mut batch).await? {
+            yield event;
+        }
+
+        for cid in roots {
+            if provide {
+                client.try_p2p()?.start_providing(&cid).await?;
+            }
+            yield ImportEvent::Root { cid, provided: provide };
+        }
+    }
+}
+
+/// Writes the blocks of `batch` to the store, returning an event per block.
+async fn store_batch(
+    store: &StoreClient,
+    batch: &mut Vec<(Cid, Bytes, Vec<Cid>)>,
+) -> Result<Vec<ImportEvent>> {
+    if batch.is_empty() {
+        return Ok(Vec::new());
+    }
+    let events = batch
+        .iter()
+        .map(|(cid, data, _)| ImportEvent::ProgressDelta {
+            cid: *cid,
+            size: data.len() as u64,
+        })
+        .collect();
+    store.put_many(std::mem::take(batch)).await?;
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use cid::multihash::{Code, MultihashDigest};
+    use iroh_car::{CarHeader, CarWriter};
+    use iroh_rpc_client::Config as RpcClientConfig;
+    use iroh_rpc_types::Addr;
+    use tokio::task::JoinHandle;
+
+    use super::*;
+
+    /// Starts a store on disk and returns a client connected to it.
+    async fn store_client(dir: &std::path::Path) -> (Client, JoinHandle<()>) {
+        let (server_addr, client_addr) = Addr::new_mem();
+        let rpc_client = RpcClientConfig {
+            store_addr: Some(client_addr),
+            ..Default::default()
+        };
+        let config = iroh_store::Config {
+            path: dir.join("db"),
+            rpc_client: rpc_client.clone(),
+            metrics: Default::default(),
+        };
+        let store = iroh_store::Store::create(config).await.unwrap();
+        let task =
+            tokio::spawn(async move { iroh_store::rpc::new(server_addr, store).await.unwrap() });
+        (Client::new(rpc_client).await.unwrap(), task)
+    }
+
+    #[tokio::test]
+    async fn import_car_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let (client, task) = store_client(dir.path()).await;
+
+        let car = tokio::fs::read("../iroh-car/tests/carv1_basic.car")
+            .await
+            .unwrap();
+        let events: Vec<_> = import_car(client.clone(), Cursor::new(car.clone()), false)
+            .try_collect()
+            .await
+            .unwrap();
+
+        let reader = CarReader::new(Cursor::new(car)).await.unwrap();
+        let roots = reader.header().roots().to_vec();
+        let blocks: Vec<_> = reader.stream().try_collect().await.unwrap();
+        let stored = events
+            .iter()
+            .filter(|event| matches!(event, ImportEvent::ProgressDelta { .. }))
+            .count();
+        assert_eq!(stored, blocks.len());
+        let imported_roots: Vec<_> = events
+            .iter()
+            .filter_map(|event| match event {
+                ImportEvent::Root { cid, provided } => {
+                    assert!(!provided);
+                    Some(*cid)
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(imported_roots, roots);
+
+        let store = client.try_store().unwrap();
+        for (cid, data) in blocks {
+            assert_eq!(store.get(cid).await.unwrap().unwrap(), data);
+            let links = store.get_links(cid).await.unwrap().unwrap();
+            assert_eq!(links, parse_links(&cid, &data).unwrap());
+        }
+        task.abort();
+    }
+
+    #[tokio::test]
+    async fn import_car_into_store_with_existing_block() {
+        let dir = tempfile::tempdir().unwrap();
+        let (client, task) = store_client(dir.path()).await;
+
+        let car = tokio::fs::read("../iroh-car/tests/carv1_basic.car")
+            .await
+            .unwrap();
+        let reader = CarReader::new(Cursor::new(car.clone())).await.unwrap();
+        let blocks: Vec<_> = reader.stream().try_collect().await.unwrap();
+
+        // a block from the start of the file is stored already
+        let store = client.try_store().unwrap();
+        let (cid, data) = blocks[1].clone();
+        let links = parse_links(&cid, &data).unwrap();
+        store.put(cid, Bytes::from(data), links).await.unwrap();
+
+        let _: Vec<_> = import_car(client.clone(), Cursor::new(car), false)
+            .try_collect()
+            .await
+            .unwrap();
+        for (cid, data) in blocks {
+            assert_eq!(store.get(cid).await.unwrap().unwrap(), data);
+        }
+        task.abort();
+    }
+
+    #[tokio::test]
+    async fn import_car_unsupported_codec() {
+        let dir = tempfile::tempdir().unwrap();
+        let (client, task) = store_client(dir.path()).await;
+
+        // a git-raw block, which can not be decoded
+        let data = b"blob 5\0hello";
+        let cid = Cid::new_v1(0x78, Code::Sha2_256.digest(data));
+        let mut car = Vec::new();
+        let mut writer = CarWriter::new(CarHeader::new_v1(vec![cid]), &mut car);
+        writer.write(cid, data).await.unwrap();
+        writer.finish().await.unwrap();
+
+        let _: Vec<_> = import_car(client.clone(), Cursor::new(car), false)
+            .try_collect()
+            .await
+            .unwrap();
+        let store = client.try_store().unwrap();
+        assert_eq!(store.get(cid).await.unwrap().unwrap(), &data[..]);
+        assert_eq!(store.get_links(cid).await.unwrap(), Some(Vec::new()));
+        task.abort();
+    }
+
+    #[tokio::test]
+    async fn import_car_undecodable_block() {
+        let dir = tempfile::tempdir().unwrap();
+        let (client, task) = store_client(dir.path()).await;
+
+        // the digest matches, but the block is not valid dag-cbor
+        let data = b"\xffnot cbor";
+        let cid = Cid::new_v1(0x71, Code::Sha2_256.digest(data));
+        let mut car = Vec::new();
+        let mut writer = CarWriter::new(CarHeader::new_v1(vec![cid]), &mut car);
+        writer.write(cid, data).await.unwrap();
+        writer.finish().await.unwrap();
+
+        let res: Result<Vec<_>> = import_car(client.clone(), Cursor::new(car), false)
+            .try_collect()
+            .await;
+        let err = res.unwrap_err();
+        assert!(err.to_string().contains(&cid.to_string()), "{:?}", err);
+        assert!(!client.try_store().unwrap().has(cid).await.unwrap());
+        task.abort();
+    }
+}
@@ -0,0 +1,160 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use anyhow::Result;
+use cid::Cid;
+use futures::{StreamExt, TryStreamExt};
+use iroh_resolver::resolver::{Out, OutType as ResolvedType, Path, Resolver, UnixfsType};
+use iroh_resolver::unixfs::Link;
+use iroh_rpc_client::{Client, StoreClient};
+
+/// Multihash code of identity hashes, whose content is inlined into the cid.
+const IDENTITY_HASH: u64 = 0x00;
+
+/// Number of blocks looked up in the store at the same time when counting local blocks.
+const COUNT_BLOCKS_CONCURRENCY: usize = 16;
+
+/// Kind of content a path or directory entry resolves to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryType {
+    Dir,
+    File,
+    Symlink,
+    /// Content that is neither unixfs nor raw, e.g. dag-cbor.
+    Other,
+}
+
+impl EntryType {
+    pub(crate) fn from_out(out: &Out) -> Self {
+        match out.metadata().unixfs_type {
+            Some(UnixfsType::Dir) => EntryType::Dir,
+            Some(UnixfsType::File) => EntryType::File,
+            Some(UnixfsType::Symlink) => EntryType::Symlink,
+            // raw blocks are the leaves of unixfs files
+            None if out.typ() == ResolvedType::Raw => EntryType::File,
+            None => EntryType::Other,
+        }
+    }
+}
+
+impl fmt::Display for EntryType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            EntryType::Dir => "directory",
+            EntryType::File => "file",
+            EntryType::Symlink => "symlink",
+            EntryType::Other => "other",
+        };
+        f.write_str(name)
+    }
+}
+
+/// An entry of a listed directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LsEntry {
+    pub name: String,
+    pub cid: Cid,
+    /// Size of the content in bytes, if known.
+    pub size: Option<u64>,
+    pub typ: EntryType,
+}
+
+pub(crate) async fn ls_entry(resolver: &Resolver<Client>, link: Link) -> Result<LsEntry> {
+    let out = resolver.resolve(Path::from_cid(link.cid)).await?;
+    Ok(LsEntry {
+        name: link.name.unwrap_or_default(),
+        cid: link.cid,
+        size: out.metadata().size.or(link.tsize),
+        typ: EntryType::from_out(&out),
+    })
+}
+
+/// Summary of the content a path resolves to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stat {
+    pub cid: Cid,
+    /// Name of the multicodec of the cid, e.g. `dag-pb`.
+    pub codec: String,
+    pub typ: EntryType,
+    /// Size of the content in bytes, if known.
+    pub size: Option<u64>,
+    /// Number of blocks of the DAG found in the local store.
+    pub blocks: u64,
+    /// Number of blocks of the DAG missing from the local store.
+    ///
+    /// The links of missing blocks are unknown, so their children are not counted.
+    pub missing_blocks: u64,
+}
+
+impl Stat {
+    /// Whether the full DAG is available in the local store.
+    pub fn is_local(&self) -> bool {
+        self.missing_blocks == 0
+    }
+}
+
+pub(crate) fn codec_name(codec: u64) -> String {
+    match codec {
+        0x55 => "raw".to_string(),
+        0x70 => "dag-pb".to_string(),
+        0x71 => "dag-cbor".to_string(),
+        0x0129 => "dag-json".to_string(),
+        codec => format!("0x{:x}", codec),
+    }
+}
+
+/// Walks the DAG below `root` through the links recorded in the store, without fetching
+/// anything from the network, and returns the number of present and missing blocks.
+pub(crate) async fn count_local_blocks(store: &StoreClient, root: Cid) -> Result<(u64, u64)> {
+    let mut level = vec![root];
+    let mut seen = HashSet::from([root]);
+    let (mut present, mut missing) = (0, 0);
+    while !level.is_empty() {
+        // the blocks of a level are looked up concurrently
+        let found: Vec<Option<Vec<Cid>>> = futures::stream::iter(level)
+            .map(|cid| local_links(store, cid))
+            .buffer_unordered(COUNT_BLOCKS_CONCURRENCY)
+            .try_collect()
+            .await?;
+        level = Vec::new();
+        for links in found {
+            match links {
+                Some(links) => {
+                    present += 1;
+                    level.extend(links.into_iter().filter(|link| seen.insert(*link)));
+                }
+                None => missing += 1,
+            }
+        }
+    }
+    Ok((present, missing))
+}
+
+/// Returns the links of `cid`, or `None` if the block is not in the store.
+async fn local_links(store: &StoreClient, cid: Cid) -> Result<Option<Vec<Cid>>> {
+    if cid.hash().code() == IDENTITY_HASH {
+        return Ok(Some(Vec::new()));
+    }
+    if !store.has(cid).await? {
+        return Ok(None);
+    }
+    Ok(Some(store.get_links(cid).await?.unwrap_or_default()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codec_names() {
+        let cid: Cid = "bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi"
+            .parse()
+            .unwrap();
+        assert_eq!(codec_name(cid.codec()), "dag-pb");
+        let cid: Cid = "bafkreidyeivj7adnnac6ljvzj2e3rd5xdw3revw4da7mx2ckrstapoupoq"
+            .parse()
+            .unwrap();
+        assert_eq!(codec_name(cid.codec()), "raw");
+        assert_eq!(codec_name(0x0200), "0x200");
+    }
+}
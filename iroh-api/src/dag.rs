@@ -0,0 +1,143 @@
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Context as _, Result};
+use bytes::Bytes;
+use cid::multihash::{Code, MultihashDigest};
+use cid::Cid;
+use iroh_resolver::car_export::{self, ExportOptions};
+use iroh_resolver::resolver::{parse_links, Out, Path, Resolver};
+use iroh_rpc_client::Client;
+use libipld::codec::Encode;
+use libipld::prelude::Codec as _;
+use libipld::{Ipld, IpldCodec};
+use tokio::io::AsyncWrite;
+
+/// Codec used to store the data of `dag put`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DagCodec {
+    DagCbor,
+    DagJson,
+}
+
+impl From<DagCodec> for IpldCodec {
+    fn from(codec: DagCodec) -> Self {
+        match codec {
+            DagCodec::DagCbor => IpldCodec::DagCbor,
+            DagCodec::DagJson => IpldCodec::DagJson,
+        }
+    }
+}
+
+impl fmt::Display for DagCodec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DagCodec::DagCbor => f.write_str("dag-cbor"),
+            DagCodec::DagJson => f.write_str("dag-json"),
+        }
+    }
+}
+
+impl FromStr for DagCodec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "dag-cbor" => Ok(DagCodec::DagCbor),
+            "dag-json" => Ok(DagCodec::DagJson),
+            _ => bail!("unsupported codec '{}', expected dag-cbor or dag-json", s),
+        }
+    }
+}
+
+/// Encodes the dag-json document `json` with `codec`, returning the block and its links.
+pub(crate) fn encode_json(json: &[u8], codec: DagCodec) -> Result<(Cid, Bytes, Vec<Cid>)> {
+    let ipld: Ipld = IpldCodec::DagJson
+        .decode(json)
+        .map_err(|e| anyhow!("invalid json: {:?}", e))?;
+    let codec = IpldCodec::from(codec);
+    let mut bytes = Vec::new();
+    ipld.encode(codec, &mut bytes)?;
+    let cid = Cid::new_v1(codec.into(), Code::Sha2_256.digest(&bytes));
+    let links = parse_links(&cid, &bytes)?;
+    Ok((cid, bytes.into(), links))
+}
+
+/// Renders the resolved content as dag-json.
+///
+/// Unixfs content is rendered as the dag-pb node it is stored in.
+pub(crate) async fn render_json(resolver: &Resolver<Client>, out: &Out) -> Result<Bytes> {
+    let mut bytes = Vec::new();
+    match out.ipld() {
+        Some(ipld) => ipld.encode(IpldCodec::DagJson, &mut bytes)?,
+        None => {
+            let cid = *out
+                .metadata()
+                .resolved_path
+                .last()
+                .context("path did not resolve to a cid")?;
+            let raw = resolver.load_raw(cid, out.context()).await?;
+            let codec = IpldCodec::try_from(cid.codec())?;
+            let ipld: Ipld = codec
+                .decode(raw.content())
+                .map_err(|e| anyhow!("invalid {:?}: {:?}", codec, e))?;
+            ipld.encode(IpldCodec::DagJson, &mut bytes)?;
+        }
+    }
+    Ok(bytes.into())
+}
+
+/// Writes the DAG below `root` as a CAR file with `root` as its only root, returning the
+/// number of blocks written.
+pub(crate) async fn export_car<W>(resolver: &Resolver<Client>, root: Cid, writer: W) -> Result<u64>
+where
+    W: AsyncWrite + Send + Unpin,
+{
+    car_export::export_car(
+        resolver,
+        Path::from_cid(root),
+        ExportOptions::default(),
+        writer,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dag_codec_from_str() {
+        assert_eq!("dag-cbor".parse::<DagCodec>().unwrap(), DagCodec::DagCbor);
+        assert_eq!("dag-json".parse::<DagCodec>().unwrap(), DagCodec::DagJson);
+        assert!("dag-pb".parse::<DagCodec>().is_err());
+        assert_eq!(DagCodec::DagJson.to_string(), "dag-json");
+    }
+
+    #[test]
+    fn encode_json_codecs() {
+        let link = "bafkreidyeivj7adnnac6ljvzj2e3rd5xdw3revw4da7mx2ckrstapoupoq";
+        let json = format!(r#"{{"name":"foo","link":{{"/":"{}"}}}}"#, link);
+
+        let (cid, bytes, links) = encode_json(json.as_bytes(), DagCodec::DagCbor).unwrap();
+        assert_eq!(cid.codec(), u64::from(IpldCodec::DagCbor));
+        assert_eq!(cid.hash().code(), u64::from(Code::Sha2_256));
+        assert_eq!(links, vec![link.parse::<Cid>().unwrap()]);
+        let ipld: Ipld = IpldCodec::DagCbor.decode(&bytes).unwrap();
+        let input: Ipld = IpldCodec::DagJson.decode(json.as_bytes()).unwrap();
+        assert_eq!(ipld, input);
+
+        let (json_cid, json_bytes, json_links) =
+            encode_json(json.as_bytes(), DagCodec::DagJson).unwrap();
+        assert_eq!(json_cid.codec(), u64::from(IpldCodec::DagJson));
+        assert_ne!(json_cid, cid);
+        assert_eq!(json_links, links);
+        let roundtrip: Ipld = IpldCodec::DagJson.decode(&json_bytes).unwrap();
+        assert_eq!(roundtrip, ipld);
+    }
+
+    #[test]
+    fn encode_json_invalid() {
+        assert!(encode_json(b"{not json", DagCodec::DagCbor).is_err());
+    }
+}
@@ -1,5 +1,9 @@
 mod api;
+mod block;
+mod car;
 mod config;
+mod content;
+mod dag;
 mod error;
 mod p2p;
 
@@ -10,6 +14,10 @@ pub use crate::api::Api;
 #[cfg(feature = "testing")]
 pub use crate::api::MockApi as Api;
 pub use crate::api::OutType;
+pub use crate::block::{codec_from_name, multihash_from_name, BlockStat};
+pub use crate::car::ImportEvent;
+pub use crate::content::{EntryType, LsEntry, Stat};
+pub use crate::dag::DagCodec;
 pub use crate::error::ApiError;
 #[cfg(feature = "testing")]
 pub use crate::p2p::MockP2p as P2pApi;
@@ -17,9 +25,12 @@ pub use crate::p2p::MockP2p as P2pApi;
 pub use crate::p2p::P2p as P2pApi;
 pub use crate::p2p::PeerIdOrAddr;
 pub use bytes::Bytes;
+pub use cid::multihash::Code as MultihashCode;
 pub use cid::Cid;
 pub use iroh_resolver::resolver::Path as IpfsPath;
-pub use iroh_resolver::unixfs_builder::AddEvent;
+pub use iroh_resolver::unixfs_builder::{
+    AddEvent, Directory, DirectoryBuilder, File, FileBuilder, Symlink, SymlinkBuilder,
+};
 pub use iroh_rpc_client::{Lookup, ServiceStatus, StatusRow, StatusTable};
 pub use libp2p::gossipsub::MessageId;
 pub use libp2p::{Multiaddr, PeerId};
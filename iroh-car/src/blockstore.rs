@@ -0,0 +1,229 @@
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::sync::Arc;
+
+use cid::Cid;
+use futures::future::BoxFuture;
+use tokio::io::{AsyncRead, AsyncSeek};
+
+use crate::{
+    error::Error,
+    header::CarHeader,
+    index::{Index, IndexCodec},
+    reader::CarReader,
+};
+
+/// Multihash code of identity hashes, whose content is inlined into the cid.
+const IDENTITY_HASH: u64 = 0x00;
+
+type OpenReader<R> = Arc<dyn Fn() -> BoxFuture<'static, io::Result<R>> + Send + Sync>;
+
+/// Random access to the blocks of a CARv1 or CARv2 file.
+///
+/// The blocks are looked up in the index of a CARv2 file, or in an index built by reading
+/// through the file once when it is opened. Every lookup opens its own reader, so
+/// concurrent lookups do not wait on each other, and checks the block against its cid.
+pub struct CarBlockstore<R> {
+    open_reader: OpenReader<R>,
+    header: CarHeader,
+    index: Arc<Index>,
+}
+
+impl<R> Clone for CarBlockstore<R> {
+    fn clone(&self) -> Self {
+        CarBlockstore {
+            open_reader: self.open_reader.clone(),
+            header: self.header.clone(),
+            index: self.index.clone(),
+        }
+    }
+}
+
+impl<R> fmt::Debug for CarBlockstore<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CarBlockstore")
+            .field("header", &self.header)
+            .field("blocks", &self.index.len())
+            .finish()
+    }
+}
+
+impl<R> CarBlockstore<R>
+where
+    R: AsyncRead + AsyncSeek + Send + Unpin + 'static,
+{
+    /// Opens the CAR file read by the readers `open_reader` returns, which have to be
+    /// positioned at the start of the file.
+    pub async fn open<F, Fut>(open_reader: F) -> Result<Self, Error>
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = io::Result<R>> + Send + 'static,
+    {
+        let open_reader: OpenReader<R> = Arc::new(move || Box::pin(open_reader()));
+        let mut reader = CarReader::new(open_reader().await?).await?;
+        let index = match reader.read_index().await? {
+            Some(index) => index,
+            None => reader.index_blocks(IndexCodec::MultihashSorted).await?,
+        };
+
+        Ok(CarBlockstore {
+            open_reader,
+            header: reader.header().clone(),
+            index: Arc::new(index),
+        })
+    }
+
+    /// Returns the header of the CAR file.
+    pub fn header(&self) -> &CarHeader {
+        &self.header
+    }
+
+    /// Returns the data of the block `cid`.
+    ///
+    /// Fails with `Error::HashMismatch` if the data in the file does not match `cid`.
+    pub async fn get(&self, cid: &Cid) -> Result<Option<Vec<u8>>, Error> {
+        if cid.hash().code() == IDENTITY_HASH {
+            return Ok(Some(cid.hash().digest().to_vec()));
+        }
+        if self.index.get(cid.hash()).is_none() {
+            return Ok(None);
+        }
+        let reader = (self.open_reader)().await?;
+        CarReader::new_verifying(reader)
+            .await?
+            .get_block(&self.index, cid)
+            .await
+    }
+
+    /// Checks whether the CAR file contains the block `cid`.
+    pub fn has(&self, cid: &Cid) -> bool {
+        cid.hash().code() == IDENTITY_HASH || self.index.get(cid.hash()).is_some()
+    }
+
+    /// Returns the size in bytes of the block `cid`.
+    pub async fn get_size(&self, cid: &Cid) -> Result<Option<u64>, Error> {
+        Ok(self.get(cid).await?.map(|data| data.len() as u64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use cid::multihash::Multihash;
+    use ipld_cbor::DagCborCodec;
+    use multihash::MultihashDigest;
+
+    use super::*;
+    use crate::writer::{CarWriter, CarWriterV2};
+
+    fn blocks() -> Vec<(Cid, Vec<u8>)> {
+        (1..20u8)
+            .map(|i| {
+                let data = vec![i; 33 * i as usize];
+                let cid = Cid::new_v1(DagCborCodec.into(), multihash::Code::Sha2_256.digest(&data));
+                (cid, data)
+            })
+            .collect()
+    }
+
+    async fn open_buffer(buffer: Vec<u8>) -> CarBlockstore<Cursor<Arc<[u8]>>> {
+        let buffer: Arc<[u8]> = buffer.into();
+        CarBlockstore::open(move || {
+            let buffer = buffer.clone();
+            async move { Ok(Cursor::new(buffer)) }
+        })
+        .await
+        .unwrap()
+    }
+
+    async fn assert_blocks<R>(store: &CarBlockstore<R>, blocks: &[(Cid, Vec<u8>)])
+    where
+        R: AsyncRead + AsyncSeek + Send + Unpin + 'static,
+    {
+        for (cid, data) in blocks.iter().rev() {
+            assert!(store.has(cid));
+            assert_eq!(store.get(cid).await.unwrap().as_ref(), Some(data));
+            assert_eq!(store.get_size(cid).await.unwrap(), Some(data.len() as u64));
+        }
+
+        let missing = Cid::new_v1(
+            DagCborCodec.into(),
+            multihash::Code::Sha2_256.digest(b"missing"),
+        );
+        assert!(!store.has(&missing));
+        assert_eq!(store.get(&missing).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn blockstore_v1() {
+        let blocks = blocks();
+        let mut buffer = Vec::new();
+        let mut writer = CarWriter::new(CarHeader::new_v1(vec![blocks[0].0]), &mut buffer);
+        for (cid, data) in &blocks {
+            writer.write(*cid, data).await.unwrap();
+        }
+        writer.finish().await.unwrap();
+
+        let store = open_buffer(buffer).await;
+        assert_eq!(store.header().version(), 1);
+        assert_blocks(&store, &blocks).await;
+    }
+
+    #[tokio::test]
+    async fn blockstore_v2() {
+        let blocks = blocks();
+        for codec in [None, Some(IndexCodec::Sorted)] {
+            let mut writer = CarWriterV2::new(vec![blocks[0].0], codec, Cursor::new(Vec::new()));
+            for (cid, data) in &blocks {
+                writer.write(*cid, data).await.unwrap();
+            }
+            let (cursor, _) = writer.finish().await.unwrap();
+
+            let store = open_buffer(cursor.into_inner()).await;
+            assert_eq!(store.header().version(), 2);
+            assert_blocks(&store, &blocks).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn blockstore_identity() {
+        let blocks = blocks();
+        let mut writer = CarWriterV2::new(vec![blocks[0].0], None, Cursor::new(Vec::new()));
+        writer.write(blocks[0].0, &blocks[0].1).await.unwrap();
+        let (cursor, _) = writer.finish().await.unwrap();
+        let store = open_buffer(cursor.into_inner()).await;
+
+        let identity = Cid::new_v1(0x55, Multihash::wrap(IDENTITY_HASH, b"inline").unwrap());
+        assert!(store.has(&identity));
+        assert_eq!(
+            store.get(&identity).await.unwrap(),
+            Some(b"inline".to_vec())
+        );
+    }
+
+    #[tokio::test]
+    async fn blockstore_hash_mismatch() {
+        let blocks = blocks();
+        let mut buffer = Vec::new();
+        let mut writer = CarWriter::new(CarHeader::new_v1(vec![blocks[0].0]), &mut buffer);
+        for (cid, data) in &blocks {
+            writer.write(*cid, data).await.unwrap();
+        }
+        writer.finish().await.unwrap();
+        // the last block ends the file, corrupt its data
+        *buffer.last_mut().unwrap() ^= 0xff;
+
+        let store = open_buffer(buffer).await;
+        let (corrupted, _) = blocks.last().unwrap();
+        assert!(matches!(
+            store.get(corrupted).await,
+            Err(Error::HashMismatch(cid)) if cid == *corrupted
+        ));
+        assert_eq!(
+            store.get(&blocks[0].0).await.unwrap().as_ref(),
+            Some(&blocks[0].1)
+        );
+    }
+}
@@ -13,6 +13,14 @@ pub enum Error {
     Cbor(#[from] ipld::error::Error),
     #[error("ld read too large {0}")]
     LdReadTooLarge(usize),
+    #[error("Unsupported CAR index: {0:#x}")]
+    UnsupportedIndex(u64),
+    #[error("Unsupported multihash: {0:#x}")]
+    UnsupportedHash(u64),
+    #[error("Block data does not match its cid {0}")]
+    HashMismatch(cid::Cid),
+    #[error("Digest of {0} is too short to be verified")]
+    DigestTooShort(cid::Cid),
 }
 
 impl From<cid::Error> for Error {
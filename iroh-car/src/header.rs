@@ -1,14 +1,24 @@
 use cid::Cid;
 use ipld::codec::Codec;
+use ipld::Ipld;
 use ipld_cbor::DagCborCodec;
 
 use crate::error::Error;
 
+/// The fixed first bytes of a CARv2 file, a CARv1 style header announcing version 2.
+pub(crate) const PRAGMA_V2: [u8; 11] = [
+    0x0a, 0xa1, 0x67, 0x76, 0x65, 0x72, 0x73, 0x69, 0x6f, 0x6e, 0x02,
+];
+
+/// Size of the CARv2 header following the pragma.
+pub(crate) const HEADER_V2_SIZE: usize = 40;
+
 /// A car header.
 #[derive(Debug, Clone, PartialEq, Eq)]
 #[non_exhaustive]
 pub enum CarHeader {
     V1(CarHeaderV1),
+    V2(CarHeaderV2),
 }
 
 impl CarHeader {
@@ -16,6 +26,22 @@ impl CarHeader {
         Self::V1(roots.into())
     }
 
+    /// Decodes the `version` field shared by the headers of all CAR versions.
+    pub(crate) fn decode_version(buffer: &[u8]) -> Result<u64, Error> {
+        let header: Ipld = DagCborCodec
+            .decode(buffer)
+            .map_err(|e| Error::Parsing(e.to_string()))?;
+        let version = match &header {
+            Ipld::Map(map) => map.get("version"),
+            _ => None,
+        };
+        match version {
+            Some(Ipld::Integer(version)) => u64::try_from(*version)
+                .map_err(|_| Error::Parsing(format!("invalid version {}", version))),
+            _ => Err(Error::Parsing("missing CAR file version".to_string())),
+        }
+    }
+
     pub fn decode(buffer: &[u8]) -> Result<Self, Error> {
         let header: CarHeaderV1 = DagCborCodec
             .decode(buffer)
@@ -34,24 +60,33 @@ impl CarHeader {
         Ok(CarHeader::V1(header))
     }
 
+    /// Encodes the CARv1 header.
+    ///
+    /// A CARv2 header is not a single dag-cbor value, it is written by
+    /// [`CarWriterV2`](crate::CarWriterV2) and encoding it fails.
     pub fn encode(&self) -> Result<Vec<u8>, Error> {
         match self {
             CarHeader::V1(ref header) => {
                 let res = DagCborCodec.encode(header)?;
                 Ok(res)
             }
+            CarHeader::V2(_) => Err(Error::InvalidFile(
+                "CARv2 headers can only be written by CarWriterV2".to_string(),
+            )),
         }
     }
 
     pub fn roots(&self) -> &[Cid] {
         match self {
             CarHeader::V1(header) => &header.roots,
+            CarHeader::V2(header) => &header.roots,
         }
     }
 
     pub fn version(&self) -> u64 {
         match self {
             CarHeader::V1(_) => 1,
+            CarHeader::V2(_) => 2,
         }
     }
 }
@@ -78,6 +113,74 @@ impl From<Vec<Cid>> for CarHeaderV1 {
     }
 }
 
+/// Characteristics of a CARv2 file, a 128 bit bitfield.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Characteristics(pub [u8; 16]);
+
+impl Characteristics {
+    /// Whether the index of the file contains every block, including identity cids.
+    pub fn is_fully_indexed(&self) -> bool {
+        self.0[0] & 0x80 != 0
+    }
+}
+
+/// CAR file header version 2.
+///
+/// A CARv2 file wraps a CARv1 payload, optionally followed by an index of the blocks in it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CarHeaderV2 {
+    pub characteristics: Characteristics,
+    /// Byte offset of the CARv1 payload from the start of the file.
+    pub data_offset: u64,
+    /// Size of the CARv1 payload in bytes.
+    pub data_size: u64,
+    /// Byte offset of the index from the start of the file, 0 if there is no index.
+    pub index_offset: u64,
+    /// The roots of the CARv1 payload.
+    pub roots: Vec<Cid>,
+}
+
+impl CarHeaderV2 {
+    /// Encodes the fixed size part of the header, which follows the pragma.
+    pub(crate) fn encode_fixed(&self) -> [u8; HEADER_V2_SIZE] {
+        let mut buf = [0u8; HEADER_V2_SIZE];
+        buf[..16].copy_from_slice(&self.characteristics.0);
+        buf[16..24].copy_from_slice(&self.data_offset.to_le_bytes());
+        buf[24..32].copy_from_slice(&self.data_size.to_le_bytes());
+        buf[32..].copy_from_slice(&self.index_offset.to_le_bytes());
+        buf
+    }
+
+    /// Decodes the fixed size part of the header, the roots are read from the CARv1 payload.
+    pub(crate) fn decode_fixed(buf: &[u8; HEADER_V2_SIZE]) -> Result<Self, Error> {
+        let u64_at = |i: usize| u64::from_le_bytes(buf[i..i + 8].try_into().expect("8 bytes"));
+        let header = CarHeaderV2 {
+            characteristics: Characteristics(buf[..16].try_into().expect("16 bytes")),
+            data_offset: u64_at(16),
+            data_size: u64_at(24),
+            index_offset: u64_at(32),
+            roots: Vec::new(),
+        };
+
+        let header_end = (PRAGMA_V2.len() + HEADER_V2_SIZE) as u64;
+        if header.data_offset < header_end {
+            return Err(Error::InvalidFile(format!(
+                "CARv2 data offset {} overlaps the header",
+                header.data_offset
+            )));
+        }
+        if header.index_offset != 0
+            && header.index_offset < header.data_offset.saturating_add(header.data_size)
+        {
+            return Err(Error::InvalidFile(format!(
+                "CARv2 index offset {} overlaps the data",
+                header.index_offset
+            )));
+        }
+        Ok(header)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use ipld::codec::{Decode, Encode};
@@ -101,4 +204,40 @@ mod tests {
             header
         );
     }
+
+    #[test]
+    fn pragma_v2_version() {
+        assert_eq!(CarHeader::decode_version(&PRAGMA_V2[1..]).unwrap(), 2);
+
+        let digest = multihash::Code::Blake2b256.digest(b"test");
+        let cid = Cid::new_v1(DagCborCodec.into(), digest);
+        let v1 = CarHeader::new_v1(vec![cid]).encode().unwrap();
+        assert_eq!(CarHeader::decode_version(&v1).unwrap(), 1);
+
+        let v2 = CarHeader::V2(CarHeaderV2 {
+            roots: vec![cid],
+            ..Default::default()
+        });
+        assert!(v2.encode().is_err());
+    }
+
+    #[test]
+    fn symmetric_header_v2() {
+        let header = CarHeaderV2 {
+            characteristics: Characteristics([0x80; 16]),
+            data_offset: 51,
+            data_size: 1024,
+            index_offset: 1075,
+            roots: Vec::new(),
+        };
+        let decoded = CarHeaderV2::decode_fixed(&header.encode_fixed()).unwrap();
+        assert_eq!(decoded, header);
+        assert!(decoded.characteristics.is_fully_indexed());
+
+        let overlapping = CarHeaderV2 {
+            index_offset: 60,
+            ..header
+        };
+        assert!(CarHeaderV2::decode_fixed(&overlapping.encode_fixed()).is_err());
+    }
 }
@@ -0,0 +1,348 @@
+use std::collections::BTreeMap;
+
+use cid::multihash::Multihash;
+use integer_encoding::VarInt;
+
+use crate::error::Error;
+
+/// Multicodec of an `IndexSorted` index.
+const INDEX_SORTED: u64 = 0x0400;
+/// Multicodec of a `MultihashIndexSorted` index.
+const MULTIHASH_INDEX_SORTED: u64 = 0x0401;
+
+/// The index formats of CARv2 files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexCodec {
+    /// Index by multihash digest, regardless of the hash function.
+    Sorted,
+    /// Index by multihash code and digest.
+    MultihashSorted,
+}
+
+impl IndexCodec {
+    pub fn code(&self) -> u64 {
+        match self {
+            IndexCodec::Sorted => INDEX_SORTED,
+            IndexCodec::MultihashSorted => MULTIHASH_INDEX_SORTED,
+        }
+    }
+}
+
+impl TryFrom<u64> for IndexCodec {
+    type Error = Error;
+
+    fn try_from(code: u64) -> Result<Self, Error> {
+        match code {
+            INDEX_SORTED => Ok(IndexCodec::Sorted),
+            MULTIHASH_INDEX_SORTED => Ok(IndexCodec::MultihashSorted),
+            code => Err(Error::UnsupportedIndex(code)),
+        }
+    }
+}
+
+/// An index of the blocks in a CARv1 payload.
+///
+/// Offsets point at the start of a block's section, relative to the start of the payload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Index {
+    Sorted(IndexSorted),
+    MultihashSorted(MultihashIndexSorted),
+}
+
+impl Index {
+    /// Builds an index of the given format from `(multihash, offset)` entries.
+    pub fn new<I>(codec: IndexCodec, entries: I) -> Self
+    where
+        I: IntoIterator<Item = (Multihash, u64)>,
+    {
+        match codec {
+            IndexCodec::Sorted => Index::Sorted(IndexSorted::new(
+                entries
+                    .into_iter()
+                    .map(|(hash, offset)| (hash.digest().to_vec(), offset)),
+            )),
+            IndexCodec::MultihashSorted => {
+                let mut by_code: BTreeMap<u64, Vec<(Vec<u8>, u64)>> = BTreeMap::new();
+                for (hash, offset) in entries {
+                    by_code
+                        .entry(hash.code())
+                        .or_default()
+                        .push((hash.digest().to_vec(), offset));
+                }
+                Index::MultihashSorted(MultihashIndexSorted(
+                    by_code
+                        .into_iter()
+                        .map(|(code, entries)| (code, IndexSorted::new(entries)))
+                        .collect(),
+                ))
+            }
+        }
+    }
+
+    pub fn codec(&self) -> IndexCodec {
+        match self {
+            Index::Sorted(_) => IndexCodec::Sorted,
+            Index::MultihashSorted(_) => IndexCodec::MultihashSorted,
+        }
+    }
+
+    /// Returns the offset of the block with the given multihash.
+    pub fn get(&self, hash: &Multihash) -> Option<u64> {
+        match self {
+            Index::Sorted(index) => index.get(hash.digest()),
+            Index::MultihashSorted(index) => index.0.get(&hash.code())?.get(hash.digest()),
+        }
+    }
+
+    /// Number of indexed blocks.
+    pub fn len(&self) -> usize {
+        match self {
+            Index::Sorted(index) => index.len(),
+            Index::MultihashSorted(index) => index.0.values().map(IndexSorted::len).sum(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Encodes the index, prefixed with its multicodec.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = self.codec().code().encode_var_vec();
+        match self {
+            Index::Sorted(index) => index.encode(&mut buf),
+            Index::MultihashSorted(index) => {
+                put_u32(&mut buf, index.0.len() as u32);
+                for (code, index) in &index.0 {
+                    buf.extend_from_slice(&code.to_le_bytes());
+                    index.encode(&mut buf);
+                }
+            }
+        }
+        buf
+    }
+
+    /// Decodes an index prefixed with its multicodec.
+    pub fn decode(buf: &[u8]) -> Result<Self, Error> {
+        let (code, len) =
+            u64::decode_var(buf).ok_or_else(|| Error::Parsing("invalid index codec".into()))?;
+        let mut reader = ByteReader(&buf[len..]);
+        let index = match IndexCodec::try_from(code)? {
+            IndexCodec::Sorted => Index::Sorted(IndexSorted::decode(&mut reader)?),
+            IndexCodec::MultihashSorted => {
+                let count = reader.u32()?;
+                let mut by_code = BTreeMap::new();
+                for _ in 0..count {
+                    let code = reader.u64()?;
+                    by_code.insert(code, IndexSorted::decode(&mut reader)?);
+                }
+                Index::MultihashSorted(MultihashIndexSorted(by_code))
+            }
+        };
+        Ok(index)
+    }
+}
+
+/// Index of digests to offsets, grouped by digest length and sorted by digest.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IndexSorted {
+    buckets: BTreeMap<u32, Vec<(Vec<u8>, u64)>>,
+}
+
+impl IndexSorted {
+    fn new(entries: impl IntoIterator<Item = (Vec<u8>, u64)>) -> Self {
+        let mut buckets: BTreeMap<u32, Vec<(Vec<u8>, u64)>> = BTreeMap::new();
+        for (digest, offset) in entries {
+            buckets
+                .entry(digest.len() as u32)
+                .or_default()
+                .push((digest, offset));
+        }
+        for bucket in buckets.values_mut() {
+            bucket.sort();
+        }
+        IndexSorted { buckets }
+    }
+
+    fn get(&self, digest: &[u8]) -> Option<u64> {
+        let bucket = self.buckets.get(&(digest.len() as u32))?;
+        let i = bucket
+            .binary_search_by(|(d, _)| d.as_slice().cmp(digest))
+            .ok()?;
+        Some(bucket[i].1)
+    }
+
+    fn len(&self) -> usize {
+        self.buckets.values().map(Vec::len).sum()
+    }
+
+    fn encode(&self, buf: &mut Vec<u8>) {
+        put_u32(buf, self.buckets.len() as u32);
+        for (digest_len, bucket) in &self.buckets {
+            // each record is the digest followed by the offset
+            let width = digest_len + 8;
+            put_u32(buf, width);
+            buf.extend_from_slice(&(width as u64 * bucket.len() as u64).to_le_bytes());
+            for (digest, offset) in bucket {
+                buf.extend_from_slice(digest);
+                buf.extend_from_slice(&offset.to_le_bytes());
+            }
+        }
+    }
+
+    fn decode(reader: &mut ByteReader<'_>) -> Result<Self, Error> {
+        let count = reader.u32()?;
+        let mut buckets = BTreeMap::new();
+        for _ in 0..count {
+            let width = reader.u32()?;
+            if width < 8 {
+                return Err(Error::Parsing(format!("invalid index width {}", width)));
+            }
+            let size = reader.u64()?;
+            if size % width as u64 != 0 {
+                return Err(Error::Parsing(format!(
+                    "index size {} is not a multiple of its width {}",
+                    size, width
+                )));
+            }
+            let records = reader.take(size)?;
+            let bucket: Vec<_> = records
+                .chunks_exact(width as usize)
+                .map(|record| {
+                    let (digest, offset) = record.split_at(width as usize - 8);
+                    let offset = u64::from_le_bytes(offset.try_into().expect("8 bytes"));
+                    (digest.to_vec(), offset)
+                })
+                .collect();
+            // lookups binary search the bucket, so it must be sorted by digest
+            if bucket.windows(2).any(|pair| pair[0].0 > pair[1].0) {
+                return Err(Error::Parsing(format!(
+                    "index bucket of width {} is not sorted",
+                    width
+                )));
+            }
+            buckets.insert(width - 8, bucket);
+        }
+        Ok(IndexSorted { buckets })
+    }
+}
+
+/// Index of multihashes to offsets, an `IndexSorted` per multihash code.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MultihashIndexSorted(BTreeMap<u64, IndexSorted>);
+
+fn put_u32(buf: &mut Vec<u8>, n: u32) {
+    buf.extend_from_slice(&n.to_le_bytes());
+}
+
+struct ByteReader<'a>(&'a [u8]);
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, n: u64) -> Result<&'a [u8], Error> {
+        if (self.0.len() as u64) < n {
+            return Err(Error::Parsing("unexpected end of index".into()));
+        }
+        let (head, tail) = self.0.split_at(n as usize);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(
+            self.take(4)?.try_into().expect("4 bytes"),
+        ))
+    }
+
+    fn u64(&mut self) -> Result<u64, Error> {
+        Ok(u64::from_le_bytes(
+            self.take(8)?.try_into().expect("8 bytes"),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use multihash::{Code, MultihashDigest};
+
+    use super::*;
+
+    fn entries() -> Vec<(Multihash, u64)> {
+        vec![
+            (Code::Sha2_256.digest(b"foo"), 59),
+            (Code::Sha2_256.digest(b"bar"), 100),
+            (Code::Blake2b256.digest(b"baz"), 141),
+            (Code::Sha2_512.digest(b"qux"), 182),
+        ]
+    }
+
+    #[test]
+    fn index_lookup_and_roundtrip() {
+        for codec in [IndexCodec::Sorted, IndexCodec::MultihashSorted] {
+            let index = Index::new(codec, entries());
+            assert_eq!(index.codec(), codec);
+            assert_eq!(index.len(), 4);
+            for (hash, offset) in entries() {
+                assert_eq!(index.get(&hash), Some(offset));
+            }
+            assert_eq!(index.get(&Code::Sha2_256.digest(b"missing")), None);
+
+            let decoded = Index::decode(&index.encode()).unwrap();
+            assert_eq!(decoded, index);
+        }
+    }
+
+    #[test]
+    fn multihash_index_distinguishes_codes() {
+        let sha = Code::Sha2_256.digest(b"foo");
+        let blake = Multihash::wrap(Code::Blake2b256.into(), sha.digest()).unwrap();
+
+        let index = Index::new(IndexCodec::MultihashSorted, vec![(sha, 1)]);
+        assert_eq!(index.get(&blake), None);
+        // the sorted index only compares digests
+        let index = Index::new(IndexCodec::Sorted, vec![(sha, 1)]);
+        assert_eq!(index.get(&blake), Some(1));
+    }
+
+    #[test]
+    fn index_sorted_layout() {
+        let hash = Code::Sha2_256.digest(b"foo");
+        let encoded = Index::new(IndexCodec::Sorted, vec![(hash, 7)]).encode();
+
+        let mut expected = vec![0x80, 0x08];
+        expected.extend_from_slice(&1u32.to_le_bytes());
+        expected.extend_from_slice(&40u32.to_le_bytes());
+        expected.extend_from_slice(&40u64.to_le_bytes());
+        expected.extend_from_slice(hash.digest());
+        expected.extend_from_slice(&7u64.to_le_bytes());
+        assert_eq!(encoded, expected);
+    }
+
+    #[test]
+    fn decode_unsorted_index() {
+        let mut digests = vec![
+            Code::Sha2_256.digest(b"foo").digest().to_vec(),
+            Code::Sha2_256.digest(b"bar").digest().to_vec(),
+        ];
+        digests.sort();
+        digests.reverse();
+
+        let mut buf = vec![0x80, 0x08];
+        buf.extend_from_slice(&1u32.to_le_bytes());
+        buf.extend_from_slice(&40u32.to_le_bytes());
+        buf.extend_from_slice(&80u64.to_le_bytes());
+        for (offset, digest) in digests.iter().enumerate() {
+            buf.extend_from_slice(digest);
+            buf.extend_from_slice(&(offset as u64).to_le_bytes());
+        }
+        assert!(matches!(Index::decode(&buf), Err(Error::Parsing(_))));
+    }
+
+    #[test]
+    fn decode_unsupported_index() {
+        let buf = 0x0402u64.encode_var_vec();
+        assert!(matches!(
+            Index::decode(&buf),
+            Err(Error::UnsupportedIndex(0x0402))
+        ));
+    }
+}
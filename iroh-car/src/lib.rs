@@ -1,11 +1,17 @@
 //! Implementation of the [car](https://ipld.io/specs/transport/car/) format.
 
+mod blockstore;
 mod error;
 mod header;
+mod index;
 mod reader;
 mod util;
+mod verify;
 mod writer;
 
-pub use crate::header::CarHeader;
+pub use crate::blockstore::CarBlockstore;
+pub use crate::header::{CarHeader, CarHeaderV2, Characteristics};
+pub use crate::index::{Index, IndexCodec, IndexSorted, MultihashIndexSorted};
 pub use crate::reader::CarReader;
-pub use crate::writer::CarWriter;
+pub use crate::verify::{verify_block, DagReport};
+pub use crate::writer::{CarWriter, CarWriterV2};
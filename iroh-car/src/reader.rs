@@ -1,18 +1,28 @@
+use std::io::SeekFrom;
+
 use cid::Cid;
 use futures::Stream;
-use tokio::io::AsyncRead;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, Take};
 
 use crate::{
     error::Error,
-    header::CarHeader,
+    header::{CarHeader, CarHeaderV2, HEADER_V2_SIZE, PRAGMA_V2},
+    index::{Index, IndexCodec},
     util::{ld_read, read_node},
+    verify::{verify_block, DagChecker, DagReport},
 };
 
 /// Reads CAR files that are in a BufReader
+///
+/// Both CARv1 and CARv2 files are supported, for CARv2 the blocks of the embedded CARv1
+/// payload are read.
 pub struct CarReader<R> {
-    reader: R,
+    /// Limited to the end of the CARv1 payload.
+    reader: Take<R>,
     header: CarHeader,
     buffer: Vec<u8>,
+    /// Whether blocks are checked against their cids.
+    verify: bool,
 }
 
 impl<R> CarReader<R>
@@ -20,23 +30,81 @@ where
     R: AsyncRead + Send + Unpin,
 {
     /// Creates a new CarReader and parses the CarHeader
-    pub async fn new(mut reader: R) -> Result<Self, Error> {
+    pub async fn new(reader: R) -> Result<Self, Error> {
+        let mut reader = reader.take(u64::MAX);
         let mut buffer = Vec::new();
 
-        match ld_read(&mut reader, &mut buffer).await? {
-            Some(buf) => {
-                let header = CarHeader::decode(buf)?;
+        let header_v1 = match ld_read(&mut reader, &mut buffer).await? {
+            Some(buf) => match CarHeader::decode_version(buf)? {
+                1 => Ok(CarHeader::decode(buf)?),
+                version => Err(version),
+            },
+            None => {
+                return Err(Error::Parsing(
+                    "failed to parse uvarint for header".to_string(),
+                ))
+            }
+        };
+
+        let header = match header_v1 {
+            Ok(header) => header,
+            Err(2) => {
+                let mut fixed = [0u8; HEADER_V2_SIZE];
+                reader.read_exact(&mut fixed).await?;
+                let mut header = CarHeaderV2::decode_fixed(&fixed)?;
+
+                // skip any padding before the payload
+                let pos = (PRAGMA_V2.len() + HEADER_V2_SIZE) as u64;
+                let padding = header.data_offset - pos;
+                let skipped =
+                    tokio::io::copy(&mut (&mut reader).take(padding), &mut tokio::io::sink())
+                        .await?;
+                if skipped != padding {
+                    return Err(Error::Parsing("unexpected end of CARv2 file".to_string()));
+                }
+                reader.set_limit(header.data_size);
 
-                Ok(CarReader {
-                    reader,
-                    header,
-                    buffer,
-                })
+                match ld_read(&mut reader, &mut buffer).await? {
+                    Some(buf) => header.roots = CarHeader::decode(buf)?.roots().to_vec(),
+                    None => {
+                        return Err(Error::Parsing(
+                            "failed to parse uvarint for CARv1 payload header".to_string(),
+                        ))
+                    }
+                }
+                CarHeader::V2(header)
             }
-            None => Err(Error::Parsing(
-                "failed to parse uvarint for header".to_string(),
-            )),
-        }
+            Err(version) => {
+                return Err(Error::InvalidFile(format!(
+                    "CAR file version {} is not supported",
+                    version
+                )))
+            }
+        };
+
+        Ok(CarReader {
+            reader,
+            header,
+            buffer,
+            verify: false,
+        })
+    }
+
+    /// Creates a new CarReader that checks every block it reads against the multihash of
+    /// its cid, see [`CarReader::verify_hashes`].
+    pub async fn new_verifying(reader: R) -> Result<Self, Error> {
+        Ok(CarReader::new(reader).await?.verify_hashes(true))
+    }
+
+    /// Enables or disables rehashing of blocks.
+    ///
+    /// When enabled, reading a block whose data does not match its cid fails with
+    /// `Error::HashMismatch`, a block hashed with an unsupported function fails with
+    /// `Error::UnsupportedHash` and one whose digest is truncated below 20 bytes with
+    /// `Error::DigestTooShort`.
+    pub fn verify_hashes(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
     }
 
     /// Returns the header of this car file.
@@ -46,15 +114,112 @@ where
 
     /// Returns the next IPLD Block in the buffer
     pub async fn next_block(&mut self) -> Result<Option<(Cid, Vec<u8>)>, Error> {
-        read_node(&mut self.reader, &mut self.buffer).await
+        let block = read_node(&mut self.reader, &mut self.buffer).await?;
+        if let (true, Some((cid, data))) = (self.verify, &block) {
+            verify_block(cid, data)?;
+        }
+        Ok(block)
     }
 
     pub fn stream(self) -> impl Stream<Item = Result<(Cid, Vec<u8>), Error>> {
         futures::stream::try_unfold(self, |mut this| async move {
-            let maybe_block = read_node(&mut this.reader, &mut this.buffer).await?;
+            let maybe_block = this.next_block().await?;
             Ok(maybe_block.map(|b| (b, this)))
         })
     }
+
+    /// Reads the remaining blocks, verifying their hashes, and checks that they form the
+    /// complete DAG under the roots of the header.
+    ///
+    /// Hash mismatches fail with an error, while missing and unreachable blocks are listed
+    /// in the returned report.
+    pub async fn verify_dag(mut self) -> Result<DagReport, Error> {
+        self.verify = true;
+        let mut checker = DagChecker::default();
+        while let Some((cid, data)) = self.next_block().await? {
+            checker.add(cid, &data);
+        }
+        Ok(checker.report(self.header.roots()))
+    }
+}
+
+impl<R> CarReader<R>
+where
+    R: AsyncRead + AsyncSeek + Send + Unpin,
+{
+    /// Reads the index of a CARv2 file, returns `None` for files without an index.
+    ///
+    /// Offsets in the file are relative to the position of the reader when the `CarReader`
+    /// was created, which has to be 0. The position of the stream of blocks is kept.
+    pub async fn read_index(&mut self) -> Result<Option<Index>, Error> {
+        let index_offset = match &self.header {
+            CarHeader::V2(header) if header.index_offset != 0 => header.index_offset,
+            _ => return Ok(None),
+        };
+
+        let reader = self.reader.get_mut();
+        let pos = reader.seek(SeekFrom::Current(0)).await?;
+        reader.seek(SeekFrom::Start(index_offset)).await?;
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).await?;
+        reader.seek(SeekFrom::Start(pos)).await?;
+
+        Index::decode(&buf).map(Some)
+    }
+
+    /// Builds an index of the remaining blocks by reading through them.
+    ///
+    /// This consumes the stream of blocks, but blocks can still be read with `get_block`.
+    pub async fn index_blocks(&mut self, codec: IndexCodec) -> Result<Index, Error> {
+        let data_offset = self.data_offset();
+        let mut offsets = Vec::new();
+        loop {
+            let pos = self.reader.get_mut().seek(SeekFrom::Current(0)).await?;
+            match self.next_block().await? {
+                Some((cid, _)) => offsets.push((*cid.hash(), pos - data_offset)),
+                None => break,
+            }
+        }
+        Ok(Index::new(codec, offsets))
+    }
+
+    /// Offset of the CARv1 payload in the file.
+    fn data_offset(&self) -> u64 {
+        match &self.header {
+            CarHeader::V2(header) => header.data_offset,
+            _ => 0,
+        }
+    }
+
+    /// Reads the block `cid` at the offset recorded in the `index`.
+    ///
+    /// The position of the stream of blocks is kept.
+    pub async fn get_block(&mut self, index: &Index, cid: &Cid) -> Result<Option<Vec<u8>>, Error> {
+        let offset = match index.get(cid.hash()) {
+            Some(offset) => offset,
+            None => return Ok(None),
+        };
+        let data_offset = self.data_offset();
+        let reader = self.reader.get_mut();
+        let pos = reader.seek(SeekFrom::Current(0)).await?;
+        reader.seek(SeekFrom::Start(data_offset + offset)).await?;
+        let block = read_node(reader, &mut self.buffer).await;
+        reader.seek(SeekFrom::Start(pos)).await?;
+
+        match block? {
+            // the sorted index only records digests, so compare just the hash
+            Some((found, data)) if found.hash() == cid.hash() => {
+                if self.verify {
+                    verify_block(&found, &data)?;
+                }
+                Ok(Some(data))
+            }
+            _ => Err(Error::InvalidFile(format!(
+                "index entry for {} does not point at its block",
+                cid
+            ))),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -96,4 +261,64 @@ mod tests {
         assert_eq!(files[1].0, cid_foo);
         assert_eq!(files[1].1, b"foo");
     }
+
+    #[tokio::test]
+    async fn car_read_verifying() {
+        let cid_foo = Cid::new_v1(
+            DagCborCodec.into(),
+            multihash::Code::Sha2_256.digest(b"foo"),
+        );
+        let cid_bar = Cid::new_v1(
+            DagCborCodec.into(),
+            multihash::Code::Sha2_256.digest(b"bar"),
+        );
+
+        let mut buffer = Vec::new();
+        let mut writer = CarWriter::new(CarHeader::new_v1(vec![cid_foo]), &mut buffer);
+        writer.write(cid_foo, b"foo").await.unwrap();
+        // data that does not match its cid
+        writer.write(cid_bar, b"baz").await.unwrap();
+        writer.finish().await.unwrap();
+
+        // not checked by default
+        let car_reader = CarReader::new(Cursor::new(&buffer)).await.unwrap();
+        let blocks: Vec<_> = car_reader.stream().try_collect().await.unwrap();
+        assert_eq!(blocks.len(), 2);
+
+        let mut car_reader = CarReader::new_verifying(Cursor::new(&buffer))
+            .await
+            .unwrap();
+        assert_eq!(car_reader.next_block().await.unwrap().unwrap().0, cid_foo);
+        assert!(matches!(
+            car_reader.next_block().await,
+            Err(Error::HashMismatch(cid)) if cid == cid_bar
+        ));
+
+        let car_reader = CarReader::new(Cursor::new(&buffer)).await.unwrap();
+        assert!(matches!(
+            car_reader.verify_dag().await,
+            Err(Error::HashMismatch(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn car_verify_dag() {
+        let cid_foo = Cid::new_v1(
+            DagCborCodec.into(),
+            multihash::Code::Sha2_256.digest(b"foo"),
+        );
+        let cid_bar = Cid::new_v1(0x55, multihash::Code::Sha2_256.digest(b"bar"));
+
+        let mut buffer = Vec::new();
+        let mut writer = CarWriter::new(CarHeader::new_v1(vec![cid_bar, cid_foo]), &mut buffer);
+        writer.write(cid_bar, b"bar").await.unwrap();
+        writer.finish().await.unwrap();
+
+        let car_reader = CarReader::new(Cursor::new(&buffer)).await.unwrap();
+        let report = car_reader.verify_dag().await.unwrap();
+        assert_eq!(report.blocks, 1);
+        assert_eq!(report.missing, vec![cid_foo]);
+        assert!(report.extra.is_empty());
+        assert!(!report.is_complete());
+    }
 }
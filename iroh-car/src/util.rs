@@ -1,6 +1,6 @@
 use cid::Cid;
-use integer_encoding::VarIntAsyncReader;
-use tokio::io::{AsyncRead, AsyncReadExt};
+use integer_encoding::{VarInt, VarIntAsyncReader, VarIntAsyncWriter};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
 use super::error::Error;
 
@@ -53,6 +53,27 @@ where
     Ok(None)
 }
 
+/// Writes a length prefixed block section, returning the number of bytes written.
+pub(crate) async fn write_node<W>(
+    writer: &mut W,
+    cid: &Cid,
+    data: &[u8],
+    cid_buffer: &mut Vec<u8>,
+) -> Result<u64, Error>
+where
+    W: AsyncWrite + Send + Unpin,
+{
+    cid_buffer.clear();
+    cid.write_bytes(&mut *cid_buffer).expect("vec write");
+
+    let len = cid_buffer.len() + data.len();
+    writer.write_varint_async(len).await?;
+    writer.write_all(cid_buffer).await?;
+    writer.write_all(data).await?;
+
+    Ok((len.required_space() + len) as u64)
+}
+
 #[cfg(test)]
 mod tests {
     use integer_encoding::VarIntAsyncWriter;
@@ -0,0 +1,342 @@
+use std::collections::{BTreeSet, HashMap, VecDeque};
+
+use blake2b_simd::Params as Blake2bParams;
+use blake2s_simd::Params as Blake2sParams;
+use cid::Cid;
+use ipld::codec::Codec;
+use ipld::{Ipld, IpldCodec};
+use multihash::MultihashDigest;
+use sha1::Sha1;
+use sha2::{Digest, Sha384};
+use sha3::digest::{ExtendableOutput, Update, XofReader};
+use sha3::{Shake128, Shake256};
+
+use crate::error::Error;
+
+const IDENTITY: u64 = 0x00;
+const SHA1: u64 = 0x11;
+const SHAKE_128: u64 = 0x18;
+const SHAKE_256: u64 = 0x19;
+const SHA2_384: u64 = 0x20;
+const MURMUR3_X64_64: u64 = 0x22;
+const BLAKE2B_MIN: u64 = 0xb201;
+const BLAKE2B_MAX: u64 = 0xb240;
+const BLAKE2S_MIN: u64 = 0xb241;
+const BLAKE2S_MAX: u64 = 0xb260;
+
+/// Output size of the extendable output functions, unless the multihash asks for more.
+const SHAKE_128_SIZE: usize = 32;
+const SHAKE_256_SIZE: usize = 64;
+/// Minimum size of a truncated digest, shorter ones are too easy to collide.
+const MIN_TRUNCATED_DIGEST_SIZE: usize = 20;
+
+/// Checks that `data` hashes to the multihash of `cid`.
+///
+/// Fails with `Error::UnsupportedHash` for hash functions that can not be computed, rather
+/// than letting the block through unchecked. Truncated digests are accepted down to
+/// 20 bytes, identity multihashes must contain exactly the data.
+pub fn verify_block(cid: &Cid, data: &[u8]) -> Result<(), Error> {
+    let hash = cid.hash();
+    let recorded = hash.digest();
+    if hash.code() == IDENTITY {
+        return match recorded == data {
+            true => Ok(()),
+            false => Err(Error::HashMismatch(*cid)),
+        };
+    }
+    let digest = digest(hash.code(), data, recorded.len())
+        .ok_or_else(|| Error::UnsupportedHash(hash.code()))?;
+    if recorded.is_empty()
+        || (recorded.len() < digest.len() && recorded.len() < MIN_TRUNCATED_DIGEST_SIZE)
+    {
+        return Err(Error::DigestTooShort(*cid));
+    }
+    // multihashes may be truncated, compare the prefix that was recorded
+    if digest.len() >= recorded.len() && &digest[..recorded.len()] == recorded {
+        Ok(())
+    } else {
+        Err(Error::HashMismatch(*cid))
+    }
+}
+
+/// Computes the digest of `data` with the hash function `code`.
+///
+/// Extendable output functions produce their default size, or `size` if it is larger.
+fn digest(code: u64, data: &[u8], size: usize) -> Option<Vec<u8>> {
+    if let Ok(code) = multihash::Code::try_from(code) {
+        return Some(code.digest(data).digest().to_vec());
+    }
+    let digest = match code {
+        IDENTITY => data.to_vec(),
+        SHA1 => Sha1::digest(data).to_vec(),
+        SHA2_384 => Sha384::digest(data).to_vec(),
+        SHAKE_128 => {
+            let mut hasher = Shake128::default();
+            hasher.update(data);
+            let mut digest = vec![0u8; size.max(SHAKE_128_SIZE)];
+            hasher.finalize_xof().read(&mut digest);
+            digest
+        }
+        SHAKE_256 => {
+            let mut hasher = Shake256::default();
+            hasher.update(data);
+            let mut digest = vec![0u8; size.max(SHAKE_256_SIZE)];
+            hasher.finalize_xof().read(&mut digest);
+            digest
+        }
+        MURMUR3_X64_64 => {
+            // h1 of murmur3 x64 128 is its low half, encoded big endian like go-multihash
+            let h1 = fastmurmur3::hash(data) as u64;
+            h1.to_be_bytes().to_vec()
+        }
+        BLAKE2B_MIN..=BLAKE2B_MAX => Blake2bParams::new()
+            .hash_length((code - BLAKE2B_MIN + 1) as usize)
+            .hash(data)
+            .as_bytes()
+            .to_vec(),
+        BLAKE2S_MIN..=BLAKE2S_MAX => Blake2sParams::new()
+            .hash_length((code - BLAKE2S_MIN + 1) as usize)
+            .hash(data)
+            .as_bytes()
+            .to_vec(),
+        _ => return None,
+    };
+    Some(digest)
+}
+
+/// Result of checking the DAG of a CAR file against its roots.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DagReport {
+    /// Number of blocks in the file.
+    pub blocks: usize,
+    /// Blocks reachable from the roots that are not in the file.
+    pub missing: Vec<Cid>,
+    /// Blocks in the file that are not reachable from the roots.
+    pub extra: Vec<Cid>,
+    /// Reachable blocks whose links could not be read, so their children were not checked.
+    pub undecodable: Vec<Cid>,
+}
+
+impl DagReport {
+    /// Whether every block reachable from the roots is in the file.
+    pub fn is_complete(&self) -> bool {
+        self.missing.is_empty() && self.undecodable.is_empty()
+    }
+
+    /// Whether the file contains exactly the DAG under its roots.
+    pub fn is_exact(&self) -> bool {
+        self.is_complete() && self.extra.is_empty()
+    }
+}
+
+/// Collects the links of the blocks of a CAR file to build a `DagReport`.
+#[derive(Debug, Default)]
+pub(crate) struct DagChecker {
+    /// Links of every block, `None` if they could not be read.
+    blocks: HashMap<Cid, Option<Vec<Cid>>>,
+    count: usize,
+}
+
+impl DagChecker {
+    pub(crate) fn add(&mut self, cid: Cid, data: &[u8]) {
+        self.count += 1;
+        self.blocks.insert(cid, links(&cid, data));
+    }
+
+    pub(crate) fn report(mut self, roots: &[Cid]) -> DagReport {
+        let mut report = DagReport {
+            blocks: self.count,
+            ..Default::default()
+        };
+
+        let mut seen = BTreeSet::new();
+        let mut queue: VecDeque<Cid> = roots.iter().copied().collect();
+        while let Some(cid) = queue.pop_front() {
+            if !seen.insert(cid) {
+                continue;
+            }
+            let links = if cid.hash().code() == IDENTITY {
+                // the content of identity cids is inlined
+                links(&cid, cid.hash().digest())
+            } else {
+                match self.blocks.get(&cid) {
+                    Some(links) => links.clone(),
+                    None => {
+                        report.missing.push(cid);
+                        continue;
+                    }
+                }
+            };
+            match links {
+                Some(links) => queue.extend(links),
+                None => report.undecodable.push(cid),
+            }
+        }
+
+        self.blocks.retain(|cid, _| !seen.contains(cid));
+        report.extra = self.blocks.into_keys().collect();
+        report.extra.sort();
+        report
+    }
+}
+
+fn links(cid: &Cid, data: &[u8]) -> Option<Vec<Cid>> {
+    let codec = IpldCodec::try_from(cid.codec()).ok()?;
+    let mut links = BTreeSet::new();
+    codec.references::<Ipld, _>(data, &mut links).ok()?;
+    Some(links.into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use cid::multihash::Multihash;
+    use ipld_cbor::DagCborCodec;
+    use multihash::Code;
+
+    use super::*;
+
+    const RAW: u64 = 0x55;
+
+    #[test]
+    fn verify_supported_hashes() {
+        let data = b"hello world";
+        let mut codes = vec![
+            Code::Sha2_256.into(),
+            Code::Sha2_512.into(),
+            Code::Sha3_256.into(),
+            Code::Keccak256.into(),
+            Code::Blake2b256.into(),
+            Code::Blake2s256.into(),
+            Code::Blake3_256.into(),
+            IDENTITY,
+            SHA1,
+            SHAKE_128,
+            SHAKE_256,
+            SHA2_384,
+            MURMUR3_X64_64,
+        ];
+        codes.extend([BLAKE2B_MIN, 0xb210, BLAKE2S_MIN, BLAKE2S_MAX]);
+
+        for code in codes {
+            let hash = digest(code, data, 0).unwrap();
+            let cid = Cid::new_v1(RAW, Multihash::wrap(code, &hash).unwrap());
+            verify_block(&cid, data).unwrap();
+            assert!(matches!(
+                verify_block(&cid, b"hello moon"),
+                Err(Error::HashMismatch(c)) if c == cid
+            ));
+        }
+    }
+
+    #[test]
+    fn verify_known_digests() {
+        let sha1 = digest(SHA1, b"abc", 0).unwrap();
+        assert_eq!(
+            sha1,
+            [
+                0xa9, 0x99, 0x3e, 0x36, 0x47, 0x06, 0x81, 0x6a, 0xba, 0x3e, 0x25, 0x71, 0x78, 0x50,
+                0xc2, 0x6c, 0x9c, 0xd0, 0xd8, 0x9d
+            ]
+        );
+        // blake2b-256 by length matches the fixed size implementation
+        assert_eq!(
+            digest(0xb220, b"abc", 0).unwrap(),
+            Code::Blake2b256.digest(b"abc").digest()
+        );
+
+        let data = b"hello world";
+        let known = [
+            (SHA2_384, "fdbd8e75a67f29f701a4e040385e2e23986303ea10239211af907fcbb83578b3e417cb71ce646efd0819dd8c088de1bd"),
+            (SHAKE_128, "3a9159f071e4dd1c8c4f968607c30942e120d8156b8b1e72e0d376e8871cb8b8"),
+            (SHAKE_256, "369771bb2cb9d2b04c1d54cca487e372d9f187f73f7ba3f65b95c8ee7798c527f4f3c2d55c2d46a29f2e945d469c3df27853a8735271f5cc2d9e889544357116"),
+            (MURMUR3_X64_64, "533f6046eb7f610e"),
+            (0xb214, "70e8ece5e293e1bda064deef6b080edde357010f"),
+            (BLAKE2B_MIN, "93"),
+            (0xb250, "37deae0226c30da2ab424a7b8ee14e83"),
+            (BLAKE2S_MIN, "bb"),
+            (IDENTITY, "68656c6c6f20776f726c64"),
+        ];
+        for (code, expected) in known {
+            let digest: String = digest(code, data, 0)
+                .unwrap()
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect();
+            assert_eq!(digest, expected, "{:#x}", code);
+        }
+    }
+
+    #[test]
+    fn verify_truncated_digests() {
+        let data = b"hello world";
+        let full = Code::Sha2_256.digest(data);
+        let wrap = |code, digest: &[u8]| Cid::new_v1(RAW, Multihash::wrap(code, digest).unwrap());
+
+        let cid = wrap(full.code(), &full.digest()[..20]);
+        verify_block(&cid, data).unwrap();
+        let cid = wrap(full.code(), &full.digest()[..19]);
+        assert!(matches!(verify_block(&cid, data), Err(Error::DigestTooShort(c)) if c == cid));
+        let cid = wrap(full.code(), &[]);
+        assert!(matches!(verify_block(&cid, data), Err(Error::DigestTooShort(c)) if c == cid));
+
+        // shake digests of any size are prefixes of each other
+        let shake = digest(SHAKE_256, data, 100).unwrap();
+        verify_block(&wrap(SHAKE_256, &shake), data).unwrap();
+        verify_block(&wrap(SHAKE_256, &shake[..32]), data).unwrap();
+        assert!(verify_block(&wrap(SHAKE_256, &shake[..8]), data).is_err());
+
+        // hash functions with short outputs are not truncated
+        verify_block(
+            &wrap(BLAKE2B_MIN, &digest(BLAKE2B_MIN, data, 0).unwrap()),
+            data,
+        )
+        .unwrap();
+
+        // identity cids must hold all of the data
+        verify_block(&wrap(IDENTITY, data), data).unwrap();
+        let cid = wrap(IDENTITY, b"hello");
+        assert!(matches!(verify_block(&cid, data), Err(Error::HashMismatch(c)) if c == cid));
+        let cid = wrap(IDENTITY, &[]);
+        assert!(matches!(verify_block(&cid, data), Err(Error::HashMismatch(c)) if c == cid));
+    }
+
+    #[test]
+    fn verify_unsupported_hash() {
+        let cid = Cid::new_v1(RAW, Multihash::wrap(0xd5, &[0u8; 16]).unwrap());
+        assert!(matches!(
+            verify_block(&cid, b"data"),
+            Err(Error::UnsupportedHash(0xd5))
+        ));
+    }
+
+    fn cbor_block(ipld: &Ipld) -> (Cid, Vec<u8>) {
+        let data = DagCborCodec.encode(ipld).unwrap();
+        let cid = Cid::new_v1(DagCborCodec.into(), Code::Sha2_256.digest(&data));
+        (cid, data)
+    }
+
+    #[test]
+    fn dag_report() {
+        let leaf = Cid::new_v1(RAW, Code::Sha2_256.digest(b"leaf"));
+        let absent = Cid::new_v1(RAW, Code::Sha2_256.digest(b"absent"));
+        let inline = Cid::new_v1(RAW, Multihash::wrap(IDENTITY, b"inline").unwrap());
+        let (root, root_data) = cbor_block(&Ipld::List(vec![
+            Ipld::Link(leaf),
+            Ipld::Link(absent),
+            Ipld::Link(inline),
+        ]));
+        let stray = Cid::new_v1(RAW, Code::Sha2_256.digest(b"stray"));
+
+        let mut checker = DagChecker::default();
+        checker.add(root, &root_data);
+        checker.add(leaf, b"leaf");
+        checker.add(stray, b"stray");
+        let report = checker.report(&[root]);
+
+        assert_eq!(report.blocks, 3);
+        assert_eq!(report.missing, vec![absent]);
+        assert_eq!(report.extra, vec![stray]);
+        assert!(report.undecodable.is_empty());
+        assert!(!report.is_complete());
+    }
+}
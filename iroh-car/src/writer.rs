@@ -1,8 +1,16 @@
+use std::io::SeekFrom;
+
+use cid::multihash::Multihash;
 use cid::Cid;
-use integer_encoding::VarIntAsyncWriter;
-use tokio::io::{AsyncWrite, AsyncWriteExt};
+use integer_encoding::{VarInt, VarIntAsyncWriter};
+use tokio::io::{AsyncSeek, AsyncSeekExt, AsyncWrite, AsyncWriteExt};
 
-use crate::{error::Error, header::CarHeader};
+use crate::{
+    error::Error,
+    header::{CarHeader, CarHeaderV2, HEADER_V2_SIZE, PRAGMA_V2},
+    index::{Index, IndexCodec},
+    util::write_node,
+};
 
 #[derive(Debug)]
 pub struct CarWriter<W> {
@@ -16,6 +24,10 @@ impl<W> CarWriter<W>
 where
     W: AsyncWrite + Send + Unpin,
 {
+    /// Creates a writer for a CARv1 file.
+    ///
+    /// Writing fails for a [`CarHeader::V2`] header, CARv2 files are written with
+    /// [`CarWriterV2`].
     pub fn new(header: CarHeader, writer: W) -> Self {
         CarWriter {
             header,
@@ -39,15 +51,7 @@ where
         }
 
         // Write the given block.
-        self.cid_buffer.clear();
-        cid.write_bytes(&mut self.cid_buffer).expect("vec write");
-
-        let data = data.as_ref();
-        let len = self.cid_buffer.len() + data.len();
-
-        self.writer.write_varint_async(len).await?;
-        self.writer.write_all(&self.cid_buffer).await?;
-        self.writer.write_all(data).await?;
+        write_node(&mut self.writer, &cid, data.as_ref(), &mut self.cid_buffer).await?;
 
         Ok(())
     }
@@ -69,3 +73,178 @@ where
         self.writer
     }
 }
+
+/// Writes CARv2 files, with an optional index of the written blocks.
+///
+/// The header of a CARv2 file records the size of the payload, so it is written last, which
+/// requires a seekable writer.
+#[derive(Debug)]
+pub struct CarWriterV2<W> {
+    header: CarHeaderV2,
+    writer: W,
+    cid_buffer: Vec<u8>,
+    index: Option<IndexCodec>,
+    /// Multihashes and payload offsets of the written blocks.
+    offsets: Vec<(Multihash, u64)>,
+    is_header_written: bool,
+}
+
+impl<W> CarWriterV2<W>
+where
+    W: AsyncWrite + AsyncSeek + Send + Unpin,
+{
+    /// Creates a writer for a CARv2 file with the given roots, indexed in the `index` format.
+    ///
+    /// The file is written from the current position of `writer`.
+    pub fn new(roots: Vec<Cid>, index: Option<IndexCodec>, writer: W) -> Self {
+        let data_offset = (PRAGMA_V2.len() + HEADER_V2_SIZE) as u64;
+        CarWriterV2 {
+            header: CarHeaderV2 {
+                data_offset,
+                roots,
+                ..Default::default()
+            },
+            writer,
+            cid_buffer: Vec::new(),
+            index,
+            offsets: Vec::new(),
+            is_header_written: false,
+        }
+    }
+
+    async fn write_header(&mut self) -> Result<(), Error> {
+        // the fixed header is rewritten with the final sizes by `finish`
+        self.writer.write_all(&PRAGMA_V2).await?;
+        self.writer.write_all(&[0u8; HEADER_V2_SIZE]).await?;
+
+        let header_bytes = CarHeader::new_v1(self.header.roots.clone()).encode()?;
+        self.writer.write_varint_async(header_bytes.len()).await?;
+        self.writer.write_all(&header_bytes).await?;
+        self.header.data_size = (header_bytes.len().required_space() + header_bytes.len()) as u64;
+        self.is_header_written = true;
+        Ok(())
+    }
+
+    /// Writes a block to the CARv1 payload.
+    pub async fn write<T>(&mut self, cid: Cid, data: T) -> Result<(), Error>
+    where
+        T: AsRef<[u8]>,
+    {
+        if !self.is_header_written {
+            self.write_header().await?;
+        }
+
+        let offset = self.header.data_size;
+        let written =
+            write_node(&mut self.writer, &cid, data.as_ref(), &mut self.cid_buffer).await?;
+        if self.index.is_some() {
+            self.offsets.push((*cid.hash(), offset));
+        }
+        self.header.data_size += written;
+
+        Ok(())
+    }
+
+    /// Writes the index and the final header, flushes and returns the writer and the header.
+    pub async fn finish(mut self) -> Result<(W, CarHeaderV2), Error> {
+        if !self.is_header_written {
+            self.write_header().await?;
+        }
+
+        let start = self
+            .writer
+            .seek(SeekFrom::Current(0))
+            .await?
+            .checked_sub(self.header.data_offset + self.header.data_size)
+            .ok_or_else(|| Error::InvalidFile("writer was moved while writing".to_string()))?;
+        if let Some(codec) = self.index {
+            self.header.index_offset = self.header.data_offset + self.header.data_size;
+            let index = Index::new(codec, std::mem::take(&mut self.offsets));
+            self.writer.write_all(&index.encode()).await?;
+        }
+        let end = self.writer.seek(SeekFrom::Current(0)).await?;
+
+        self.writer
+            .seek(SeekFrom::Start(start + PRAGMA_V2.len() as u64))
+            .await?;
+        self.writer.write_all(&self.header.encode_fixed()).await?;
+        self.writer.seek(SeekFrom::Start(end)).await?;
+        self.writer.flush().await?;
+
+        Ok((self.writer, self.header))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use futures::TryStreamExt;
+    use ipld_cbor::DagCborCodec;
+    use multihash::MultihashDigest;
+
+    use super::*;
+    use crate::reader::CarReader;
+
+    fn blocks() -> Vec<(Cid, Vec<u8>)> {
+        (0..10u8)
+            .map(|i| {
+                let data = vec![i; 100 * i as usize];
+                let cid = Cid::new_v1(DagCborCodec.into(), multihash::Code::Sha2_256.digest(&data));
+                (cid, data)
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn car_writer_rejects_v2_header() {
+        let blocks = blocks();
+        let header = CarHeader::V2(CarHeaderV2 {
+            roots: vec![blocks[0].0],
+            ..Default::default()
+        });
+        let mut buffer = Vec::new();
+        let mut writer = CarWriter::new(header, &mut buffer);
+        assert!(writer.write(blocks[0].0, &blocks[0].1).await.is_err());
+        assert!(buffer.is_empty());
+    }
+
+    #[tokio::test]
+    async fn car_v2_write_read() {
+        let blocks = blocks();
+        let roots = vec![blocks[0].0];
+        for codec in [
+            None,
+            Some(IndexCodec::Sorted),
+            Some(IndexCodec::MultihashSorted),
+        ] {
+            let mut writer = CarWriterV2::new(roots.clone(), codec, Cursor::new(Vec::new()));
+            for (cid, data) in &blocks {
+                writer.write(*cid, data).await.unwrap();
+            }
+            let (cursor, header) = writer.finish().await.unwrap();
+            assert_eq!(header.data_offset, 51);
+            assert_eq!(header.index_offset != 0, codec.is_some());
+
+            let mut reader = CarReader::new(Cursor::new(cursor.into_inner()))
+                .await
+                .unwrap();
+            assert_eq!(reader.header(), &CarHeader::V2(header.clone()));
+            assert_eq!(reader.header().roots(), &roots[..]);
+
+            let index = reader.read_index().await.unwrap();
+            assert_eq!(index.as_ref().map(Index::codec), codec);
+            if let Some(index) = &index {
+                assert_eq!(index.len(), blocks.len());
+                for (cid, data) in blocks.iter().rev() {
+                    let block = reader.get_block(index, cid).await.unwrap().unwrap();
+                    assert_eq!(&block, data);
+                }
+            }
+
+            // reading the index and blocks does not move the stream of blocks
+            let read: Vec<_> = reader.stream().try_collect().await.unwrap();
+            assert_eq!(read, blocks);
+        }
+    }
+}
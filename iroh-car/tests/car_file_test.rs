@@ -72,3 +72,108 @@ async fn roundtrip_carv1_basic_fixtures_file() {
     let file = fs::read("tests/carv1_basic.car").await.unwrap();
     assert_eq!(file, buffer);
 }
+
+#[tokio::test]
+async fn wrap_carv1_test_file_in_carv2() {
+    let file = File::open("tests/testv1.car").await.unwrap();
+    let car_reader = CarReader::new(BufReader::new(file)).await.unwrap();
+    let roots = car_reader.header().roots().to_vec();
+    let files: Vec<_> = car_reader.stream().try_collect().await.unwrap();
+
+    let mut writer = CarWriterV2::new(
+        roots.clone(),
+        Some(IndexCodec::MultihashSorted),
+        std::io::Cursor::new(Vec::new()),
+    );
+    for (cid, data) in &files {
+        writer.write(*cid, data).await.unwrap();
+    }
+    let (cursor, header) = writer.finish().await.unwrap();
+    let buffer = cursor.into_inner();
+
+    // the payload is the original CARv1 file
+    let payload = &buffer[header.data_offset as usize..][..header.data_size as usize];
+    let file = fs::read("tests/testv1.car").await.unwrap();
+    assert_eq!(payload, &file[..]);
+
+    let mut car_reader = CarReader::new(std::io::Cursor::new(buffer)).await.unwrap();
+    assert_eq!(car_reader.header().version(), 2);
+    assert_eq!(car_reader.header().roots(), &roots[..]);
+    let index = car_reader.read_index().await.unwrap().unwrap();
+    assert_eq!(index.len(), files.len());
+    let (cid, data) = &files[files.len() / 2];
+    assert_eq!(
+        car_reader.get_block(&index, cid).await.unwrap().as_ref(),
+        Some(data)
+    );
+
+    let read: Vec<_> = car_reader.stream().try_collect().await.unwrap();
+    assert_eq!(read, files);
+}
+
+/// `carv2_basic.car` is `carv1_basic.car` wrapped in a CARv2 file with a
+/// `MultihashIndexSorted` index, laid out as specified by the CARv2 spec and go-car.
+#[tokio::test]
+async fn read_carv2_basic_fixtures_file() {
+    let carv1 = fs::read("tests/carv1_basic.car").await.unwrap();
+    let carv1_reader = CarReader::new(std::io::Cursor::new(&carv1)).await.unwrap();
+    let roots = carv1_reader.header().roots().to_vec();
+    let files: Vec<_> = carv1_reader.stream().try_collect().await.unwrap();
+
+    let file = File::open("tests/carv2_basic.car").await.unwrap();
+    let mut car_reader = CarReader::new(BufReader::new(file)).await.unwrap();
+    let header = match car_reader.header() {
+        CarHeader::V2(header) => header.clone(),
+        header => panic!("unexpected header {:?}", header),
+    };
+    assert_eq!(header.data_offset, 51);
+    assert_eq!(header.data_size, carv1.len() as u64);
+    assert_eq!(header.index_offset, 51 + carv1.len() as u64);
+    assert_eq!(header.roots, roots);
+
+    let index = car_reader.read_index().await.unwrap().unwrap();
+    assert_eq!(index.codec(), IndexCodec::MultihashSorted);
+    assert_eq!(index.len(), files.len());
+    for (cid, data) in &files {
+        assert_eq!(
+            car_reader.get_block(&index, cid).await.unwrap().as_ref(),
+            Some(data)
+        );
+    }
+
+    let read: Vec<_> = car_reader.stream().try_collect().await.unwrap();
+    assert_eq!(read, files);
+}
+
+#[tokio::test]
+async fn write_carv2_basic_fixtures_file() {
+    let file = File::open("tests/carv1_basic.car").await.unwrap();
+    let car_reader = CarReader::new(BufReader::new(file)).await.unwrap();
+    let roots = car_reader.header().roots().to_vec();
+    let files: Vec<_> = car_reader.stream().try_collect().await.unwrap();
+
+    let mut writer = CarWriterV2::new(
+        roots,
+        Some(IndexCodec::MultihashSorted),
+        std::io::Cursor::new(Vec::new()),
+    );
+    for (cid, data) in &files {
+        writer.write(*cid, data).await.unwrap();
+    }
+    let (cursor, _) = writer.finish().await.unwrap();
+
+    let file = fs::read("tests/carv2_basic.car").await.unwrap();
+    assert_eq!(cursor.into_inner(), file);
+}
+
+#[tokio::test]
+async fn verify_test_file_hashes() {
+    for path in ["tests/testv1.car", "tests/carv1_basic.car"] {
+        let file = File::open(path).await.unwrap();
+        let car_reader = CarReader::new_verifying(BufReader::new(file))
+            .await
+            .unwrap();
+        let files: Vec<_> = car_reader.stream().try_collect().await.unwrap();
+        assert!(!files.is_empty());
+    }
+}
@@ -0,0 +1,549 @@
+use std::{
+    fmt::Write as _,
+    future::Future,
+    io::Write as _,
+    net::SocketAddr,
+    path::PathBuf,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context as _, Result};
+use axum::{
+    body::{self, HttpBody},
+    extract::ConnectInfo,
+    http::{
+        header::{REFERER, USER_AGENT},
+        HeaderMap, HeaderName, Method, Request, StatusCode, Version,
+    },
+    response::Response,
+};
+use bytes::Bytes;
+use config::{ConfigError, Map, Source, Value};
+use iroh_resolver::resolver::{ContentLoader, Source as ContentSource};
+use iroh_util::insert_into_config_map;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use time::OffsetDateTime;
+use tracing_appender::{
+    non_blocking::{NonBlocking, NonBlockingBuilder, WorkerGuard},
+    rolling::{RollingFileAppender, Rotation},
+};
+
+use crate::{
+    constants::{HEADER_X_IPFS_ROOTS, HEADER_X_TRACE_ID},
+    core::State,
+};
+
+/// Access log configuration.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AccessLogConfig {
+    /// directory the log files are written to
+    pub directory: PathBuf,
+    /// name of the log files, suffixed with the date and time once rotated
+    #[serde(default = "default_file_name")]
+    pub file_name: String,
+    #[serde(default)]
+    pub format: AccessLogFormat,
+    #[serde(default)]
+    pub rotation: AccessLogRotation,
+}
+
+fn default_file_name() -> String {
+    "access.log".to_string()
+}
+
+impl Source for AccessLogConfig {
+    fn clone_into_box(&self) -> Box<dyn Source + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn collect(&self) -> Result<Map<String, Value>, ConfigError> {
+        let mut map: Map<String, Value> = Map::new();
+        insert_into_config_map(
+            &mut map,
+            "directory",
+            self.directory.to_string_lossy().to_string(),
+        );
+        insert_into_config_map(&mut map, "file_name", self.file_name.clone());
+        insert_into_config_map(&mut map, "format", self.format.as_str());
+        insert_into_config_map(&mut map, "rotation", self.rotation.as_str());
+        Ok(map)
+    }
+}
+
+/// Format of the access log lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccessLogFormat {
+    /// One JSON object per line.
+    Json,
+    /// The NCSA combined log format, followed by the duration in milliseconds,
+    /// the resolved cid, the content source and the trace id.
+    Combined,
+}
+
+impl Default for AccessLogFormat {
+    fn default() -> Self {
+        AccessLogFormat::Json
+    }
+}
+
+impl AccessLogFormat {
+    fn as_str(&self) -> &'static str {
+        match self {
+            AccessLogFormat::Json => "json",
+            AccessLogFormat::Combined => "combined",
+        }
+    }
+}
+
+/// How often a new log file is started.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccessLogRotation {
+    Hourly,
+    Daily,
+    Never,
+}
+
+impl Default for AccessLogRotation {
+    fn default() -> Self {
+        AccessLogRotation::Daily
+    }
+}
+
+impl AccessLogRotation {
+    fn as_str(&self) -> &'static str {
+        match self {
+            AccessLogRotation::Hourly => "hourly",
+            AccessLogRotation::Daily => "daily",
+            AccessLogRotation::Never => "never",
+        }
+    }
+}
+
+/// Lines queued for the access log file before lines are dropped.
+const ACCESS_LOG_BUFFERED_LINES: usize = 256 * 1024;
+
+/// Dropped lines are reported once, then every this many lines.
+const DROPPED_LINES_REPORT: usize = 1000;
+
+/// Writer of the access log, lines are written to the file on a background thread.
+#[derive(Clone)]
+pub struct AccessLog {
+    format: AccessLogFormat,
+    writer: NonBlocking,
+    /// Dropped lines at the last warning.
+    dropped: Arc<AtomicUsize>,
+    _guard: Arc<WorkerGuard>,
+}
+
+impl std::fmt::Debug for AccessLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AccessLog")
+            .field("format", &self.format)
+            .finish()
+    }
+}
+
+impl AccessLog {
+    pub fn new(config: &AccessLogConfig) -> Result<Self> {
+        std::fs::create_dir_all(&config.directory)
+            .with_context(|| format!("creating {}", config.directory.display()))?;
+        let rotation = match config.rotation {
+            AccessLogRotation::Hourly => Rotation::HOURLY,
+            AccessLogRotation::Daily => Rotation::DAILY,
+            AccessLogRotation::Never => Rotation::NEVER,
+        };
+        let appender = RollingFileAppender::new(rotation, &config.directory, &config.file_name);
+        // requests must not wait on the disk, lines are dropped when the writer falls
+        // behind by more than the buffer and the drops are reported
+        let (writer, guard) = NonBlockingBuilder::default()
+            .buffered_lines_limit(ACCESS_LOG_BUFFERED_LINES)
+            .lossy(true)
+            .finish(appender);
+        Ok(AccessLog {
+            format: config.format,
+            writer,
+            dropped: Arc::new(AtomicUsize::new(0)),
+            _guard: Arc::new(guard),
+        })
+    }
+
+    fn write(&self, entry: &AccessLogEntry) {
+        let mut line = match self.format {
+            AccessLogFormat::Json => entry.to_json(),
+            AccessLogFormat::Combined => entry.to_combined(),
+        };
+        line.push('\n');
+        if let Err(err) = self.writer.clone().write_all(line.as_bytes()) {
+            tracing::warn!("failed to write access log: {:?}", err);
+        }
+
+        let dropped = self.writer.error_counter().dropped_lines();
+        let reported = self.dropped.load(Ordering::Relaxed);
+        if dropped > reported
+            && (reported == 0 || dropped / DROPPED_LINES_REPORT > reported / DROPPED_LINES_REPORT)
+            && self
+                .dropped
+                .compare_exchange(reported, dropped, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        {
+            tracing::warn!("access log writer is behind, {} lines dropped", dropped);
+        }
+    }
+}
+
+/// A single request, as written to the access log.
+#[derive(Debug, Clone)]
+struct AccessLogEntry {
+    time: OffsetDateTime,
+    client: Option<SocketAddr>,
+    method: Method,
+    uri: String,
+    version: Version,
+    status: StatusCode,
+    bytes: u64,
+    duration: Duration,
+    cid: Option<String>,
+    source: Option<&'static str>,
+    referer: Option<String>,
+    user_agent: Option<String>,
+    trace_id: Option<String>,
+}
+
+impl AccessLogEntry {
+    fn to_json(&self) -> String {
+        json!({
+            "time": format_rfc3339(self.time),
+            "client": self.client.map(|addr| addr.ip().to_string()),
+            "method": self.method.as_str(),
+            "path": self.uri,
+            "status": self.status.as_u16(),
+            "bytes": self.bytes,
+            "duration_ms": self.duration.as_micros() as f64 / 1000.0,
+            "cid": self.cid,
+            "source": self.source,
+            "referer": self.referer,
+            "user_agent": self.user_agent,
+            "trace_id": self.trace_id,
+        })
+        .to_string()
+    }
+
+    fn to_combined(&self) -> String {
+        let dash = |value: &Option<String>| value.clone().unwrap_or_else(|| "-".to_string());
+        let mut line = String::new();
+        write!(
+            line,
+            "{} - - [{}] \"{} {} {:?}\" {} {} \"{}\" \"{}\" {} {} {} {}",
+            self.client
+                .map(|addr| addr.ip().to_string())
+                .unwrap_or_else(|| "-".to_string()),
+            format_clf(self.time),
+            self.method,
+            escape(&self.uri),
+            self.version,
+            self.status.as_u16(),
+            self.bytes,
+            escape(&dash(&self.referer)),
+            escape(&dash(&self.user_agent)),
+            self.duration.as_millis(),
+            dash(&self.cid),
+            self.source.unwrap_or("-"),
+            dash(&self.trace_id),
+        )
+        .unwrap();
+        line
+    }
+}
+
+fn format_rfc3339(time: OffsetDateTime) -> String {
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        time.year(),
+        time.month() as u8,
+        time.day(),
+        time.hour(),
+        time.minute(),
+        time.second(),
+        time.millisecond(),
+    )
+}
+
+fn format_clf(time: OffsetDateTime) -> String {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    format!(
+        "{:02}/{}/{:04}:{:02}:{:02}:{:02} +0000",
+        time.day(),
+        MONTHS[time.month() as usize - 1],
+        time.year(),
+        time.hour(),
+        time.minute(),
+        time.second(),
+    )
+}
+
+/// Escapes quotes and control characters of a quoted combined log field.
+fn escape(value: &str) -> String {
+    value.escape_default().to_string()
+}
+
+/// Where the content of a request was loaded from, shared with the tasks spawned to stream
+/// the response.
+type SourceSlot = Arc<Mutex<Option<&'static str>>>;
+
+tokio::task_local! {
+    static CONTENT_SOURCE: SourceSlot;
+}
+
+/// Runs `fut` in the scope of the current request, so that a task spawned to stream the
+/// response can record where its content was loaded from.
+pub fn in_current_request<F: Future>(fut: F) -> impl Future<Output = F::Output> {
+    let slot = CONTENT_SOURCE.try_with(Arc::clone).ok();
+    async move {
+        match slot {
+            Some(slot) => CONTENT_SOURCE.scope(slot, fut).await,
+            None => fut.await,
+        }
+    }
+}
+
+/// Records where the content of the current request was loaded from.
+pub fn record_source(source: &ContentSource) {
+    set_source(match source {
+        ContentSource::Bitswap => "bitswap",
+        ContentSource::Http(_) => "http",
+        ContentSource::Store(_) => "store",
+    });
+}
+
+/// Records that the current request was served from the response cache.
+pub fn record_cache_hit() {
+    set_source("cache");
+}
+
+fn set_source(source: &'static str) {
+    // requests are only tracked while the access log is enabled
+    let _ = CONTENT_SOURCE.try_with(|current| *current.lock().unwrap() = Some(source));
+}
+
+/// Writes an entry to the access log once the response body has been sent,
+/// or the client went away.
+pub async fn access_log_middleware<T: ContentLoader, B>(
+    request: Request<B>,
+    next: axum::middleware::Next<B>,
+) -> Response {
+    let access_log = request
+        .extensions()
+        .get::<Arc<State<T>>>()
+        .and_then(|state| state.access_log.clone());
+    let access_log = match access_log {
+        Some(access_log) => access_log,
+        None => return next.run(request).await,
+    };
+
+    let start = Instant::now();
+    let time = OffsetDateTime::now_utc();
+    let client = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| *addr);
+    let method = request.method().clone();
+    let uri = request
+        .uri()
+        .path_and_query()
+        .map(|pq| pq.as_str())
+        .unwrap_or("/")
+        .to_string();
+    let version = request.version();
+    let referer = header(request.headers(), &REFERER);
+    let user_agent = header(request.headers(), &USER_AGENT);
+
+    let source = SourceSlot::default();
+    let res = CONTENT_SOURCE
+        .scope(source.clone(), next.run(request))
+        .await;
+
+    let entry = AccessLogEntry {
+        time,
+        client,
+        method,
+        uri,
+        version,
+        status: res.status(),
+        bytes: 0,
+        duration: Duration::ZERO,
+        // the last root is the cid the path resolved to
+        cid: header(res.headers(), &HEADER_X_IPFS_ROOTS)
+            .and_then(|roots| roots.rsplit(',').next().map(ToString::to_string))
+            .filter(|cid| !cid.is_empty()),
+        source: None,
+        referer,
+        user_agent,
+        trace_id: header(res.headers(), &HEADER_X_TRACE_ID),
+    };
+    res.map(|body| {
+        body::boxed(LoggedBody {
+            body,
+            entry,
+            source,
+            start,
+            access_log,
+        })
+    })
+}
+
+fn header(headers: &HeaderMap, name: &HeaderName) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(ToString::to_string)
+}
+
+/// Body counting the bytes sent, which writes the access log entry when dropped.
+struct LoggedBody<B> {
+    body: B,
+    entry: AccessLogEntry,
+    /// Set while the body is streamed, by tasks loading the content.
+    source: SourceSlot,
+    start: Instant,
+    access_log: AccessLog,
+}
+
+impl<B> Drop for LoggedBody<B> {
+    fn drop(&mut self) {
+        self.entry.duration = self.start.elapsed();
+        self.entry.source = *self.source.lock().unwrap();
+        self.access_log.write(&self.entry);
+    }
+}
+
+impl<B: HttpBody<Data = Bytes> + Unpin> HttpBody for LoggedBody<B> {
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let res = Pin::new(&mut self.body).poll_data(cx);
+        if let Poll::Ready(Some(Ok(data))) = &res {
+            self.entry.bytes += data.len() as u64;
+        }
+        res
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        Pin::new(&mut self.body).poll_trailers(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.body.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry() -> AccessLogEntry {
+        AccessLogEntry {
+            time: OffsetDateTime::from_unix_timestamp(1664978136).unwrap()
+                + Duration::from_millis(120),
+            client: Some("10.0.0.1:54321".parse().unwrap()),
+            method: Method::GET,
+            uri: "/ipfs/bafy/file.txt?format=raw".to_string(),
+            version: Version::HTTP_11,
+            status: StatusCode::OK,
+            bytes: 2326,
+            duration: Duration::from_millis(42),
+            cid: Some("bafkqaaa".to_string()),
+            source: Some("bitswap"),
+            referer: None,
+            user_agent: Some("curl/7.85.0 \"test\"".to_string()),
+            trace_id: None,
+        }
+    }
+
+    #[test]
+    fn formats_json_lines() {
+        let line: serde_json::Value = serde_json::from_str(&entry().to_json()).unwrap();
+        assert_eq!(
+            line,
+            json!({
+                "time": "2022-10-05T13:55:36.120Z",
+                "client": "10.0.0.1",
+                "method": "GET",
+                "path": "/ipfs/bafy/file.txt?format=raw",
+                "status": 200,
+                "bytes": 2326,
+                "duration_ms": 42.0,
+                "cid": "bafkqaaa",
+                "source": "bitswap",
+                "referer": null,
+                "user_agent": "curl/7.85.0 \"test\"",
+                "trace_id": null,
+            })
+        );
+    }
+
+    #[test]
+    fn formats_combined_log() {
+        assert_eq!(
+            entry().to_combined(),
+            "10.0.0.1 - - [05/Oct/2022:13:55:36 +0000] \
+             \"GET /ipfs/bafy/file.txt?format=raw HTTP/1.1\" 200 2326 \
+             \"-\" \"curl/7.85.0 \\\"test\\\"\" 42 bafkqaaa bitswap -"
+        );
+    }
+
+    #[tokio::test]
+    async fn records_source_in_scope() {
+        record_cache_hit();
+        let slot = SourceSlot::default();
+        CONTENT_SOURCE
+            .scope(slot.clone(), async {
+                record_source(&ContentSource::Store("rpc"));
+            })
+            .await;
+        assert_eq!(*slot.lock().unwrap(), Some("store"));
+    }
+
+    #[tokio::test]
+    async fn records_source_of_spawned_tasks() {
+        let slot = SourceSlot::default();
+        CONTENT_SOURCE
+            .scope(slot.clone(), async {
+                tokio::spawn(in_current_request(async {
+                    record_source(&ContentSource::Bitswap);
+                }))
+                .await
+                .unwrap();
+            })
+            .await;
+        assert_eq!(*slot.lock().unwrap(), Some("bitswap"));
+
+        // outside of a request nothing is recorded
+        tokio::spawn(in_current_request(async {
+            record_source(&ContentSource::Bitswap);
+        }))
+        .await
+        .unwrap();
+    }
+}
@@ -0,0 +1,929 @@
+//! Subset of the kubo HTTP RPC API, served at `/api/v0`.
+//!
+//! Errors are reported in the kubo format, as `{"Message": .., "Code": .., "Type": "error"}`.
+
+use std::{
+    collections::HashMap,
+    env::consts::{ARCH, OS},
+    fmt::Display,
+    str::FromStr,
+    sync::Arc,
+    time,
+};
+
+use anyhow::{anyhow, Context as _};
+use axum::{
+    body::{self, Body},
+    extract::{multipart::Field, Extension, Multipart, RawQuery},
+    http::{header::*, StatusCode},
+    response::{IntoResponse, Response},
+    routing::post,
+    Json, Router,
+};
+use bytes::Bytes;
+use cid::{
+    multihash::{Code, MultihashDigest},
+    Cid,
+};
+use futures::{channel::mpsc, SinkExt, StreamExt};
+use git_version::git_version;
+use iroh_resolver::{
+    chunker::{DEFAULT_CHUNKS_SIZE, DEFAULT_CHUNK_SIZE_LIMIT},
+    resolver::{parse_links, Block, ContentLoader, Path},
+    unixfs_builder::{
+        add_blocks_to_store, AddEvent, Directory, DirectoryBuilder, File, FileBuilder, Store as _,
+        StoreAndProvideClient, Symlink,
+    },
+};
+use iroh_rpc_client::Client as RpcClient;
+use libipld::{prelude::Codec as _, Ipld, IpldCodec};
+use libp2p::{multiaddr::Protocol, Multiaddr, PeerId};
+use serde_json::{json, Value};
+use tokio_util::io::StreamReader;
+use tracing::warn;
+use url::form_urlencoded;
+
+use crate::{access_log, client::FileResult, constants::*, core::State, handlers::check_bad_bits};
+
+pub fn get_api_routes<T: ContentLoader + std::marker::Unpin>() -> Router {
+    Router::new()
+        .route("/api/v0/add", post(add::<T>))
+        .route("/api/v0/cat", post(cat::<T>))
+        .route("/api/v0/get", post(get::<T>))
+        .route("/api/v0/block/get", post(block_get::<T>))
+        .route("/api/v0/block/put", post(block_put::<T>))
+        .route("/api/v0/block/stat", post(block_stat::<T>))
+        .route("/api/v0/dag/get", post(dag_get::<T>))
+        .route("/api/v0/dag/put", post(dag_put::<T>))
+        .route("/api/v0/swarm/peers", post(swarm_peers::<T>))
+        .route("/api/v0/swarm/connect", post(swarm_connect::<T>))
+        .route("/api/v0/id", post(id::<T>))
+        .route("/api/v0/version", post(version))
+}
+
+#[derive(Debug)]
+pub struct ApiError {
+    status_code: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    fn bad_request(message: impl Display) -> Self {
+        ApiError {
+            status_code: StatusCode::BAD_REQUEST,
+            message: message.to_string(),
+        }
+    }
+
+    fn internal(message: impl Display) -> Self {
+        ApiError {
+            status_code: StatusCode::INTERNAL_SERVER_ERROR,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(err: anyhow::Error) -> Self {
+        ApiError::internal(err)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        // kubo uses code 1 for client errors and 0 for everything else
+        let code = u8::from(self.status_code.is_client_error());
+        let mut res = Json(json!({
+            "Message": self.message,
+            "Code": code,
+            "Type": "error",
+        }))
+        .into_response();
+        *res.status_mut() = self.status_code;
+        res
+    }
+}
+
+/// Query arguments of a request, kubo allows repeating arguments.
+#[derive(Debug, Default)]
+struct Args(Vec<(String, String)>);
+
+impl Args {
+    fn parse(query: Option<String>) -> Self {
+        match query {
+            Some(query) => Args(
+                form_urlencoded::parse(query.as_bytes())
+                    .into_owned()
+                    .collect(),
+            ),
+            None => Args::default(),
+        }
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn all(&self, name: &str) -> Vec<&str> {
+        self.0
+            .iter()
+            .filter(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+            .collect()
+    }
+
+    /// The required positional argument.
+    fn arg(&self) -> Result<&str, ApiError> {
+        self.get("arg")
+            .ok_or_else(|| ApiError::bad_request("argument \"arg\" is required"))
+    }
+
+    fn flag(&self, name: &str, default: bool) -> Result<bool, ApiError> {
+        match self.get(name) {
+            None => Ok(default),
+            Some("") => Ok(true),
+            Some(v) => v
+                .parse()
+                .map_err(|_| ApiError::bad_request(format!("invalid value for {}: {}", name, v))),
+        }
+    }
+
+    fn number(&self, name: &str) -> Result<Option<u64>, ApiError> {
+        self.get(name)
+            .map(|v| {
+                v.parse().map_err(|_| {
+                    ApiError::bad_request(format!("invalid value for {}: {}", name, v))
+                })
+            })
+            .transpose()
+    }
+}
+
+fn rpc_client<T: ContentLoader>(state: &State<T>) -> Result<&RpcClient, ApiError> {
+    state
+        .rpc_client
+        .as_ref()
+        .ok_or_else(|| ApiError::internal("rpc client is not available"))
+}
+
+async fn parse_path<T: ContentLoader>(state: &State<T>, arg: &str) -> Result<Path, ApiError> {
+    let path = Path::from_str(arg).map_err(ApiError::bad_request)?;
+    let content_path = match path.tail().is_empty() {
+        true => String::new(),
+        false => format!("/{}", path.tail().join("/")),
+    };
+    if check_bad_bits(state, &path.root().to_string(), &content_path).await {
+        return Err(ApiError {
+            status_code: StatusCode::GONE,
+            message: "CID is in the denylist".to_string(),
+        });
+    }
+    Ok(path)
+}
+
+fn parse_codec(name: &str) -> Result<IpldCodec, ApiError> {
+    match name {
+        "raw" => Ok(IpldCodec::Raw),
+        "dag-pb" => Ok(IpldCodec::DagPb),
+        "dag-cbor" => Ok(IpldCodec::DagCbor),
+        "dag-json" => Ok(IpldCodec::DagJson),
+        _ => Err(ApiError::bad_request(format!(
+            "unsupported codec: {}",
+            name
+        ))),
+    }
+}
+
+fn check_hash(args: &Args, name: &str) -> Result<(), ApiError> {
+    match args.get(name) {
+        None | Some("sha2-256") => Ok(()),
+        Some(hash) => Err(ApiError::bad_request(format!(
+            "unsupported hash function: {}",
+            hash
+        ))),
+    }
+}
+
+/// Checks the options of an add request against what the unixfs builder does, which always
+/// creates CIDv1 with raw leaves, and returns the chunk size to use.
+///
+/// Everything added is kept in the store, so unpinned adds are rejected as well.
+fn parse_add_options(args: &Args) -> Result<usize, ApiError> {
+    if !matches!(args.number("cid-version")?, None | Some(1)) {
+        return Err(ApiError::bad_request("only cid-version 1 is supported"));
+    }
+    if !args.flag("raw-leaves", true)? {
+        return Err(ApiError::bad_request("raw-leaves=false is not supported"));
+    }
+    if !args.flag("pin", true)? {
+        return Err(ApiError::bad_request("pin=false is not supported"));
+    }
+    match args.get("chunker") {
+        None | Some("") => Ok(DEFAULT_CHUNKS_SIZE),
+        Some(chunker) => chunker
+            .strip_prefix("size-")
+            .and_then(|size| size.parse().ok())
+            .filter(|size| (1..=DEFAULT_CHUNK_SIZE_LIMIT).contains(size))
+            .ok_or_else(|| ApiError::bad_request(format!("unsupported chunker: {}", chunker))),
+    }
+}
+
+fn parse_peer(arg: &str) -> Result<(PeerId, Option<Multiaddr>), ApiError> {
+    if let Ok(peer_id) = PeerId::from_str(arg) {
+        return Ok((peer_id, None));
+    }
+    let addr = Multiaddr::from_str(arg).map_err(ApiError::bad_request)?;
+    match addr.iter().find(|p| matches!(*p, Protocol::P2p(_))) {
+        Some(Protocol::P2p(peer_id)) => {
+            let peer_id = PeerId::from_multihash(peer_id).map_err(|_| {
+                ApiError::bad_request(format!("invalid peer id in multiaddr: {}", arg))
+            })?;
+            Ok((peer_id, Some(addr)))
+        }
+        _ => Err(ApiError::bad_request(format!(
+            "multiaddr must include the peer id: {}",
+            arg
+        ))),
+    }
+}
+
+/// Reads the first file of a multipart request body.
+async fn read_file(multipart: &mut Multipart) -> Result<Bytes, ApiError> {
+    let field = multipart
+        .next_field()
+        .await
+        .map_err(ApiError::bad_request)?
+        .ok_or_else(|| ApiError::bad_request("file argument is required"))?;
+    field.bytes().await.map_err(ApiError::bad_request)
+}
+
+fn stream_response<B>(body: B, content_type: &'static str) -> Response
+where
+    B: 'static + axum::body::HttpBody<Data = Bytes> + Send,
+    <B as axum::body::HttpBody>::Error: Into<axum::BoxError>,
+{
+    let mut res = Response::new(body::boxed(body));
+    let headers = res.headers_mut();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+    headers.insert(&HEADER_X_STREAM_OUTPUT, HeaderValue::from_static("1"));
+    res
+}
+
+/// A file or directory uploaded as part of a multipart request.
+#[derive(Debug, PartialEq)]
+enum UploadEntry {
+    /// A file, which is stored while it is received.
+    File {
+        name: String,
+        root: Block,
+        size: u64,
+    },
+    Directory {
+        name: String,
+        entries: Vec<UploadEntry>,
+    },
+    Symlink {
+        name: String,
+        target: String,
+    },
+}
+
+impl UploadEntry {
+    fn name(&self) -> &str {
+        match self {
+            UploadEntry::File { name, .. }
+            | UploadEntry::Directory { name, .. }
+            | UploadEntry::Symlink { name, .. } => name,
+        }
+    }
+
+    /// Inserts an entry at the given relative path, creating missing parent directories.
+    fn insert(
+        entries: &mut Vec<UploadEntry>,
+        path: &[&str],
+        entry: UploadEntry,
+    ) -> Result<(), ApiError> {
+        match path {
+            [] => Err(ApiError::bad_request("file name must not be empty")),
+            [_] => {
+                if let Some(existing) = entries.iter().find(|e| e.name() == entry.name()) {
+                    // directories may be announced before their contents
+                    if matches!(
+                        (existing, &entry),
+                        (UploadEntry::Directory { .. }, UploadEntry::Directory { .. })
+                    ) {
+                        return Ok(());
+                    }
+                    return Err(ApiError::bad_request(format!(
+                        "duplicate file name: {}",
+                        entry.name()
+                    )));
+                }
+                entries.push(entry);
+                Ok(())
+            }
+            [dir, rest @ ..] => {
+                let pos = match entries.iter().position(|e| e.name() == *dir) {
+                    Some(pos) => pos,
+                    None => {
+                        entries.push(UploadEntry::Directory {
+                            name: dir.to_string(),
+                            entries: Vec::new(),
+                        });
+                        entries.len() - 1
+                    }
+                };
+                match &mut entries[pos] {
+                    UploadEntry::Directory { entries, .. } => {
+                        UploadEntry::insert(entries, rest, entry)
+                    }
+                    _ => Err(ApiError::bad_request(format!("{} is not a directory", dir))),
+                }
+            }
+        }
+    }
+
+    fn size(&self) -> u64 {
+        match self {
+            UploadEntry::File { size, .. } => *size,
+            UploadEntry::Directory { entries, .. } => entries.iter().map(|e| e.size()).sum(),
+            UploadEntry::Symlink { .. } => 0,
+        }
+    }
+}
+
+/// Max size of the batches of blocks written to the store while adding a file.
+const ADD_BATCH_BYTES: usize = 16 * 1024 * 1024;
+/// Number of received chunks buffered for the unixfs builder while adding a file.
+const UPLOAD_CHANNEL_SIZE: usize = 16;
+
+/// Encodes a file and stores its blocks, returning the root block.
+async fn store_file(store: &StoreAndProvideClient, file: File) -> anyhow::Result<Block> {
+    let blocks = file.encode().await?;
+    tokio::pin!(blocks);
+    let mut batch = Vec::new();
+    let mut batch_bytes = 0;
+    let mut root = None;
+    while let Some(block) = blocks.next().await {
+        let block = block?;
+        if batch_bytes + block.data().len() > ADD_BATCH_BYTES {
+            store.put_many(std::mem::take(&mut batch)).await?;
+            batch_bytes = 0;
+        }
+        batch_bytes += block.data().len();
+        root = Some(block.clone());
+        batch.push(block);
+    }
+    store.put_many(batch).await?;
+    root.context("no blocks were added")
+}
+
+/// Streams the content of a multipart field into the unixfs builder, storing the file.
+///
+/// If `progress` is set, the bytes received so far are reported for the given name.
+async fn store_field(
+    store: &StoreAndProvideClient,
+    name: &str,
+    chunk_size: usize,
+    field: &mut Field<'_>,
+    sender: &mut hyper::body::Sender,
+    mut progress: Option<(&str, &mut u64)>,
+) -> anyhow::Result<(Block, u64)> {
+    let (mut tx, rx) = mpsc::channel::<std::io::Result<Bytes>>(UPLOAD_CHANNEL_SIZE);
+    let mut file = FileBuilder::new();
+    file.name(name)
+        .chunk_size(chunk_size)
+        .content_reader(StreamReader::new(rx));
+    let file = file.build().await?;
+
+    let forward = async move {
+        let mut size = 0;
+        while let Some(chunk) = field.chunk().await? {
+            size += chunk.len() as u64;
+            if let Some((name, received)) = progress.as_mut() {
+                **received += chunk.len() as u64;
+                send_json(sender, json!({ "Name": name, "Bytes": **received })).await?;
+            }
+            // the encoder only goes away if it failed, which is reported below
+            if tx.send(Ok(chunk)).await.is_err() {
+                break;
+            }
+        }
+        anyhow::Ok(size)
+    };
+    let (root, size) = futures::try_join!(store_file(store, file), forward)?;
+    Ok((root, size))
+}
+
+fn build_directory(name: &str, entries: Vec<UploadEntry>) -> anyhow::Result<Directory> {
+    let mut dir = DirectoryBuilder::new();
+    dir.name(name);
+    for entry in entries {
+        match entry {
+            UploadEntry::File { name, root, .. } => {
+                dir.add_encoded(name, root);
+            }
+            UploadEntry::Directory { name, entries } => {
+                dir.add_dir(build_directory(&name, entries)?)?;
+            }
+            UploadEntry::Symlink { name, target } => {
+                dir.add_symlink(Symlink::new(name, target));
+            }
+        }
+    }
+    dir.build()
+}
+
+/// Stores the nodes of an entry whose files are already stored, returning its root.
+async fn store_entry(store: &StoreAndProvideClient, entry: UploadEntry) -> anyhow::Result<Cid> {
+    match entry {
+        UploadEntry::File { root, .. } => Ok(*root.cid()),
+        UploadEntry::Directory { name, entries } => {
+            let blocks = build_directory(&name, entries)?.encode();
+            let events = add_blocks_to_store(Some(store.clone()), blocks).await;
+            tokio::pin!(events);
+            let mut root = None;
+            while let Some(event) = events.next().await {
+                let AddEvent::ProgressDelta { cid, .. } = event?;
+                root = Some(cid);
+            }
+            root.context("no blocks were added")
+        }
+        UploadEntry::Symlink { name, target } => {
+            let block = Symlink::new(name, target).encode()?;
+            let cid = *block.cid();
+            store.put_many(vec![block]).await?;
+            Ok(cid)
+        }
+    }
+}
+
+async fn send_json(sender: &mut hyper::body::Sender, value: Value) -> anyhow::Result<()> {
+    let mut line = serde_json::to_vec(&value)?;
+    line.push(b'\n');
+    sender.send_data(line.into()).await?;
+    Ok(())
+}
+
+/// Adds the files and directories of the upload, storing every file while it is received.
+///
+/// Reports progress and the root of each entry as newline delimited json.
+async fn add_upload(
+    client: RpcClient,
+    mut multipart: Multipart,
+    wrap: bool,
+    progress: bool,
+    chunk_size: usize,
+    sender: &mut hyper::body::Sender,
+) -> anyhow::Result<()> {
+    let store = StoreAndProvideClient {
+        client: client.clone(),
+    };
+    let mut entries = Vec::new();
+    // bytes received per top level entry
+    let mut received: HashMap<String, u64> = HashMap::new();
+    while let Some(mut field) = multipart.next_field().await? {
+        // kubo clients url encode the file names
+        let path = field
+            .file_name()
+            .or_else(|| field.name())
+            .map(|name| urlencoding::decode(name).map(|n| n.into_owned()))
+            .transpose()?
+            .unwrap_or_default();
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        let (top, name) = match (segments.first(), segments.last()) {
+            (Some(top), Some(name)) => (top.to_string(), name.to_string()),
+            _ => anyhow::bail!("file name must not be empty"),
+        };
+        let entry = match field.content_type() {
+            Some(CONTENT_TYPE_X_DIRECTORY) => UploadEntry::Directory {
+                name,
+                entries: Vec::new(),
+            },
+            Some(CONTENT_TYPE_SYMLINK) => UploadEntry::Symlink {
+                name,
+                target: field.text().await?,
+            },
+            _ => {
+                let received = received.entry(top.clone()).or_default();
+                let progress = progress.then(|| (top.as_str(), received));
+                let (root, size) =
+                    store_field(&store, &name, chunk_size, &mut field, sender, progress).await?;
+                UploadEntry::File { name, root, size }
+            }
+        };
+        UploadEntry::insert(&mut entries, &segments, entry).map_err(|e| anyhow!(e.message))?;
+    }
+    anyhow::ensure!(!entries.is_empty(), "file argument is required");
+
+    let entries = match wrap {
+        true => vec![UploadEntry::Directory {
+            name: String::new(),
+            entries,
+        }],
+        false => entries,
+    };
+    for entry in entries {
+        let name = entry.name().to_string();
+        let size = entry.size();
+        let root = store_entry(&store, entry).await?;
+        if let Ok(p2p) = client.try_p2p() {
+            if let Err(e) = p2p.start_providing(&root).await {
+                warn!("failed to provide {}: {:?}", root, e);
+            }
+        }
+        send_json(
+            sender,
+            json!({ "Name": name, "Hash": root.to_string(), "Size": size.to_string() }),
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+#[tracing::instrument(skip(state, multipart))]
+pub async fn add<T: ContentLoader + std::marker::Unpin>(
+    Extension(state): Extension<Arc<State<T>>>,
+    RawQuery(query): RawQuery,
+    multipart: Multipart,
+) -> Result<Response, ApiError> {
+    let args = Args::parse(query);
+    let wrap = args.flag("wrap-with-directory", false)?;
+    let progress = args.flag("progress", false)?;
+    if args.flag("only-hash", false)? {
+        return Err(ApiError::bad_request("only-hash is not supported"));
+    }
+    check_hash(&args, "hash")?;
+    let chunk_size = parse_add_options(&args)?;
+    let client = rpc_client(&state)?.clone();
+    client.try_store()?;
+
+    // the upload is read while the response is streamed, like kubo does
+    let (mut sender, body) = Body::channel();
+    tokio::spawn(async move {
+        if let Err(e) = add_upload(client, multipart, wrap, progress, chunk_size, &mut sender).await
+        {
+            warn!("failed to add: {:?}", e);
+            let error = json!({ "Message": e.to_string(), "Code": 0, "Type": "error" });
+            if send_json(&mut sender, error).await.is_err() {
+                sender.abort();
+            }
+        }
+    });
+
+    let mut res = stream_response(body, "application/json");
+    res.headers_mut()
+        .insert(&HEADER_X_CHUNKED_OUTPUT, HeaderValue::from_static("1"));
+    Ok(res)
+}
+
+#[tracing::instrument(skip(state))]
+pub async fn cat<T: ContentLoader + std::marker::Unpin>(
+    Extension(state): Extension<Arc<State<T>>>,
+    RawQuery(query): RawQuery,
+) -> Result<Response, ApiError> {
+    let args = Args::parse(query);
+    let path = parse_path(&state, args.arg()?).await?;
+    let offset = args.number("offset")?;
+    let length = args.number("length")?;
+    let range = match (offset, length) {
+        (None, None) => None,
+        (offset, length) => {
+            let start = offset.unwrap_or(0);
+            Some(start..length.map(|l| start.saturating_add(l)).unwrap_or(u64::MAX))
+        }
+    };
+
+    let (body, metadata) = state
+        .client
+        .get_file(path, time::Instant::now(), range)
+        .await
+        .map_err(ApiError::internal)?;
+    access_log::record_source(&metadata.source);
+    match body {
+        FileResult::File(body) | FileResult::Raw(body) => Ok(stream_response(body, "text/plain")),
+        FileResult::Directory(_) => Err(ApiError::internal("this dag node is a directory")),
+    }
+}
+
+#[tracing::instrument(skip(state))]
+pub async fn get<T: ContentLoader + std::marker::Unpin>(
+    Extension(state): Extension<Arc<State<T>>>,
+    RawQuery(query): RawQuery,
+) -> Result<Response, ApiError> {
+    let args = Args::parse(query);
+    let path = parse_path(&state, args.arg()?).await?;
+    if args.flag("compress", false)? {
+        return Err(ApiError::bad_request("compression is not supported"));
+    }
+    let body = state
+        .client
+        .clone()
+        .get_tar(path, time::Instant::now())
+        .await
+        .map_err(ApiError::internal)?;
+    Ok(stream_response(body, "application/x-tar"))
+}
+
+/// Loads the block the path resolves to.
+async fn load_block<T: ContentLoader>(
+    state: &State<T>,
+    path: Path,
+) -> Result<(Cid, Bytes), ApiError> {
+    let (_, blocks) = state.client.resolver.resolve_with_path_blocks(path).await?;
+    let block = blocks.last().ok_or_else(|| anyhow!("block not found"))?;
+    access_log::record_source(block.source());
+    Ok((*block.cid(), block.content().clone()))
+}
+
+#[tracing::instrument(skip(state))]
+pub async fn block_get<T: ContentLoader + std::marker::Unpin>(
+    Extension(state): Extension<Arc<State<T>>>,
+    RawQuery(query): RawQuery,
+) -> Result<Response, ApiError> {
+    let args = Args::parse(query);
+    let path = parse_path(&state, args.arg()?).await?;
+    let (_, data) = load_block(&state, path).await?;
+    Ok(([(CONTENT_TYPE, "application/octet-stream")], data).into_response())
+}
+
+#[tracing::instrument(skip(state))]
+pub async fn block_stat<T: ContentLoader + std::marker::Unpin>(
+    Extension(state): Extension<Arc<State<T>>>,
+    RawQuery(query): RawQuery,
+) -> Result<Response, ApiError> {
+    let args = Args::parse(query);
+    let path = parse_path(&state, args.arg()?).await?;
+    let (cid, data) = load_block(&state, path).await?;
+    Ok(Json(json!({ "Key": cid.to_string(), "Size": data.len() })).into_response())
+}
+
+/// Hashes and stores a block, returning its cid.
+async fn put_block(client: &RpcClient, codec: IpldCodec, data: Bytes) -> Result<Cid, ApiError> {
+    let cid = Cid::new_v1(codec.into(), Code::Sha2_256.digest(&data));
+    let links = parse_links(&cid, &data).map_err(ApiError::bad_request)?;
+    client.try_store()?.put(cid, data, links).await?;
+    Ok(cid)
+}
+
+#[tracing::instrument(skip(state, multipart))]
+pub async fn block_put<T: ContentLoader + std::marker::Unpin>(
+    Extension(state): Extension<Arc<State<T>>>,
+    RawQuery(query): RawQuery,
+    mut multipart: Multipart,
+) -> Result<Response, ApiError> {
+    let args = Args::parse(query);
+    let codec = parse_codec(args.get("cid-codec").unwrap_or("raw"))?;
+    check_hash(&args, "mhtype")?;
+    let client = rpc_client(&state)?;
+    let data = read_file(&mut multipart).await?;
+    let size = data.len();
+    let cid = put_block(client, codec, data).await?;
+    Ok(Json(json!({ "Key": cid.to_string(), "Size": size })).into_response())
+}
+
+#[tracing::instrument(skip(state))]
+pub async fn dag_get<T: ContentLoader + std::marker::Unpin>(
+    Extension(state): Extension<Arc<State<T>>>,
+    RawQuery(query): RawQuery,
+) -> Result<Response, ApiError> {
+    let args = Args::parse(query);
+    let path = parse_path(&state, args.arg()?).await?;
+    let output_codec = parse_codec(args.get("output-codec").unwrap_or("dag-json"))?;
+    let resolver = &state.client.resolver;
+    let out = resolver.resolve(path).await?;
+    access_log::record_source(&out.metadata().source);
+    let encoded = match out.ipld() {
+        Some(node) => output_codec.encode(node),
+        None => {
+            // unixfs content is returned as the dag-pb node it is stored in
+            let cid = *out
+                .metadata()
+                .resolved_path
+                .last()
+                .ok_or_else(|| anyhow!("path did not resolve to a cid"))?;
+            let block = resolver.load_raw(cid, out.context()).await?;
+            let codec = IpldCodec::try_from(cid.codec()).map_err(ApiError::internal)?;
+            let node: Ipld = codec.decode(block.content()).map_err(ApiError::internal)?;
+            output_codec.encode(&node)
+        }
+    }
+    .map_err(ApiError::internal)?;
+    let content_type = match output_codec {
+        IpldCodec::DagJson => "application/json",
+        _ => "application/octet-stream",
+    };
+    Ok(([(CONTENT_TYPE, content_type)], encoded).into_response())
+}
+
+#[tracing::instrument(skip(state, multipart))]
+pub async fn dag_put<T: ContentLoader + std::marker::Unpin>(
+    Extension(state): Extension<Arc<State<T>>>,
+    RawQuery(query): RawQuery,
+    mut multipart: Multipart,
+) -> Result<Response, ApiError> {
+    let args = Args::parse(query);
+    let store_codec = parse_codec(args.get("store-codec").unwrap_or("dag-cbor"))?;
+    let input_codec = parse_codec(args.get("input-codec").unwrap_or("dag-json"))?;
+    check_hash(&args, "hash")?;
+    let client = rpc_client(&state)?;
+    let data = read_file(&mut multipart).await?;
+    let data = match store_codec == input_codec {
+        true => data,
+        false => {
+            let node: Ipld = input_codec.decode(&data).map_err(ApiError::bad_request)?;
+            store_codec
+                .encode(&node)
+                .map_err(ApiError::bad_request)?
+                .into()
+        }
+    };
+    let cid = put_block(client, store_codec, data).await?;
+    Ok(Json(json!({ "Cid": { "/": cid.to_string() } })).into_response())
+}
+
+#[tracing::instrument(skip(state))]
+pub async fn swarm_peers<T: ContentLoader + std::marker::Unpin>(
+    Extension(state): Extension<Arc<State<T>>>,
+) -> Result<Response, ApiError> {
+    let p2p = rpc_client(&state)?.try_p2p()?;
+    let mut peers: Vec<_> = p2p.get_peers().await?.into_iter().collect();
+    peers.sort_by_key(|(peer_id, _)| peer_id.to_string());
+    let peers: Vec<Value> = peers
+        .into_iter()
+        .map(|(peer_id, addrs)| {
+            let addr = addrs.first().map(|a| a.to_string()).unwrap_or_default();
+            json!({ "Addr": addr, "Peer": peer_id.to_string() })
+        })
+        .collect();
+    Ok(Json(json!({ "Peers": peers })).into_response())
+}
+
+#[tracing::instrument(skip(state))]
+pub async fn swarm_connect<T: ContentLoader + std::marker::Unpin>(
+    Extension(state): Extension<Arc<State<T>>>,
+    RawQuery(query): RawQuery,
+) -> Result<Response, ApiError> {
+    let args = Args::parse(query);
+    let targets = args.all("arg");
+    if targets.is_empty() {
+        return Err(ApiError::bad_request("argument \"address\" is required"));
+    }
+    let p2p = rpc_client(&state)?.try_p2p()?;
+    let mut strings = Vec::new();
+    for target in targets {
+        let (peer_id, addr) = parse_peer(target)?;
+        p2p.connect(peer_id, addr.into_iter().collect())
+            .await
+            .map_err(|e| ApiError::internal(format!("connect {} failure: {}", peer_id, e)))?;
+        strings.push(format!("connect {} success", peer_id));
+    }
+    Ok(Json(json!({ "Strings": strings })).into_response())
+}
+
+#[tracing::instrument(skip(state))]
+pub async fn id<T: ContentLoader + std::marker::Unpin>(
+    Extension(state): Extension<Arc<State<T>>>,
+    RawQuery(query): RawQuery,
+) -> Result<Response, ApiError> {
+    let args = Args::parse(query);
+    let p2p = rpc_client(&state)?.try_p2p()?;
+    let (peer_id, addrs, agent_version, protocol_version, protocols) = match args.get("arg") {
+        Some(peer) => {
+            let (peer_id, addr) = parse_peer(peer)?;
+            let lookup = p2p.lookup(peer_id, addr).await?;
+            (
+                lookup.peer_id,
+                lookup.listen_addrs,
+                lookup.agent_version,
+                lookup.protocol_version,
+                lookup.protocols,
+            )
+        }
+        None => {
+            let (peer_id, mut addrs) = p2p.get_listening_addrs().await?;
+            addrs.extend(p2p.external_addresses().await?);
+            (
+                peer_id,
+                addrs,
+                format!("iroh-gateway/{}", env!("CARGO_PKG_VERSION")),
+                String::new(),
+                Vec::new(),
+            )
+        }
+    };
+    let addresses: Vec<String> = addrs
+        .into_iter()
+        .map(|addr| format!("{}/p2p/{}", addr, peer_id))
+        .collect();
+    Ok(Json(json!({
+        "ID": peer_id.to_string(),
+        "PublicKey": "",
+        "Addresses": addresses,
+        "AgentVersion": agent_version,
+        "ProtocolVersion": protocol_version,
+        "Protocols": protocols,
+    }))
+    .into_response())
+}
+
+#[tracing::instrument()]
+pub async fn version() -> Json<Value> {
+    Json(json!({
+        "Version": env!("CARGO_PKG_VERSION"),
+        "Commit": git_version!(),
+        "Repo": "",
+        "System": format!("{}/{}", ARCH, OS),
+        "Golang": "",
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(name: &str, content: &str) -> UploadEntry {
+        let content = Bytes::from(content.to_string());
+        let cid = Cid::new_v1(IpldCodec::Raw.into(), Code::Sha2_256.digest(&content));
+        UploadEntry::File {
+            name: name.to_string(),
+            size: content.len() as u64,
+            root: Block::new(cid, content, Vec::new()),
+        }
+    }
+
+    #[test]
+    fn upload_entries_tree() {
+        let mut entries = Vec::new();
+        let dir = UploadEntry::Directory {
+            name: "dir".to_string(),
+            entries: Vec::new(),
+        };
+        UploadEntry::insert(&mut entries, &["dir"], dir).unwrap();
+        UploadEntry::insert(&mut entries, &["dir", "a.txt"], file("a.txt", "a")).unwrap();
+        // parent directories are created if they were not announced
+        UploadEntry::insert(&mut entries, &["dir", "sub", "b.txt"], file("b.txt", "bb")).unwrap();
+        UploadEntry::insert(&mut entries, &["c.txt"], file("c.txt", "ccc")).unwrap();
+
+        assert_eq!(
+            entries,
+            vec![
+                UploadEntry::Directory {
+                    name: "dir".to_string(),
+                    entries: vec![
+                        file("a.txt", "a"),
+                        UploadEntry::Directory {
+                            name: "sub".to_string(),
+                            entries: vec![file("b.txt", "bb")],
+                        },
+                    ],
+                },
+                file("c.txt", "ccc"),
+            ]
+        );
+        assert_eq!(entries[0].size(), 3);
+
+        assert!(UploadEntry::insert(&mut entries, &["c.txt"], file("c.txt", "")).is_err());
+        assert!(UploadEntry::insert(&mut entries, &["c.txt", "d"], file("d", "")).is_err());
+        assert!(UploadEntry::insert(&mut entries, &[], file("", "")).is_err());
+    }
+
+    #[test]
+    fn args() {
+        let args = Args::parse(Some(
+            "arg=%2Fip4%2F127.0.0.1&arg=b&progress&quiet=false&offset=3".to_string(),
+        ));
+        assert_eq!(args.arg().unwrap(), "/ip4/127.0.0.1");
+        assert_eq!(args.all("arg"), vec!["/ip4/127.0.0.1", "b"]);
+        assert!(args.flag("progress", false).unwrap());
+        assert!(!args.flag("quiet", true).unwrap());
+        assert!(args.flag("pin", true).unwrap());
+        assert_eq!(args.number("offset").unwrap(), Some(3));
+        assert_eq!(args.number("length").unwrap(), None);
+        assert!(Args::parse(None).arg().is_err());
+    }
+
+    #[test]
+    fn add_options() {
+        let parse = |query: &str| parse_add_options(&Args::parse(Some(query.to_string())));
+        assert_eq!(
+            parse_add_options(&Args::default()).unwrap(),
+            DEFAULT_CHUNKS_SIZE
+        );
+        assert_eq!(
+            parse("cid-version=1&raw-leaves=true&pin=true").unwrap(),
+            DEFAULT_CHUNKS_SIZE
+        );
+        assert_eq!(parse("chunker=size-1024").unwrap(), 1024);
+
+        assert!(parse("cid-version=0").is_err());
+        assert!(parse("raw-leaves=false").is_err());
+        assert!(parse("pin=false").is_err());
+        assert!(parse("chunker=rabin").is_err());
+        assert!(parse("chunker=size-0").is_err());
+        assert!(parse("chunker=size-2097152").is_err());
+    }
+}
@@ -0,0 +1,499 @@
+use std::{
+    borrow::Cow,
+    fmt,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::http::{header::AUTHORIZATION, HeaderMap, StatusCode};
+use config::{ConfigError, Map, Source, Value};
+use hmac::{Hmac, Mac};
+use iroh_util::insert_into_config_map;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Query parameters of a signed url.
+pub const PARAM_AUTH_SCOPE: &str = "auth_scope";
+pub const PARAM_AUTH_EXPIRES: &str = "auth_expires";
+pub const PARAM_AUTH_SIGNATURE: &str = "auth_signature";
+
+/// Bearer token and the scopes it grants access to.
+#[derive(Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AccessToken {
+    pub token: String,
+    /// CIDs or path prefixes, eg. `bafy...`, `/ipfs/bafy.../docs` or `/` for everything
+    pub scopes: Vec<String>,
+}
+
+impl fmt::Debug for AccessToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AccessToken")
+            .field("token", &"<redacted>")
+            .field("scopes", &self.scopes)
+            .finish()
+    }
+}
+
+impl Source for AccessToken {
+    fn clone_into_box(&self) -> Box<dyn Source + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn collect(&self) -> Result<Map<String, Value>, ConfigError> {
+        let mut map: Map<String, Value> = Map::new();
+        insert_into_config_map(&mut map, "token", self.token.clone());
+        insert_into_config_map(&mut map, "scopes", self.scopes.clone());
+        Ok(map)
+    }
+}
+
+/// Access control configuration, disabled unless any option is set.
+#[derive(Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AuthConfig {
+    /// reject requests without valid credentials
+    pub required: bool,
+    /// only serve content that is stored locally to requests without credentials
+    pub anonymous_local_only: bool,
+    /// key used to verify HMAC signed urls, signed urls are rejected if unset
+    pub signing_key: Option<String>,
+    /// bearer tokens accepted in the `Authorization` header
+    pub tokens: Vec<AccessToken>,
+}
+
+impl fmt::Debug for AuthConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuthConfig")
+            .field("required", &self.required)
+            .field("anonymous_local_only", &self.anonymous_local_only)
+            .field(
+                "signing_key",
+                &self.signing_key.as_ref().map(|_| "<redacted>"),
+            )
+            .field("tokens", &self.tokens)
+            .finish()
+    }
+}
+
+impl Source for AuthConfig {
+    fn clone_into_box(&self) -> Box<dyn Source + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn collect(&self) -> Result<Map<String, Value>, ConfigError> {
+        let mut map: Map<String, Value> = Map::new();
+        insert_into_config_map(&mut map, "required", self.required);
+        insert_into_config_map(&mut map, "anonymous_local_only", self.anonymous_local_only);
+        if let Some(key) = &self.signing_key {
+            insert_into_config_map(&mut map, "signing_key", key.clone());
+        }
+        let tokens = self
+            .tokens
+            .iter()
+            .map(|token| token.collect().map(|map| Value::new(None, map)))
+            .collect::<Result<Vec<_>, _>>()?;
+        insert_into_config_map(&mut map, "tokens", tokens);
+        Ok(map)
+    }
+}
+
+/// Access granted to an authorized request, stored in its extensions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// Valid credentials covering the requested path.
+    Granted,
+    /// No credentials.
+    Anonymous,
+    /// No credentials, only locally stored content may be served.
+    LocalOnly,
+}
+
+/// Reason a request is not authorized.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthError {
+    /// No credentials were provided.
+    Missing,
+    /// The credentials are malformed, unknown or expired.
+    Invalid(&'static str),
+    /// The credentials are valid but don't cover the requested path.
+    OutOfScope,
+}
+
+impl AuthError {
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            AuthError::Missing | AuthError::Invalid(_) => StatusCode::UNAUTHORIZED,
+            AuthError::OutOfScope => StatusCode::FORBIDDEN,
+        }
+    }
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::Missing => write!(f, "missing credentials"),
+            AuthError::Invalid(reason) => write!(f, "invalid credentials: {}", reason),
+            AuthError::OutOfScope => write!(f, "credentials don't grant access to this path"),
+        }
+    }
+}
+
+impl AuthConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.required
+            || self.anonymous_local_only
+            || self.signing_key.is_some()
+            || !self.tokens.is_empty()
+    }
+
+    /// Authorizes a request for `path` from its bearer token or signed url parameters.
+    ///
+    /// Requests without credentials are only let through to the content routes, and only
+    /// if credentials are not `required`.
+    pub fn authorize(
+        &self,
+        path: &str,
+        query: Option<&str>,
+        headers: &HeaderMap,
+    ) -> Result<Access, AuthError> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        self.authorize_at(path, query, headers, now)
+    }
+
+    fn authorize_at(
+        &self,
+        path: &str,
+        query: Option<&str>,
+        headers: &HeaderMap,
+        now: u64,
+    ) -> Result<Access, AuthError> {
+        let path = urlencoding::decode(path).map_err(|_| AuthError::Invalid("invalid path"))?;
+
+        if let Some(token) = bearer_token(headers)? {
+            return self.authorize_token(token, &path);
+        }
+
+        if let Some(signed) = SignedUrl::from_query(query)? {
+            let key = self
+                .signing_key
+                .as_ref()
+                .ok_or(AuthError::Invalid("signed urls are not enabled"))?;
+            signed.verify(key, now)?;
+            return match scope_matches(&signed.scope, &path) {
+                true => Ok(Access::Granted),
+                false => Err(AuthError::OutOfScope),
+            };
+        }
+
+        let is_content = path.starts_with("/ipfs/") || path.starts_with("/ipns/");
+        if self.required || !is_content {
+            return Err(AuthError::Missing);
+        }
+        match self.anonymous_local_only {
+            true => Ok(Access::LocalOnly),
+            false => Ok(Access::Anonymous),
+        }
+    }
+
+    /// Authorizes a request to the admin endpoints at `path`.
+    ///
+    /// Admin endpoints always require a bearer token covering `path`, even if access control
+    /// is otherwise disabled. Signed urls are not accepted.
+    pub fn authorize_admin(&self, path: &str, headers: &HeaderMap) -> Result<Access, AuthError> {
+        let token = bearer_token(headers)?.ok_or(AuthError::Missing)?;
+        self.authorize_token(token, path)
+    }
+
+    fn authorize_token(&self, token: &str, path: &str) -> Result<Access, AuthError> {
+        let token = self
+            .tokens
+            .iter()
+            .find(|t| constant_time_eq(t.token.as_bytes(), token.as_bytes()))
+            .ok_or(AuthError::Invalid("unknown token"))?;
+        match token.scopes.iter().any(|s| scope_matches(s, path)) {
+            true => Ok(Access::Granted),
+            false => Err(AuthError::OutOfScope),
+        }
+    }
+}
+
+/// Returns the hex encoded signature granting access to `scope` until `expires`
+/// (unix seconds).
+pub fn sign(key: &str, scope: &str, expires: u64) -> String {
+    hex::encode(signature_mac(key, scope, expires).finalize().into_bytes())
+}
+
+/// Returns the query string of a url signed with `key`, granting access to `scope`
+/// until `expires` (unix seconds).
+pub fn signed_query(key: &str, scope: &str, expires: u64) -> String {
+    url::form_urlencoded::Serializer::new(String::new())
+        .append_pair(PARAM_AUTH_SCOPE, scope)
+        .append_pair(PARAM_AUTH_EXPIRES, &expires.to_string())
+        .append_pair(PARAM_AUTH_SIGNATURE, &sign(key, scope, expires))
+        .finish()
+}
+
+fn signature_mac(key: &str, scope: &str, expires: u64) -> HmacSha256 {
+    let mut mac =
+        HmacSha256::new_from_slice(key.as_bytes()).expect("hmac accepts keys of any size");
+    mac.update(format!("{}\n{}", scope, expires).as_bytes());
+    mac
+}
+
+#[derive(Debug)]
+struct SignedUrl {
+    scope: String,
+    expires: u64,
+    signature: Vec<u8>,
+}
+
+impl SignedUrl {
+    fn from_query(query: Option<&str>) -> Result<Option<Self>, AuthError> {
+        let (mut scope, mut expires, mut signature) = (None, None, None);
+        for (key, value) in url::form_urlencoded::parse(query.unwrap_or_default().as_bytes()) {
+            match key.as_ref() {
+                PARAM_AUTH_SCOPE => scope = Some(value.into_owned()),
+                PARAM_AUTH_EXPIRES => expires = Some(value.into_owned()),
+                PARAM_AUTH_SIGNATURE => signature = Some(value.into_owned()),
+                _ => {}
+            }
+        }
+        match (scope, expires, signature) {
+            (None, None, None) => Ok(None),
+            (Some(scope), Some(expires), Some(signature)) => Ok(Some(SignedUrl {
+                scope,
+                expires: expires
+                    .parse()
+                    .map_err(|_| AuthError::Invalid("invalid expiry"))?,
+                signature: hex::decode(signature)
+                    .map_err(|_| AuthError::Invalid("invalid signature"))?,
+            })),
+            _ => Err(AuthError::Invalid("incomplete signed url")),
+        }
+    }
+
+    fn verify(&self, key: &str, now: u64) -> Result<(), AuthError> {
+        signature_mac(key, &self.scope, self.expires)
+            .verify_slice(&self.signature)
+            .map_err(|_| AuthError::Invalid("invalid signature"))?;
+        if now > self.expires {
+            return Err(AuthError::Invalid("signed url expired"));
+        }
+        Ok(())
+    }
+}
+
+/// Returns the bearer token of the `Authorization` header, if any.
+fn bearer_token(headers: &HeaderMap) -> Result<Option<&str>, AuthError> {
+    match headers.get(AUTHORIZATION) {
+        Some(value) => {
+            let value = value
+                .to_str()
+                .map_err(|_| AuthError::Invalid("invalid authorization header"))?;
+            match value.split_once(' ') {
+                Some((scheme, token)) if scheme.eq_ignore_ascii_case("bearer") => {
+                    Ok(Some(token.trim()))
+                }
+                _ => Err(AuthError::Invalid("only bearer tokens are supported")),
+            }
+        }
+        None => Ok(None),
+    }
+}
+
+/// Checks if `path` is `scope` or below it. Bare CIDs are scoped to `/ipfs/<cid>`.
+fn scope_matches(scope: &str, path: &str) -> bool {
+    let prefix = match scope.starts_with('/') {
+        true => Cow::Borrowed(scope),
+        false => Cow::Owned(format!("/ipfs/{}", scope)),
+    };
+    let prefix = prefix.trim_end_matches('/');
+    prefix.is_empty()
+        || path
+            .strip_prefix(prefix)
+            .map(|rest| rest.is_empty() || rest.starts_with('/'))
+            .unwrap_or(false)
+}
+
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    const CID: &str = "bafkreidyeivj7adnnac6ljvzj2e3rd5xdw3revw4da7mx2ckrstapoupoq";
+
+    fn config() -> AuthConfig {
+        AuthConfig {
+            required: true,
+            anonymous_local_only: false,
+            signing_key: Some("secret".to_string()),
+            tokens: vec![
+                AccessToken {
+                    token: "admin".to_string(),
+                    scopes: vec!["/".to_string()],
+                },
+                AccessToken {
+                    token: "reader".to_string(),
+                    scopes: vec![CID.to_string(), "/ipns/docs.example.com/public".to_string()],
+                },
+            ],
+        }
+    }
+
+    fn bearer(token: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            AUTHORIZATION,
+            HeaderValue::from_str(&format!("Bearer {}", token)).unwrap(),
+        );
+        headers
+    }
+
+    #[test]
+    fn scopes() {
+        assert!(scope_matches("/", "/ipfs/abc"));
+        assert!(scope_matches("abc", "/ipfs/abc"));
+        assert!(scope_matches("abc", "/ipfs/abc/file.txt"));
+        assert!(!scope_matches("abc", "/ipfs/abcd"));
+        assert!(!scope_matches("abc", "/ipns/abc"));
+        assert!(scope_matches("/ipfs/abc/dir/", "/ipfs/abc/dir"));
+        assert!(!scope_matches("/ipfs/abc/dir", "/ipfs/abc/directory"));
+    }
+
+    #[test]
+    fn bearer_tokens() {
+        let config = config();
+        let path = format!("/ipfs/{}/file.txt", CID);
+        assert_eq!(
+            config.authorize_at(&path, None, &bearer("admin"), 0),
+            Ok(Access::Granted)
+        );
+        assert_eq!(
+            config.authorize_at(&path, None, &bearer("reader"), 0),
+            Ok(Access::Granted)
+        );
+        assert_eq!(
+            config.authorize_at(
+                "/ipns/docs.example.com/public/a",
+                None,
+                &bearer("reader"),
+                0
+            ),
+            Ok(Access::Granted)
+        );
+        assert_eq!(
+            config.authorize_at("/ipns/docs.example.com/private", None, &bearer("reader"), 0),
+            Err(AuthError::OutOfScope)
+        );
+        assert_eq!(
+            config.authorize_at("/admin/denylist", None, &bearer("reader"), 0),
+            Err(AuthError::OutOfScope)
+        );
+        assert_eq!(
+            config.authorize_at(&path, None, &bearer("unknown"), 0),
+            Err(AuthError::Invalid("unknown token"))
+        );
+
+        let mut basic = HeaderMap::new();
+        basic.insert(AUTHORIZATION, HeaderValue::from_static("Basic YTpi"));
+        assert!(matches!(
+            config.authorize_at(&path, None, &basic, 0),
+            Err(AuthError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn admin() {
+        let config = config();
+        assert_eq!(
+            config.authorize_admin("/admin/denylist", &bearer("admin")),
+            Ok(Access::Granted)
+        );
+        assert_eq!(
+            config.authorize_admin("/admin/denylist", &bearer("reader")),
+            Err(AuthError::OutOfScope)
+        );
+        assert_eq!(
+            config.authorize_admin("/admin/denylist", &HeaderMap::new()),
+            Err(AuthError::Missing)
+        );
+        // admin endpoints are closed if access control is disabled
+        assert_eq!(
+            AuthConfig::default().authorize_admin("/admin/denylist", &bearer("admin")),
+            Err(AuthError::Invalid("unknown token"))
+        );
+    }
+
+    #[test]
+    fn signed_urls() {
+        let config = config();
+        let scope = format!("/ipfs/{}", CID);
+        let path = format!("{}/file.txt", scope);
+        let query = signed_query("secret", &scope, 100);
+        let headers = HeaderMap::new();
+
+        assert_eq!(
+            config.authorize_at(&path, Some(&query), &headers, 100),
+            Ok(Access::Granted)
+        );
+        assert_eq!(
+            config.authorize_at(&path, Some(&query), &headers, 101),
+            Err(AuthError::Invalid("signed url expired"))
+        );
+        assert_eq!(
+            config.authorize_at("/ipfs/other", Some(&query), &headers, 100),
+            Err(AuthError::OutOfScope)
+        );
+
+        // the scope and expiry are covered by the signature
+        let forged = query.replace("100", "200");
+        assert_eq!(
+            config.authorize_at(&path, Some(&forged), &headers, 100),
+            Err(AuthError::Invalid("invalid signature"))
+        );
+        let other_key = signed_query("other", &scope, 100);
+        assert_eq!(
+            config.authorize_at(&path, Some(&other_key), &headers, 100),
+            Err(AuthError::Invalid("invalid signature"))
+        );
+        assert_eq!(
+            config.authorize_at(&path, Some("auth_scope=%2F"), &headers, 100),
+            Err(AuthError::Invalid("incomplete signed url"))
+        );
+    }
+
+    #[test]
+    fn anonymous() {
+        let mut config = config();
+        let path = format!("/ipfs/{}", CID);
+        let headers = HeaderMap::new();
+        assert_eq!(
+            config.authorize_at(&path, None, &headers, 0),
+            Err(AuthError::Missing)
+        );
+
+        config.required = false;
+        assert_eq!(
+            config.authorize_at(&path, None, &headers, 0),
+            Ok(Access::Anonymous)
+        );
+        assert_eq!(
+            config.authorize_at("/api/v0/add", None, &headers, 0),
+            Err(AuthError::Missing)
+        );
+
+        config.anonymous_local_only = true;
+        assert_eq!(
+            config.authorize_at(&path, None, &headers, 0),
+            Ok(Access::LocalOnly)
+        );
+    }
+}
@@ -1,12 +1,23 @@
+use anyhow::{anyhow, bail, Context, Result};
 use cid::Cid;
+use config::{ConfigError, Map, Value};
+use iroh_util::insert_into_config_map;
 use serde::{de, Deserialize, Deserializer, Serialize};
 use sha2::{Digest, Sha256};
-use std::{collections::HashSet, str::FromStr, sync::Arc, time::Duration};
+use std::{
+    collections::HashSet,
+    path::PathBuf,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
 use tokio::{sync::RwLock, task::JoinHandle};
-use tracing::{debug, log::error};
+use tracing::{debug, log::error, warn};
 
 const BAD_BITS_UPDATE_INTERVAL: Duration = Duration::from_secs(3600 * 8);
-const DEFAULT_DENY_LIST_URI: &str = "http://badbits.dwebops.pub/denylist.json";
+pub const DEFAULT_DENY_LIST_URI: &str = "http://badbits.dwebops.pub/denylist.json";
+/// Maximum size of a single denylist source.
+const MAX_DENY_LIST_SIZE: usize = 1 << 26; // 64MB
 
 #[derive(Debug, Deserialize, Serialize, Clone, Eq, Hash, PartialEq)]
 pub struct BadBitsAnchor {
@@ -19,29 +30,221 @@ where
     D: Deserializer<'de>,
 {
     let s = String::deserialize(deserializer)?;
-    let b = hex::decode(&s).unwrap();
-    b[..32].try_into().map_err(de::Error::custom)
+    let b = hex::decode(&s).map_err(de::Error::custom)?;
+    b.get(..32)
+        .ok_or_else(|| de::Error::custom("anchor too short"))?
+        .try_into()
+        .map_err(de::Error::custom)
+}
+
+/// Format of the entries of a denylist source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DenylistFormat {
+    /// JSON list of `{"anchor": "<hex sha256>"}` objects, as published by badbits.dwebops.pub.
+    BadBits,
+    /// One `<cid>[/<path>]` per line, optionally prefixed with `/ipfs/`.
+    /// Empty lines and lines starting with `#` are ignored.
+    Lines,
+}
+
+impl DenylistFormat {
+    fn as_str(&self) -> &'static str {
+        match self {
+            DenylistFormat::BadBits => "badbits",
+            DenylistFormat::Lines => "lines",
+        }
+    }
+}
+
+/// A source the denylist is loaded from.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum DenylistSource {
+    /// Fetched over http(s).
+    Url { url: String, format: DenylistFormat },
+    /// Read from a local file.
+    File {
+        path: PathBuf,
+        format: DenylistFormat,
+    },
+    /// Entries listed directly in the configuration, in the `lines` format.
+    Inline { entries: Vec<String> },
+}
+
+impl DenylistSource {
+    /// The badbits list maintained by the dwebops project.
+    pub fn default_badbits() -> Self {
+        DenylistSource::Url {
+            url: DEFAULT_DENY_LIST_URI.to_string(),
+            format: DenylistFormat::BadBits,
+        }
+    }
+
+    pub fn collect(&self) -> Result<Map<String, Value>, ConfigError> {
+        let mut map: Map<String, Value> = Map::new();
+        match self {
+            DenylistSource::Url { url, format } => {
+                insert_into_config_map(&mut map, "type", "url");
+                insert_into_config_map(&mut map, "url", url.clone());
+                insert_into_config_map(&mut map, "format", format.as_str());
+            }
+            DenylistSource::File { path, format } => {
+                let path = path
+                    .to_str()
+                    .ok_or_else(|| ConfigError::Foreign(anyhow!("non utf-8 path").into()))?;
+                insert_into_config_map(&mut map, "type", "file");
+                insert_into_config_map(&mut map, "path", path);
+                insert_into_config_map(&mut map, "format", format.as_str());
+            }
+            DenylistSource::Inline { entries } => {
+                insert_into_config_map(&mut map, "type", "inline");
+                insert_into_config_map(&mut map, "entries", entries.clone());
+            }
+        }
+        Ok(map)
+    }
+
+    /// Loads all entries of this source.
+    pub async fn load(&self) -> Result<HashSet<BadBitsAnchor>> {
+        match self {
+            DenylistSource::Url { url, format } => {
+                let mut res = reqwest::get(url).await?;
+                if !res.status().is_success() {
+                    bail!("failed to fetch {}: {}", url, res.status());
+                }
+                if res.content_length().unwrap_or_default() > MAX_DENY_LIST_SIZE as u64 {
+                    bail!("denylist {} too large", url);
+                }
+                // the content length is optional, so the size is checked while reading as well
+                let mut body = Vec::new();
+                while let Some(chunk) = res.chunk().await? {
+                    if body.len() + chunk.len() > MAX_DENY_LIST_SIZE {
+                        bail!("denylist {} too large", url);
+                    }
+                    body.extend_from_slice(&chunk);
+                }
+                parse_denylist(&body, *format)
+            }
+            DenylistSource::File { path, format } => {
+                let body = tokio::fs::read(path)
+                    .await
+                    .with_context(|| format!("failed to read {}", path.display()))?;
+                parse_denylist(&body, *format)
+            }
+            DenylistSource::Inline { entries } => entries
+                .iter()
+                .filter_map(|entry| parse_line(entry).transpose())
+                .collect(),
+        }
+    }
+}
+
+impl std::fmt::Display for DenylistSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DenylistSource::Url { url, .. } => write!(f, "{}", url),
+            DenylistSource::File { path, .. } => write!(f, "{}", path.display()),
+            DenylistSource::Inline { entries } => write!(f, "inline ({} entries)", entries.len()),
+        }
+    }
+}
+
+fn parse_denylist(body: &[u8], format: DenylistFormat) -> Result<HashSet<BadBitsAnchor>> {
+    if body.len() > MAX_DENY_LIST_SIZE {
+        bail!("denylist too large: {}", body.len());
+    }
+    match format {
+        DenylistFormat::BadBits => {
+            let anchors = serde_json::from_slice::<Vec<BadBitsAnchor>>(body)?;
+            Ok(HashSet::from_iter(anchors))
+        }
+        DenylistFormat::Lines => std::str::from_utf8(body)?
+            .lines()
+            .filter_map(|line| parse_line(line).transpose())
+            .collect(),
+    }
+}
+
+/// Parses a single `<cid>[/<path>]` entry, returning `None` for empty and comment lines.
+fn parse_line(line: &str) -> Result<Option<BadBitsAnchor>> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+    let line = line.strip_prefix("/ipfs/").unwrap_or(line);
+    let (cid, path) = match line.split_once('/') {
+        Some((cid, path)) => (cid, path),
+        None => (line, ""),
+    };
+    let cid = Cid::from_str(cid).with_context(|| format!("invalid denylist entry: {}", line))?;
+    Ok(Some(BadBits::to_anchor(cid, path)))
 }
 
 #[derive(Debug)]
 pub struct BadBits {
-    pub last_updated: time::Instant,
+    /// Time of the last successful update, `None` if the denylist was never loaded.
+    pub last_updated: Option<SystemTime>,
+    /// Time of the last reload requested through the admin endpoint, successful or not.
+    last_reload: Option<Instant>,
     pub denylist: HashSet<BadBitsAnchor>,
+    pub sources: Vec<DenylistSource>,
+    /// Entries last loaded from each of the `sources`, `None` if a source was never loaded.
+    source_entries: Vec<Option<HashSet<BadBitsAnchor>>>,
 }
 
 impl BadBits {
     pub fn new() -> Self {
+        Self::with_sources(Vec::new())
+    }
+
+    pub fn with_sources(sources: Vec<DenylistSource>) -> Self {
         Self {
-            last_updated: time::Instant::now(),
+            last_updated: None,
+            last_reload: None,
             denylist: HashSet::new(),
+            source_entries: vec![None; sources.len()],
+            sources,
         }
     }
 
     pub fn update(&mut self, denylist: HashSet<BadBitsAnchor>) {
-        self.last_updated = time::Instant::now();
+        self.last_updated = Some(SystemTime::now());
         self.denylist = denylist;
     }
 
+    /// Replaces the entries of each source that was loaded, `None` keeps the entries
+    /// previously loaded from that source, and rebuilds the denylist from all sources.
+    fn update_sources(&mut self, loaded: Vec<Option<HashSet<BadBitsAnchor>>>) {
+        for (entries, loaded) in self.source_entries.iter_mut().zip(loaded) {
+            if loaded.is_some() {
+                *entries = loaded;
+            }
+        }
+        let denylist = self
+            .source_entries
+            .iter()
+            .flatten()
+            .flatten()
+            .cloned()
+            .collect();
+        self.update(denylist);
+    }
+
+    /// Records a reload attempt, unless the previous one was less than `min_interval` ago.
+    ///
+    /// Returns whether the reload may go ahead. Failed reloads count as well, so unreachable
+    /// sources can't be hammered by repeated requests.
+    pub fn start_reload(&mut self, min_interval: Duration) -> bool {
+        if let Some(last) = self.last_reload {
+            if last.elapsed() < min_interval {
+                return false;
+            }
+        }
+        self.last_reload = Some(Instant::now());
+        true
+    }
+
     pub fn is_bad(&self, cid: &str, path: &str) -> bool {
         let cid = match Cid::from_str(cid) {
             Ok(cid) => cid,
@@ -70,32 +273,46 @@ impl Default for BadBits {
     }
 }
 
+/// Reloads the denylist from all of its sources.
+///
+/// Sources that fail to load keep the entries they were last loaded with, so a temporarily
+/// unreachable source never clears its part of the denylist, while entries removed from
+/// the other sources are dropped. Returns the number of sources that failed.
+pub async fn reload_bad_bits(bad_bits: &RwLock<BadBits>) -> usize {
+    let sources = bad_bits.read().await.sources.clone();
+    let mut loaded = Vec::with_capacity(sources.len());
+    let mut failed = 0;
+    for source in &sources {
+        match source.load().await {
+            Ok(entries) => {
+                debug!("loaded denylist {}: len={}", source, entries.len());
+                loaded.push(Some(entries));
+            }
+            Err(e) => {
+                error!("failed to load denylist {}: {:?}", source, e);
+                loaded.push(None);
+                failed += 1;
+            }
+        }
+    }
+
+    let mut bbits = bad_bits.write().await;
+    if failed > 0 && failed == sources.len() {
+        warn!("no denylist source could be loaded, keeping the current denylist");
+        return failed;
+    }
+    bbits.update_sources(loaded);
+    debug!("updated denylist: len={}", bbits.denylist.len());
+    failed
+}
+
 pub fn spawn_bad_bits_updater(bad_bits: Arc<Option<RwLock<BadBits>>>) -> Option<JoinHandle<()>> {
     if bad_bits.is_some() {
         return Some(tokio::spawn(async move {
             let bad_bits = bad_bits.as_ref();
             if let Some(bbits) = bad_bits {
                 loop {
-                    let denylist_uri = DEFAULT_DENY_LIST_URI;
-                    let res = reqwest::get(denylist_uri).await.unwrap();
-                    if res.status().is_success() {
-                        let body = res.bytes().await.unwrap();
-                        if body.len() > 1 << 26 {
-                            // 64MB
-                            error!("denylist too large: {}", body.len());
-                        } else {
-                            let body =
-                                serde_json::from_slice::<Vec<BadBitsAnchor>>(&body[..]).unwrap();
-                            let new_denylist = HashSet::from_iter(body);
-                            bbits.write().await.update(new_denylist);
-                            debug!(
-                                "updated denylist: len={}",
-                                bbits.read().await.denylist.len()
-                            );
-                        }
-                    } else {
-                        error!("Failed to fetch denylist: {}", res.status());
-                    }
+                    reload_bad_bits(bbits).await;
                     tokio::time::sleep(BAD_BITS_UPDATE_INTERVAL).await;
                 }
             }
@@ -159,6 +376,105 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_denylist_formats() {
+        let cid =
+            Cid::from_str("bafkreidyeivj7adnnac6ljvzj2e3rd5xdw3revw4da7mx2ckrstapoupoq").unwrap();
+
+        let body =
+            br#"[{"anchor": "d572cfd7fca1f89293f2d71270c51d82445b4502207a0df0707586b3e799521b"}]"#;
+        let denylist = parse_denylist(body, DenylistFormat::BadBits).unwrap();
+        assert_eq!(denylist, HashSet::from([BadBits::to_anchor(cid, "")]));
+
+        let body = format!("# comment\n\n{}\n/ipfs/{}/test\n", cid, cid);
+        let denylist = parse_denylist(body.as_bytes(), DenylistFormat::Lines).unwrap();
+        assert_eq!(
+            denylist,
+            HashSet::from([BadBits::to_anchor(cid, ""), BadBits::to_anchor(cid, "test")])
+        );
+
+        assert!(parse_denylist(b"not-a-cid\n", DenylistFormat::Lines).is_err());
+        assert!(parse_denylist(b"[{\"anchor\": \"zz\"}]", DenylistFormat::BadBits).is_err());
+    }
+
+    #[tokio::test]
+    async fn reload_bad_bits_keeps_entries_on_failure() {
+        let cid =
+            Cid::from_str("bafkreidyeivj7adnnac6ljvzj2e3rd5xdw3revw4da7mx2ckrstapoupoq").unwrap();
+        let other_cid = Cid::from_str("QmdZ8zoh1iCsk8TdSAWN49tziH5MMn8XPvJcWmpFD1ygB7").unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("denylist.txt");
+        tokio::fs::write(&path, format!("{}\n", other_cid))
+            .await
+            .unwrap();
+
+        let bbits = RwLock::new(BadBits::with_sources(vec![
+            DenylistSource::Inline {
+                entries: vec![cid.to_string()],
+            },
+            DenylistSource::File {
+                path: path.clone(),
+                format: DenylistFormat::Lines,
+            },
+        ]));
+        assert_eq!(reload_bad_bits(&bbits).await, 0);
+        assert!(bbits.read().await.last_updated.is_some());
+        assert!(bbits.read().await.is_bad(&cid.to_string(), ""));
+        assert!(bbits.read().await.is_bad(&other_cid.to_string(), "/"));
+
+        // the file source is gone, its entries must not be dropped
+        tokio::fs::remove_file(&path).await.unwrap();
+        assert_eq!(reload_bad_bits(&bbits).await, 1);
+        assert!(bbits.read().await.is_bad(&other_cid.to_string(), ""));
+
+        // entries removed from a source that loads are dropped, even if another one fails
+        let updated_path = dir.path().join("updated.txt");
+        tokio::fs::write(&updated_path, format!("{}\n", cid))
+            .await
+            .unwrap();
+        let bbits = RwLock::new(BadBits::with_sources(vec![
+            DenylistSource::File {
+                path: updated_path.clone(),
+                format: DenylistFormat::Lines,
+            },
+            DenylistSource::File {
+                path: path.clone(),
+                format: DenylistFormat::Lines,
+            },
+        ]));
+        tokio::fs::write(&path, format!("{}\n", other_cid))
+            .await
+            .unwrap();
+        assert_eq!(reload_bad_bits(&bbits).await, 0);
+        tokio::fs::write(&updated_path, format!("{}/test\n", cid))
+            .await
+            .unwrap();
+        tokio::fs::remove_file(&path).await.unwrap();
+        assert_eq!(reload_bad_bits(&bbits).await, 1);
+        assert!(!bbits.read().await.is_bad(&cid.to_string(), ""));
+        assert!(bbits.read().await.is_bad(&cid.to_string(), "test"));
+        assert!(bbits.read().await.is_bad(&other_cid.to_string(), ""));
+
+        // no source can be loaded at all
+        let bbits = RwLock::new(BadBits::with_sources(vec![DenylistSource::File {
+            path,
+            format: DenylistFormat::Lines,
+        }]));
+        assert_eq!(reload_bad_bits(&bbits).await, 1);
+        assert!(bbits.read().await.last_updated.is_none());
+    }
+
+    #[test]
+    fn reload_throttling() {
+        let mut bbits = BadBits::new();
+        assert!(bbits.start_reload(Duration::from_secs(60)));
+        // the first attempt counts, even though it never updated the denylist
+        assert!(bbits.last_updated.is_none());
+        assert!(!bbits.start_reload(Duration::from_secs(60)));
+        assert!(bbits.start_reload(Duration::ZERO));
+    }
+
     #[tokio::test]
     async fn gateway_bad_bits() {
         let bad_cid =
@@ -275,6 +591,17 @@ mod tests {
         let res = client.get(uri).await.unwrap();
         assert!(res.status() != StatusCode::GONE);
 
+        // the admin endpoints require a token, even though access control is disabled
+        let uri = hyper::Uri::builder()
+            .scheme("http")
+            .authority(format!("localhost:{}", addr.port()))
+            .path_and_query("/admin/denylist")
+            .build()
+            .unwrap();
+        let client = hyper::Client::new();
+        let res = client.get(uri).await.unwrap();
+        assert_eq!(StatusCode::UNAUTHORIZED, res.status());
+
         core_task.abort();
         core_task.await.unwrap_err();
     }
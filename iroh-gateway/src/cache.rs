@@ -0,0 +1,240 @@
+use std::{
+    fmt,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use axum::{body::HttpBody, http::StatusCode};
+use bytes::Bytes;
+use config::{ConfigError, Map, Source, Value};
+use http::HeaderMap;
+use iroh_resolver::resolver::{Metadata, PathType};
+use iroh_util::insert_into_config_map;
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+
+/// Approximate per entry overhead, accounted in addition to the entry contents.
+const ENTRY_OVERHEAD: usize = 128;
+
+/// Configuration of the in-process gateway caches.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CacheConfig {
+    /// memory budget in bytes for rendered responses, `0` disables the response cache,
+    /// which is the default
+    pub max_response_bytes: u64,
+    /// largest response body in bytes that is cached
+    pub max_entry_bytes: u64,
+    /// memory budget in bytes for resolved path metadata, `0` disables the metadata cache
+    pub max_metadata_bytes: u64,
+    /// time in seconds entries for mutable `/ipns/` paths are kept
+    pub ipns_ttl_secs: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            max_response_bytes: 0,
+            max_entry_bytes: 1024 * 1024,
+            max_metadata_bytes: 4 * 1024 * 1024,
+            ipns_ttl_secs: 60,
+        }
+    }
+}
+
+impl CacheConfig {
+    /// How long an entry for a path of the given type may be served from the cache,
+    /// `None` if it never expires.
+    pub fn ttl(&self, typ: PathType) -> Option<Duration> {
+        match typ {
+            PathType::Ipfs => None,
+            PathType::Ipns => Some(Duration::from_secs(self.ipns_ttl_secs)),
+        }
+    }
+}
+
+impl Source for CacheConfig {
+    fn clone_into_box(&self) -> Box<dyn Source + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn collect(&self) -> Result<Map<String, Value>, ConfigError> {
+        let mut map: Map<String, Value> = Map::new();
+        insert_into_config_map(
+            &mut map,
+            "max_response_bytes",
+            self.max_response_bytes as i64,
+        );
+        insert_into_config_map(&mut map, "max_entry_bytes", self.max_entry_bytes as i64);
+        insert_into_config_map(
+            &mut map,
+            "max_metadata_bytes",
+            self.max_metadata_bytes as i64,
+        );
+        insert_into_config_map(&mut map, "ipns_ttl_secs", self.ipns_ttl_secs as i64);
+        Ok(map)
+    }
+}
+
+struct Entry<V> {
+    value: V,
+    size: usize,
+    expires: Option<Instant>,
+}
+
+struct Inner<V> {
+    entries: LruCache<String, Entry<V>>,
+    size: usize,
+}
+
+/// LRU cache bounded by the accounted size of its entries, with optional per entry expiry.
+pub struct Cache<V> {
+    max_bytes: usize,
+    inner: Mutex<Inner<V>>,
+}
+
+impl<V> fmt::Debug for Cache<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cache")
+            .field("max_bytes", &self.max_bytes)
+            .finish()
+    }
+}
+
+impl<V: Clone> Cache<V> {
+    pub fn new(max_bytes: usize) -> Self {
+        Cache {
+            max_bytes,
+            inner: Mutex::new(Inner {
+                entries: LruCache::unbounded(),
+                size: 0,
+            }),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<V> {
+        let mut inner = self.inner.lock().unwrap();
+        match inner.entries.get(key) {
+            Some(entry) if entry.expires.map(|e| e > Instant::now()).unwrap_or(true) => {
+                return Some(entry.value.clone());
+            }
+            Some(_) => {}
+            None => return None,
+        }
+        // expired
+        if let Some(entry) = inner.entries.pop(key) {
+            inner.size -= entry.size;
+        }
+        None
+    }
+
+    /// Inserts a value accounted with `size` bytes, evicting the least recently used
+    /// entries to stay within the budget. Values larger than the budget are not cached.
+    pub fn insert(&self, key: String, value: V, size: usize, ttl: Option<Duration>) {
+        let size = size + key.len() + ENTRY_OVERHEAD;
+        if size > self.max_bytes {
+            return;
+        }
+        let entry = Entry {
+            value,
+            size,
+            expires: ttl.map(|ttl| Instant::now() + ttl),
+        };
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(old) = inner.entries.put(key, entry) {
+            inner.size -= old.size;
+        }
+        inner.size += size;
+        while inner.size > self.max_bytes {
+            match inner.entries.pop_lru() {
+                Some((_, entry)) => inner.size -= entry.size,
+                None => break,
+            }
+        }
+    }
+
+    /// Accounted size of all entries in bytes.
+    pub fn size(&self) -> usize {
+        self.inner.lock().unwrap().size
+    }
+}
+
+/// Cache of resolved path metadata, keyed by path.
+pub type MetadataCache = Cache<Metadata>;
+
+/// Approximate memory used by a metadata entry.
+pub fn metadata_size(metadata: &Metadata) -> usize {
+    std::mem::size_of::<Metadata>() + metadata.resolved_path.len() * 64
+}
+
+/// A fully rendered response.
+#[derive(Debug, Clone)]
+pub struct CachedResponse {
+    pub status_code: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+impl CachedResponse {
+    /// Approximate memory used by this response.
+    pub fn size(&self) -> usize {
+        let headers: usize = self
+            .headers
+            .iter()
+            .map(|(k, v)| k.as_str().len() + v.len())
+            .sum();
+        self.body.len() + headers
+    }
+}
+
+/// Cache of rendered responses, keyed by request.
+pub type ResponseCache = Cache<CachedResponse>;
+
+/// Returns the exact size of the body if it is known and small enough to be cached.
+pub fn cacheable_size<B: HttpBody>(body: &B, config: &CacheConfig) -> Option<u64> {
+    body.size_hint()
+        .exact()
+        .filter(|size| *size <= config.max_entry_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_evicts_lru_within_budget() {
+        let cache = Cache::<u32>::new(3 * (ENTRY_OVERHEAD + 11));
+        cache.insert("a".into(), 1, 10, None);
+        cache.insert("b".into(), 2, 10, None);
+        cache.insert("c".into(), 3, 10, None);
+        // touch "a" so that "b" is the least recently used
+        assert_eq!(cache.get("a"), Some(1));
+        cache.insert("d".into(), 4, 10, None);
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("a"), Some(1));
+        assert_eq!(cache.get("c"), Some(3));
+        assert_eq!(cache.get("d"), Some(4));
+        assert_eq!(cache.size(), 3 * (ENTRY_OVERHEAD + 11));
+
+        // too large to ever fit
+        cache.insert("e".into(), 5, 1024, None);
+        assert_eq!(cache.get("e"), None);
+        assert_eq!(cache.get("d"), Some(4));
+    }
+
+    #[test]
+    fn cache_expires_entries() {
+        let cache = Cache::<u32>::new(1024);
+        cache.insert("ipns".into(), 1, 10, Some(Duration::from_secs(0)));
+        cache.insert("ipfs".into(), 2, 10, None);
+        assert_eq!(cache.get("ipns"), None);
+        assert_eq!(cache.get("ipfs"), Some(2));
+        assert_eq!(cache.size(), ENTRY_OVERHEAD + 14);
+    }
+
+    #[test]
+    fn ttl_by_path_type() {
+        let config = CacheConfig::default();
+        assert_eq!(config.ttl(PathType::Ipfs), None);
+        assert_eq!(config.ttl(PathType::Ipns), Some(Duration::from_secs(60)));
+    }
+}
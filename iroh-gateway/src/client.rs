@@ -1,40 +1,54 @@
+use std::collections::HashSet;
 use std::ops::Range;
 use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Arc;
 use std::task::Poll;
 
 use anyhow::Result;
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
 use cid::Cid;
 use futures::{StreamExt, TryStream};
-use http::HeaderMap;
+use http::{HeaderMap, HeaderValue};
 use iroh_car::{CarHeader, CarWriter};
 use iroh_metrics::{
     core::{MObserver, MRecorder},
     gateway::{GatewayHistograms, GatewayMetrics},
-    observe, record,
+    inc, observe, record,
 };
+use iroh_resolver::car_export::{export_blocks, ExportOptions};
+use iroh_resolver::codecs::Codec;
 use iroh_resolver::resolver::{
-    CidOrDomain, ContentLoader, Metadata, Out, OutMetrics, OutPrettyReader, OutType, Resolver,
-    ResponseClip, Source,
+    parse_links, CidOrDomain, ContentLoader, Metadata, Out, OutMetrics, OutPrettyReader, OutRaw,
+    OutType, Resolver, ResponseClip, Source,
 };
+use iroh_resolver::unixfs::UnixfsNode;
 use mime::Mime;
-use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncSeekExt, AsyncWrite};
-use tokio_util::io::ReaderStream;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt};
+use tokio_util::io::{poll_read_buf, ReaderStream};
 use tracing::{info, warn};
 
 use crate::response::ResponseFormat;
-use crate::{constants::RECURSION_LIMIT, handlers::GetParams};
+use crate::{
+    access_log,
+    cache::{metadata_size, CacheConfig, MetadataCache},
+    constants::{BODY_CHUNK_SIZE, RECURSION_LIMIT},
+    handlers::GetParams,
+};
 
 #[derive(Debug, Clone)]
 pub struct Client<T: ContentLoader> {
     pub(crate) resolver: Resolver<T>,
+    cache_config: CacheConfig,
+    metadata_cache: Option<Arc<MetadataCache>>,
 }
 
-pub struct PrettyStreamBody<T: ContentLoader>(
-    ReaderStream<tokio::io::BufReader<OutPrettyReader<T>>>,
-    Option<u64>,
-    Option<Mime>,
-);
+pub struct PrettyStreamBody<T: ContentLoader> {
+    reader: tokio::io::Take<tokio::io::BufReader<OutPrettyReader<T>>>,
+    buf: BytesMut,
+    size: Option<u64>,
+    mime: Option<Mime>,
+}
 
 #[allow(clippy::large_enum_variant)]
 pub enum FileResult<T: ContentLoader> {
@@ -44,12 +58,104 @@ pub enum FileResult<T: ContentLoader> {
 }
 
 impl<T: ContentLoader> PrettyStreamBody<T> {
+    fn new(
+        reader: tokio::io::BufReader<OutPrettyReader<T>>,
+        size: Option<u64>,
+        mime: Option<Mime>,
+    ) -> Self {
+        PrettyStreamBody {
+            reader: reader.take(u64::MAX),
+            buf: BytesMut::new(),
+            size,
+            mime,
+        }
+    }
+
     pub fn get_mime(&self) -> Option<Mime> {
-        self.2.clone()
+        self.mime.clone()
     }
 
     pub fn get_size(&self) -> Option<u64> {
-        self.1
+        self.size
+    }
+}
+
+impl<T: ContentLoader + std::marker::Unpin> PrettyStreamBody<T> {
+    /// Restricts the body to the given byte range of the content.
+    pub async fn seek_range(&mut self, range: Range<u64>) -> std::io::Result<()> {
+        self.reader
+            .get_mut()
+            .seek(tokio::io::SeekFrom::Start(range.start))
+            .await?;
+        let len = range.end.saturating_sub(range.start);
+        self.reader.set_limit(len);
+        self.size = Some(len);
+        Ok(())
+    }
+
+    /// Turns the body into a `multipart/byteranges` body containing the given ranges,
+    /// each part tagged with `content_type`.
+    ///
+    /// Returns the body and its exact length.
+    pub fn into_multipart(
+        self,
+        ranges: Vec<Range<u64>>,
+        size: u64,
+        content_type: Option<HeaderValue>,
+        boundary: String,
+    ) -> (
+        axum::body::StreamBody<ReaderStream<tokio::io::DuplexStream>>,
+        u64,
+    ) {
+        let content_type = content_type
+            .as_ref()
+            .and_then(|ct| ct.to_str().ok())
+            .unwrap_or("application/octet-stream")
+            .to_string();
+        let parts: Vec<_> = ranges
+            .into_iter()
+            .enumerate()
+            .map(|(i, range)| {
+                let header = format!(
+                    "{}--{}\r\nContent-Type: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+                    if i == 0 { "" } else { "\r\n" },
+                    boundary,
+                    content_type,
+                    range.start,
+                    range.end - 1,
+                    size
+                );
+                (header, range)
+            })
+            .collect();
+        let trailer = format!("\r\n--{}--\r\n", boundary);
+        let len = parts
+            .iter()
+            .map(|(header, range)| header.len() as u64 + (range.end - range.start))
+            .sum::<u64>()
+            + trailer.len() as u64;
+
+        let (mut writer, reader) = tokio::io::duplex(1024 * 64);
+        let body = axum::body::StreamBody::new(ReaderStream::new(reader));
+        let mut reader = self.reader.into_inner();
+        tokio::task::spawn(async move {
+            let res: std::io::Result<()> = async move {
+                for (header, range) in parts {
+                    writer.write_all(header.as_bytes()).await?;
+                    reader.seek(tokio::io::SeekFrom::Start(range.start)).await?;
+                    let mut part = (&mut reader).take(range.end - range.start);
+                    tokio::io::copy(&mut part, &mut writer).await?;
+                }
+                writer.write_all(trailer.as_bytes()).await?;
+                writer.flush().await
+            }
+            .await;
+            if let Err(e) = res {
+                warn!("failed to write multipart body: {:?}", e);
+            }
+        });
+
+        (body, len)
     }
 }
 
@@ -61,12 +167,16 @@ impl<T: ContentLoader + std::marker::Unpin> http_body::Body for PrettyStreamBody
         mut self: Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
-        let stream = Pin::new(&mut self.0);
-        match stream.try_poll_next(cx) {
+        let this = &mut *self;
+        // the buffer keeps the capacity left over from the previous chunk, which can be tiny
+        if this.buf.capacity() < BODY_CHUNK_SIZE {
+            this.buf.reserve(BODY_CHUNK_SIZE);
+        }
+        match poll_read_buf(Pin::new(&mut this.reader), cx, &mut this.buf) {
             Poll::Pending => Poll::Pending,
-            Poll::Ready(Some(Ok(chunk))) => Poll::Ready(Some(Ok(chunk))),
-            Poll::Ready(Some(Err(err))) => Poll::Ready(Some(Err(err.to_string()))),
-            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Ready(Ok(0)) => Poll::Ready(None),
+            Poll::Ready(Ok(_)) => Poll::Ready(Some(Ok(this.buf.split().freeze()))),
+            Poll::Ready(Err(err)) => Poll::Ready(Some(Err(err.to_string()))),
         }
     }
 
@@ -79,7 +189,7 @@ impl<T: ContentLoader + std::marker::Unpin> http_body::Body for PrettyStreamBody
 
     fn size_hint(&self) -> http_body::SizeHint {
         let mut size_hint = http_body::SizeHint::new();
-        if let Some(size) = self.1 {
+        if let Some(size) = self.size {
             size_hint.set_exact(size);
         }
         size_hint
@@ -87,12 +197,72 @@ impl<T: ContentLoader + std::marker::Unpin> http_body::Body for PrettyStreamBody
 }
 
 impl<T: ContentLoader + std::marker::Unpin> Client<T> {
-    pub fn new(rpc_client: &T) -> Self {
+    pub fn new(rpc_client: &T, cache_config: &CacheConfig) -> Self {
+        let metadata_cache = match cache_config.max_metadata_bytes {
+            0 => None,
+            max => Some(Arc::new(MetadataCache::new(max as usize))),
+        };
         Self {
             resolver: Resolver::new(rpc_client.clone()),
+            cache_config: cache_config.clone(),
+            metadata_cache,
+        }
+    }
+
+    /// Returns a client that serves content from the local store only, without ever
+    /// fetching blocks from the network.
+    pub fn local_only(&self) -> Self {
+        Client {
+            resolver: self.resolver.local_only(),
+            ..self.clone()
         }
     }
 
+    /// Resolves the path, using the cached resolution of the path if there is one.
+    ///
+    /// Returns the output together with the metadata of the original path.
+    async fn resolve_cached(&self, path: iroh_resolver::resolver::Path) -> Result<(Out, Metadata)> {
+        let cache = match &self.metadata_cache {
+            Some(cache) => cache,
+            None => {
+                let res = self.resolver.resolve(path).await?;
+                let metadata = res.metadata().clone();
+                return Ok((res, metadata));
+            }
+        };
+
+        let key = path.to_string();
+        if let Some(cached) = cache.get(&key) {
+            if let Some(cid) = cached.resolved_path.last() {
+                inc!(GatewayMetrics::MetadataCacheHits);
+                let res = self
+                    .resolver
+                    .resolve(iroh_resolver::resolver::Path::from_cid(*cid))
+                    .await?;
+                let metadata = Metadata {
+                    source: res.metadata().source.clone(),
+                    ..cached
+                };
+                return Ok((res, metadata));
+            }
+        }
+
+        inc!(GatewayMetrics::MetadataCacheMisses);
+        let typ = path.typ();
+        let res = self.resolver.resolve(path).await?;
+        let metadata = res.metadata().clone();
+        // only unixfs and raw outputs are complete blocks that can be resolved by cid
+        if matches!(metadata.typ, OutType::Unixfs | OutType::Raw) {
+            cache.insert(
+                key,
+                metadata.clone(),
+                metadata_size(&metadata),
+                self.cache_config.ttl(typ),
+            );
+        }
+        Ok((res, metadata))
+    }
+
     #[tracing::instrument(skip(self))]
     pub async fn get_file(
         &self,
@@ -101,12 +271,7 @@ impl<T: ContentLoader + std::marker::Unpin> Client<T> {
         range: Option<Range<u64>>,
     ) -> Result<(FileResult<T>, Metadata), String> {
         info!("get file {}", path);
-        let res = self
-            .resolver
-            .resolve(path)
-            .await
-            .map_err(|e| e.to_string())?;
-        let metadata = res.metadata().clone();
+        let (res, metadata) = self.resolve_cached(path).await.map_err(|e| e.to_string())?;
         record_ttfb_metrics(start_time, &metadata.source);
 
         if res.is_dir() {
@@ -128,15 +293,13 @@ impl<T: ContentLoader + std::marker::Unpin> Client<T> {
             let mut buf_reader = tokio::io::BufReader::with_capacity(1024 * 1024, reader);
             let body_sample = buf_reader.fill_buf().await.map_err(|e| e.to_string())?;
             let mime = sniff_content_type(body_sample);
-            if let Some(range) = range {
-                buf_reader
-                    .seek(tokio::io::SeekFrom::Start(range.start))
-                    .await
-                    .map_err(|e| e.to_string())?;
+            let mut body = PrettyStreamBody::new(buf_reader, metadata.size, Some(mime));
+            if let Some(mut range) = range {
+                if let Some(size) = metadata.size {
+                    range.end = std::cmp::min(range.end, size);
+                }
+                body.seek_range(range).await.map_err(|e| e.to_string())?;
             }
-            let stream = ReaderStream::new(buf_reader);
-
-            let body = PrettyStreamBody(stream, metadata.size, Some(mime));
 
             if metadata.typ == OutType::Raw {
                 return Ok((FileResult::Raw(body), metadata));
@@ -157,11 +320,77 @@ impl<T: ContentLoader + std::marker::Unpin> Client<T> {
         let (writer, reader) = tokio::io::duplex(1024 * 64);
         let body = axum::body::StreamBody::new(ReaderStream::new(reader));
         let client = self.clone();
-        tokio::task::spawn(async move {
+        tokio::task::spawn(access_log::in_current_request(async move {
             if let Err(e) = fetch_car_recursive(&client.resolver, path, writer, start_time).await {
                 warn!("failed to load recursively: {:?}", e);
             }
-        });
+        }));
+
+        Ok(body)
+    }
+
+    /// Resolves the path of a scoped CAR response, returning the resolved content and the
+    /// blocks on the path, the last of which is the root of the content.
+    #[tracing::instrument(skip(self))]
+    pub async fn resolve_car_scoped(
+        &self,
+        path: iroh_resolver::resolver::Path,
+    ) -> Result<(Out, Vec<OutRaw>), String> {
+        self.resolver
+            .resolve_with_path_blocks(path)
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    /// Streams the content resolved by [`Client::resolve_car_scoped`] as a CAR file.
+    #[tracing::instrument(skip(self, out, path_blocks))]
+    pub async fn get_car_scoped(
+        self,
+        out: Out,
+        path_blocks: Vec<OutRaw>,
+        scope: DagScope,
+        entity_bytes: Option<EntityBytes>,
+        start_time: std::time::Instant,
+    ) -> Result<axum::body::StreamBody<ReaderStream<tokio::io::DuplexStream>>, String> {
+        info!("get car {} (dag-scope={})", out.metadata().path, scope);
+        let (writer, reader) = tokio::io::duplex(1024 * 64);
+        let body = axum::body::StreamBody::new(ReaderStream::new(reader));
+        let client = self.clone();
+        tokio::task::spawn(access_log::in_current_request(async move {
+            if let Err(e) = fetch_car_scoped(
+                &client.resolver,
+                out,
+                path_blocks,
+                scope,
+                entity_bytes,
+                writer,
+                start_time,
+            )
+            .await
+            {
+                warn!("failed to load scoped car: {:?}", e);
+            }
+        }));
+
+        Ok(body)
+    }
+
+    /// Streams the DAG below the path as a tar archive, like `ipfs get`.
+    #[tracing::instrument(skip(self))]
+    pub async fn get_tar(
+        self,
+        path: iroh_resolver::resolver::Path,
+        start_time: std::time::Instant,
+    ) -> Result<axum::body::StreamBody<ReaderStream<tokio::io::DuplexStream>>, String> {
+        info!("get tar {}", path);
+        let (writer, reader) = tokio::io::duplex(1024 * 64);
+        let body = axum::body::StreamBody::new(ReaderStream::new(reader));
+        let client = self.clone();
+        tokio::task::spawn(access_log::in_current_request(async move {
+            if let Err(e) = fetch_tar(&client.resolver, path, writer, start_time).await {
+                warn!("failed to write tar archive: {:?}", e);
+            }
+        }));
 
         Ok(body)
     }
@@ -243,26 +472,364 @@ where
     T: ContentLoader,
     W: AsyncWrite + Send + Unpin,
 {
-    let stream = resolver.resolve_recursive_raw(path, Some(RECURSION_LIMIT));
+    // a deterministic order makes archives of the same root byte-identical
+    let stream = export_blocks(resolver, path, ExportOptions::default());
     tokio::pin!(stream);
 
     let root = stream
         .next()
         .await
         .ok_or_else(|| anyhow::anyhow!("root cid not found"))??;
+    access_log::record_source(root.source());
 
     let header = CarHeader::new_v1(vec![*root.cid()]);
     let mut writer = CarWriter::new(header, writer);
     writer.write(*root.cid(), root.content()).await?;
 
+    let mut count = 1;
     while let Some(block) = stream.next().await {
         let block = block?;
+        count += 1;
+        if count > RECURSION_LIMIT {
+            anyhow::bail!("Number of blocks exceeds the recursion limit.");
+        }
         record_ttfb_metrics(start_time, block.source());
         writer.write(*block.cid(), block.content()).await?;
     }
     Ok(())
 }
 
+async fn fetch_tar<T, W>(
+    resolver: &Resolver<T>,
+    path: iroh_resolver::resolver::Path,
+    writer: W,
+    start_time: std::time::Instant,
+) -> Result<(), anyhow::Error>
+where
+    T: ContentLoader + std::marker::Unpin,
+    W: AsyncWrite + Send + Unpin + 'static,
+{
+    let mut builder = tokio_tar::Builder::new(writer);
+    let res = write_tar_entries(resolver, path, &mut builder, start_time).await;
+    if res.is_err() {
+        // don't terminate the archive, the client must not mistake it for a complete one
+        builder.skip_termination();
+    }
+    res?;
+    builder.into_inner().await?;
+    Ok(())
+}
+
+async fn write_tar_entries<T, W>(
+    resolver: &Resolver<T>,
+    path: iroh_resolver::resolver::Path,
+    builder: &mut tokio_tar::Builder<W>,
+    start_time: std::time::Instant,
+) -> Result<(), anyhow::Error>
+where
+    T: ContentLoader + std::marker::Unpin,
+    W: AsyncWrite + Send + Unpin,
+{
+    // entries are named relative to the last segment of the requested path
+    let segments = path.tail().iter().filter(|s| !s.is_empty()).count();
+    let base = match path.tail().iter().rev().find(|s| !s.is_empty()) {
+        Some(name) => name.clone(),
+        None => path.root().to_string(),
+    };
+
+    let stream = resolver.resolve_recursive_with_paths(path);
+    tokio::pin!(stream);
+    let mut is_root = true;
+    while let Some(res) = stream.next().await {
+        let (entry_path, out) = res?;
+        record_ttfb_metrics(start_time, &out.metadata().source);
+        if is_root {
+            access_log::record_source(&out.metadata().source);
+            is_root = false;
+        }
+        let name = std::iter::once(base.as_str())
+            .chain(
+                entry_path
+                    .tail()
+                    .iter()
+                    .filter(|s| !s.is_empty())
+                    .skip(segments)
+                    .map(|s| s.as_str()),
+            )
+            .collect::<Vec<_>>()
+            .join("/");
+
+        let mut header = tokio_tar::Header::new_gnu();
+        if out.is_dir() {
+            header.set_entry_type(tokio_tar::EntryType::Directory);
+            header.set_mode(0o755);
+            header.set_size(0);
+            builder
+                .append_data(&mut header, &name, tokio::io::empty())
+                .await?;
+        } else if out.is_symlink() {
+            let mut reader = out.pretty(
+                resolver.clone(),
+                OutMetrics { start: start_time },
+                ResponseClip::NoClip,
+            )?;
+            let mut target = String::new();
+            reader.read_to_string(&mut target).await?;
+            header.set_entry_type(tokio_tar::EntryType::Symlink);
+            header.set_mode(0o777);
+            header.set_size(0);
+            header.set_link_name(&target)?;
+            builder
+                .append_data(&mut header, &name, tokio::io::empty())
+                .await?;
+        } else {
+            let size = out
+                .metadata()
+                .size
+                .ok_or_else(|| anyhow::anyhow!("unknown size of {}", name))?;
+            let reader = out.pretty(
+                resolver.clone(),
+                OutMetrics { start: start_time },
+                ResponseClip::NoClip,
+            )?;
+            header.set_entry_type(tokio_tar::EntryType::Regular);
+            header.set_mode(0o644);
+            header.set_size(size);
+            builder
+                .append_data(&mut header, &name, reader.take(size))
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Selects which blocks below the requested path are part of a CAR response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DagScope {
+    /// Only the blocks needed to verify the path, ending with the root block of the target.
+    Block,
+    /// The blocks needed to render the target: all blocks of a file, or a directory
+    /// including its HAMT shards, but not its children.
+    Entity,
+    /// The complete DAG below the target.
+    All,
+}
+
+impl DagScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DagScope::Block => "block",
+            DagScope::Entity => "entity",
+            DagScope::All => "all",
+        }
+    }
+}
+
+impl std::fmt::Display for DagScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for DagScope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "block" => Ok(DagScope::Block),
+            "entity" => Ok(DagScope::Entity),
+            "all" => Ok(DagScope::All),
+            _ => Err(format!("invalid dag-scope: {}", s)),
+        }
+    }
+}
+
+/// A byte range of a UnixFS file, as given by `entity-bytes=from:to`.
+///
+/// Both ends are inclusive, negative values count from the end of the file and
+/// `to` is `None` if the range was given as open ended (`from:*`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EntityBytes {
+    pub from: i64,
+    pub to: Option<i64>,
+}
+
+impl EntityBytes {
+    /// Resolves the range against a file of the given size, returning the exclusive
+    /// byte range. Returns `None` if the range does not cover any bytes of the file.
+    pub fn to_range(&self, size: u64) -> Option<Range<u64>> {
+        let start = if self.from < 0 {
+            size.saturating_sub(self.from.unsigned_abs())
+        } else {
+            self.from as u64
+        };
+        let end = match self.to {
+            None => size,
+            Some(to) if to < 0 => (size + 1).saturating_sub(to.unsigned_abs()),
+            Some(to) => std::cmp::min(to as u64 + 1, size),
+        };
+        if start >= end {
+            return None;
+        }
+        Some(start..end)
+    }
+}
+
+impl std::fmt::Display for EntityBytes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.to {
+            Some(to) => write!(f, "{}:{}", self.from, to),
+            None => write!(f, "{}:*", self.from),
+        }
+    }
+}
+
+impl FromStr for EntityBytes {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid entity-bytes: {}", s);
+        let (from, to) = s.split_once(':').ok_or_else(invalid)?;
+        let from: i64 = from.parse().map_err(|_| invalid())?;
+        let to = match to {
+            "*" => None,
+            to => Some(to.parse::<i64>().map_err(|_| invalid())?),
+        };
+        if let Some(to) = to {
+            if from >= 0 && to >= 0 && to < from {
+                return Err(invalid());
+            }
+        }
+        Ok(EntityBytes { from, to })
+    }
+}
+
+/// Writes a CAR file containing the blocks along `path` and the blocks of the target
+/// selected by `scope`.
+///
+/// Blocks are written in depth first order, starting with the path from the root, and
+/// each block is written only once. This way every block can be verified as soon as it
+/// is read, as it is always linked from a block that was written before.
+async fn fetch_car_scoped<T, W>(
+    resolver: &Resolver<T>,
+    out: Out,
+    path_blocks: Vec<OutRaw>,
+    scope: DagScope,
+    entity_bytes: Option<EntityBytes>,
+    writer: W,
+    start_time: std::time::Instant,
+) -> Result<(), anyhow::Error>
+where
+    T: ContentLoader,
+    W: AsyncWrite + Send + Unpin,
+{
+    access_log::record_source(&out.metadata().source);
+    let root = path_blocks
+        .first()
+        .ok_or_else(|| anyhow::anyhow!("root cid not found"))?;
+    let target = path_blocks.last().expect("not empty");
+
+    let header = CarHeader::new_v1(vec![*root.cid()]);
+    let mut writer = CarWriter::new(header, writer);
+    let mut seen = HashSet::new();
+    for block in &path_blocks {
+        record_ttfb_metrics(start_time, block.source());
+        if seen.insert(*block.cid()) {
+            writer.write(*block.cid(), block.content()).await?;
+        }
+    }
+
+    if scope != DagScope::Block {
+        let range = match (entity_bytes, out.metadata().size) {
+            (Some(entity_bytes), Some(size)) => Some(entity_bytes.to_range(size).unwrap_or(0..0)),
+            _ => None,
+        };
+        let mut stack = scoped_links(target, scope, range)?;
+        stack.reverse();
+        while let Some((cid, range)) = stack.pop() {
+            if seen.contains(&cid) {
+                continue;
+            }
+            if seen.len() >= RECURSION_LIMIT {
+                anyhow::bail!("Number of blocks exceeds the recursion limit.");
+            }
+            let block = resolver.load_raw(cid, out.context()).await?;
+            writer.write(cid, block.content()).await?;
+            seen.insert(cid);
+
+            let mut links = scoped_links(&block, scope, range)?;
+            links.reverse();
+            stack.extend(links);
+        }
+    }
+    writer.finish().await?;
+
+    Ok(())
+}
+
+/// Returns the links of the given block that are part of `scope`, in the order they
+/// appear in the block.
+///
+/// For UnixFS files, only links overlapping `range` are returned, together with the
+/// part of the range that falls into the linked block.
+fn scoped_links(
+    block: &OutRaw,
+    scope: DagScope,
+    range: Option<Range<u64>>,
+) -> Result<Vec<(Cid, Option<Range<u64>>)>, anyhow::Error> {
+    if scope == DagScope::Block {
+        return Ok(Vec::new());
+    }
+    let cid = block.cid();
+    let node = match Codec::try_from(cid.codec()) {
+        Ok(Codec::DagPb) => UnixfsNode::decode(cid, block.content().clone()).ok(),
+        Ok(Codec::Raw) => return Ok(Vec::new()),
+        _ => None,
+    };
+
+    match node {
+        Some(node @ UnixfsNode::File(_)) => {
+            let links = node.links_owned()?;
+            let blocksizes = node.blocksizes();
+            let range = match range {
+                Some(range) if blocksizes.len() == links.len() => range,
+                _ => return Ok(links.into_iter().map(|l| (l.cid, None)).collect()),
+            };
+            let mut offset = 0;
+            let mut out = Vec::new();
+            for (link, size) in links.into_iter().zip(blocksizes) {
+                let start = std::cmp::max(range.start, offset);
+                let end = std::cmp::min(range.end, offset + size);
+                if start < end {
+                    out.push((link.cid, Some(start - offset..end - offset)));
+                }
+                offset += size;
+            }
+            Ok(out)
+        }
+        Some(UnixfsNode::HamtShard(node, hamt)) if scope == DagScope::Entity => {
+            // only follow links to nested shards, their names consist of the prefix only
+            let padding_len = hamt.padding_len();
+            let links = node.links().collect::<Result<Vec<_>>>()?;
+            Ok(links
+                .into_iter()
+                .filter(|l| l.name.map(|n| n.len()) == Some(padding_len))
+                .map(|l| (l.cid, None))
+                .collect())
+        }
+        Some(node) if scope == DagScope::All => Ok(node
+            .links_owned()?
+            .into_iter()
+            .map(|l| (l.cid, None))
+            .collect()),
+        None if scope == DagScope::All => Ok(parse_links(cid, block.content())?
+            .into_iter()
+            .map(|l| (l, None))
+            .collect()),
+        _ => Ok(Vec::new()),
+    }
+}
+
 fn record_ttfb_metrics(start_time: std::time::Instant, source: &Source) {
     record!(
         GatewayMetrics::TimeToFetchFirstBlock,
@@ -294,3 +861,71 @@ pub(crate) fn sniff_content_type(body_sample: &[u8]) -> Mime {
         body_sample,
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dag_scope_from_str() {
+        assert_eq!("block".parse::<DagScope>(), Ok(DagScope::Block));
+        assert_eq!("entity".parse::<DagScope>(), Ok(DagScope::Entity));
+        assert_eq!("all".parse::<DagScope>(), Ok(DagScope::All));
+        assert!("Block".parse::<DagScope>().is_err());
+        assert!("".parse::<DagScope>().is_err());
+    }
+
+    #[test]
+    fn entity_bytes_from_str() {
+        let eb: EntityBytes = "0:10".parse().unwrap();
+        assert_eq!(
+            eb,
+            EntityBytes {
+                from: 0,
+                to: Some(10)
+            }
+        );
+        let eb: EntityBytes = "5:*".parse().unwrap();
+        assert_eq!(eb, EntityBytes { from: 5, to: None });
+        let eb: EntityBytes = "-10:-1".parse().unwrap();
+        assert_eq!(
+            eb,
+            EntityBytes {
+                from: -10,
+                to: Some(-1)
+            }
+        );
+
+        assert!("10:5".parse::<EntityBytes>().is_err());
+        assert!("10".parse::<EntityBytes>().is_err());
+        assert!("a:5".parse::<EntityBytes>().is_err());
+        assert!("0:b".parse::<EntityBytes>().is_err());
+    }
+
+    #[test]
+    fn entity_bytes_to_range() {
+        let eb = EntityBytes {
+            from: 0,
+            to: Some(9),
+        };
+        assert_eq!(eb.to_range(100), Some(0..10));
+        assert_eq!(eb.to_range(5), Some(0..5));
+
+        let eb = EntityBytes { from: 10, to: None };
+        assert_eq!(eb.to_range(100), Some(10..100));
+        assert_eq!(eb.to_range(10), None);
+
+        let eb = EntityBytes {
+            from: -10,
+            to: Some(-1),
+        };
+        assert_eq!(eb.to_range(100), Some(90..100));
+        assert_eq!(eb.to_range(5), Some(0..5));
+
+        let eb = EntityBytes {
+            from: 0,
+            to: Some(-101),
+        };
+        assert_eq!(eb.to_range(100), None);
+    }
+}
@@ -1,4 +1,7 @@
-use crate::constants::*;
+use crate::{
+    access_log::AccessLogConfig, auth::AuthConfig, bad_bits::DenylistSource, cache::CacheConfig,
+    constants::*, rate_limit::RateLimitConfig, tls::TlsConfig,
+};
 use anyhow::{bail, Result};
 use axum::http::{header::*, Method};
 use config::{ConfigError, Map, Source, Value};
@@ -10,6 +13,7 @@ use iroh_rpc_client::Config as RpcClientConfig;
 use iroh_rpc_types::{gateway::GatewayServerAddr, Addr};
 use iroh_util::insert_into_config_map;
 use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
 
 /// CONFIG_FILE_NAME is the name of the optional config file located in the iroh home directory
 pub const CONFIG_FILE_NAME: &str = "gateway.config.toml";
@@ -18,6 +22,8 @@ pub const CONFIG_FILE_NAME: &str = "gateway.config.toml";
 /// For example, `IROH_GATEWAY_PORT=1000` would set the value of the `Config.port` field
 pub const ENV_PREFIX: &str = "IROH_GATEWAY";
 pub const DEFAULT_PORT: u16 = 9050;
+/// Default port of the HTTP RPC API, the one kubo uses.
+pub const DEFAULT_HTTP_API_PORT: u16 = 5001;
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct Config {
@@ -28,6 +34,12 @@ pub struct Config {
     pub port: u16,
     /// flag to toggle whether the gateway should use denylist on requests
     pub use_denylist: bool,
+    /// flag to serve the kubo compatible HTTP RPC API at `/api/v0`
+    #[serde(default)]
+    pub http_api: bool,
+    /// address the HTTP RPC API listens on, it is not served on the gateway port
+    #[serde(default = "default_http_api_addr")]
+    pub http_api_addr: SocketAddr,
     /// URL of gateways to be used by the racing resolver.
     /// strings can either be urls or subdomain gateway roots
     /// values without https:// prefix are treated as subdomain gateways (eg: dweb.link)
@@ -37,6 +49,22 @@ pub struct Config {
     pub rpc_client: RpcClientConfig,
     /// metrics configuration
     pub metrics: MetricsConfig,
+    /// per client rate limits
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+    /// in-process response and metadata caches
+    #[serde(default)]
+    pub cache: CacheConfig,
+    /// access tokens and signed urls for private gateways
+    #[serde(default)]
+    pub auth: AuthConfig,
+    /// serve HTTPS with the given certificate instead of plain HTTP
+    pub tls: Option<TlsConfig>,
+    /// write a structured log of every request to a rotating file
+    pub access_log: Option<AccessLogConfig>,
+    /// sources the denylist is loaded from when `use_denylist` is set
+    #[serde(default = "default_denylist")]
+    pub denylist: Vec<DenylistSource>,
     // NOTE: for toml to serialize properly, the "table" values must be serialized at the end, and
     // so much come at the end of the `Config` struct
     /// set of user provided headers to attach to all responses
@@ -54,6 +82,14 @@ impl Config {
             http_resolvers: None,
             metrics: MetricsConfig::default(),
             use_denylist: false,
+            http_api: false,
+            http_api_addr: default_http_api_addr(),
+            denylist: default_denylist(),
+            rate_limit: RateLimitConfig::default(),
+            cache: CacheConfig::default(),
+            auth: AuthConfig::default(),
+            tls: None,
+            access_log: None,
         }
     }
 
@@ -82,6 +118,14 @@ impl Config {
     }
 }
 
+fn default_http_api_addr() -> SocketAddr {
+    SocketAddr::from(([127, 0, 0, 1], DEFAULT_HTTP_API_PORT))
+}
+
+fn default_denylist() -> Vec<DenylistSource> {
+    vec![DenylistSource::default_badbits()]
+}
+
 fn default_headers() -> HeaderMap {
     let mut headers = HeaderMap::new();
     headers.typed_insert(AccessControlAllowOrigin::ANY);
@@ -122,6 +166,14 @@ impl Default for Config {
             http_resolvers: None,
             metrics: MetricsConfig::default(),
             use_denylist: false,
+            http_api: false,
+            http_api_addr: default_http_api_addr(),
+            denylist: default_denylist(),
+            rate_limit: RateLimitConfig::default(),
+            cache: CacheConfig::default(),
+            auth: AuthConfig::default(),
+            tls: None,
+            access_log: None,
         };
         t.set_default_headers();
         t
@@ -138,6 +190,8 @@ impl Source for Config {
         let mut map: Map<String, Value> = Map::new();
         insert_into_config_map(&mut map, "public_url_base", self.public_url_base.clone());
         insert_into_config_map(&mut map, "use_denylist", self.use_denylist);
+        insert_into_config_map(&mut map, "http_api", self.http_api);
+        insert_into_config_map(&mut map, "http_api_addr", self.http_api_addr.to_string());
         // Some issue between deserializing u64 & u16, converting this to
         // an signed int fixes the issue
         insert_into_config_map(&mut map, "port", self.port as i32);
@@ -145,7 +199,17 @@ impl Source for Config {
         insert_into_config_map(&mut map, "rpc_client", rpc_client);
         let metrics = self.metrics.collect()?;
         insert_into_config_map(&mut map, "metrics", metrics);
+        insert_into_config_map(&mut map, "rate_limit", self.rate_limit.collect()?);
+        insert_into_config_map(&mut map, "cache", self.cache.collect()?);
+        insert_into_config_map(&mut map, "auth", self.auth.collect()?);
+        insert_into_config_map(&mut map, "denylist", collect_denylist(&self.denylist)?);
 
+        if let Some(tls) = &self.tls {
+            insert_into_config_map(&mut map, "tls", tls.collect()?);
+        }
+        if let Some(access_log) = &self.access_log {
+            insert_into_config_map(&mut map, "access_log", access_log.collect()?);
+        }
         if let Some(http_resolvers) = &self.http_resolvers {
             insert_into_config_map(&mut map, "http_resolvers", http_resolvers.clone());
         }
@@ -169,6 +233,41 @@ impl crate::handlers::StateConfig for Config {
     fn user_headers(&self) -> &HeaderMap<HeaderValue> {
         &self.headers
     }
+
+    fn rate_limit(&self) -> &RateLimitConfig {
+        &self.rate_limit
+    }
+
+    fn cache(&self) -> &CacheConfig {
+        &self.cache
+    }
+
+    fn http_api(&self) -> bool {
+        self.http_api
+    }
+
+    fn http_api_addr(&self) -> SocketAddr {
+        self.http_api_addr
+    }
+
+    fn auth(&self) -> &AuthConfig {
+        &self.auth
+    }
+
+    fn tls(&self) -> Option<&TlsConfig> {
+        self.tls.as_ref()
+    }
+
+    fn access_log(&self) -> Option<&AccessLogConfig> {
+        self.access_log.as_ref()
+    }
+}
+
+fn collect_denylist(denylist: &[DenylistSource]) -> Result<Vec<Value>, ConfigError> {
+    denylist
+        .iter()
+        .map(|source| source.collect().map(|map| Value::new(None, map)))
+        .collect()
 }
 
 fn collect_headers(headers: &HeaderMap) -> Result<Map<String, Value>, ConfigError> {
@@ -215,6 +314,11 @@ mod tests {
             "use_denylist".to_string(),
             Value::new(None, default.use_denylist),
         );
+        expect.insert("http_api".to_string(), Value::new(None, default.http_api));
+        expect.insert(
+            "http_api_addr".to_string(),
+            Value::new(None, default.http_api_addr.to_string()),
+        );
         expect.insert(
             "headers".to_string(),
             Value::new(None, collect_headers(&default.headers).unwrap()),
@@ -227,6 +331,22 @@ mod tests {
             "metrics".to_string(),
             Value::new(None, default.metrics.collect().unwrap()),
         );
+        expect.insert(
+            "rate_limit".to_string(),
+            Value::new(None, default.rate_limit.collect().unwrap()),
+        );
+        expect.insert(
+            "cache".to_string(),
+            Value::new(None, default.cache.collect().unwrap()),
+        );
+        expect.insert(
+            "auth".to_string(),
+            Value::new(None, default.auth.collect().unwrap()),
+        );
+        expect.insert(
+            "denylist".to_string(),
+            Value::new(None, collect_denylist(&default.denylist).unwrap()),
+        );
 
         let got = default.collect().unwrap();
         for key in got.keys() {
@@ -272,4 +392,91 @@ mod tests {
 
         assert_eq!(expect, got);
     }
+
+    #[test]
+    fn test_build_config_with_auth() {
+        let mut expect = Config::default();
+        expect.auth = AuthConfig {
+            required: true,
+            anonymous_local_only: false,
+            signing_key: Some("secret".into()),
+            tokens: vec![crate::auth::AccessToken {
+                token: "token".into(),
+                scopes: vec!["/ipns/docs.example.com".into()],
+            }],
+        };
+        let source = expect.clone();
+        let got: Config = ConfigBuilder::builder()
+            .add_source(source)
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+
+        assert_eq!(expect, got);
+    }
+
+    #[test]
+    fn test_build_config_with_tls() {
+        let mut expect = Config::default();
+        expect.tls = Some(TlsConfig {
+            cert_path: "/etc/iroh/cert.pem".into(),
+            key_path: "/etc/iroh/key.pem".into(),
+            reload_interval_secs: 30,
+            redirect_http_port: Some(80),
+        });
+        let source = expect.clone();
+        let got: Config = ConfigBuilder::builder()
+            .add_source(source)
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+
+        assert_eq!(expect, got);
+    }
+
+    #[test]
+    fn test_build_config_with_access_log() {
+        let mut expect = Config::default();
+        expect.access_log = Some(AccessLogConfig {
+            directory: "/var/log/iroh".into(),
+            file_name: "gateway.log".into(),
+            format: crate::access_log::AccessLogFormat::Combined,
+            rotation: crate::access_log::AccessLogRotation::Hourly,
+        });
+        let source = expect.clone();
+        let got: Config = ConfigBuilder::builder()
+            .add_source(source)
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+
+        assert_eq!(expect, got);
+    }
+
+    #[test]
+    fn test_build_config_with_denylist() {
+        let mut expect = Config::default();
+        expect.denylist = vec![
+            DenylistSource::default_badbits(),
+            DenylistSource::File {
+                path: "/etc/iroh/denylist.txt".into(),
+                format: crate::bad_bits::DenylistFormat::Lines,
+            },
+            DenylistSource::Inline {
+                entries: vec!["bafkreidyeivj7adnnac6ljvzj2e3rd5xdw3revw4da7mx2ckrstapoupoq".into()],
+            },
+        ];
+        let source = expect.clone();
+        let got: Config = ConfigBuilder::builder()
+            .add_source(source)
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+
+        assert_eq!(expect, got);
+    }
 }
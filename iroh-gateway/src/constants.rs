@@ -1,4 +1,5 @@
 use axum::http::{header::HeaderName, HeaderValue};
+use std::time::Duration;
 
 // Headers
 pub static HEADER_X_IPFS_PATH: HeaderName = HeaderName::from_static("x-ipfs-path");
@@ -10,6 +11,9 @@ pub static HEADER_X_IPFS_GATEWAY_PREFIX: HeaderName =
 pub static HEADER_X_IPFS_ROOTS: HeaderName = HeaderName::from_static("x-ipfs-roots");
 pub static HEADER_SERVICE_WORKER: HeaderName = HeaderName::from_static("service-worker");
 pub static HEADER_CACHE_CONTROL: HeaderName = HeaderName::from_static("cache-control");
+pub static HEADER_X_API_KEY: HeaderName = HeaderName::from_static("x-api-key");
+pub static HEADER_X_STREAM_OUTPUT: HeaderName = HeaderName::from_static("x-stream-output");
+pub static HEADER_X_CHUNKED_OUTPUT: HeaderName = HeaderName::from_static("x-chunked-output");
 
 // Common Header Values
 pub static VALUE_XCTO_NOSNIFF: HeaderValue = HeaderValue::from_static("nosniff");
@@ -26,6 +30,11 @@ pub static CONTENT_TYPE_IPLD_RAW: HeaderValue =
     HeaderValue::from_static("application/vnd.ipld.raw");
 pub static CONTENT_TYPE_IPLD_CAR: HeaderValue =
     HeaderValue::from_static("application/vnd.ipld.car; version=1");
+pub static CONTENT_TYPE_JSON: HeaderValue = HeaderValue::from_static("application/json");
+
+// Content types of the parts of a multipart upload to the HTTP RPC API
+pub const CONTENT_TYPE_X_DIRECTORY: &str = "application/x-directory";
+pub const CONTENT_TYPE_SYMLINK: &str = "application/symlink";
 
 // Schemes
 pub static SCHEME_IPFS: &str = "ipfs";
@@ -34,3 +43,34 @@ pub static SCHEME_IPNS: &str = "ipns";
 // Max number of links to return in a single recursive request.
 // TODO: Make configurable.
 pub static RECURSION_LIMIT: usize = 4096;
+
+// Default and max number of entries in a single page of a JSON directory listing.
+pub static DIR_LIST_DEFAULT_LIMIT: usize = 1000;
+pub static DIR_LIST_MAX_LIMIT: usize = 10_000;
+
+// Number of directory entries resolved concurrently for a JSON directory listing.
+pub static DIR_LIST_CONCURRENCY: usize = 16;
+
+// Max number of ranges accepted in a single `Range` header.
+pub static MAX_RANGES: usize = 32;
+
+// Size of the chunks file bodies are streamed in.
+pub static BODY_CHUNK_SIZE: usize = 4096;
+
+// Length of the random boundary separating the parts of a multipart response.
+pub static MULTIPART_BOUNDARY_LEN: usize = 24;
+
+// Minimum time between two denylist reloads triggered through the admin endpoint.
+pub const DENYLIST_MIN_RELOAD_INTERVAL: Duration = Duration::from_secs(60);
+
+// Maximum number of tracked rate limit buckets, the least recently used are dropped beyond it.
+pub static RATE_LIMIT_MAX_CLIENTS: usize = 100_000;
+
+// Time a client has to complete the TLS handshake.
+pub const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Maximum number of TLS handshakes in flight, no new connections are accepted beyond it.
+pub static MAX_TLS_HANDSHAKES: usize = 1024;
+
+// Retry-After sent when the recursive car concurrency limit is reached.
+pub const CONCURRENCY_LIMIT_RETRY_AFTER: Duration = Duration::from_secs(5);
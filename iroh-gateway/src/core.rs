@@ -1,17 +1,22 @@
 use axum::Router;
+use hyper::server::conn::AddrIncoming;
 use iroh_resolver::resolver::ContentLoader;
+use iroh_rpc_client::Client as RpcClient;
 use iroh_rpc_types::gateway::GatewayServerAddr;
 
-use std::{collections::HashMap, sync::Arc};
-use tokio::sync::RwLock;
+use std::{collections::HashMap, net::SocketAddr, sync::Arc};
+use tokio::{sync::RwLock, task::JoinHandle};
 
 use crate::{
+    access_log::AccessLog,
     bad_bits::BadBits,
+    cache::ResponseCache,
     client::Client,
-    handlers::{get_app_routes, StateConfig},
+    handlers::{get_api_app_routes, get_app_routes, StateConfig},
+    rate_limit::RateLimiter,
     rpc,
     rpc::Gateway,
-    templates,
+    templates, tls,
 };
 
 #[derive(Debug, Clone)]
@@ -25,6 +30,33 @@ pub struct State<T: ContentLoader> {
     pub client: Client<T>,
     pub handlebars: HashMap<String, String>,
     pub bad_bits: Arc<Option<RwLock<BadBits>>>,
+    pub rate_limiter: RateLimiter,
+    pub response_cache: Option<Arc<ResponseCache>>,
+    /// rpc client used by the HTTP RPC API, only set if the API is enabled
+    pub rpc_client: Option<RpcClient>,
+    pub access_log: Option<AccessLog>,
+    /// state that serves content from the local store only, shared by all anonymous
+    /// requests to a private gateway
+    local_only: Option<Arc<State<T>>>,
+}
+
+impl<T: ContentLoader + std::marker::Unpin> State<T> {
+    /// Wraps the state, building the local-only variant once up front.
+    fn into_shared(self) -> Arc<Self> {
+        let local_only = State {
+            client: self.client.local_only(),
+            ..self.clone()
+        };
+        Arc::new(State {
+            local_only: Some(Arc::new(local_only)),
+            ..self
+        })
+    }
+
+    /// Returns the state that serves content from the local store only.
+    pub fn local_only(self: &Arc<Self>) -> Arc<Self> {
+        self.local_only.clone().unwrap_or_else(|| self.clone())
+    }
 }
 
 impl<T: ContentLoader + std::marker::Unpin> Core<T> {
@@ -48,15 +80,25 @@ impl<T: ContentLoader + std::marker::Unpin> Core<T> {
             "not_found".to_string(),
             templates::NOT_FOUND_TEMPLATE.to_string(),
         );
-        let client = Client::<T>::new(&content_loader);
+        let client = Client::<T>::new(&content_loader, config.cache());
+        let rate_limiter = RateLimiter::new(config.rate_limit().clone());
+        let response_cache = make_response_cache(config.as_ref());
+        let rpc_client = make_rpc_client(config.as_ref()).await?;
+        let access_log = config.access_log().map(AccessLog::new).transpose()?;
 
         Ok(Self {
-            state: Arc::new(State {
+            state: State {
                 config,
                 client,
                 handlebars: templates,
                 bad_bits,
-            }),
+                rate_limiter,
+                response_cache,
+                rpc_client,
+                access_log,
+                local_only: None,
+            }
+            .into_shared(),
         })
     }
 
@@ -86,19 +128,31 @@ impl<T: ContentLoader + std::marker::Unpin> Core<T> {
             "not_found".to_string(),
             templates::NOT_FOUND_TEMPLATE.to_string(),
         );
-        let client = Client::new(&content_loader);
-        Ok(Arc::new(State {
+        let client = Client::new(&content_loader, config.cache());
+        let rate_limiter = RateLimiter::new(config.rate_limit().clone());
+        let response_cache = make_response_cache(config.as_ref());
+        let rpc_client = make_rpc_client(config.as_ref()).await?;
+        let access_log = config.access_log().map(AccessLog::new).transpose()?;
+        Ok(State {
             config,
             client,
             handlebars: templates,
             bad_bits,
-        }))
+            rate_limiter,
+            response_cache,
+            rpc_client,
+            access_log,
+            local_only: None,
+        }
+        .into_shared())
     }
 
     pub fn server(
         self,
-    ) -> axum::Server<hyper::server::conn::AddrIncoming, axum::routing::IntoMakeService<Router>>
-    {
+    ) -> axum::Server<
+        hyper::server::conn::AddrIncoming,
+        axum::extract::connect_info::IntoMakeServiceWithConnectInfo<Router, SocketAddr>,
+    > {
         let app = get_app_routes(&self.state);
 
         // todo(arqu): make configurable
@@ -107,10 +161,93 @@ impl<T: ContentLoader + std::marker::Unpin> Core<T> {
         axum::Server::bind(&addr.parse().unwrap())
             .http1_preserve_header_case(true)
             .http1_title_case_headers(true)
-            .serve(app.into_make_service())
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+    }
+
+    /// Serves the HTTP RPC API on its own listener, bound to `http_api_addr`.
+    ///
+    /// Returns `None` if the API is disabled. The API can modify the store, so it is never
+    /// served on the gateway port.
+    pub fn spawn_api_server(&self) -> anyhow::Result<Option<(SocketAddr, JoinHandle<()>)>> {
+        if !self.state.config.http_api() {
+            return Ok(None);
+        }
+        let app = get_api_app_routes(&self.state);
+        let server = axum::Server::try_bind(&self.state.config.http_api_addr())?
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>());
+        let addr = server.local_addr();
+        let task = tokio::spawn(async move {
+            if let Err(err) = server.await {
+                tracing::error!("http api server failed: {}", err);
+            }
+        });
+        Ok(Some((addr, task)))
+    }
+
+    /// Serves the gateway over HTTPS if TLS is configured, and over plain HTTP otherwise.
+    ///
+    /// Returns the address the gateway listens on and the task serving it, which also
+    /// serves the redirects from HTTP to HTTPS if configured.
+    pub fn spawn_server(self) -> anyhow::Result<(SocketAddr, JoinHandle<()>)> {
+        let tls = match self.state.config.tls().cloned() {
+            Some(tls) => tls,
+            None => {
+                let server = self.server();
+                let addr = server.local_addr();
+                let task = tokio::spawn(async move {
+                    if let Err(err) = server.await {
+                        tracing::error!("gateway server failed: {}", err);
+                    }
+                });
+                return Ok((addr, task));
+            }
+        };
+
+        let addr: SocketAddr = format!("0.0.0.0:{}", self.state.config.port()).parse()?;
+        let incoming = tls::TlsIncoming::new(AddrIncoming::bind(&addr)?, tls::server_config(&tls)?);
+        let addr = incoming.local_addr();
+        let app = get_app_routes(&self.state);
+        let server = axum::Server::builder(incoming)
+            .http1_preserve_header_case(true)
+            .http1_title_case_headers(true)
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>());
+        let redirect = match tls.redirect_http_port {
+            Some(port) => {
+                let redirect_addr: SocketAddr = format!("0.0.0.0:{}", port).parse()?;
+                Some(
+                    axum::Server::try_bind(&redirect_addr)?
+                        .serve(tls::get_redirect_routes(addr.port()).into_make_service()),
+                )
+            }
+            None => None,
+        };
+        let task = tokio::spawn(async move {
+            let res = match redirect {
+                Some(redirect) => tokio::try_join!(server, redirect).map(|_| ()),
+                None => server.await,
+            };
+            if let Err(err) = res {
+                tracing::error!("gateway server failed: {}", err);
+            }
+        });
+        Ok((addr, task))
     }
 }
 
+fn make_response_cache(config: &dyn StateConfig) -> Option<Arc<ResponseCache>> {
+    match config.cache().max_response_bytes {
+        0 => None,
+        max => Some(Arc::new(ResponseCache::new(max as usize))),
+    }
+}
+
+async fn make_rpc_client(config: &dyn StateConfig) -> anyhow::Result<Option<RpcClient>> {
+    if !config.http_api() {
+        return Ok(None);
+    }
+    Ok(Some(RpcClient::new(config.rpc_client().clone()).await?))
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashSet;
@@ -119,12 +256,15 @@ mod tests {
     use super::*;
     use cid::Cid;
     use futures::{StreamExt, TryStreamExt};
+    use http::header::{CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE};
+    use iroh_resolver::resolver::{ContextId, LoadedCid, LoaderContext, Source};
     use iroh_resolver::unixfs::UnixfsNode;
     use iroh_resolver::unixfs_builder::{DirectoryBuilder, FileBuilder};
     use iroh_rpc_client::Client as RpcClient;
     use iroh_rpc_client::Config as RpcClientConfig;
     use iroh_rpc_types::store::StoreClientAddr;
     use iroh_rpc_types::Addr;
+    use libipld::{prelude::Codec as _, Ipld, IpldCodec};
     use std::io;
     use tokio_util::io::StreamReader;
 
@@ -191,6 +331,51 @@ mod tests {
         core_task.await.unwrap_err();
     }
 
+    #[tokio::test]
+    async fn gateway_tls() {
+        let mut config = Config::new(
+            0,
+            RpcClientConfig {
+                gateway_addr: None,
+                p2p_addr: None,
+                store_addr: None,
+                channels: Some(1),
+            },
+        );
+        config.set_default_headers();
+        config.tls = Some(crate::tls::TlsConfig {
+            cert_path: "./fixtures/tls/cert.pem".into(),
+            key_path: "./fixtures/tls/key.pem".into(),
+            reload_interval_secs: 60,
+            redirect_http_port: None,
+        });
+
+        let rpc_addr = "grpc://0.0.0.0:0".parse().unwrap();
+        let rpc_client = RpcClient::new(config.rpc_client().clone()).await.unwrap();
+        let core = Core::new(Arc::new(config), rpc_addr, Arc::new(None), rpc_client)
+            .await
+            .unwrap();
+        let (addr, core_task) = core.spawn_server().unwrap();
+
+        let ca = std::fs::read("./fixtures/tls/ca.pem").unwrap();
+        let client = reqwest::Client::builder()
+            .add_root_certificate(reqwest::Certificate::from_pem(&ca).unwrap())
+            .build()
+            .unwrap();
+        let res = client
+            .get(format!("https://localhost:{}/health", addr.port()))
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(http::StatusCode::OK, res.status());
+        // negotiated through ALPN
+        assert_eq!(http::Version::HTTP_2, res.version());
+        assert_eq!("OK", res.text().await.unwrap());
+        core_task.abort();
+        core_task.await.unwrap_err();
+    }
+
     #[tokio::test]
     async fn fetch_car_recursive() {
         let (store_client_addr, store_task) = spawn_store().await;
@@ -298,4 +483,838 @@ mod tests {
         store_task.abort();
         store_task.await.unwrap_err();
     }
+
+    #[tokio::test]
+    async fn fetch_car_scoped() {
+        let (store_client_addr, store_task) = spawn_store().await;
+        let mut config = Config::new(
+            0,
+            RpcClientConfig {
+                gateway_addr: None,
+                p2p_addr: None,
+                store_addr: Some(store_client_addr),
+                channels: Some(1),
+            },
+        );
+        config.set_default_headers();
+
+        let (addr, rpc_client, core_task) = spawn_gateway(Arc::new(config)).await;
+
+        // add a directory with a file that is split into four chunks.
+        let (root_cid, file_cid, chunk_cids) = {
+            let store = rpc_client.try_store().unwrap();
+            let mut cids = vec![];
+            let mut file = FileBuilder::new();
+            file.name("big.txt")
+                .chunk_size(4)
+                .content_bytes(b"0123456789abcdef".to_vec());
+            let mut dir_builder = DirectoryBuilder::new();
+            dir_builder
+                .name("demo")
+                .add_file(file.build().await.unwrap());
+
+            let root_dir = dir_builder.build().unwrap();
+            let mut parts = root_dir.encode();
+            while let Some(part) = parts.next().await {
+                let (cid, bytes, links) = part.unwrap().into_parts();
+                cids.push(cid);
+                store.put(cid, bytes, links).await.unwrap();
+            }
+            // leaves first, then the file root and the directory
+            (cids[5], cids[4], cids[..4].to_vec())
+        };
+
+        let get_car = |query: String| async move {
+            let client = hyper::Client::new();
+            let uri = hyper::Uri::builder()
+                .scheme("http")
+                .authority(format!("localhost:{}", addr.port()))
+                .path_and_query(format!("/ipfs/{}/big.txt?{}", root_cid, query))
+                .build()
+                .unwrap();
+            let res = client.get(uri).await.unwrap();
+            assert_eq!(http::StatusCode::OK, res.status());
+            let body = StreamReader::new(
+                res.into_body()
+                    .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string())),
+            );
+            let car_reader = iroh_car::CarReader::new(body).await.unwrap();
+            assert_eq!(car_reader.header().roots(), &[root_cid]);
+            car_reader
+                .stream()
+                .map(|res| res.unwrap().0)
+                .collect::<Vec<_>>()
+                .await
+        };
+
+        let cids = get_car("format=car&dag-scope=block".into()).await;
+        assert_eq!(cids, vec![root_cid, file_cid]);
+
+        let cids = get_car("format=car&dag-scope=entity".into()).await;
+        let mut expected = vec![root_cid, file_cid];
+        expected.extend_from_slice(&chunk_cids);
+        assert_eq!(cids, expected);
+
+        let cids = get_car("format=car&dag-scope=entity&entity-bytes=5:8".into()).await;
+        assert_eq!(cids, vec![root_cid, file_cid, chunk_cids[1], chunk_cids[2]]);
+
+        let cids = get_car("format=car&entity-bytes=-2:*".into()).await;
+        assert_eq!(cids, vec![root_cid, file_cid, chunk_cids[3]]);
+
+        // the etag is that of the file the path resolves to, not of the root
+        let get_status = |query: &str, etag: String| {
+            let uri = hyper::Uri::builder()
+                .scheme("http")
+                .authority(format!("localhost:{}", addr.port()))
+                .path_and_query(format!("/ipfs/{}/big.txt?{}", root_cid, query))
+                .build()
+                .unwrap();
+            let req = hyper::Request::get(uri)
+                .header("If-None-Match", etag)
+                .body(hyper::Body::empty())
+                .unwrap();
+            async move {
+                let res = hyper::Client::new().request(req).await.unwrap();
+                let etag = res.headers().get("etag").cloned();
+                (res.status(), etag)
+            }
+        };
+        let (status, etag) = get_status(
+            "format=car&dag-scope=entity",
+            format!("W/\"{}.car.entity\"", root_cid),
+        )
+        .await;
+        assert_eq!(http::StatusCode::OK, status);
+        assert_eq!(
+            etag.unwrap(),
+            format!("W/\"{}.car.entity\"", file_cid).as_str()
+        );
+        let (status, _) = get_status(
+            "format=car&dag-scope=entity",
+            format!("W/\"{}.car.entity\"", file_cid),
+        )
+        .await;
+        assert_eq!(http::StatusCode::NOT_MODIFIED, status);
+        let (status, _) = get_status(
+            "format=car&dag-scope=entity&entity-bytes=0:3",
+            format!("W/\"{}.car.entity\"", file_cid),
+        )
+        .await;
+        assert_eq!(http::StatusCode::OK, status);
+
+        core_task.abort();
+        core_task.await.unwrap_err();
+        store_task.abort();
+        store_task.await.unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn fetch_ranges() {
+        let (store_client_addr, store_task) = spawn_store().await;
+        let mut config = Config::new(
+            0,
+            RpcClientConfig {
+                gateway_addr: None,
+                p2p_addr: None,
+                store_addr: Some(store_client_addr),
+                channels: Some(1),
+            },
+        );
+        config.set_default_headers();
+
+        let (addr, rpc_client, core_task) = spawn_gateway(Arc::new(config)).await;
+
+        let root_cid = {
+            let store = rpc_client.try_store().unwrap();
+            let mut file = FileBuilder::new();
+            file.name("range.txt")
+                .chunk_size(4)
+                .content_bytes(b"0123456789abcdef".to_vec());
+            let mut dir_builder = DirectoryBuilder::new();
+            dir_builder
+                .name("demo")
+                .add_file(file.build().await.unwrap());
+            let root_dir = dir_builder.build().unwrap();
+            let mut parts = root_dir.encode();
+            let mut root_cid = None;
+            while let Some(part) = parts.next().await {
+                let (cid, bytes, links) = part.unwrap().into_parts();
+                root_cid = Some(cid);
+                store.put(cid, bytes, links).await.unwrap();
+            }
+            root_cid.unwrap()
+        };
+
+        let get_range = |range: &'static str| async move {
+            let client = hyper::Client::new();
+            let uri = hyper::Uri::builder()
+                .scheme("http")
+                .authority(format!("localhost:{}", addr.port()))
+                .path_and_query(format!("/ipfs/{}/range.txt", root_cid))
+                .build()
+                .unwrap();
+            let req = hyper::Request::builder()
+                .method("GET")
+                .header("range", range)
+                .uri(uri)
+                .body(hyper::Body::empty())
+                .unwrap();
+            let res = client.request(req).await.unwrap();
+            let status = res.status();
+            let headers = res.headers().clone();
+            let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            (status, headers, body)
+        };
+
+        let (status, headers, body) = get_range("bytes=2-5").await;
+        assert_eq!(http::StatusCode::PARTIAL_CONTENT, status);
+        assert_eq!(headers.get(CONTENT_RANGE).unwrap(), "bytes 2-5/16");
+        assert_eq!(&body[..], b"2345");
+
+        let (status, headers, body) = get_range("bytes=-3").await;
+        assert_eq!(http::StatusCode::PARTIAL_CONTENT, status);
+        assert_eq!(headers.get(CONTENT_RANGE).unwrap(), "bytes 13-15/16");
+        assert_eq!(&body[..], b"def");
+
+        let (status, headers, body) = get_range("bytes=1-2,10-").await;
+        assert_eq!(http::StatusCode::PARTIAL_CONTENT, status);
+        let content_type = headers.get(CONTENT_TYPE).unwrap().to_str().unwrap();
+        let boundary = content_type
+            .strip_prefix("multipart/byteranges; boundary=")
+            .unwrap();
+        assert_eq!(
+            headers.get(CONTENT_LENGTH).unwrap().to_str().unwrap(),
+            body.len().to_string()
+        );
+        let expected = format!(
+            "--{b}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Range: bytes 1-2/16\r\n\r\n12\
+             \r\n--{b}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Range: bytes 10-15/16\r\n\r\nabcdef\
+             \r\n--{b}--\r\n",
+            b = boundary
+        );
+        assert_eq!(std::str::from_utf8(&body).unwrap(), expected);
+
+        let (status, headers, _body) = get_range("bytes=20-30").await;
+        assert_eq!(http::StatusCode::RANGE_NOT_SATISFIABLE, status);
+        assert_eq!(headers.get(CONTENT_RANGE).unwrap(), "bytes */16");
+
+        let (status, _headers, _body) = get_range("bytes=5-1").await;
+        assert_eq!(http::StatusCode::RANGE_NOT_SATISFIABLE, status);
+
+        core_task.abort();
+        core_task.await.unwrap_err();
+        store_task.abort();
+        store_task.await.unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn fetch_dir_json() {
+        let (store_client_addr, store_task) = spawn_store().await;
+        let mut config = Config::new(
+            0,
+            RpcClientConfig {
+                gateway_addr: None,
+                p2p_addr: None,
+                store_addr: Some(store_client_addr),
+                channels: Some(1),
+            },
+        );
+        config.set_default_headers();
+
+        let (addr, rpc_client, core_task) = spawn_gateway(Arc::new(config)).await;
+
+        // add a directory with two files and a sub directory to the store.
+        let root_cid = {
+            let store = rpc_client.try_store().unwrap();
+            let mut cids = vec![];
+            let mut dir_builder = DirectoryBuilder::new();
+            dir_builder.name("demo");
+            for (name, content) in [("hello.txt", "ola"), ("world.txt", "mundo")] {
+                let mut file = FileBuilder::new();
+                file.name(name).content_bytes(content.as_bytes().to_vec());
+                dir_builder.add_file(file.build().await.unwrap());
+            }
+            let mut sub_builder = DirectoryBuilder::new();
+            sub_builder.name("sub");
+            dir_builder.add_dir(sub_builder.build().unwrap()).unwrap();
+
+            let root_dir = dir_builder.build().unwrap();
+            let mut parts = root_dir.encode();
+            while let Some(part) = parts.next().await {
+                let (cid, bytes, links) = part.unwrap().into_parts();
+                cids.push(cid);
+                store.put(cid, bytes, links).await.unwrap();
+            }
+            *cids.last().unwrap()
+        };
+
+        let get_json = |query: String, accept: Option<&'static str>| {
+            let client = hyper::Client::new();
+            let uri = hyper::Uri::builder()
+                .scheme("http")
+                .authority(format!("localhost:{}", addr.port()))
+                .path_and_query(format!("/ipfs/{}{}", root_cid, query))
+                .build()
+                .unwrap();
+            let mut req = hyper::Request::builder().method("GET").uri(uri);
+            if let Some(accept) = accept {
+                req = req.header("accept", accept);
+            }
+            async move {
+                let res = client
+                    .request(req.body(hyper::Body::empty()).unwrap())
+                    .await
+                    .unwrap();
+                assert_eq!(http::StatusCode::OK, res.status());
+                assert_eq!(res.headers().get(CONTENT_TYPE).unwrap(), "application/json");
+                let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+                serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+            }
+        };
+
+        // first page
+        let list = get_json("?format=json&limit=2".to_string(), None).await;
+        assert_eq!(list["cid"], root_cid.to_string());
+        assert_eq!(list["offset"], 0);
+        assert_eq!(list["next_offset"], 2);
+        let entries = list["entries"].as_array().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["name"], "hello.txt");
+        assert_eq!(entries[0]["type"], "file");
+        assert_eq!(entries[0]["size"], 3);
+        assert_eq!(entries[1]["name"], "world.txt");
+        assert_eq!(entries[1]["type"], "file");
+        assert_eq!(entries[1]["size"], 5);
+
+        // last page, requested through the accept header
+        let list = get_json("?offset=2&limit=2".to_string(), Some("application/json")).await;
+        assert_eq!(list["offset"], 2);
+        assert!(list["next_offset"].is_null());
+        let entries = list["entries"].as_array().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0]["name"], "sub");
+        assert_eq!(entries[0]["type"], "directory");
+        assert!(entries[0].get("mode").is_none());
+        assert!(entries[0].get("mtime").is_none());
+
+        core_task.abort();
+        core_task.await.unwrap_err();
+        store_task.abort();
+        store_task.await.unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn private_gateway() {
+        let (store_client_addr, store_task) = spawn_store().await;
+        let mut config = Config::new(
+            0,
+            RpcClientConfig {
+                gateway_addr: None,
+                p2p_addr: None,
+                store_addr: Some(store_client_addr),
+                channels: Some(1),
+            },
+        );
+        config.set_default_headers();
+
+        let (root_cid, missing_cid) = {
+            let rpc_client = RpcClient::new(config.rpc_client().clone()).await.unwrap();
+            let store = rpc_client.try_store().unwrap();
+            let mut file = FileBuilder::new();
+            file.name("hello.txt").content_bytes(b"ola".to_vec());
+            let mut dir_builder = DirectoryBuilder::new();
+            dir_builder
+                .name("demo")
+                .add_file(file.build().await.unwrap());
+            let mut parts = dir_builder.build().unwrap().encode();
+            let mut root_cid = None;
+            while let Some(part) = parts.next().await {
+                let (cid, bytes, links) = part.unwrap().into_parts();
+                root_cid = Some(cid);
+                store.put(cid, bytes, links).await.unwrap();
+            }
+            let missing_cid: Cid = "bafkreidyeivj7adnnac6ljvzj2e3rd5xdw3revw4da7mx2ckrstapoupoq"
+                .parse()
+                .unwrap();
+            (root_cid.unwrap(), missing_cid)
+        };
+
+        config.auth = crate::auth::AuthConfig {
+            required: true,
+            anonymous_local_only: false,
+            signing_key: Some("secret".to_string()),
+            tokens: vec![crate::auth::AccessToken {
+                token: "reader".to_string(),
+                scopes: vec![root_cid.to_string()],
+            }],
+        };
+        let (addr, _rpc_client, core_task) = spawn_gateway(Arc::new(config.clone())).await;
+
+        let get = |addr: SocketAddr, path_and_query: String, token: Option<&'static str>| async move {
+            let client = hyper::Client::new();
+            let uri = hyper::Uri::builder()
+                .scheme("http")
+                .authority(format!("localhost:{}", addr.port()))
+                .path_and_query(path_and_query)
+                .build()
+                .unwrap();
+            let mut req = hyper::Request::builder().method("GET").uri(uri);
+            if let Some(token) = token {
+                req = req.header("authorization", format!("Bearer {}", token));
+            }
+            let res = client
+                .request(req.body(hyper::Body::empty()).unwrap())
+                .await
+                .unwrap();
+            (res.status(), res.headers().clone())
+        };
+        let file_path = format!("/ipfs/{}/hello.txt", root_cid);
+
+        // health checks stay public
+        let (status, _) = get(addr, "/health".to_string(), None).await;
+        assert_eq!(http::StatusCode::OK, status);
+
+        let (status, headers) = get(addr, file_path.clone(), None).await;
+        assert_eq!(http::StatusCode::UNAUTHORIZED, status);
+        assert_eq!(headers.get("www-authenticate").unwrap(), "Bearer");
+        let (status, _) = get(addr, file_path.clone(), Some("unknown")).await;
+        assert_eq!(http::StatusCode::UNAUTHORIZED, status);
+        let (status, _) = get(addr, file_path.clone(), Some("reader")).await;
+        assert_eq!(http::StatusCode::OK, status);
+        let (status, _) = get(addr, format!("/ipfs/{}", missing_cid), Some("reader")).await;
+        assert_eq!(http::StatusCode::FORBIDDEN, status);
+        let (status, _) = get(addr, "/admin/denylist".to_string(), Some("reader")).await;
+        assert_eq!(http::StatusCode::FORBIDDEN, status);
+
+        // signed urls
+        let expires = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            + 60;
+        let query = crate::auth::signed_query("secret", &file_path, expires);
+        let (status, _) = get(addr, format!("{}?{}", file_path, query), None).await;
+        assert_eq!(http::StatusCode::OK, status);
+        let query = crate::auth::signed_query("secret", &file_path, expires - 120);
+        let (status, _) = get(addr, format!("{}?{}", file_path, query), None).await;
+        assert_eq!(http::StatusCode::UNAUTHORIZED, status);
+        core_task.abort();
+        core_task.await.unwrap_err();
+
+        // anonymous requests are only served from the local store
+        config.auth.required = false;
+        config.auth.anonymous_local_only = true;
+        let (addr, _rpc_client, core_task) = spawn_gateway(Arc::new(config)).await;
+        let (status, _) = get(addr, file_path.clone(), None).await;
+        assert_eq!(http::StatusCode::OK, status);
+        let (status, _) = get(addr, format!("/ipfs/{}", missing_cid), None).await;
+        assert_eq!(http::StatusCode::PRECONDITION_FAILED, status);
+        let (status, _) = get(addr, "/admin/denylist".to_string(), None).await;
+        assert_eq!(http::StatusCode::UNAUTHORIZED, status);
+
+        core_task.abort();
+        core_task.await.unwrap_err();
+        store_task.abort();
+        store_task.await.unwrap_err();
+    }
+
+    /// Serves blocks from the store and records the blocks it would fetch from the network.
+    #[derive(Debug, Clone)]
+    struct RecordingLoader {
+        client: RpcClient,
+        fetched: Arc<std::sync::Mutex<Vec<Cid>>>,
+    }
+
+    #[async_trait::async_trait]
+    impl ContentLoader for RecordingLoader {
+        async fn load_cid(&self, cid: &Cid, ctx: &LoaderContext) -> anyhow::Result<LoadedCid> {
+            if let Some(data) = self.client.try_store()?.get(*cid).await? {
+                return Ok(LoadedCid {
+                    data,
+                    source: Source::Store("test"),
+                });
+            }
+            anyhow::ensure!(!ctx.local_only(), "{} is not available locally", cid);
+            self.fetched.lock().unwrap().push(*cid);
+            anyhow::bail!("{} not found on the network", cid)
+        }
+
+        async fn stop_session(&self, _ctx: ContextId) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        async fn has_cid(&self, cid: &Cid) -> anyhow::Result<bool> {
+            self.client.try_store()?.has(*cid).await
+        }
+    }
+
+    #[tokio::test]
+    async fn local_only_gateway() {
+        let (store_client_addr, store_task) = spawn_store().await;
+        let mut config = Config::new(
+            0,
+            RpcClientConfig {
+                gateway_addr: None,
+                p2p_addr: None,
+                store_addr: Some(store_client_addr),
+                channels: Some(1),
+            },
+        );
+        config.set_default_headers();
+        config.auth = crate::auth::AuthConfig {
+            required: false,
+            anonymous_local_only: true,
+            signing_key: None,
+            tokens: vec![crate::auth::AccessToken {
+                token: "reader".to_string(),
+                scopes: vec!["/".to_string()],
+            }],
+        };
+        let rpc_client = RpcClient::new(config.rpc_client().clone()).await.unwrap();
+
+        // the root and one file are stored, the block of the other file is missing
+        let (root_cid, missing_cid) = {
+            let store = rpc_client.try_store().unwrap();
+            let mut dir_builder = DirectoryBuilder::new();
+            dir_builder.name("demo");
+            for (name, content) in [("hello.txt", "ola"), ("missing.txt", "gone")] {
+                let mut file = FileBuilder::new();
+                file.name(name).content_bytes(content.as_bytes().to_vec());
+                dir_builder.add_file(file.build().await.unwrap());
+            }
+            let mut parts = dir_builder.build().unwrap().encode();
+            let mut root_cid = None;
+            let mut missing_cid = None;
+            while let Some(part) = parts.next().await {
+                let (cid, bytes, links) = part.unwrap().into_parts();
+                root_cid = Some(cid);
+                if bytes.windows(4).any(|w| w == b"gone") {
+                    missing_cid = Some(cid);
+                } else {
+                    store.put(cid, bytes, links).await.unwrap();
+                }
+            }
+            (root_cid.unwrap(), missing_cid.unwrap())
+        };
+
+        let fetched = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let loader = RecordingLoader {
+            client: rpc_client,
+            fetched: fetched.clone(),
+        };
+        let rpc_addr = "grpc://0.0.0.0:0".parse().unwrap();
+        let core = Core::new(Arc::new(config), rpc_addr, Arc::new(None), loader)
+            .await
+            .unwrap();
+        let server = core.server();
+        let addr = server.local_addr();
+        let core_task = tokio::spawn(async move {
+            server.await.unwrap();
+        });
+
+        let get = |path_and_query: String, token: Option<&'static str>| async move {
+            let client = hyper::Client::new();
+            let uri = hyper::Uri::builder()
+                .scheme("http")
+                .authority(format!("localhost:{}", addr.port()))
+                .path_and_query(path_and_query)
+                .build()
+                .unwrap();
+            let mut req = hyper::Request::builder().method("GET").uri(uri);
+            if let Some(token) = token {
+                req = req.header("authorization", format!("Bearer {}", token));
+            }
+            let res = client
+                .request(req.body(hyper::Body::empty()).unwrap())
+                .await
+                .unwrap();
+            let status = res.status();
+            // drain the body, errors of streamed bodies are expected here
+            hyper::body::to_bytes(res.into_body()).await.ok();
+            status
+        };
+
+        let status = get(format!("/ipfs/{}/hello.txt", root_cid), None).await;
+        assert_eq!(http::StatusCode::OK, status);
+        let status = get(format!("/ipfs/{}/missing.txt", root_cid), None).await;
+        assert_ne!(http::StatusCode::OK, status);
+        get(
+            format!("/ipfs/{}?format=car&recursive=true", root_cid),
+            None,
+        )
+        .await;
+        get(format!("/ipfs/{}?format=car&dag-scope=all", root_cid), None).await;
+        get(format!("/ipfs/{}?format=json", root_cid), None).await;
+        assert!(fetched.lock().unwrap().is_empty());
+
+        // authorized clients still fetch missing blocks
+        get(format!("/ipfs/{}/missing.txt", root_cid), Some("reader")).await;
+        assert!(fetched.lock().unwrap().contains(&missing_cid));
+
+        core_task.abort();
+        core_task.await.unwrap_err();
+        store_task.abort();
+        store_task.await.unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn api_v0() {
+        let (store_client_addr, store_task) = spawn_store().await;
+        let mut config = Config::new(
+            0,
+            RpcClientConfig {
+                gateway_addr: None,
+                p2p_addr: None,
+                store_addr: Some(store_client_addr),
+                channels: Some(1),
+            },
+        );
+        config.http_api = true;
+        config.http_api_addr = "127.0.0.1:0".parse().unwrap();
+        config.set_default_headers();
+
+        let rpc_client = RpcClient::new(config.rpc_client().clone()).await.unwrap();
+        let core = Core::new(
+            Arc::new(config),
+            "grpc://0.0.0.0:0".parse().unwrap(),
+            Arc::new(None),
+            rpc_client,
+        )
+        .await
+        .unwrap();
+        let (addr, api_task) = core.spawn_api_server().unwrap().unwrap();
+        let server = core.server();
+        let gateway_addr = server.local_addr();
+        let core_task = tokio::spawn(async move {
+            server.await.unwrap();
+        });
+
+        let client = hyper::Client::new();
+        let post = |path: String, body: hyper::Body, content_type: Option<String>| {
+            let uri = hyper::Uri::builder()
+                .scheme("http")
+                .authority(format!("localhost:{}", addr.port()))
+                .path_and_query(path)
+                .build()
+                .unwrap();
+            let mut req = hyper::Request::builder().method("POST").uri(uri);
+            if let Some(content_type) = content_type {
+                req = req.header("content-type", content_type);
+            }
+            let req = req.body(body).unwrap();
+            let client = client.clone();
+            async move {
+                let res = client.request(req).await.unwrap();
+                let status = res.status();
+                let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+                (status, body)
+            }
+        };
+        let multipart = |parts: &[(&str, &str, &str)]| {
+            let mut body = String::new();
+            for (name, content_type, content) in parts {
+                body.push_str(&format!(
+                    "--boundary\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\nContent-Type: {}\r\n\r\n{}\r\n",
+                    name, content_type, content
+                ));
+            }
+            body.push_str("--boundary--\r\n");
+            (
+                hyper::Body::from(body),
+                Some("multipart/form-data; boundary=boundary".to_string()),
+            )
+        };
+
+        // the API is not served on the gateway port
+        let uri = hyper::Uri::builder()
+            .scheme("http")
+            .authority(format!("localhost:{}", gateway_addr.port()))
+            .path_and_query("/api/v0/version")
+            .build()
+            .unwrap();
+        let req = hyper::Request::post(uri)
+            .body(hyper::Body::empty())
+            .unwrap();
+        let res = client.request(req).await.unwrap();
+        assert!(!res.status().is_success());
+
+        // add a directory with a file
+        let (body, content_type) = multipart(&[
+            ("demo", "application/x-directory", ""),
+            (
+                "demo%2Fhello.txt",
+                "application/octet-stream",
+                "hello world",
+            ),
+        ]);
+        let (status, body) = post("/api/v0/add?progress=true".into(), body, content_type).await;
+        assert_eq!(http::StatusCode::OK, status);
+        let lines: Vec<serde_json::Value> = std::str::from_utf8(&body)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines[0]["Name"], "demo");
+        assert_eq!(lines[0]["Bytes"], 11);
+        let last = lines.last().unwrap();
+        assert_eq!(last["Name"], "demo");
+        assert_eq!(last["Size"], "11");
+        let root = last["Hash"].as_str().unwrap().to_string();
+
+        let (status, body) = post(
+            format!("/api/v0/cat?arg=/ipfs/{}/hello.txt", root),
+            hyper::Body::empty(),
+            None,
+        )
+        .await;
+        assert_eq!(http::StatusCode::OK, status);
+        assert_eq!(&body[..], b"hello world");
+
+        let (status, body) = post(
+            format!("/api/v0/cat?arg={}/hello.txt&offset=6&length=3", root),
+            hyper::Body::empty(),
+            None,
+        )
+        .await;
+        assert_eq!(http::StatusCode::OK, status);
+        assert_eq!(&body[..], b"wor");
+
+        // a directory can't be printed
+        let (status, body) = post(
+            format!("/api/v0/cat?arg={}", root),
+            hyper::Body::empty(),
+            None,
+        )
+        .await;
+        assert_eq!(http::StatusCode::INTERNAL_SERVER_ERROR, status);
+        let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(error["Type"], "error");
+
+        // the directory is returned as tar archive
+        let (status, body) = post(
+            format!("/api/v0/get?arg={}", root),
+            hyper::Body::empty(),
+            None,
+        )
+        .await;
+        assert_eq!(http::StatusCode::OK, status);
+        let mut archive = tokio_tar::Archive::new(&body[..]);
+        let mut entries = archive.entries().unwrap();
+        let mut names = Vec::new();
+        while let Some(entry) = entries.next().await {
+            let mut entry = entry.unwrap();
+            let name = entry
+                .path()
+                .unwrap()
+                .to_string_lossy()
+                .trim_end_matches('/')
+                .to_string();
+            let mut content = String::new();
+            tokio::io::AsyncReadExt::read_to_string(&mut entry, &mut content)
+                .await
+                .unwrap();
+            names.push((name, content));
+        }
+        assert_eq!(
+            names,
+            vec![
+                (root.clone(), String::new()),
+                (format!("{}/hello.txt", root), "hello world".to_string()),
+            ]
+        );
+
+        // blocks
+        let (body, content_type) = multipart(&[("block", "application/octet-stream", "raw")]);
+        let (status, body) = post("/api/v0/block/put".into(), body, content_type).await;
+        assert_eq!(http::StatusCode::OK, status);
+        let put: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(put["Size"], 3);
+        let key = put["Key"].as_str().unwrap();
+
+        let (status, body) = post(
+            format!("/api/v0/block/get?arg={}", key),
+            hyper::Body::empty(),
+            None,
+        )
+        .await;
+        assert_eq!(http::StatusCode::OK, status);
+        assert_eq!(&body[..], b"raw");
+
+        let (status, body) = post(
+            format!("/api/v0/block/stat?arg={}", key),
+            hyper::Body::empty(),
+            None,
+        )
+        .await;
+        assert_eq!(http::StatusCode::OK, status);
+        let stat: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(stat["Key"], key);
+        assert_eq!(stat["Size"], 3);
+
+        // dag nodes are stored as dag-cbor and returned as dag-json
+        let (body, content_type) =
+            multipart(&[("node", "application/json", r#"{"hello":{"to":["world"]}}"#)]);
+        let (status, body) = post("/api/v0/dag/put".into(), body, content_type).await;
+        assert_eq!(http::StatusCode::OK, status);
+        let put: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let cid: Cid = put["Cid"]["/"].as_str().unwrap().parse().unwrap();
+        assert_eq!(cid.codec(), 0x71);
+
+        let (status, body) = post(
+            format!("/api/v0/dag/get?arg={}", cid),
+            hyper::Body::empty(),
+            None,
+        )
+        .await;
+        assert_eq!(http::StatusCode::OK, status);
+        assert_eq!(&body[..], br#"{"hello":{"to":["world"]}}"#);
+
+        // paths are resolved into the node
+        let (status, body) = post(
+            format!("/api/v0/dag/get?arg={}/hello/to/0", cid),
+            hyper::Body::empty(),
+            None,
+        )
+        .await;
+        assert_eq!(http::StatusCode::OK, status);
+        assert_eq!(&body[..], br#""world""#);
+
+        let (status, body) = post(
+            format!("/api/v0/dag/get?arg={}/hello&output-codec=dag-cbor", cid),
+            hyper::Body::empty(),
+            None,
+        )
+        .await;
+        assert_eq!(http::StatusCode::OK, status);
+        let node: Ipld = IpldCodec::DagCbor.decode(&body).unwrap();
+        assert_eq!(
+            node,
+            Ipld::StringMap(
+                [(
+                    "to".to_string(),
+                    Ipld::List(vec![Ipld::String("world".to_string())])
+                )]
+                .into_iter()
+                .collect()
+            )
+        );
+
+        let (status, body) = post("/api/v0/version".into(), hyper::Body::empty(), None).await;
+        assert_eq!(http::StatusCode::OK, status);
+        let version: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(version["Version"], env!("CARGO_PKG_VERSION"));
+
+        // missing arguments are client errors
+        let (status, body) = post("/api/v0/cat".into(), hyper::Body::empty(), None).await;
+        assert_eq!(http::StatusCode::BAD_REQUEST, status);
+        let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(error["Code"], 1);
+
+        api_task.abort();
+        api_task.await.unwrap_err();
+        core_task.abort();
+        core_task.await.unwrap_err();
+        store_task.abort();
+        store_task.await.unwrap_err();
+    }
 }
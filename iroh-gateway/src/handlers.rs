@@ -10,15 +10,16 @@ use axum::{
     BoxError, Router,
 };
 use bytes::Bytes;
-use futures::TryStreamExt;
+use futures::{StreamExt, TryStreamExt};
 use handlebars::Handlebars;
 use http::Method;
 use iroh_metrics::{core::MRecorder, gateway::GatewayMetrics, get_current_trace_id, inc};
 use iroh_resolver::{
-    resolver::{CidOrDomain, ContentLoader, OutMetrics, UnixfsType},
+    resolver::{CidOrDomain, ContentLoader, Out, OutMetrics, Resolver, UnixfsType},
     unixfs::Link,
 };
 use iroh_util::human::format_bytes;
+use rand::Rng;
 use serde::{Deserialize, Serialize};
 use serde_json::{
     json,
@@ -29,25 +30,33 @@ use std::{
     collections::HashMap,
     error::Error,
     fmt::Write,
+    net::SocketAddr,
     ops::Range,
     sync::Arc,
     time::{self, Duration},
 };
 
-use tower::ServiceBuilder;
+use tower::{timeout::TimeoutLayer, ServiceBuilder};
 use tower_http::{compression::CompressionLayer, trace::TraceLayer};
 use tracing::info_span;
 use url::Url;
 use urlencoding::encode;
 
 use crate::{
-    client::{FileResult, Request},
+    access_log::{self, access_log_middleware, AccessLogConfig},
+    api_v0::get_api_routes,
+    auth::{Access, AuthConfig, AuthError},
+    bad_bits,
+    cache::{cacheable_size, CacheConfig, CachedResponse, ResponseCache},
+    client::{DagScope, EntityBytes, FileResult, PrettyStreamBody, Request},
     constants::*,
     core::State,
     error::GatewayError,
     headers::*,
+    rate_limit::{PermitBody, RateLimitConfig, Rejection, RouteClass},
     response::{get_response_format, GatewayResponse, ResponseFormat},
     templates::{icon_class_name, ICONS_STYLESHEET, STYLESHEET},
+    tls::TlsConfig,
 };
 
 /// Trait describing what needs to be accessed on the configuration
@@ -57,27 +66,61 @@ pub trait StateConfig: std::fmt::Debug + Sync + Send {
     fn public_url_base(&self) -> &str;
     fn port(&self) -> u16;
     fn user_headers(&self) -> &HeaderMap<HeaderValue>;
+    fn rate_limit(&self) -> &RateLimitConfig;
+    fn cache(&self) -> &CacheConfig;
+    fn http_api(&self) -> bool;
+    fn http_api_addr(&self) -> SocketAddr;
+    fn auth(&self) -> &AuthConfig;
+    fn tls(&self) -> Option<&TlsConfig>;
+    fn access_log(&self) -> Option<&AccessLogConfig>;
 }
 
 pub fn get_app_routes<T: ContentLoader + std::marker::Unpin>(state: &Arc<State<T>>) -> Router {
     // todo(arqu): ?uri=... https://github.com/ipfs/go-ipfs/pull/7802
-    Router::new()
+    let admin = Router::new()
+        .route(
+            "/admin/denylist",
+            get(denylist_status::<T>).post(denylist_reload::<T>),
+        )
+        .route_layer(middleware::from_fn(admin_auth_middleware::<T, _>));
+    let router = Router::new()
         .route("/:scheme/:cid", get(get_handler::<T>))
         .route("/:scheme/:cid/*cpath", get(get_handler::<T>))
+        .route_layer(middleware::from_fn(auth_middleware::<T, _>))
+        .merge(admin)
+        // routes added after the auth layer are public
         .route("/health", get(health_check))
         .route("/icons.css", get(stylesheet_icons))
-        .route("/style.css", get(stylesheet_main))
+        .route("/style.css", get(stylesheet_main));
+    with_layers(router, state, Some(Duration::from_secs(120)))
+}
+
+/// Routes of the HTTP RPC API, served on their own listener.
+///
+/// Uploads can take arbitrarily long, so API requests have no timeout.
+pub fn get_api_app_routes<T: ContentLoader + std::marker::Unpin>(state: &Arc<State<T>>) -> Router {
+    let router = get_api_routes::<T>().route_layer(middleware::from_fn(auth_middleware::<T, _>));
+    with_layers(router, state, None)
+}
+
+fn with_layers<T: ContentLoader + std::marker::Unpin>(
+    router: Router,
+    state: &Arc<State<T>>,
+    timeout: Option<Duration>,
+) -> Router {
+    router
         .layer(Extension(Arc::clone(state)))
         .layer(
             ServiceBuilder::new()
                 // Handle errors from middleware
                 .layer(Extension(Arc::clone(state)))
+                .layer(middleware::from_fn(access_log_middleware::<T, _>))
                 .layer(middleware::from_fn(request_middleware))
                 .layer(CompressionLayer::new())
                 .layer(HandleErrorLayer::new(middleware_error_handler::<T>))
                 .load_shed()
                 .concurrency_limit(2048 * 1024)
-                .timeout(Duration::from_secs(120))
+                .option_layer(timeout.map(TimeoutLayer::new))
                 .into_inner(),
         )
         .layer(
@@ -105,6 +148,20 @@ pub struct GetParams {
     /// uri query parameter for handling navigator.registerProtocolHandler Web API requests
     uri: Option<String>,
     recursive: Option<bool>,
+    /// specifies which blocks of the DAG are part of a car response: block, entity or all
+    #[serde(rename = "dag-scope")]
+    dag_scope: Option<String>,
+    /// specifies the byte range of a file to include in a car response, as `from:to`
+    #[serde(rename = "entity-bytes")]
+    entity_bytes: Option<String>,
+    /// number of entries to skip in a JSON directory listing
+    offset: Option<usize>,
+    /// max number of entries in a JSON directory listing
+    limit: Option<usize>,
+    /// signed url parameters, kept so that redirects stay authorized
+    auth_scope: Option<String>,
+    auth_expires: Option<String>,
+    auth_signature: Option<String>,
 }
 
 impl GetParams {
@@ -162,9 +219,16 @@ pub async fn get_handler<T: ContentLoader + std::marker::Unpin>(
     // TODO: handle 404 or error
     let resolved_cid = resolved_path.root();
 
-    if handle_only_if_cached(&request_headers, &state, resolved_cid).await? {
+    // anonymous clients of a private gateway must not trigger network fetches
+    let local_only = http_req.extensions().get::<Access>() == Some(&Access::LocalOnly);
+    if handle_only_if_cached(&request_headers, &state, resolved_cid, local_only).await? {
         return response(StatusCode::OK, Body::empty(), HeaderMap::new());
     }
+    let state = if local_only {
+        state.local_only()
+    } else {
+        state
+    };
 
     if check_bad_bits(&state, resolved_cid.to_string().as_str(), cpath).await {
         return Err(error(StatusCode::GONE, "CID is in the denylist", &state));
@@ -181,13 +245,58 @@ pub async fn get_handler<T: ContentLoader + std::marker::Unpin>(
     let query_file_name = query_params.filename.unwrap_or_default();
     let download = query_params.download.unwrap_or_default();
     let recursive = query_params.recursive.unwrap_or_default();
+    let scoped = query_params.dag_scope.is_some() || query_params.entity_bytes.is_some();
+
+    let dag_scope_all = query_params.dag_scope.as_deref() == Some("all");
+    let route_class = match format {
+        _ if recursive => RouteClass::RecursiveCar,
+        ResponseFormat::Car if dag_scope_all => RouteClass::RecursiveCar,
+        ResponseFormat::Raw => RouteClass::Raw,
+        ResponseFormat::Car => RouteClass::Car,
+        ResponseFormat::Json | ResponseFormat::Fs(_) => RouteClass::Fs,
+    };
+    if let Err(rejection) = state
+        .rate_limiter
+        .check(route_class, state.rate_limiter.client_key(&http_req))
+    {
+        return rejected(rejection, &state);
+    }
 
     let mut headers = HeaderMap::new();
 
-    if let Some(resp) = etag_check(&request_headers, resolved_cid, &format, &state) {
-        return Ok(resp);
+    // the etag of a scoped CAR depends on the content the path resolves to, it is checked
+    // once the path is resolved
+    let scoped_car = scoped && !recursive && format == ResponseFormat::Car;
+    if !scoped_car {
+        if let Some(resp) = etag_check(&request_headers, resolved_cid, &format, &state) {
+            return Ok(resp);
+        }
     }
 
+    // small rendered responses are cached, unless only a part of them is requested
+    let cache_key = match state.response_cache {
+        Some(_) if !recursive && !scoped && !request_headers.contains_key(RANGE) => Some(format!(
+            "{}{}#{}",
+            full_content_path,
+            query_params_copy.to_query_string(),
+            format.get_extenstion()
+        )),
+        _ => None,
+    };
+    if let (Some(cache), Some(key)) = (&state.response_cache, &cache_key) {
+        if let Some(cached) = cache.get(key) {
+            inc!(GatewayMetrics::ResponseCacheHits);
+            access_log::record_cache_hit();
+            return response(
+                cached.status_code,
+                body::Full::from(cached.body),
+                cached.headers,
+            );
+        }
+        inc!(GatewayMetrics::ResponseCacheMisses);
+    }
+    let cache_ttl = state.config.cache().ttl(resolved_path.typ());
+
     // init headers
     format.write_headers(&mut headers);
     add_user_headers(&mut headers, state.config.user_headers().clone());
@@ -213,22 +322,127 @@ pub async fn get_handler<T: ContentLoader + std::marker::Unpin>(
         query_params: query_params_copy,
     };
 
-    if recursive {
-        serve_car_recursive(&req, state, headers, start_time).await
+    let res = if recursive {
+        serve_car_recursive(&req, Arc::clone(&state), headers, start_time).await
     } else {
+        let state = Arc::clone(&state);
         match req.format {
             ResponseFormat::Raw => serve_raw(&req, state, headers, &http_req, start_time).await,
+            ResponseFormat::Car if scoped => {
+                serve_car_scoped(&req, state, headers, &http_req, start_time).await
+            }
             ResponseFormat::Car => serve_car(&req, state, headers, start_time).await,
-            ResponseFormat::Fs(_) => serve_fs(&req, state, headers, &http_req, start_time).await,
+            ResponseFormat::Json | ResponseFormat::Fs(_) => {
+                serve_fs(&req, state, headers, &http_req, start_time).await
+            }
         }
+    };
+
+    match (&state.response_cache, cache_key) {
+        (Some(cache), Some(key)) => cache_response(cache, key, cache_ttl, res?, &state).await,
+        _ => res,
     }
 }
 
+/// Stores a successful response in the cache if its body is small enough.
+async fn cache_response<T: ContentLoader>(
+    cache: &ResponseCache,
+    key: String,
+    ttl: Option<Duration>,
+    res: GatewayResponse,
+    state: &State<T>,
+) -> Result<GatewayResponse, GatewayError> {
+    if res.status_code != StatusCode::OK
+        || cacheable_size(&res.body, state.config.cache()).is_none()
+    {
+        return Ok(res);
+    }
+    let GatewayResponse {
+        status_code,
+        body,
+        headers,
+        trace_id,
+    } = res;
+    let body = hyper::body::to_bytes(body)
+        .await
+        .map_err(|e| error(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string(), state))?;
+    let cached = CachedResponse {
+        status_code,
+        headers,
+        body,
+    };
+    let size = cached.size();
+    cache.insert(key, cached.clone(), size, ttl);
+    Ok(GatewayResponse {
+        status_code,
+        body: body::boxed(body::Full::from(cached.body)),
+        headers: cached.headers,
+        trace_id,
+    })
+}
+
 #[tracing::instrument()]
 pub async fn health_check() -> String {
     "OK".to_string()
 }
 
+/// Reports the state of the denylist.
+#[tracing::instrument()]
+pub async fn denylist_status<T: ContentLoader + std::marker::Unpin>(
+    Extension(state): Extension<Arc<State<T>>>,
+) -> Result<GatewayResponse, GatewayError> {
+    denylist_response(&state, None).await
+}
+
+/// Reloads the denylist from its sources and reports its new state.
+#[tracing::instrument()]
+pub async fn denylist_reload<T: ContentLoader + std::marker::Unpin>(
+    Extension(state): Extension<Arc<State<T>>>,
+) -> Result<GatewayResponse, GatewayError> {
+    let bbits = match state.bad_bits.as_ref() {
+        Some(bbits) => bbits,
+        None => return Err(error(StatusCode::NOT_FOUND, "denylist is disabled", &state)),
+    };
+    if !bbits
+        .write()
+        .await
+        .start_reload(DENYLIST_MIN_RELOAD_INTERVAL)
+    {
+        return Err(error(
+            StatusCode::TOO_MANY_REQUESTS,
+            "denylist was reloaded recently",
+            &state,
+        ));
+    }
+    let failed = bad_bits::reload_bad_bits(bbits).await;
+    denylist_response(&state, Some(failed)).await
+}
+
+async fn denylist_response<T: ContentLoader>(
+    state: &State<T>,
+    failed_sources: Option<usize>,
+) -> Result<GatewayResponse, GatewayError> {
+    let bbits = match state.bad_bits.as_ref() {
+        Some(bbits) => bbits.read().await,
+        None => return Err(error(StatusCode::NOT_FOUND, "denylist is disabled", state)),
+    };
+    let last_updated = bbits
+        .last_updated
+        .and_then(|t| t.duration_since(time::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs());
+    let mut body = json!({
+        "entries": bbits.denylist.len(),
+        "sources": bbits.sources.iter().map(|s| s.to_string()).collect::<Vec<_>>(),
+        "last_updated": last_updated,
+    });
+    if let Some(failed) = failed_sources {
+        body["failed_sources"] = json!(failed);
+    }
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    response(StatusCode::OK, body.to_string(), headers)
+}
+
 async fn stylesheet_main() -> (HeaderMap, &'static str) {
     let mut headers = HeaderMap::new();
     headers.insert(
@@ -316,46 +530,44 @@ fn unsuported_header_check<T: ContentLoader>(
     Ok(())
 }
 
+/// Checks that the root of the content is stored locally if the request is limited to
+/// cached content, failing with `412 Precondition Failed` otherwise.
+///
+/// Returns whether the request only asked for this check, through `Cache-Control: only-if-cached`.
 #[tracing::instrument()]
 async fn handle_only_if_cached<T: ContentLoader>(
     request_headers: &HeaderMap,
     state: &State<T>,
     cid: &CidOrDomain,
+    local_only: bool,
 ) -> Result<bool, GatewayError> {
-    if request_headers.contains_key(&HEADER_CACHE_CONTROL) {
-        let hv = request_headers.get(&HEADER_CACHE_CONTROL).unwrap();
-        if hv.to_str().unwrap() == "only-if-cached" {
-            match cid {
-                CidOrDomain::Cid(cid) => match state.client.has_file_locally(cid).await {
-                    Ok(true) => {
-                        return Ok(true);
-                    }
-                    Ok(false) => {
-                        return Err(error(
-                            StatusCode::PRECONDITION_FAILED,
-                            "File not found in cache",
-                            state,
-                        ));
-                    }
-                    Err(e) => {
-                        return Err(error(
-                            StatusCode::PRECONDITION_FAILED,
-                            &format!("Error checking cache: {}", e),
-                            state,
-                        ));
-                    }
-                },
-                CidOrDomain::Domain(_) => {
-                    return Err(error(
-                        StatusCode::PRECONDITION_FAILED,
-                        "Cannot resolve in cache: invalid CID.",
-                        state,
-                    ));
-                }
-            }
-        }
+    let only_if_cached = request_headers
+        .get(&HEADER_CACHE_CONTROL)
+        .and_then(|hv| hv.to_str().ok())
+        == Some("only-if-cached");
+    if !only_if_cached && !local_only {
+        return Ok(false);
+    }
+    match cid {
+        CidOrDomain::Cid(cid) => match state.client.has_file_locally(cid).await {
+            Ok(true) => Ok(only_if_cached),
+            Ok(false) => Err(error(
+                StatusCode::PRECONDITION_FAILED,
+                "File not found in cache",
+                state,
+            )),
+            Err(e) => Err(error(
+                StatusCode::PRECONDITION_FAILED,
+                &format!("Error checking cache: {}", e),
+                state,
+            )),
+        },
+        CidOrDomain::Domain(_) => Err(error(
+            StatusCode::PRECONDITION_FAILED,
+            "Cannot resolve in cache: invalid CID.",
+            state,
+        )),
     }
-    Ok(false)
 }
 
 pub async fn check_bad_bits<T: ContentLoader>(state: &State<T>, cid: &str, path: &str) -> bool {
@@ -404,11 +616,8 @@ async fn serve_raw<T: ContentLoader + std::marker::Unpin>(
     http_req: &HttpRequest<Body>,
     start_time: std::time::Instant,
 ) -> Result<GatewayResponse, GatewayError> {
-    let range: Option<Range<u64>> = if http_req.headers().contains_key(RANGE) {
-        parse_range_header(http_req.headers().get(RANGE).unwrap())
-    } else {
-        None
-    };
+    let ranges = parse_ranges(http_req, &state)?;
+    let range = bounded_range(&ranges);
     // FIXME: we currently only retrieve full cids
     let (body, metadata) = state
         .client
@@ -429,18 +638,13 @@ async fn serve_raw<T: ContentLoader + std::marker::Unpin>(
                 return Ok(res);
             }
             add_cache_control_headers(&mut headers, metadata.clone());
+            access_log::record_source(&metadata.source);
             add_ipfs_roots_headers(&mut headers, metadata.clone());
             add_content_length_header(&mut headers, metadata.clone());
 
-            if let Some(mut capped_range) = range {
-                if let Some(size) = metadata.size {
-                    capped_range.end = std::cmp::min(capped_range.end, size);
-                }
-                add_etag_range(&mut headers, capped_range.clone());
-                add_content_range_headers(&mut headers, capped_range, metadata.size);
-                response(StatusCode::PARTIAL_CONTENT, body, headers)
-            } else {
-                response(StatusCode::OK, body, headers)
+            match ranges {
+                Some(ranges) => serve_ranges(body, ranges, metadata.size, headers, &state).await,
+                None => response(StatusCode::OK, body, headers),
             }
         }
         FileResult::Directory(_) => Err(error(
@@ -482,6 +686,7 @@ async fn serve_car<T: ContentLoader + std::marker::Unpin>(
             if let Some(res) = etag_check(&headers, &req.cid, &req.format, &state) {
                 return Ok(res);
             }
+            access_log::record_source(&metadata.source);
             add_ipfs_roots_headers(&mut headers, metadata);
             response(StatusCode::OK, body, headers)
         }
@@ -500,12 +705,17 @@ async fn serve_car_recursive<T: ContentLoader + std::marker::Unpin>(
     mut headers: HeaderMap,
     start_time: std::time::Instant,
 ) -> Result<GatewayResponse, GatewayError> {
+    let permit = match state.rate_limiter.acquire_recursive_car() {
+        Ok(permit) => permit,
+        Err(rejection) => return rejected(rejection, &state),
+    };
     let body = state
         .client
         .clone()
         .get_car_recursive(req.resolved_path.clone(), start_time)
         .await
         .map_err(|e| error(StatusCode::INTERNAL_SERVER_ERROR, &e, &state))?;
+    let body = PermitBody::new(body, permit);
 
     let file_name = match req.query_file_name.is_empty() {
         true => format!("{}.car", req.cid),
@@ -524,6 +734,91 @@ async fn serve_car_recursive<T: ContentLoader + std::marker::Unpin>(
     response(StatusCode::OK, body, headers)
 }
 
+#[tracing::instrument()]
+async fn serve_car_scoped<T: ContentLoader + std::marker::Unpin>(
+    req: &Request,
+    state: Arc<State<T>>,
+    mut headers: HeaderMap,
+    http_req: &HttpRequest<Body>,
+    start_time: std::time::Instant,
+) -> Result<GatewayResponse, GatewayError> {
+    let entity_bytes = match req.query_params.entity_bytes {
+        Some(ref entity_bytes) => Some(
+            entity_bytes
+                .parse::<EntityBytes>()
+                .map_err(|e| error(StatusCode::BAD_REQUEST, &e, &state))?,
+        ),
+        None => None,
+    };
+    let scope = match req.query_params.dag_scope {
+        Some(ref scope) => scope
+            .parse::<DagScope>()
+            .map_err(|e| error(StatusCode::BAD_REQUEST, &e, &state))?,
+        None => DagScope::Entity,
+    };
+    if entity_bytes.is_some() && scope != DagScope::Entity {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            "entity-bytes is only supported with dag-scope=entity",
+            &state,
+        ));
+    }
+
+    // whole DAGs are as expensive as recursive car responses
+    let permit = match scope {
+        DagScope::All => match state.rate_limiter.acquire_recursive_car() {
+            Ok(permit) => permit,
+            Err(rejection) => return rejected(rejection, &state),
+        },
+        DagScope::Block | DagScope::Entity => None,
+    };
+    let (out, path_blocks) = state
+        .client
+        .resolve_car_scoped(req.resolved_path.clone())
+        .await
+        .map_err(|e| error(StatusCode::INTERNAL_SERVER_ERROR, &e, &state))?;
+
+    // the etag identifies the content the path resolves to and the part of it that is sent
+    let target = match path_blocks.last() {
+        Some(block) => CidOrDomain::Cid(*block.cid()),
+        None => {
+            return Err(error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "path did not resolve to a block",
+                &state,
+            ))
+        }
+    };
+    let etag = format!("W/{}", get_etag(&target, Some(req.format.clone())));
+    set_etag_headers(&mut headers, etag);
+    add_etag_dag_scope(&mut headers, scope, entity_bytes);
+    let inm = http_req
+        .headers()
+        .get("If-None-Match")
+        .and_then(|inm| inm.to_str().ok());
+    if let (Some(inm), Some(etag)) = (inm, headers.get(ETAG)) {
+        if etag_matches(inm, etag.to_str().unwrap()) {
+            return Ok(GatewayResponse::not_modified());
+        }
+    }
+
+    let body = state
+        .client
+        .clone()
+        .get_car_scoped(out, path_blocks, scope, entity_bytes, start_time)
+        .await
+        .map_err(|e| error(StatusCode::INTERNAL_SERVER_ERROR, &e, &state))?;
+    let body = PermitBody::new(body, permit);
+
+    let file_name = match req.query_file_name.is_empty() {
+        true => format!("{}.car", req.cid),
+        false => req.query_file_name.clone(),
+    };
+
+    set_content_disposition_headers(&mut headers, &file_name, DISPOSITION_ATTACHMENT);
+    response(StatusCode::OK, body, headers)
+}
+
 #[tracing::instrument()]
 #[async_recursion]
 async fn serve_fs<T: ContentLoader + std::marker::Unpin>(
@@ -533,11 +828,8 @@ async fn serve_fs<T: ContentLoader + std::marker::Unpin>(
     http_req: &HttpRequest<Body>,
     start_time: std::time::Instant,
 ) -> Result<GatewayResponse, GatewayError> {
-    let range: Option<Range<u64>> = if http_req.headers().contains_key(RANGE) {
-        parse_range_header(http_req.headers().get(RANGE).unwrap())
-    } else {
-        None
-    };
+    let ranges = parse_ranges(http_req, &state)?;
+    let range = bounded_range(&ranges);
 
     // FIXME: we currently only retrieve full cids
     let (body, metadata) = state
@@ -546,8 +838,12 @@ async fn serve_fs<T: ContentLoader + std::marker::Unpin>(
         .await
         .map_err(|e| error(StatusCode::INTERNAL_SERVER_ERROR, &e, &state))?;
 
+    access_log::record_source(&metadata.source);
     add_ipfs_roots_headers(&mut headers, metadata.clone());
     match body {
+        FileResult::Directory(res) if req.format == ResponseFormat::Json => {
+            serve_fs_dir_json(&res, req, state, headers, start_time).await
+        }
         FileResult::Directory(res) => {
             let dir_list: anyhow::Result<Vec<_>> = res
                 .unixfs_read_dir(&state.client.resolver, OutMetrics { start: start_time })
@@ -596,15 +892,11 @@ async fn serve_fs<T: ContentLoader + std::marker::Unpin>(
                         add_content_type_headers(&mut headers, &name, content_sniffed_mime);
                     }
 
-                    if let Some(mut capped_range) = range {
-                        if let Some(size) = metadata.size {
-                            capped_range.end = std::cmp::min(capped_range.end, size);
+                    match ranges {
+                        Some(ranges) => {
+                            serve_ranges(body, ranges, metadata.size, headers, &state).await
                         }
-                        add_etag_range(&mut headers, capped_range.clone());
-                        add_content_range_headers(&mut headers, capped_range, metadata.size);
-                        response(StatusCode::PARTIAL_CONTENT, body, headers)
-                    } else {
-                        response(StatusCode::OK, body, headers)
+                        None => response(StatusCode::OK, body, headers),
                     }
                 }
                 None => Err(error(
@@ -631,11 +923,246 @@ async fn serve_fs<T: ContentLoader + std::marker::Unpin>(
             );
             let content_sniffed_mime = body.get_mime();
             add_content_type_headers(&mut headers, &name, content_sniffed_mime);
-            response(StatusCode::OK, body, headers)
+            match ranges {
+                Some(ranges) => serve_ranges(body, ranges, metadata.size, headers, &state).await,
+                None => response(StatusCode::OK, body, headers),
+            }
         }
     }
 }
 
+/// Parses the `Range` header of the request, if any.
+fn parse_ranges<T: ContentLoader>(
+    http_req: &HttpRequest<Body>,
+    state: &State<T>,
+) -> Result<Option<Vec<ByteRange>>, GatewayError> {
+    match http_req.headers().get(RANGE) {
+        Some(range) => parse_range_header(range)
+            .map(Some)
+            .map_err(|e| error(StatusCode::RANGE_NOT_SATISFIABLE, &e, state)),
+        None => Ok(None),
+    }
+}
+
+/// Returns the range to fetch upfront, which is only possible for a single range
+/// with a known end.
+fn bounded_range(ranges: &Option<Vec<ByteRange>>) -> Option<Range<u64>> {
+    match ranges.as_deref() {
+        Some([range]) => range.bounded(),
+        _ => None,
+    }
+}
+
+/// Serves the requested ranges of a file body, either as a single partial response
+/// or as a `multipart/byteranges` response.
+async fn serve_ranges<T: ContentLoader + std::marker::Unpin>(
+    mut body: PrettyStreamBody<T>,
+    ranges: Vec<ByteRange>,
+    size: Option<u64>,
+    mut headers: HeaderMap,
+    state: &State<T>,
+) -> Result<GatewayResponse, GatewayError> {
+    let bounded = bounded_range(&Some(ranges.clone()));
+    let size = match size {
+        Some(size) => size,
+        None => {
+            // without a known size only a single bounded range can be served
+            return match bounded {
+                Some(range) => {
+                    headers.remove(CONTENT_LENGTH);
+                    add_etag_range(&mut headers, range.clone());
+                    add_content_range_headers(&mut headers, range, None);
+                    response(StatusCode::PARTIAL_CONTENT, body, headers)
+                }
+                None => response(StatusCode::OK, body, headers),
+            };
+        }
+    };
+
+    let resolved = resolve_ranges(&ranges, size);
+    match resolved.as_slice() {
+        [] => {
+            headers.remove(CONTENT_LENGTH);
+            add_unsatisfiable_range_headers(&mut headers, Some(size));
+            response(StatusCode::RANGE_NOT_SATISFIABLE, Body::empty(), headers)
+        }
+        [range] => {
+            let range = range.clone();
+            if bounded.is_none() {
+                body.seek_range(range.clone())
+                    .await
+                    .map_err(|e| error(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string(), state))?;
+            }
+            headers.insert(
+                CONTENT_LENGTH,
+                HeaderValue::from_str(&(range.end - range.start).to_string()).unwrap(),
+            );
+            add_etag_range(&mut headers, range.clone());
+            add_content_range_headers(&mut headers, range, Some(size));
+            response(StatusCode::PARTIAL_CONTENT, body, headers)
+        }
+        _ => {
+            for range in &resolved {
+                add_etag_range(&mut headers, range.clone());
+            }
+            let boundary: String = rand::thread_rng()
+                .sample_iter(&rand::distributions::Alphanumeric)
+                .take(MULTIPART_BOUNDARY_LEN)
+                .map(char::from)
+                .collect();
+            let content_type = headers.get(CONTENT_TYPE).cloned();
+            let (body, len) = body.into_multipart(resolved, size, content_type, boundary.clone());
+            headers.insert(
+                CONTENT_TYPE,
+                HeaderValue::from_str(&format!("multipart/byteranges; boundary={}", boundary))
+                    .unwrap(),
+            );
+            headers.insert(
+                CONTENT_LENGTH,
+                HeaderValue::from_str(&len.to_string()).unwrap(),
+            );
+            response(StatusCode::PARTIAL_CONTENT, body, headers)
+        }
+    }
+}
+
+/// A single entry of a JSON directory listing.
+#[derive(Debug, Serialize)]
+struct DirListEntry {
+    name: String,
+    cid: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<u64>,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    typ: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mode: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mtime: Option<DirListMtime>,
+}
+
+#[derive(Debug, Serialize)]
+struct DirListMtime {
+    secs: i64,
+    nsecs: u32,
+}
+
+/// A page of a JSON directory listing.
+#[derive(Debug, Serialize)]
+struct DirList {
+    path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cid: Option<String>,
+    entries: Vec<DirListEntry>,
+    offset: usize,
+    /// Offset of the next page, if there are more entries.
+    next_offset: Option<usize>,
+}
+
+/// Serves a page of the entries of a directory as JSON.
+///
+/// The directory is read lazily up to the end of the requested page, so the
+/// shards of a HAMT directory that follow the page are not loaded. The entries
+/// before the page are still read to be skipped, as shards don't record how
+/// many entries they hold.
+#[tracing::instrument(skip(dir))]
+async fn serve_fs_dir_json<T: ContentLoader + std::marker::Unpin>(
+    dir: &Out,
+    req: &Request,
+    state: Arc<State<T>>,
+    mut headers: HeaderMap,
+    start_time: std::time::Instant,
+) -> Result<GatewayResponse, GatewayError> {
+    let offset = req.query_params.offset.unwrap_or_default();
+    let limit = req
+        .query_params
+        .limit
+        .unwrap_or(DIR_LIST_DEFAULT_LIMIT)
+        .clamp(1, DIR_LIST_MAX_LIMIT);
+
+    let links: anyhow::Result<Vec<Link>> = dir
+        .unixfs_read_dir(&state.client.resolver, OutMetrics { start: start_time })
+        .map_err(|e| error(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string(), &state))?
+        .expect("already known this is a directory")
+        .skip(offset)
+        // read one more entry to know if there is a next page
+        .take(limit + 1)
+        .try_collect()
+        .await;
+    let mut links = links.map_err(|e| {
+        tracing::warn!("failed to read dir: {:?}", e);
+        error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "failed to read dir listing",
+            &state,
+        )
+    })?;
+    let next_offset = if links.len() > limit {
+        links.truncate(limit);
+        Some(offset + limit)
+    } else {
+        None
+    };
+
+    let resolver = &state.client.resolver;
+    let entries = futures::stream::iter(links)
+        .map(|link| dir_list_entry(resolver, link))
+        .buffered(DIR_LIST_CONCURRENCY)
+        .collect()
+        .await;
+    let dir_list = DirList {
+        path: req.resolved_path.to_string(),
+        cid: match req.cid {
+            CidOrDomain::Cid(cid) => Some(cid.to_string()),
+            CidOrDomain::Domain(_) => None,
+        },
+        entries,
+        offset,
+        next_offset,
+    };
+    let body = serde_json::to_vec(&dir_list)
+        .map_err(|e| error(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string(), &state))?;
+
+    headers.insert(CONTENT_TYPE, CONTENT_TYPE_JSON.clone());
+    set_etag_headers(&mut headers, get_etag(&req.cid, Some(req.format.clone())));
+    add_etag_dir_page(&mut headers, offset, limit);
+    response(StatusCode::OK, body::Full::from(body), headers)
+}
+
+/// Resolves the root block of a directory entry to find its type, size, mode and mtime.
+///
+/// Entries that can't be resolved are still listed, with only what the link itself carries.
+async fn dir_list_entry<T: ContentLoader>(resolver: &Resolver<T>, link: Link) -> DirListEntry {
+    let mut entry = DirListEntry {
+        name: link.name.unwrap_or_default(),
+        cid: link.cid.to_string(),
+        size: link.tsize,
+        typ: None,
+        mode: None,
+        mtime: None,
+    };
+    match resolver
+        .resolve(iroh_resolver::resolver::Path::from_cid(link.cid))
+        .await
+    {
+        Ok(child) => {
+            let metadata = child.metadata();
+            entry.size = metadata.size.or(link.tsize);
+            entry.typ = metadata.unixfs_type.map(|typ| match typ {
+                UnixfsType::Dir => "directory",
+                UnixfsType::File => "file",
+                UnixfsType::Symlink => "symlink",
+            });
+            entry.mode = child.unixfs_mode();
+            entry.mtime = child
+                .unixfs_mtime()
+                .map(|(secs, nsecs)| DirListMtime { secs, nsecs });
+        }
+        Err(e) => tracing::debug!("failed to resolve dir entry {}: {:?}", link.cid, e),
+    }
+    entry
+}
+
 #[tracing::instrument()]
 async fn serve_fs_dir<T: ContentLoader + std::marker::Unpin>(
     dir_list: &[Link],
@@ -757,6 +1284,32 @@ where
     })
 }
 
+/// Turns a rate limiter rejection into a `429 Too Many Requests` response.
+fn rejected<T: ContentLoader>(
+    rejection: Rejection,
+    state: &State<T>,
+) -> Result<GatewayResponse, GatewayError> {
+    let (message, retry_after) = match rejection {
+        Rejection::RateLimited(retry_after) => {
+            inc!(GatewayMetrics::RateLimited);
+            ("rate limit exceeded", retry_after)
+        }
+        Rejection::TooManyConcurrent => {
+            inc!(GatewayMetrics::ConcurrencyLimited);
+            (
+                "too many concurrent recursive requests",
+                CONCURRENCY_LIMIT_RETRY_AFTER,
+            )
+        }
+    };
+    let mut headers = HeaderMap::new();
+    add_user_headers(&mut headers, state.config.user_headers().clone());
+    // round up, a zero retry-after would invite an immediate retry
+    let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    headers.insert(RETRY_AFTER, HeaderValue::from(secs.max(1)));
+    response(StatusCode::TOO_MANY_REQUESTS, message.to_string(), headers)
+}
+
 #[tracing::instrument()]
 fn error<T: ContentLoader>(
     status_code: StatusCode,
@@ -786,6 +1339,73 @@ pub async fn request_middleware<B>(
     r
 }
 
+/// Rejects requests that are not authorized by the auth config, and records the access
+/// granted to the others in their extensions.
+pub async fn auth_middleware<T: ContentLoader, B>(
+    mut request: axum::http::Request<B>,
+    next: axum::middleware::Next<B>,
+) -> axum::response::Response {
+    let state = request
+        .extensions()
+        .get::<Arc<State<T>>>()
+        .cloned()
+        .expect("state extension is set");
+    let config = state.config.auth();
+    if !config.is_enabled() {
+        return next.run(request).await;
+    }
+    let uri = request.uri().clone();
+    match config.authorize(uri.path(), uri.query(), request.headers()) {
+        Ok(access) => {
+            request.extensions_mut().insert(access);
+            next.run(request).await
+        }
+        Err(err) => unauthorized(err, &state, request.method()),
+    }
+}
+
+/// Rejects requests to the admin endpoints without a bearer token covering them, whether
+/// access control is enabled or not.
+pub async fn admin_auth_middleware<T: ContentLoader, B>(
+    mut request: axum::http::Request<B>,
+    next: axum::middleware::Next<B>,
+) -> axum::response::Response {
+    let state = request
+        .extensions()
+        .get::<Arc<State<T>>>()
+        .cloned()
+        .expect("state extension is set");
+    let path = request.uri().path().to_string();
+    match state
+        .config
+        .auth()
+        .authorize_admin(&path, request.headers())
+    {
+        Ok(access) => {
+            request.extensions_mut().insert(access);
+            next.run(request).await
+        }
+        Err(err) => unauthorized(err, &state, request.method()),
+    }
+}
+
+fn unauthorized<T: ContentLoader>(
+    err: AuthError,
+    state: &State<T>,
+    method: &Method,
+) -> axum::response::Response {
+    inc!(GatewayMetrics::Unauthorized);
+    let status_code = err.status_code();
+    let mut res = error(status_code, &err.to_string(), state)
+        .with_method(method.clone())
+        .into_response();
+    if status_code == StatusCode::UNAUTHORIZED {
+        res.headers_mut()
+            .insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+    }
+    res
+}
+
 #[tracing::instrument()]
 pub async fn middleware_error_handler<T: ContentLoader>(
     method: Method,
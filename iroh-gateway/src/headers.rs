@@ -1,4 +1,8 @@
-use crate::{constants::*, response::ResponseFormat};
+use crate::{
+    client::{DagScope, EntityBytes},
+    constants::*,
+    response::ResponseFormat,
+};
 use ::time::OffsetDateTime;
 use axum::http::header::*;
 use iroh_resolver::resolver::{CidOrDomain, Metadata, PathType};
@@ -75,7 +79,7 @@ pub fn set_content_disposition_headers(headers: &mut HeaderMap, filename: &str,
 #[tracing::instrument()]
 pub fn add_content_range_headers(headers: &mut HeaderMap, range: Range<u64>, size: Option<u64>) {
     if range.end == 0 {
-        // this should never happen as it is checked for in parse_range_header
+        // this should never happen as empty ranges are dropped in resolve_ranges
         // but just to avoid any footguns
         return;
     }
@@ -90,20 +94,115 @@ pub fn add_content_range_headers(headers: &mut HeaderMap, range: Range<u64>, siz
     );
 }
 
-pub fn parse_range_header(range: &HeaderValue) -> Option<Range<u64>> {
-    // TODO: potentially support multiple ranges ie bytes=0-100,200-300
-    let range = range.to_str().ok()?;
-    let mut parts = range.splitn(2, '=');
-    if parts.next() != Some("bytes") {
-        return None;
+#[tracing::instrument()]
+pub fn add_unsatisfiable_range_headers(headers: &mut HeaderMap, size: Option<u64>) {
+    let content_range = match size {
+        Some(size) => format!("bytes */{}", size),
+        None => "bytes */*".to_string(),
+    };
+    headers.insert(
+        CONTENT_RANGE,
+        HeaderValue::from_str(&content_range).unwrap(),
+    );
+}
+
+/// A single byte range as requested in a `Range` header, see RFC 7233 section 2.1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteRange {
+    /// `first-last`, both positions inclusive.
+    FromTo(u64, u64),
+    /// `first-`, everything from `first` until the end of the content.
+    From(u64),
+    /// `-length`, the last `length` bytes of the content.
+    Suffix(u64),
+}
+
+impl ByteRange {
+    /// Resolves the range against a content of `size` bytes, returning the (exclusive)
+    /// byte range to serve or `None` if the range is not satisfiable.
+    pub fn to_range(&self, size: u64) -> Option<Range<u64>> {
+        let range = match *self {
+            ByteRange::FromTo(start, end) => start..std::cmp::min(end.saturating_add(1), size),
+            ByteRange::From(start) => start..size,
+            ByteRange::Suffix(len) => size.saturating_sub(len)..size,
+        };
+        if range.start >= range.end {
+            return None;
+        }
+        Some(range)
     }
-    let mut range = parts.next()?.splitn(2, '-');
-    let start = range.next()?.parse().ok()?;
-    let end = range.next()?.parse().ok()?;
-    if start >= end || end == 0 {
-        return None;
+
+    /// Returns the range if it can be served without knowing the content size.
+    pub fn bounded(&self) -> Option<Range<u64>> {
+        match *self {
+            ByteRange::FromTo(start, end) => Some(start..end.saturating_add(1)),
+            _ => None,
+        }
     }
-    Some(Range { start, end })
+}
+
+/// Parses a `Range` header into its byte ranges.
+///
+/// Returns an error for malformed headers, unsupported units or more than
+/// `MAX_RANGES` ranges.
+pub fn parse_range_header(range: &HeaderValue) -> Result<Vec<ByteRange>, String> {
+    let range = range
+        .to_str()
+        .map_err(|_| "invalid range header".to_string())?;
+    let (unit, specs) = range
+        .split_once('=')
+        .ok_or_else(|| "invalid range header".to_string())?;
+    if unit.trim() != "bytes" {
+        return Err(format!("unsupported range unit: {}", unit));
+    }
+
+    let mut ranges = Vec::new();
+    for spec in specs.split(',') {
+        let spec = spec.trim();
+        if spec.is_empty() {
+            continue;
+        }
+        if ranges.len() == MAX_RANGES {
+            return Err(format!("too many ranges, at most {} allowed", MAX_RANGES));
+        }
+        let (start, end) = spec
+            .split_once('-')
+            .ok_or_else(|| format!("invalid range: {}", spec))?;
+        let parse = |v: &str| {
+            v.trim()
+                .parse::<u64>()
+                .map_err(|_| format!("invalid range: {}", spec))
+        };
+        let range = match (start.trim().is_empty(), end.trim().is_empty()) {
+            (false, false) => {
+                let (start, end) = (parse(start)?, parse(end)?);
+                if start > end {
+                    return Err(format!("invalid range: {}", spec));
+                }
+                ByteRange::FromTo(start, end)
+            }
+            (false, true) => ByteRange::From(parse(start)?),
+            (true, false) => {
+                let len = parse(end)?;
+                if len == 0 {
+                    return Err(format!("invalid range: {}", spec));
+                }
+                ByteRange::Suffix(len)
+            }
+            (true, true) => return Err(format!("invalid range: {}", spec)),
+        };
+        ranges.push(range);
+    }
+    if ranges.is_empty() {
+        return Err("empty range header".to_string());
+    }
+    Ok(ranges)
+}
+
+/// Resolves the requested ranges against a content of `size` bytes, dropping the
+/// unsatisfiable ones.
+pub fn resolve_ranges(ranges: &[ByteRange], size: u64) -> Vec<Range<u64>> {
+    ranges.iter().filter_map(|r| r.to_range(size)).collect()
 }
 
 #[tracing::instrument()]
@@ -155,6 +254,34 @@ pub fn add_etag_range(headers: &mut HeaderMap, range: Range<u64>) {
     }
 }
 
+#[tracing::instrument()]
+pub fn add_etag_dag_scope(
+    headers: &mut HeaderMap,
+    scope: DagScope,
+    entity_bytes: Option<EntityBytes>,
+) {
+    if headers.contains_key(ETAG) {
+        let etag = headers.get(ETAG).unwrap().to_str().unwrap();
+        let etag = etag.trim_end_matches('"');
+        let etag = match entity_bytes {
+            Some(entity_bytes) => format!("{}.{}.{}\"", etag, scope, entity_bytes),
+            None => format!("{}.{}\"", etag, scope),
+        };
+        headers.insert(ETAG, HeaderValue::from_str(&etag).unwrap());
+    }
+}
+
+/// Adds the page of a directory listing to the etag, pages of a directory differ in content.
+#[tracing::instrument()]
+pub fn add_etag_dir_page(headers: &mut HeaderMap, offset: usize, limit: usize) {
+    if headers.contains_key(ETAG) {
+        let etag = headers.get(ETAG).unwrap().to_str().unwrap();
+        let etag = etag.trim_end_matches('"');
+        let etag = format!("{}.page-{}-{}\"", etag, offset, limit);
+        headers.insert(ETAG, HeaderValue::from_str(&etag).unwrap());
+    }
+}
+
 #[tracing::instrument()]
 pub fn get_etag(cid: &CidOrDomain, response_format: Option<ResponseFormat>) -> String {
     match cid {
@@ -321,37 +448,72 @@ mod tests {
     fn parse_range_header_test() {
         let range = HeaderValue::from_str("bytes=0-10").unwrap();
         let r = parse_range_header(&range);
-        assert_eq!(r, Some(Range { start: 0, end: 10 }));
+        assert_eq!(r, Ok(vec![ByteRange::FromTo(0, 10)]));
 
         let range = HeaderValue::from_str("byts=0-10").unwrap();
         let r = parse_range_header(&range);
-        assert_eq!(r, None);
+        assert!(r.is_err());
 
         let range = HeaderValue::from_str("bytes=0-").unwrap();
         let r = parse_range_header(&range);
-        assert_eq!(r, None);
+        assert_eq!(r, Ok(vec![ByteRange::From(0)]));
+
+        let range = HeaderValue::from_str("bytes=-20").unwrap();
+        let r = parse_range_header(&range);
+        assert_eq!(r, Ok(vec![ByteRange::Suffix(20)]));
 
         let range = HeaderValue::from_str("bytes=10-1").unwrap();
         let r = parse_range_header(&range);
-        assert_eq!(r, None);
+        assert!(r.is_err());
 
         let range = HeaderValue::from_str("bytes=0-0").unwrap();
         let r = parse_range_header(&range);
-        assert_eq!(r, None);
+        assert_eq!(r, Ok(vec![ByteRange::FromTo(0, 0)]));
 
         let range = HeaderValue::from_str("bytes=100-200").unwrap();
         let r = parse_range_header(&range);
+        assert_eq!(r, Ok(vec![ByteRange::FromTo(100, 200)]));
+
+        let range = HeaderValue::from_str("bytes=0-10, 20-30,-5").unwrap();
+        let r = parse_range_header(&range);
         assert_eq!(
             r,
-            Some(Range {
-                start: 100,
-                end: 200
-            })
+            Ok(vec![
+                ByteRange::FromTo(0, 10),
+                ByteRange::FromTo(20, 30),
+                ByteRange::Suffix(5)
+            ])
         );
 
-        let range = HeaderValue::from_str("bytes=0-10,20-30").unwrap();
-        let r = parse_range_header(&range);
-        assert_eq!(r, None);
+        for invalid in ["bytes=", "bytes=-", "bytes=a-b", "bytes=-0", "bytes=1-2-3"] {
+            let range = HeaderValue::from_str(invalid).unwrap();
+            assert!(parse_range_header(&range).is_err(), "{}", invalid);
+        }
+
+        let too_many = (0..=MAX_RANGES)
+            .map(|i| format!("{}-{}", i * 10, i * 10 + 1))
+            .collect::<Vec<_>>()
+            .join(",");
+        let range = HeaderValue::from_str(&format!("bytes={}", too_many)).unwrap();
+        assert!(parse_range_header(&range).is_err());
+    }
+
+    #[test]
+    fn resolve_ranges_test() {
+        let ranges = [
+            ByteRange::FromTo(0, 9),
+            ByteRange::FromTo(90, 200),
+            ByteRange::From(50),
+            ByteRange::Suffix(10),
+            ByteRange::Suffix(200),
+            ByteRange::FromTo(100, 200),
+            ByteRange::From(100),
+        ];
+        assert_eq!(
+            resolve_ranges(&ranges, 100),
+            vec![0..10, 90..100, 50..100, 90..100, 0..100]
+        );
+        assert!(resolve_ranges(&[ByteRange::From(0)], 0).is_empty());
     }
 
     #[test]
@@ -471,4 +633,24 @@ mod tests {
         assert!(etag_matches(&long_etag, &etag));
         assert!(!etag_matches(&etag, &other_wetag));
     }
+
+    #[test]
+    fn etag_dir_page_test() {
+        let cid = CidOrDomain::Cid(
+            Cid::try_from("bafkreigh2akiscaildcqabsyg3dfr6chu3fgpregiymsck7e7aqa4s52zy").unwrap(),
+        );
+        let page_etag = |offset, limit| {
+            let mut headers = HeaderMap::new();
+            set_etag_headers(&mut headers, get_etag(&cid, Some(ResponseFormat::Json)));
+            add_etag_dir_page(&mut headers, offset, limit);
+            headers.get(ETAG).unwrap().to_str().unwrap().to_string()
+        };
+
+        assert_eq!(
+            page_etag(2, 10),
+            "\"bafkreigh2akiscaildcqabsyg3dfr6chu3fgpregiymsck7e7aqa4s52zy.json.page-2-10\""
+        );
+        assert_ne!(page_etag(0, 10), page_etag(10, 10));
+        assert_ne!(page_etag(0, 10), page_etag(0, 20));
+    }
 }
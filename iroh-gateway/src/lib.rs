@@ -1,4 +1,8 @@
+pub mod access_log;
+pub mod api_v0;
+pub mod auth;
 pub mod bad_bits;
+pub mod cache;
 pub mod cli;
 pub mod client;
 pub mod config;
@@ -8,6 +12,8 @@ mod error;
 pub mod handlers;
 pub mod headers;
 pub mod metrics;
+pub mod rate_limit;
 pub mod response;
 mod rpc;
 pub mod templates;
+pub mod tls;
@@ -40,7 +40,9 @@ async fn main() -> Result<()> {
 
     let metrics_config = config.metrics.clone();
     let bad_bits = match config.use_denylist {
-        true => Arc::new(Some(RwLock::new(BadBits::new()))),
+        true => Arc::new(Some(RwLock::new(BadBits::with_sources(
+            config.denylist.clone(),
+        )))),
         false => Arc::new(None),
     };
     let rpc_addr = config
@@ -73,14 +75,18 @@ async fn main() -> Result<()> {
         }
     }
 
-    let server = handler.server();
-    println!("listening on {}", server.local_addr());
-    let core_task = tokio::spawn(async move {
-        server.await.unwrap();
-    });
+    let api = handler.spawn_api_server()?;
+    if let Some((addr, _)) = &api {
+        println!("HTTP RPC API listening on {}", addr);
+    }
+    let (addr, core_task) = handler.spawn_server()?;
+    println!("listening on {}", addr);
 
     iroh_util::block_until_sigint().await;
     core_task.abort();
+    if let Some((_, api_task)) = api {
+        api_task.abort();
+    }
 
     metrics_handle.shutdown();
     if let Some(handle) = bad_bits_handle {
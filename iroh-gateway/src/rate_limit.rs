@@ -0,0 +1,411 @@
+use std::{
+    fmt,
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use axum::{body::HttpBody, extract::ConnectInfo, http::Request};
+use bytes::Bytes;
+use config::{ConfigError, Map, Source, Value};
+use http::HeaderMap;
+use iroh_util::insert_into_config_map;
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::auth::constant_time_eq;
+use crate::constants::{HEADER_X_API_KEY, RATE_LIMIT_MAX_CLIENTS};
+
+/// Class of a gateway request, each class is limited separately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RouteClass {
+    Raw,
+    Car,
+    RecursiveCar,
+    Fs,
+}
+
+/// Token bucket quota: `burst` requests at once, refilled at `requests_per_minute`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Quota {
+    pub requests_per_minute: u32,
+    pub burst: u32,
+}
+
+impl Source for Quota {
+    fn clone_into_box(&self) -> Box<dyn Source + Send + Sync> {
+        Box::new(*self)
+    }
+
+    fn collect(&self) -> Result<Map<String, Value>, ConfigError> {
+        let mut map: Map<String, Value> = Map::new();
+        insert_into_config_map(
+            &mut map,
+            "requests_per_minute",
+            self.requests_per_minute as i64,
+        );
+        insert_into_config_map(&mut map, "burst", self.burst as i64);
+        Ok(map)
+    }
+}
+
+/// Rate limiting configuration, limits are disabled unless set.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RateLimitConfig {
+    /// api keys whose requests are accounted per key, instead of per client address
+    #[serde(default)]
+    pub api_keys: Vec<String>,
+    /// maximum number of recursive car responses being streamed at the same time
+    pub max_concurrent_recursive_car: Option<u32>,
+    /// quota for `?format=raw` requests
+    pub raw: Option<Quota>,
+    /// quota for non recursive `?format=car` requests
+    pub car: Option<Quota>,
+    /// quota for recursive `?format=car` requests
+    pub recursive_car: Option<Quota>,
+    /// quota for unixfs requests
+    pub fs: Option<Quota>,
+}
+
+impl RateLimitConfig {
+    fn quota(&self, class: RouteClass) -> Option<Quota> {
+        match class {
+            RouteClass::Raw => self.raw,
+            RouteClass::Car => self.car,
+            RouteClass::RecursiveCar => self.recursive_car,
+            RouteClass::Fs => self.fs,
+        }
+    }
+}
+
+impl Source for RateLimitConfig {
+    fn clone_into_box(&self) -> Box<dyn Source + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn collect(&self) -> Result<Map<String, Value>, ConfigError> {
+        let mut map: Map<String, Value> = Map::new();
+        if !self.api_keys.is_empty() {
+            insert_into_config_map(&mut map, "api_keys", self.api_keys.clone());
+        }
+        if let Some(max) = self.max_concurrent_recursive_car {
+            insert_into_config_map(&mut map, "max_concurrent_recursive_car", max as i64);
+        }
+        for (name, quota) in [
+            ("raw", self.raw),
+            ("car", self.car),
+            ("recursive_car", self.recursive_car),
+            ("fs", self.fs),
+        ] {
+            if let Some(quota) = quota {
+                insert_into_config_map(&mut map, name, quota.collect()?);
+            }
+        }
+        Ok(map)
+    }
+}
+
+/// Identifies the client a request is accounted to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ClientKey {
+    ApiKey(String),
+    Ip(IpAddr),
+}
+
+impl ClientKey {
+    /// Uses the api key of the request if it is one of `api_keys`, the peer address
+    /// otherwise. Returns `None` if neither is known, eg. for requests over unix sockets.
+    pub fn from_request<B>(req: &Request<B>, api_keys: &[String]) -> Option<Self> {
+        if let Some(key) = req.headers().get(&HEADER_X_API_KEY).and_then(|v| {
+            api_keys
+                .iter()
+                .find(|key| constant_time_eq(key.as_bytes(), v.as_bytes()))
+        }) {
+            return Some(ClientKey::ApiKey(key.clone()));
+        }
+        req.extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| ClientKey::Ip(addr.ip()))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    last: Instant,
+}
+
+impl Bucket {
+    /// Takes a token, returning how long to wait for the next one if there is none.
+    fn take(&mut self, quota: Quota, now: Instant) -> Result<(), Duration> {
+        let per_sec = quota.requests_per_minute as f64 / 60.0;
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * per_sec).min(quota.burst as f64);
+        self.last = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return Ok(());
+        }
+        if per_sec == 0.0 {
+            return Err(Duration::from_secs(60));
+        }
+        Err(Duration::from_secs_f64((1.0 - self.tokens) / per_sec))
+    }
+}
+
+/// Why a request was rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rejection {
+    /// The client exhausted its quota, retry after the given duration.
+    RateLimited(Duration),
+    /// Too many recursive car responses are in flight.
+    TooManyConcurrent,
+}
+
+/// Per client token bucket rate limiter.
+#[derive(Clone)]
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: Arc<Mutex<LruCache<(RouteClass, ClientKey), Bucket>>>,
+    /// Number of buckets above which the least recently used ones are dropped.
+    max_clients: usize,
+    recursive_car: Option<Arc<Semaphore>>,
+}
+
+impl fmt::Debug for RateLimiter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RateLimiter")
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        let recursive_car = config
+            .max_concurrent_recursive_car
+            .map(|max| Arc::new(Semaphore::new(max as usize)));
+        RateLimiter {
+            config,
+            buckets: Arc::new(Mutex::new(LruCache::unbounded())),
+            max_clients: RATE_LIMIT_MAX_CLIENTS,
+            recursive_car,
+        }
+    }
+
+    /// Identifies the client of the request, see [`ClientKey::from_request`].
+    pub fn client_key<B>(&self, req: &Request<B>) -> Option<ClientKey> {
+        ClientKey::from_request(req, &self.config.api_keys)
+    }
+
+    /// Accounts a request of the given class to the client.
+    pub fn check(&self, class: RouteClass, client: Option<ClientKey>) -> Result<(), Rejection> {
+        self.check_at(class, client, Instant::now())
+    }
+
+    fn check_at(
+        &self,
+        class: RouteClass,
+        client: Option<ClientKey>,
+        now: Instant,
+    ) -> Result<(), Rejection> {
+        let (quota, client) = match (self.config.quota(class), client) {
+            (Some(quota), Some(client)) => (quota, client),
+            _ => return Ok(()),
+        };
+        let key = (class, client);
+        let mut buckets = self.buckets.lock().unwrap();
+        if let Some(bucket) = buckets.get_mut(&key) {
+            return bucket.take(quota, now).map_err(Rejection::RateLimited);
+        }
+        // the clients that were seen least recently start over with a full bucket
+        while buckets.len() >= self.max_clients {
+            buckets.pop_lru();
+        }
+        let mut bucket = Bucket {
+            tokens: quota.burst as f64,
+            last: now,
+        };
+        let res = bucket.take(quota, now);
+        buckets.put(key, bucket);
+        res.map_err(Rejection::RateLimited)
+    }
+
+    /// Reserves a slot for a recursive car response, the slot is released once the
+    /// returned permit is dropped.
+    pub fn acquire_recursive_car(&self) -> Result<Option<OwnedSemaphorePermit>, Rejection> {
+        match &self.recursive_car {
+            Some(semaphore) => semaphore
+                .clone()
+                .try_acquire_owned()
+                .map(Some)
+                .map_err(|_| Rejection::TooManyConcurrent),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Body holding on to a concurrency permit until it is fully streamed or dropped.
+pub struct PermitBody<B> {
+    body: B,
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl<B> PermitBody<B> {
+    pub fn new(body: B, permit: Option<OwnedSemaphorePermit>) -> Self {
+        PermitBody {
+            body,
+            _permit: permit,
+        }
+    }
+}
+
+impl<B: HttpBody<Data = Bytes> + Unpin> HttpBody for PermitBody<B> {
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        Pin::new(&mut self.body).poll_data(cx)
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        Pin::new(&mut self.body).poll_trailers(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.body.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter() -> RateLimiter {
+        RateLimiter::new(RateLimitConfig {
+            max_concurrent_recursive_car: Some(1),
+            raw: Some(Quota {
+                requests_per_minute: 60,
+                burst: 2,
+            }),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn token_bucket() {
+        let limiter = limiter();
+        let client = Some(ClientKey::Ip("127.0.0.1".parse().unwrap()));
+        let other = Some(ClientKey::ApiKey("key".to_string()));
+        let now = Instant::now();
+
+        assert!(limiter
+            .check_at(RouteClass::Raw, client.clone(), now)
+            .is_ok());
+        assert!(limiter
+            .check_at(RouteClass::Raw, client.clone(), now)
+            .is_ok());
+        assert_eq!(
+            limiter.check_at(RouteClass::Raw, client.clone(), now),
+            Err(Rejection::RateLimited(Duration::from_secs(1)))
+        );
+        // other clients and unlimited classes are not affected
+        assert!(limiter.check_at(RouteClass::Raw, other, now).is_ok());
+        assert!(limiter
+            .check_at(RouteClass::Fs, client.clone(), now)
+            .is_ok());
+        assert!(limiter.check_at(RouteClass::Raw, None, now).is_ok());
+
+        // one token per second is refilled
+        let later = now + Duration::from_millis(1500);
+        assert!(limiter
+            .check_at(RouteClass::Raw, client.clone(), later)
+            .is_ok());
+        assert_eq!(
+            limiter.check_at(RouteClass::Raw, client, later),
+            Err(Rejection::RateLimited(Duration::from_millis(500)))
+        );
+    }
+
+    #[test]
+    fn clients_are_bounded() {
+        let mut limiter = limiter();
+        limiter.max_clients = 2;
+        let client = |i: u8| Some(ClientKey::Ip(IpAddr::from([10, 0, 0, i])));
+        let now = Instant::now();
+
+        for _ in 0..2 {
+            assert!(limiter.check_at(RouteClass::Raw, client(1), now).is_ok());
+        }
+        assert!(limiter.check_at(RouteClass::Raw, client(1), now).is_err());
+        assert!(limiter.check_at(RouteClass::Raw, client(2), now).is_ok());
+        assert!(limiter.check_at(RouteClass::Raw, client(3), now).is_ok());
+        assert_eq!(limiter.buckets.lock().unwrap().len(), 2);
+
+        // client 1 was seen least recently and was dropped
+        assert!(limiter.check_at(RouteClass::Raw, client(1), now).is_ok());
+        assert!(limiter.check_at(RouteClass::Raw, client(3), now).is_ok());
+        assert!(limiter.check_at(RouteClass::Raw, client(3), now).is_err());
+    }
+
+    #[test]
+    fn client_key() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            api_keys: vec!["known".to_string()],
+            ..Default::default()
+        });
+        let addr: SocketAddr = "127.0.0.1:4000".parse().unwrap();
+        let request = |api_key: Option<&str>| {
+            let mut req = Request::builder();
+            if let Some(api_key) = api_key {
+                req = req.header(&HEADER_X_API_KEY, api_key);
+            }
+            let mut req = req.body(()).unwrap();
+            req.extensions_mut().insert(ConnectInfo(addr));
+            req
+        };
+
+        assert_eq!(
+            limiter.client_key(&request(Some("known"))),
+            Some(ClientKey::ApiKey("known".to_string()))
+        );
+        // unknown keys do not escape the limit of the address
+        assert_eq!(
+            limiter.client_key(&request(Some("random"))),
+            Some(ClientKey::Ip(addr.ip()))
+        );
+        assert_eq!(
+            limiter.client_key(&request(None)),
+            Some(ClientKey::Ip(addr.ip()))
+        );
+    }
+
+    #[test]
+    fn recursive_car_concurrency() {
+        let limiter = limiter();
+        let permit = limiter.acquire_recursive_car().unwrap();
+        assert!(permit.is_some());
+        assert_eq!(
+            limiter.acquire_recursive_car().unwrap_err(),
+            Rejection::TooManyConcurrent
+        );
+        drop(permit);
+        assert!(limiter.acquire_recursive_car().is_ok());
+
+        let unlimited = RateLimiter::new(RateLimitConfig::default());
+        assert!(unlimited.acquire_recursive_car().unwrap().is_none());
+    }
+}
@@ -14,6 +14,8 @@ pub const ERR_UNSUPPORTED_FORMAT: &str = "unsuported format";
 pub enum ResponseFormat {
     Raw,
     Car,
+    /// Machine readable directory listing, other content is served as with `Fs`.
+    Json,
     Fs(String),
 }
 
@@ -24,6 +26,7 @@ impl std::convert::TryFrom<&str> for ResponseFormat {
         match s.to_lowercase().as_str() {
             "application/vnd.ipld.raw" | "raw" => Ok(ResponseFormat::Raw),
             "application/vnd.ipld.car" | "car" => Ok(ResponseFormat::Car),
+            "application/json" | "json" => Ok(ResponseFormat::Json),
             "fs" | "" => Ok(ResponseFormat::Fs(String::new())),
             rf => {
                 if rf.starts_with("application/vnd.ipld.") {
@@ -48,7 +51,7 @@ impl ResponseFormat {
                 headers.insert(&HEADER_X_CONTENT_TYPE_OPTIONS, VALUE_XCTO_NOSNIFF.clone());
                 headers.insert(ACCEPT_RANGES, VALUE_NONE.clone());
             }
-            ResponseFormat::Fs(_) => {
+            ResponseFormat::Json | ResponseFormat::Fs(_) => {
                 // Don't send application/octet-stream in that case, let the
                 // client decide instead.
             }
@@ -59,6 +62,7 @@ impl ResponseFormat {
         match self {
             ResponseFormat::Raw => "bin".to_string(),
             ResponseFormat::Car => "car".to_string(),
+            ResponseFormat::Json => "json".to_string(),
             ResponseFormat::Fs(s) => {
                 if s.is_empty() {
                     String::new()
@@ -75,7 +79,8 @@ impl ResponseFormat {
                 let h_values = h_values.to_str().unwrap().split(',');
                 for h_value in h_values {
                     let h_value = h_value.trim();
-                    if h_value.starts_with("application/vnd.ipld.") {
+                    if h_value.starts_with("application/vnd.ipld.") || h_value == "application/json"
+                    {
                         return ResponseFormat::try_from(h_value);
                     }
                 }
@@ -178,6 +183,10 @@ mod tests {
         assert_eq!(rf, Ok(ResponseFormat::Fs(String::new())));
         let rf = ResponseFormat::try_from("");
         assert_eq!(rf, Ok(ResponseFormat::Fs(String::new())));
+        let rf = ResponseFormat::try_from("json");
+        assert_eq!(rf, Ok(ResponseFormat::Json));
+        let rf = ResponseFormat::try_from("application/json");
+        assert_eq!(rf, Ok(ResponseFormat::Json));
 
         let rf = ResponseFormat::try_from("RaW");
         assert_eq!(rf, Ok(ResponseFormat::Raw));
@@ -213,4 +222,29 @@ mod tests {
         rf.write_headers(&mut headers);
         assert_eq!(headers.len(), 0);
     }
+
+    #[test]
+    fn response_format_try_from_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, HeaderValue::from_static("application/json"));
+        assert_eq!(
+            ResponseFormat::try_from_headers(&headers),
+            Ok(ResponseFormat::Json)
+        );
+
+        headers.insert(
+            ACCEPT,
+            HeaderValue::from_static("text/html, application/vnd.ipld.car"),
+        );
+        assert_eq!(
+            ResponseFormat::try_from_headers(&headers),
+            Ok(ResponseFormat::Car)
+        );
+
+        headers.insert(ACCEPT, HeaderValue::from_static("text/html, */*"));
+        assert_eq!(
+            ResponseFormat::try_from_headers(&headers),
+            Ok(ResponseFormat::Fs(String::new()))
+        );
+    }
 }
@@ -0,0 +1,349 @@
+use std::{
+    fs::File,
+    io::{self, BufReader},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{Arc, RwLock, Weak},
+    task::{Context, Poll},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{anyhow, ensure, Context as _, Result};
+use axum::{
+    extract::connect_info::Connected,
+    http::{header::HOST, uri::Authority, HeaderMap, StatusCode, Uri},
+    response::{IntoResponse, Redirect, Response},
+    Router,
+};
+use config::{ConfigError, Map, Source, Value};
+use futures::{future::BoxFuture, stream::FuturesUnordered, Stream};
+use hyper::server::{
+    accept::Accept,
+    conn::{AddrIncoming, AddrStream},
+};
+use iroh_util::insert_into_config_map;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_rustls::{
+    rustls::{
+        server::{ClientHello, ResolvesServerCert},
+        sign::{self, CertifiedKey},
+        Certificate, PrivateKey, ServerConfig,
+    },
+    server::TlsStream,
+    TlsAcceptor,
+};
+
+use crate::constants::{MAX_TLS_HANDSHAKES, TLS_HANDSHAKE_TIMEOUT};
+
+/// TLS termination configuration.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TlsConfig {
+    /// path to the PEM encoded certificate chain
+    pub cert_path: PathBuf,
+    /// path to the PEM encoded private key
+    pub key_path: PathBuf,
+    /// how often the certificate and key files are checked for changes
+    #[serde(default = "default_reload_interval_secs")]
+    pub reload_interval_secs: u64,
+    /// port to serve redirects from plain HTTP to HTTPS on
+    pub redirect_http_port: Option<u16>,
+}
+
+fn default_reload_interval_secs() -> u64 {
+    60
+}
+
+impl Source for TlsConfig {
+    fn clone_into_box(&self) -> Box<dyn Source + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn collect(&self) -> Result<Map<String, Value>, ConfigError> {
+        let mut map: Map<String, Value> = Map::new();
+        insert_into_config_map(
+            &mut map,
+            "cert_path",
+            self.cert_path.to_string_lossy().to_string(),
+        );
+        insert_into_config_map(
+            &mut map,
+            "key_path",
+            self.key_path.to_string_lossy().to_string(),
+        );
+        insert_into_config_map(
+            &mut map,
+            "reload_interval_secs",
+            self.reload_interval_secs as i64,
+        );
+        if let Some(port) = self.redirect_http_port {
+            insert_into_config_map(&mut map, "redirect_http_port", port as i64);
+        }
+        Ok(map)
+    }
+}
+
+/// Builds the rustls config of the gateway, negotiating HTTP/2 or HTTP/1.1 through ALPN.
+///
+/// The certificate is reloaded in the background whenever the certificate or key file
+/// changes, for as long as the returned config is alive.
+pub fn server_config(config: &TlsConfig) -> Result<Arc<ServerConfig>> {
+    let resolver = Arc::new(CertResolver::load(config)?);
+    tokio::spawn(reload_certs(
+        Arc::downgrade(&resolver),
+        config.clone(),
+        resolver.modified(),
+    ));
+
+    let mut server_config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_cert_resolver(resolver);
+    server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(Arc::new(server_config))
+}
+
+/// Serves the current certificate, which is swapped out when the files are rotated.
+struct CertResolver {
+    key: RwLock<Arc<CertifiedKey>>,
+    cert_path: PathBuf,
+    key_path: PathBuf,
+}
+
+impl CertResolver {
+    fn load(config: &TlsConfig) -> Result<Self> {
+        let key = load_certified_key(&config.cert_path, &config.key_path)?;
+        Ok(CertResolver {
+            key: RwLock::new(Arc::new(key)),
+            cert_path: config.cert_path.clone(),
+            key_path: config.key_path.clone(),
+        })
+    }
+
+    /// Last modification times of the certificate and key files.
+    fn modified(&self) -> (Option<SystemTime>, Option<SystemTime>) {
+        let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+        (modified(&self.cert_path), modified(&self.key_path))
+    }
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        Some(Arc::clone(&self.key.read().unwrap()))
+    }
+}
+
+async fn reload_certs(
+    resolver: Weak<CertResolver>,
+    config: TlsConfig,
+    mut modified: (Option<SystemTime>, Option<SystemTime>),
+) {
+    let mut interval =
+        tokio::time::interval(Duration::from_secs(config.reload_interval_secs.max(1)));
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        let resolver = match resolver.upgrade() {
+            Some(resolver) => resolver,
+            None => return,
+        };
+        let current = resolver.modified();
+        if current == modified {
+            continue;
+        }
+        // keep serving the previous certificate if the files are being rotated
+        match load_certified_key(&config.cert_path, &config.key_path) {
+            Ok(key) => {
+                *resolver.key.write().unwrap() = Arc::new(key);
+                modified = current;
+                tracing::info!("reloaded tls certificate {}", config.cert_path.display());
+            }
+            Err(err) => tracing::warn!("failed to reload tls certificate: {:?}", err),
+        }
+    }
+}
+
+fn load_certified_key(cert_path: &Path, key_path: &Path) -> Result<CertifiedKey> {
+    let certs = rustls_pemfile::certs(&mut BufReader::new(
+        File::open(cert_path).with_context(|| format!("opening {}", cert_path.display()))?,
+    ))?;
+    ensure!(
+        !certs.is_empty(),
+        "no certificates found in {}",
+        cert_path.display()
+    );
+    let key = rustls_pemfile::read_all(&mut BufReader::new(
+        File::open(key_path).with_context(|| format!("opening {}", key_path.display()))?,
+    ))?
+    .into_iter()
+    .find_map(|item| match item {
+        rustls_pemfile::Item::RSAKey(key)
+        | rustls_pemfile::Item::PKCS8Key(key)
+        | rustls_pemfile::Item::ECKey(key) => Some(key),
+        _ => None,
+    })
+    .ok_or_else(|| anyhow!("no private key found in {}", key_path.display()))?;
+    let key = sign::any_supported_type(&PrivateKey(key))
+        .map_err(|_| anyhow!("unsupported private key in {}", key_path.display()))?;
+    Ok(CertifiedKey::new(
+        certs.into_iter().map(Certificate).collect(),
+        key,
+    ))
+}
+
+/// Accepts TCP connections and completes their TLS handshakes concurrently.
+///
+/// At most `MAX_TLS_HANDSHAKES` handshakes run at a time, further connections are left in
+/// the listen backlog until one of them finishes.
+pub struct TlsIncoming {
+    incoming: AddrIncoming,
+    acceptor: TlsAcceptor,
+    handshakes: FuturesUnordered<BoxFuture<'static, io::Result<TlsStream<AddrStream>>>>,
+}
+
+impl TlsIncoming {
+    pub fn new(incoming: AddrIncoming, config: Arc<ServerConfig>) -> Self {
+        TlsIncoming {
+            incoming,
+            acceptor: TlsAcceptor::from(config),
+            handshakes: FuturesUnordered::new(),
+        }
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.incoming.local_addr()
+    }
+}
+
+impl Accept for TlsIncoming {
+    type Conn = TlsConn;
+    type Error = io::Error;
+
+    fn poll_accept(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Conn, Self::Error>>> {
+        let this = &mut *self;
+        loop {
+            while this.handshakes.len() < MAX_TLS_HANDSHAKES {
+                match Pin::new(&mut this.incoming).poll_accept(cx) {
+                    Poll::Ready(Some(Ok(stream))) => {
+                        let acceptor = this.acceptor.clone();
+                        this.handshakes.push(Box::pin(async move {
+                            tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, acceptor.accept(stream))
+                                .await
+                                .unwrap_or_else(|_| {
+                                    Err(io::Error::new(
+                                        io::ErrorKind::TimedOut,
+                                        "tls handshake timed out",
+                                    ))
+                                })
+                        }));
+                    }
+                    Poll::Ready(Some(Err(err))) => return Poll::Ready(Some(Err(err))),
+                    Poll::Ready(None) => return Poll::Ready(None),
+                    Poll::Pending => break,
+                }
+            }
+            // a finished handshake frees a slot, so accepting resumes on the next iteration
+            match Pin::new(&mut this.handshakes).poll_next(cx) {
+                Poll::Ready(Some(Ok(stream))) => return Poll::Ready(Some(Ok(TlsConn(stream)))),
+                Poll::Ready(Some(Err(err))) => {
+                    tracing::debug!("tls handshake failed: {}", err);
+                }
+                Poll::Ready(None) | Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+/// An established TLS connection.
+pub struct TlsConn(TlsStream<AddrStream>);
+
+impl AsyncRead for TlsConn {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for TlsConn {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
+
+impl Connected<&TlsConn> for SocketAddr {
+    fn connect_info(target: &TlsConn) -> Self {
+        target.0.get_ref().0.remote_addr()
+    }
+}
+
+/// Routes redirecting every plain HTTP request to the same url over HTTPS on `https_port`.
+pub fn get_redirect_routes(https_port: u16) -> Router {
+    Router::new().fallback(axum::routing::any(move |headers: HeaderMap, uri: Uri| async move {
+        redirect_to_https(&headers, uri, https_port)
+    }))
+}
+
+fn redirect_to_https(headers: &HeaderMap, uri: Uri, https_port: u16) -> Response {
+    let host = headers
+        .get(HOST)
+        .and_then(|host| host.to_str().ok())
+        .and_then(|host| host.parse::<Authority>().ok());
+    let host = match host {
+        Some(host) => host,
+        None => return (StatusCode::BAD_REQUEST, "missing host header").into_response(),
+    };
+    let authority = match https_port {
+        443 => host.host().to_string(),
+        port => format!("{}:{}", host.host(), port),
+    };
+    let path_and_query = uri.path_and_query().map(|pq| pq.as_str()).unwrap_or("/");
+    Redirect::permanent(&format!("https://{}{}", authority, path_and_query)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::{header::LOCATION, HeaderValue};
+
+    #[test]
+    fn redirects_to_https() {
+        let mut headers = HeaderMap::new();
+        headers.insert(HOST, HeaderValue::from_static("gateway.example.com:8080"));
+        let uri: Uri = "/ipfs/bafy/file.txt?format=raw".parse().unwrap();
+
+        let res = redirect_to_https(&headers, uri.clone(), 443);
+        assert_eq!(res.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(
+            res.headers().get(LOCATION).unwrap(),
+            "https://gateway.example.com/ipfs/bafy/file.txt?format=raw"
+        );
+
+        let res = redirect_to_https(&headers, uri.clone(), 8443);
+        assert_eq!(
+            res.headers().get(LOCATION).unwrap(),
+            "https://gateway.example.com:8443/ipfs/bafy/file.txt?format=raw"
+        );
+
+        let res = redirect_to_https(&HeaderMap::new(), uri, 443);
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -1,6 +1,7 @@
 use config::{ConfigError, Map, Source, Value};
 use iroh_util::insert_into_config_map;
 use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Config {
@@ -23,6 +24,13 @@ pub struct Config {
     /// The endpoint of the prometheus push gateway.
     #[serde(alias = "prom_gateway_endpoint")]
     pub prom_gateway_endpoint: String,
+    /// Flag to enable pushing metrics to the prometheus push gateway.
+    pub push: bool,
+    /// Interval in seconds between two pushes to the prometheus push gateway.
+    pub push_interval_secs: u64,
+    /// Address of the HTTP listener serving metrics for prometheus to scrape at `/metrics`.
+    /// Disabled if not set.
+    pub listen_addr: Option<SocketAddr>,
     #[cfg(feature = "tokio-console")]
     /// Enables tokio console debugging.
     pub tokio_console: bool,
@@ -52,6 +60,17 @@ impl Source for Config {
             "prom_gateway_endpoint",
             self.prom_gateway_endpoint.clone(),
         );
+        insert_into_config_map(&mut map, "push", self.push);
+        // Some issue between deserializing u64 & u16, converting this to
+        // an signed int fixes the issue
+        insert_into_config_map(
+            &mut map,
+            "push_interval_secs",
+            self.push_interval_secs as i64,
+        );
+        if let Some(listen_addr) = self.listen_addr {
+            insert_into_config_map(&mut map, "listen_addr", listen_addr.to_string());
+        }
         #[cfg(feature = "tokio-console")]
         insert_into_config_map(&mut map, "tokio_console", self.tokio_console);
         Ok(map)
@@ -85,6 +104,9 @@ impl Default for Config {
             tracing: false,
             collector_endpoint: "http://localhost:4317".to_string(),
             prom_gateway_endpoint: "http://localhost:9091".to_string(),
+            push: true,
+            push_interval_secs: 5,
+            listen_addr: None,
             #[cfg(feature = "tokio-console")]
             tokio_console: false,
         }
@@ -130,6 +152,11 @@ mod tests {
             "prom_gateway_endpoint".to_string(),
             Value::new(None, cfg.prom_gateway_endpoint.clone()),
         );
+        expect.insert("push".to_string(), Value::new(None, cfg.push));
+        expect.insert(
+            "push_interval_secs".to_string(),
+            Value::new(None, cfg.push_interval_secs as i64),
+        );
         #[cfg(feature = "tokio-console")]
         expect.insert(
             "tokio_console".to_string(),
@@ -154,5 +181,16 @@ mod tests {
             .unwrap();
 
         assert_eq!(expect, got);
+
+        let mut expect = make_test_config();
+        expect.listen_addr = Some("127.0.0.1:9099".parse().unwrap());
+        let got: Config = config::Config::builder()
+            .add_source(expect.clone())
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+
+        assert_eq!(expect, got);
     }
 }
@@ -25,6 +25,13 @@ pub(crate) struct Metrics {
     bytes_streamed: Counter,
     error_count: Counter,
     fail_count: Counter,
+    rate_limited: Counter,
+    concurrency_limited: Counter,
+    unauthorized: Counter,
+    response_cache_hits: Counter,
+    response_cache_misses: Counter,
+    metadata_cache_hits: Counter,
+    metadata_cache_misses: Counter,
     hist_ttfb: Histogram,
     hist_ttfb_cached: Histogram,
     hist_ttsf: Histogram,
@@ -88,6 +95,55 @@ impl Metrics {
             Box::new(fail_count.clone()),
         );
 
+        let rate_limited = Counter::default();
+        sub_registry.register(
+            METRICS_RATE_LIMITED,
+            "Number of requests rejected for exceeding the client rate limit",
+            Box::new(rate_limited.clone()),
+        );
+
+        let concurrency_limited = Counter::default();
+        sub_registry.register(
+            METRICS_CONCURRENCY_LIMITED,
+            "Number of requests rejected for exceeding the concurrency limit",
+            Box::new(concurrency_limited.clone()),
+        );
+
+        let unauthorized = Counter::default();
+        sub_registry.register(
+            METRICS_UNAUTHORIZED,
+            "Number of requests rejected for missing or insufficient credentials",
+            Box::new(unauthorized.clone()),
+        );
+
+        let response_cache_hits = Counter::default();
+        sub_registry.register(
+            METRICS_RESPONSE_CACHE_HITS,
+            "Number of responses served from the response cache",
+            Box::new(response_cache_hits.clone()),
+        );
+
+        let response_cache_misses = Counter::default();
+        sub_registry.register(
+            METRICS_RESPONSE_CACHE_MISSES,
+            "Number of cacheable requests not found in the response cache",
+            Box::new(response_cache_misses.clone()),
+        );
+
+        let metadata_cache_hits = Counter::default();
+        sub_registry.register(
+            METRICS_METADATA_CACHE_HITS,
+            "Number of paths resolved from the metadata cache",
+            Box::new(metadata_cache_hits.clone()),
+        );
+
+        let metadata_cache_misses = Counter::default();
+        sub_registry.register(
+            METRICS_METADATA_CACHE_MISSES,
+            "Number of paths not found in the metadata cache",
+            Box::new(metadata_cache_misses.clone()),
+        );
+
         let hist_ttfb = Histogram::new(linear_buckets(0.0, 500.0, 240));
         sub_registry.register(
             METRICS_HIST_TTFB,
@@ -116,6 +172,13 @@ impl Metrics {
             bytes_streamed,
             error_count,
             fail_count,
+            rate_limited,
+            concurrency_limited,
+            unauthorized,
+            response_cache_hits,
+            response_cache_misses,
+            metadata_cache_hits,
+            metadata_cache_misses,
             hist_ttfb,
             hist_ttfb_cached,
             hist_ttsf,
@@ -143,6 +206,20 @@ impl MetricsRecorder for Metrics {
             self.error_count.inc_by(value);
         } else if m.name() == GatewayMetrics::FailCount.name() {
             self.fail_count.inc_by(value);
+        } else if m.name() == GatewayMetrics::RateLimited.name() {
+            self.rate_limited.inc_by(value);
+        } else if m.name() == GatewayMetrics::ConcurrencyLimited.name() {
+            self.concurrency_limited.inc_by(value);
+        } else if m.name() == GatewayMetrics::Unauthorized.name() {
+            self.unauthorized.inc_by(value);
+        } else if m.name() == GatewayMetrics::ResponseCacheHits.name() {
+            self.response_cache_hits.inc_by(value);
+        } else if m.name() == GatewayMetrics::ResponseCacheMisses.name() {
+            self.response_cache_misses.inc_by(value);
+        } else if m.name() == GatewayMetrics::MetadataCacheHits.name() {
+            self.metadata_cache_hits.inc_by(value);
+        } else if m.name() == GatewayMetrics::MetadataCacheMisses.name() {
+            self.metadata_cache_misses.inc_by(value);
         } else if m.name() == GatewayMetrics::TimeToFetchFirstBlock.name() {
             self.ttf_block.set(value);
         } else if m.name() == GatewayMetrics::TimeToServeFirstBlock.name() {
@@ -176,6 +253,13 @@ pub enum GatewayMetrics {
     BytesStreamed,
     ErrorCount,
     FailCount,
+    RateLimited,
+    ConcurrencyLimited,
+    Unauthorized,
+    ResponseCacheHits,
+    ResponseCacheMisses,
+    MetadataCacheHits,
+    MetadataCacheMisses,
     TimeToFetchFirstBlock,
     TimeToServeFirstBlock,
     TimeToServeFullFile,
@@ -188,6 +272,13 @@ impl MetricType for GatewayMetrics {
             GatewayMetrics::BytesStreamed => METRICS_BYTES_STREAMED,
             GatewayMetrics::ErrorCount => METRICS_ERROR,
             GatewayMetrics::FailCount => METRICS_FAIL,
+            GatewayMetrics::RateLimited => METRICS_RATE_LIMITED,
+            GatewayMetrics::ConcurrencyLimited => METRICS_CONCURRENCY_LIMITED,
+            GatewayMetrics::Unauthorized => METRICS_UNAUTHORIZED,
+            GatewayMetrics::ResponseCacheHits => METRICS_RESPONSE_CACHE_HITS,
+            GatewayMetrics::ResponseCacheMisses => METRICS_RESPONSE_CACHE_MISSES,
+            GatewayMetrics::MetadataCacheHits => METRICS_METADATA_CACHE_HITS,
+            GatewayMetrics::MetadataCacheMisses => METRICS_METADATA_CACHE_MISSES,
             GatewayMetrics::TimeToFetchFirstBlock => METRICS_TIME_TO_FETCH_FIRST_BLOCK,
             GatewayMetrics::TimeToServeFirstBlock => METRICS_TIME_TO_SERVE_FIRST_BLOCK,
             GatewayMetrics::TimeToServeFullFile => METRICS_TIME_TO_SERVE_FULL_FILE,
@@ -246,3 +337,10 @@ const METRICS_HIST_TTFB_CACHED: &str = "hist_time_to_fetch_first_block_cached";
 const METRICS_HIST_TTSERVE: &str = "hist_time_to_serve_full_file";
 const METRICS_ERROR: &str = "error_count";
 const METRICS_FAIL: &str = "fail_count";
+const METRICS_RATE_LIMITED: &str = "rate_limited";
+const METRICS_CONCURRENCY_LIMITED: &str = "concurrency_limited";
+const METRICS_UNAUTHORIZED: &str = "unauthorized";
+const METRICS_RESPONSE_CACHE_HITS: &str = "response_cache_hits";
+const METRICS_RESPONSE_CACHE_MISSES: &str = "response_cache_misses";
+const METRICS_METADATA_CACHE_HITS: &str = "metadata_cache_hits";
+const METRICS_METADATA_CACHE_MISSES: &str = "metadata_cache_misses";
@@ -31,28 +31,38 @@ use crate::core::MetricType;
 #[allow(unused_imports)]
 use crate::core::MetricsRecorder;
 use crate::core::CORE;
+use hyper::{
+    header::CONTENT_TYPE,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
 use opentelemetry::{
     global,
     sdk::{propagation::TraceContextPropagator, trace, Resource},
     trace::{TraceContextExt, TraceId},
 };
 use opentelemetry_otlp::WithExportConfig;
+use std::convert::Infallible;
 use std::env::consts::{ARCH, OS};
+use std::net::SocketAddr;
 use std::time::Duration;
 use tokio::task::JoinHandle;
 use tracing::log::{debug, warn};
 use tracing_opentelemetry::OpenTelemetrySpanExt;
 use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
 
+/// Content type of the encoded metrics registry.
+const METRICS_CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
 pub struct MetricsHandle {
-    metrics_task: Option<JoinHandle<()>>,
+    metrics_tasks: Vec<JoinHandle<()>>,
 }
 
 impl MetricsHandle {
     /// Shutdown the tracing and metrics subsystems.
     pub fn shutdown(&self) {
         opentelemetry::global::shutdown_tracer_provider();
-        if let Some(mt) = &self.metrics_task {
+        for mt in &self.metrics_tasks {
             mt.abort();
         }
     }
@@ -60,45 +70,84 @@ impl MetricsHandle {
     /// Initialize the tracing and metrics subsystems.
     pub async fn new(cfg: Config) -> Result<Self, Box<dyn std::error::Error>> {
         init_tracer(cfg.clone())?;
-        let metrics_task = init_metrics(cfg).await;
-        Ok(MetricsHandle { metrics_task })
+        let metrics_tasks = init_metrics(cfg).await?;
+        Ok(MetricsHandle { metrics_tasks })
     }
 }
 
 /// Initialize the metrics subsystem.
-async fn init_metrics(cfg: Config) -> Option<JoinHandle<()>> {
-    if cfg.collect {
-        CORE.set_enabled(true);
-        let prom_gateway_uri = format!(
-            "{}/metrics/job/{}/instance/{}",
-            cfg.prom_gateway_endpoint, cfg.service_name, cfg.instance_id
-        );
-        let push_client = reqwest::Client::new();
-        return Some(tokio::spawn(async move {
-            loop {
-                tokio::time::sleep(Duration::from_secs(5)).await;
-                let buff = CORE.encode();
-                let res = match push_client.post(&prom_gateway_uri).body(buff).send().await {
-                    Ok(res) => res,
-                    Err(e) => {
-                        warn!("failed to push metrics: {}", e);
-                        continue;
-                    }
-                };
-                match res.status() {
-                    reqwest::StatusCode::OK => {
-                        debug!("pushed metrics to gateway");
-                    }
-                    _ => {
-                        warn!("failed to push metrics to gateway: {:?}", res);
-                        let body = res.text().await.unwrap();
-                        warn!("error body: {}", body);
-                    }
+///
+/// Metrics are pushed to the prometheus push gateway and/or served for scraping,
+/// depending on the configuration.
+async fn init_metrics(cfg: Config) -> Result<Vec<JoinHandle<()>>, Box<dyn std::error::Error>> {
+    let mut tasks = Vec::new();
+    if !cfg.collect {
+        return Ok(tasks);
+    }
+    CORE.set_enabled(true);
+    if let Some(listen_addr) = cfg.listen_addr {
+        tasks.push(serve_metrics(listen_addr)?);
+    }
+    if cfg.push {
+        tasks.push(push_metrics(&cfg));
+    }
+    Ok(tasks)
+}
+
+/// Serves the metrics registry at `/metrics` for prometheus to scrape.
+fn serve_metrics(addr: SocketAddr) -> Result<JoinHandle<()>, Box<dyn std::error::Error>> {
+    let make_service = make_service_fn(|_conn| async {
+        Ok::<_, Infallible>(service_fn(|req: Request<Body>| async move {
+            match (req.method(), req.uri().path()) {
+                (&Method::GET, "/metrics") => Response::builder()
+                    .header(CONTENT_TYPE, METRICS_CONTENT_TYPE)
+                    .body(Body::from(CORE.encode())),
+                _ => Response::builder()
+                    .status(StatusCode::NOT_FOUND)
+                    .body(Body::empty()),
+            }
+        }))
+    });
+    let server = Server::try_bind(&addr)?.serve(make_service);
+    debug!("serving metrics on {}", server.local_addr());
+    Ok(tokio::spawn(async move {
+        if let Err(e) = server.await {
+            warn!("metrics server failed: {}", e);
+        }
+    }))
+}
+
+/// Periodically pushes the metrics registry to the prometheus push gateway.
+fn push_metrics(cfg: &Config) -> JoinHandle<()> {
+    let prom_gateway_uri = format!(
+        "{}/metrics/job/{}/instance/{}",
+        cfg.prom_gateway_endpoint, cfg.service_name, cfg.instance_id
+    );
+    let push_interval = Duration::from_secs(cfg.push_interval_secs.max(1));
+    let push_client = reqwest::Client::new();
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(push_interval).await;
+            let buff = CORE.encode();
+            let res = match push_client.post(&prom_gateway_uri).body(buff).send().await {
+                Ok(res) => res,
+                Err(e) => {
+                    warn!("failed to push metrics: {}", e);
+                    continue;
+                }
+            };
+            match res.status() {
+                reqwest::StatusCode::OK => {
+                    debug!("pushed metrics to gateway");
+                }
+                _ => {
+                    warn!("failed to push metrics to gateway: {:?}", res);
+                    let body = res.text().await.unwrap();
+                    warn!("error body: {}", body);
                 }
             }
-        }));
-    }
-    None
+        }
+    })
 }
 
 /// Initialize the tracing subsystem.
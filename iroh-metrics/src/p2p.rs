@@ -1,6 +1,9 @@
 use std::fmt;
 
-use prometheus_client::{metrics::counter::Counter, registry::Registry};
+use prometheus_client::{
+    metrics::{counter::Counter, gauge::Gauge},
+    registry::Registry,
+};
 use tracing::error;
 
 use crate::{
@@ -17,6 +20,12 @@ pub(crate) struct Metrics {
     skipped_peer_bitswap: Counter,
     skipped_peer_kad: Counter,
     loops: Counter,
+    reprovider_runs: Counter,
+    reprovided_keys: Counter,
+    reprovider_failures: Counter,
+    reprovider_progress: Gauge,
+    reprovider_last_run: Gauge,
+    reprovider_last_run_duration: Gauge,
 }
 
 impl fmt::Debug for Metrics {
@@ -54,12 +63,55 @@ impl Metrics {
         let loops = Counter::default();
         sub_registry.register(P2PMetrics::LoopCounter.name(), "", Box::new(loops.clone()));
 
+        let reprovider_runs = Counter::default();
+        sub_registry.register(
+            P2PMetrics::ReproviderRuns.name(),
+            "Number of completed reprovider runs",
+            Box::new(reprovider_runs.clone()),
+        );
+        let reprovided_keys = Counter::default();
+        sub_registry.register(
+            P2PMetrics::ReprovidedKeys.name(),
+            "Number of keys announced to the DHT by the reprovider",
+            Box::new(reprovided_keys.clone()),
+        );
+        let reprovider_failures = Counter::default();
+        sub_registry.register(
+            P2PMetrics::ReproviderFailures.name(),
+            "Number of keys the reprovider failed to announce",
+            Box::new(reprovider_failures.clone()),
+        );
+        let reprovider_progress = Gauge::default();
+        sub_registry.register(
+            P2PMetrics::ReproviderProgress.name(),
+            "Number of keys announced in the current reprovider run",
+            Box::new(reprovider_progress.clone()),
+        );
+        let reprovider_last_run = Gauge::default();
+        sub_registry.register(
+            P2PMetrics::ReproviderLastRun.name(),
+            "Unix time in seconds at which the last complete reprovider run started",
+            Box::new(reprovider_last_run.clone()),
+        );
+        let reprovider_last_run_duration = Gauge::default();
+        sub_registry.register(
+            P2PMetrics::ReproviderLastRunDuration.name(),
+            "Duration of the last complete reprovider run in milliseconds",
+            Box::new(reprovider_last_run_duration.clone()),
+        );
+
         Self {
             bad_peers,
             bad_peers_removed,
             skipped_peer_bitswap,
             skipped_peer_kad,
             loops,
+            reprovider_runs,
+            reprovided_keys,
+            reprovider_failures,
+            reprovider_progress,
+            reprovider_last_run,
+            reprovider_last_run_duration,
         }
     }
 }
@@ -79,6 +131,18 @@ impl MetricsRecorder for Metrics {
             self.skipped_peer_kad.inc_by(value);
         } else if m.name() == P2PMetrics::LoopCounter.name() {
             self.loops.inc_by(value);
+        } else if m.name() == P2PMetrics::ReproviderRuns.name() {
+            self.reprovider_runs.inc_by(value);
+        } else if m.name() == P2PMetrics::ReprovidedKeys.name() {
+            self.reprovided_keys.inc_by(value);
+        } else if m.name() == P2PMetrics::ReproviderFailures.name() {
+            self.reprovider_failures.inc_by(value);
+        } else if m.name() == P2PMetrics::ReproviderProgress.name() {
+            self.reprovider_progress.set(value);
+        } else if m.name() == P2PMetrics::ReproviderLastRun.name() {
+            self.reprovider_last_run.set(value);
+        } else if m.name() == P2PMetrics::ReproviderLastRunDuration.name() {
+            self.reprovider_last_run_duration.set(value);
         } else {
             error!("record (bitswap): unknown metric {}", m.name());
         }
@@ -99,6 +163,12 @@ pub enum P2PMetrics {
     SkippedPeerBitswap,
     SkippedPeerKad,
     LoopCounter,
+    ReproviderRuns,
+    ReprovidedKeys,
+    ReproviderFailures,
+    ReproviderProgress,
+    ReproviderLastRun,
+    ReproviderLastRunDuration,
 }
 
 impl MetricType for P2PMetrics {
@@ -109,6 +179,12 @@ impl MetricType for P2PMetrics {
             P2PMetrics::SkippedPeerBitswap => "skipped_peer_bitswap",
             P2PMetrics::SkippedPeerKad => "skipped_peer_kad",
             P2PMetrics::LoopCounter => "loop_counter",
+            P2PMetrics::ReproviderRuns => "reprovider_runs",
+            P2PMetrics::ReprovidedKeys => "reprovided_keys",
+            P2PMetrics::ReproviderFailures => "reprovider_failures",
+            P2PMetrics::ReproviderProgress => "reprovider_progress",
+            P2PMetrics::ReproviderLastRun => "reprovider_last_run",
+            P2PMetrics::ReproviderLastRunDuration => "reprovider_last_run_duration",
         }
     }
 }
@@ -130,7 +130,10 @@ fn default_p2p_config(
     key_store_path: PathBuf,
 ) -> iroh_p2p::config::Config {
     iroh_p2p::config::Config {
-        libp2p: Libp2pConfig::default(),
+        libp2p: Libp2pConfig {
+            kad_store_path: Some(key_store_path.join(iroh_p2p::config::KAD_STORE_DIR_NAME)),
+            ..Default::default()
+        },
         rpc_client: ipfsd,
         metrics,
         key_store_path,
@@ -178,4 +181,32 @@ impl iroh_gateway::handlers::StateConfig for Config {
     fn user_headers(&self) -> &HeaderMap<HeaderValue> {
         &self.gateway.headers
     }
+
+    fn rate_limit(&self) -> &iroh_gateway::rate_limit::RateLimitConfig {
+        &self.gateway.rate_limit
+    }
+
+    fn cache(&self) -> &iroh_gateway::cache::CacheConfig {
+        &self.gateway.cache
+    }
+
+    fn http_api(&self) -> bool {
+        self.gateway.http_api
+    }
+
+    fn http_api_addr(&self) -> std::net::SocketAddr {
+        self.gateway.http_api_addr
+    }
+
+    fn auth(&self) -> &iroh_gateway::auth::AuthConfig {
+        &self.gateway.auth
+    }
+
+    fn tls(&self) -> Option<&iroh_gateway::tls::TlsConfig> {
+        self.gateway.tls.as_ref()
+    }
+
+    fn access_log(&self) -> Option<&iroh_gateway::access_log::AccessLogConfig> {
+        self.gateway.access_log.as_ref()
+    }
 }
@@ -3,7 +3,11 @@ use std::sync::Arc;
 #[allow(unused_imports)]
 use anyhow::{anyhow, Result};
 use clap::Parser;
-use iroh_gateway::{bad_bits::BadBits, core::Core, metrics};
+use iroh_gateway::{
+    bad_bits::{self, BadBits},
+    core::Core,
+    metrics,
+};
 #[cfg(feature = "uds-gateway")]
 use iroh_one::uds;
 use iroh_one::{
@@ -72,7 +76,9 @@ async fn main() -> Result<()> {
         .ok_or_else(|| anyhow!("missing gateway rpc addr"))?;
 
     let bad_bits = match config.gateway.use_denylist {
-        true => Arc::new(Some(RwLock::new(BadBits::new()))),
+        true => Arc::new(Some(RwLock::new(BadBits::with_sources(
+            config.gateway.denylist.clone(),
+        )))),
         false => Arc::new(None),
     };
 
@@ -89,14 +95,17 @@ async fn main() -> Result<()> {
 
     let handler = Core::new_with_state(gateway_rpc_addr, Arc::clone(&shared_state)).await?;
 
+    let bad_bits_handle = bad_bits::spawn_bad_bits_updater(Arc::clone(&bad_bits));
+
     let metrics_handle = iroh_metrics::MetricsHandle::new(metrics_config)
         .await
         .expect("failed to initialize metrics");
-    let server = handler.server();
-    println!("HTTP endpoint listening on {}", server.local_addr());
-    let core_task = tokio::spawn(async move {
-        server.await.unwrap();
-    });
+    let api = handler.spawn_api_server()?;
+    if let Some((addr, _)) = &api {
+        println!("HTTP RPC API listening on {}", addr);
+    }
+    let (addr, core_task) = handler.spawn_server()?;
+    println!("HTTP endpoint listening on {}", addr);
 
     #[cfg(feature = "uds-gateway")]
     let uds_server_task = {
@@ -127,7 +136,13 @@ async fn main() -> Result<()> {
     #[cfg(feature = "uds-gateway")]
     uds_server_task.abort();
     core_task.abort();
+    if let Some((_, api_task)) = api {
+        api_task.abort();
+    }
 
     metrics_handle.shutdown();
+    if let Some(handle) = bad_bits_handle {
+        handle.abort();
+    }
     Ok(())
 }
@@ -1,3 +1,4 @@
+use std::num::NonZeroUsize;
 use std::time::Duration;
 
 use anyhow::Result;
@@ -9,7 +10,7 @@ use libp2p::core::identity::Keypair;
 use libp2p::core::PeerId;
 use libp2p::gossipsub::{self, MessageAuthenticity};
 use libp2p::identify;
-use libp2p::kad::store::{MemoryStore, MemoryStoreConfig};
+use libp2p::kad::store::MemoryStoreConfig;
 use libp2p::kad::{Kademlia, KademliaConfig};
 use libp2p::mdns::TokioMdns as Mdns;
 use libp2p::multiaddr::Protocol;
@@ -21,10 +22,13 @@ use libp2p::{autonat, dcutr};
 use tracing::{info, warn};
 
 pub(crate) use self::event::Event;
+use self::kad::ModalKademlia;
 use self::peer_manager::PeerManager;
 use crate::config::Libp2pConfig;
+use crate::record_store::PersistentStore;
 
 mod event;
+mod kad;
 mod peer_manager;
 
 /// Libp2p behaviour for the node.
@@ -34,7 +38,7 @@ pub(crate) struct NodeBehaviour {
     ping: Ping,
     identify: identify::Behaviour,
     pub(crate) bitswap: Toggle<Bitswap<BitswapStore>>,
-    pub(crate) kad: Toggle<Kademlia<MemoryStore>>,
+    pub(crate) kad: Toggle<ModalKademlia>,
     mdns: Toggle<Mdns>,
     pub(crate) autonat: Toggle<autonat::Behaviour>,
     relay: Toggle<relay::v2::relay::Relay>,
@@ -44,6 +48,9 @@ pub(crate) struct NodeBehaviour {
     pub(crate) peer_manager: PeerManager,
 }
 
+/// Time after which idle Kademlia connection handlers close.
+const KAD_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
 #[derive(Debug, Clone)]
 pub(crate) struct BitswapStore(Client);
 
@@ -107,20 +114,44 @@ impl NodeBehaviour {
 
         let kad = if config.kademlia {
             info!("init kademlia");
-            // TODO: persist to store
-            let mem_store_config = MemoryStoreConfig {
-                // enough for >10gb of unixfs files at the default chunk size
-                max_records: 1024 * 64,
-                max_provided_keys: 1024 * 64,
+            let store_config = MemoryStoreConfig {
+                max_records: config.kad_max_records,
+                max_provided_keys: config.kad_max_provided_keys,
+                max_providers_per_key: config.kad_max_providers_per_key,
                 ..Default::default()
             };
-            let store = MemoryStore::with_config(peer_id, mem_store_config);
+            let store_path = config.kad_store_path.clone();
+            let store = tokio::task::spawn_blocking(move || {
+                PersistentStore::open(peer_id, store_config, store_path.as_deref())
+            })
+            .await??;
 
-            // TODO: make user configurable
+            let protocol_name = config.kad_protocol_name.clone().into_bytes();
             let mut kad_config = KademliaConfig::default();
-            kad_config.set_parallelism(16usize.try_into().unwrap());
-            // TODO: potentially lower (this is per query)
-            kad_config.set_query_timeout(Duration::from_secs(60));
+            kad_config.set_protocol_name(protocol_name.clone());
+            kad_config.set_connection_idle_timeout(KAD_IDLE_TIMEOUT);
+            kad_config.set_record_ttl(Some(Duration::from_secs(config.kad_record_ttl_secs)));
+            kad_config.set_provider_record_ttl(Some(Duration::from_secs(
+                config.kad_provider_record_ttl_secs,
+            )));
+            if config.reprovider_interval_secs > 0 {
+                // the reprovider announces the keys selected by its strategy, other provided
+                // keys are not republished
+                kad_config.set_provider_publication_interval(None);
+            }
+            kad_config.set_replication_factor(
+                NonZeroUsize::new(config.kad_replication_factor)
+                    .ok_or_else(|| anyhow::anyhow!("kad_replication_factor must not be 0"))?,
+            );
+            kad_config.set_parallelism(
+                NonZeroUsize::new(config.kad_parallelism)
+                    .ok_or_else(|| anyhow::anyhow!("kad_parallelism must not be 0"))?,
+            );
+            anyhow::ensure!(
+                config.kad_query_timeout_secs > 0,
+                "kad_query_timeout_secs must not be 0"
+            );
+            kad_config.set_query_timeout(Duration::from_secs(config.kad_query_timeout_secs));
 
             let mut kademlia = Kademlia::with_config(pub_key.to_peer_id(), store, kad_config);
             for multiaddr in &config.bootstrap_peers {
@@ -139,7 +170,14 @@ impl NodeBehaviour {
                 warn!("Kademlia bootstrap failed: {}", e);
             }
 
-            Some(kademlia)
+            info!("kademlia mode: {}", config.kad_mode.as_str());
+            Some(ModalKademlia::new(
+                kademlia,
+                protocol_name,
+                KAD_IDLE_TIMEOUT,
+                config.kad_mode,
+                config.autonat,
+            ))
         } else {
             None
         }
@@ -0,0 +1,441 @@
+use std::collections::{HashSet, VecDeque};
+use std::ops::{Deref, DerefMut};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+use libp2p::{
+    autonat::NatStatus,
+    core::{connection::ConnectionId, transport::ListenerId, ConnectedPoint},
+    kad::{
+        handler::{KademliaHandlerConfig, KademliaHandlerProto},
+        Kademlia, KademliaEvent, KademliaProtocolConfig, QueryId,
+    },
+    swarm::{
+        CloseConnection, ConnectionHandler, DialError, IntoConnectionHandler, NetworkBehaviour,
+        NetworkBehaviourAction, PollParameters,
+    },
+    Multiaddr, PeerId,
+};
+use tracing::info;
+
+use crate::config::KadMode;
+use crate::record_store::PersistentStore;
+
+/// Kademlia that can switch between DHT client and server mode.
+///
+/// In client mode the connection handlers refuse inbound Kademlia streams, so peers neither
+/// query this node nor keep it in their routing tables. A switch to server mode applies to
+/// connections established afterwards. A switch to client mode closes the connections whose
+/// handlers accept inbound streams, as handlers can't be reconfigured, peers that reconnect
+/// get a client mode handler.
+///
+/// The protocols advertised through identify are fixed when the swarm is built, from the
+/// mode the node starts in. An auto mode node therefore starts as a server, so that it is
+/// advertised while it may be reachable. After switching to client mode it keeps being
+/// advertised, peers that fail to query it over a new connection drop it from their
+/// routing tables.
+pub(crate) struct ModalKademlia {
+    inner: Kademlia<PersistentStore>,
+    protocol_name: Vec<u8>,
+    protocol_config: KademliaProtocolConfig,
+    idle_timeout: Duration,
+    /// Follow the AutoNAT reachability.
+    auto: bool,
+    server: bool,
+    /// Connections established in server mode, their handlers accept inbound streams.
+    server_connections: HashSet<(PeerId, ConnectionId)>,
+    /// Server mode connections to close after a switch to client mode.
+    pending_close: VecDeque<(PeerId, ConnectionId)>,
+    waker: Option<Waker>,
+}
+
+impl ModalKademlia {
+    /// `protocol_name` and `idle_timeout` must match the config `inner` was created with.
+    ///
+    /// In [`KadMode::Auto`] the node starts as a server, it becomes a client once AutoNAT
+    /// finds that it is not publicly reachable and a server again once it is.
+    pub(crate) fn new(
+        inner: Kademlia<PersistentStore>,
+        protocol_name: Vec<u8>,
+        idle_timeout: Duration,
+        mode: KadMode,
+        autonat: bool,
+    ) -> Self {
+        let (auto, server) = match mode {
+            KadMode::Auto => (autonat, true),
+            KadMode::Client => (false, false),
+            KadMode::Server => (false, true),
+        };
+        let mut protocol_config = KademliaProtocolConfig::default();
+        protocol_config.set_protocol_name(protocol_name.clone());
+        ModalKademlia {
+            inner,
+            protocol_name,
+            protocol_config,
+            idle_timeout,
+            auto,
+            server,
+            server_connections: HashSet::new(),
+            pending_close: VecDeque::new(),
+            waker: None,
+        }
+    }
+
+    pub(crate) fn is_server(&self) -> bool {
+        self.server
+    }
+
+    /// Protocol name of the DHT, as advertised by peers through identify.
+    pub(crate) fn protocol_name(&self) -> &[u8] {
+        &self.protocol_name
+    }
+
+    /// Switches the mode according to the reachability found by AutoNAT, in auto mode.
+    ///
+    /// An unknown reachability keeps the current mode.
+    pub(crate) fn on_nat_status(&mut self, status: &NatStatus) {
+        if !self.auto {
+            return;
+        }
+        let server = match status {
+            NatStatus::Public(_) => true,
+            NatStatus::Private => false,
+            NatStatus::Unknown => return,
+        };
+        if self.server != server {
+            info!(
+                "kademlia switching to {} mode",
+                if server { "server" } else { "client" }
+            );
+            self.server = server;
+            if !server {
+                self.pending_close.extend(self.server_connections.drain());
+                if let Some(waker) = self.waker.take() {
+                    waker.wake();
+                }
+            }
+        }
+    }
+}
+
+impl Deref for ModalKademlia {
+    type Target = Kademlia<PersistentStore>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl DerefMut for ModalKademlia {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
+
+impl NetworkBehaviour for ModalKademlia {
+    type ConnectionHandler = KademliaHandlerProto<QueryId>;
+    type OutEvent = KademliaEvent;
+
+    fn new_handler(&mut self) -> Self::ConnectionHandler {
+        KademliaHandlerProto::new(KademliaHandlerConfig {
+            protocol_config: self.protocol_config.clone(),
+            allow_listening: self.server,
+            idle_timeout: self.idle_timeout,
+        })
+    }
+
+    fn addresses_of_peer(&mut self, peer_id: &PeerId) -> Vec<Multiaddr> {
+        self.inner.addresses_of_peer(peer_id)
+    }
+
+    fn inject_connection_established(
+        &mut self,
+        peer_id: &PeerId,
+        connection_id: &ConnectionId,
+        endpoint: &ConnectedPoint,
+        failed_addresses: Option<&Vec<Multiaddr>>,
+        other_established: usize,
+    ) {
+        // the handler was just created with the current mode
+        if self.server {
+            self.server_connections.insert((*peer_id, *connection_id));
+        }
+        self.inner.inject_connection_established(
+            peer_id,
+            connection_id,
+            endpoint,
+            failed_addresses,
+            other_established,
+        )
+    }
+
+    fn inject_connection_closed(
+        &mut self,
+        peer_id: &PeerId,
+        connection_id: &ConnectionId,
+        endpoint: &ConnectedPoint,
+        handler: <Self::ConnectionHandler as IntoConnectionHandler>::Handler,
+        remaining_established: usize,
+    ) {
+        self.server_connections.remove(&(*peer_id, *connection_id));
+        self.inner.inject_connection_closed(
+            peer_id,
+            connection_id,
+            endpoint,
+            handler,
+            remaining_established,
+        )
+    }
+
+    fn inject_address_change(
+        &mut self,
+        peer_id: &PeerId,
+        connection_id: &ConnectionId,
+        old: &ConnectedPoint,
+        new: &ConnectedPoint,
+    ) {
+        self.inner
+            .inject_address_change(peer_id, connection_id, old, new)
+    }
+
+    fn inject_event(
+        &mut self,
+        peer_id: PeerId,
+        connection: ConnectionId,
+        event: <<Self::ConnectionHandler as IntoConnectionHandler>::Handler as ConnectionHandler>::OutEvent,
+    ) {
+        self.inner.inject_event(peer_id, connection, event)
+    }
+
+    fn inject_dial_failure(
+        &mut self,
+        peer_id: Option<PeerId>,
+        handler: Self::ConnectionHandler,
+        error: &DialError,
+    ) {
+        self.inner.inject_dial_failure(peer_id, handler, error)
+    }
+
+    fn inject_listen_failure(
+        &mut self,
+        local_addr: &Multiaddr,
+        send_back_addr: &Multiaddr,
+        handler: Self::ConnectionHandler,
+    ) {
+        self.inner
+            .inject_listen_failure(local_addr, send_back_addr, handler)
+    }
+
+    fn inject_new_listener(&mut self, id: ListenerId) {
+        self.inner.inject_new_listener(id)
+    }
+
+    fn inject_new_listen_addr(&mut self, id: ListenerId, addr: &Multiaddr) {
+        self.inner.inject_new_listen_addr(id, addr)
+    }
+
+    fn inject_expired_listen_addr(&mut self, id: ListenerId, addr: &Multiaddr) {
+        self.inner.inject_expired_listen_addr(id, addr)
+    }
+
+    fn inject_listener_error(&mut self, id: ListenerId, err: &(dyn std::error::Error + 'static)) {
+        self.inner.inject_listener_error(id, err)
+    }
+
+    fn inject_listener_closed(&mut self, id: ListenerId, reason: Result<(), &std::io::Error>) {
+        self.inner.inject_listener_closed(id, reason)
+    }
+
+    fn inject_new_external_addr(&mut self, addr: &Multiaddr) {
+        self.inner.inject_new_external_addr(addr)
+    }
+
+    fn inject_expired_external_addr(&mut self, addr: &Multiaddr) {
+        self.inner.inject_expired_external_addr(addr)
+    }
+
+    fn poll(
+        &mut self,
+        cx: &mut Context<'_>,
+        params: &mut impl PollParameters,
+    ) -> Poll<NetworkBehaviourAction<Self::OutEvent, Self::ConnectionHandler>> {
+        if let Some((peer_id, connection)) = self.pending_close.pop_front() {
+            return Poll::Ready(NetworkBehaviourAction::CloseConnection {
+                peer_id,
+                connection: CloseConnection::One(connection),
+            });
+        }
+        self.waker = Some(cx.waker().clone());
+        self.inner.poll(cx, params)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+    use libp2p::{
+        core::{muxing::StreamMuxerBox, transport::Boxed, upgrade::Version},
+        identity::Keypair,
+        kad::QueryResult,
+        noise,
+        swarm::{SwarmBuilder, SwarmEvent},
+        tcp::{GenTcpConfig, TokioTcpTransport},
+        yamux, Swarm, Transport,
+    };
+
+    use super::*;
+
+    fn kademlia(mode: KadMode, autonat: bool) -> ModalKademlia {
+        let peer_id = Keypair::generate_ed25519().public().to_peer_id();
+        kademlia_for(peer_id, mode, autonat)
+    }
+
+    fn kademlia_for(peer_id: PeerId, mode: KadMode, autonat: bool) -> ModalKademlia {
+        let store = PersistentStore::open(peer_id, Default::default(), None).unwrap();
+        ModalKademlia::new(
+            Kademlia::new(peer_id, store),
+            b"/private/kad/1.0.0".to_vec(),
+            Duration::from_secs(10),
+            mode,
+            autonat,
+        )
+    }
+
+    fn transport(keypair: &Keypair) -> Boxed<(PeerId, StreamMuxerBox)> {
+        let dh_keys = noise::Keypair::<noise::X25519Spec>::new()
+            .into_authentic(keypair)
+            .unwrap();
+        TokioTcpTransport::new(GenTcpConfig::default())
+            .upgrade(Version::V1)
+            .authenticate(noise::NoiseConfig::xx(dh_keys).into_authenticated())
+            .multiplex(yamux::YamuxConfig::default())
+            .boxed()
+    }
+
+    fn swarm(mode: KadMode, autonat: bool) -> Swarm<ModalKademlia> {
+        let keypair = Keypair::generate_ed25519();
+        let peer_id = keypair.public().to_peer_id();
+        let kad = kademlia_for(peer_id, mode, autonat);
+        SwarmBuilder::new(transport(&keypair), kad, peer_id)
+            .executor(Box::new(|fut| {
+                tokio::task::spawn(fut);
+            }))
+            .build()
+    }
+
+    /// Whether a server peer that connects to `node` gets answers to its queries.
+    async fn answers_queries(mut node: Swarm<ModalKademlia>) -> bool {
+        node.listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+            .unwrap();
+        let addr = loop {
+            if let SwarmEvent::NewListenAddr { address, .. } = node.select_next_some().await {
+                break address;
+            }
+        };
+
+        let node_id = *node.local_peer_id();
+        let mut peer = swarm(KadMode::Server, false);
+        peer.behaviour_mut().add_address(&node_id, addr);
+        let query = peer.behaviour_mut().get_closest_peers(PeerId::random());
+
+        let answered = async {
+            loop {
+                tokio::select! {
+                    _ = node.select_next_some() => {}
+                    event = peer.select_next_some() => {
+                        if let SwarmEvent::Behaviour(KademliaEvent::OutboundQueryProgressed {
+                            id,
+                            result: QueryResult::GetClosestPeers(result),
+                            ..
+                        }) = event
+                        {
+                            if id == query {
+                                // only peers that answered are part of the result
+                                return matches!(result, Ok(ok) if ok.peers.contains(&node_id));
+                            }
+                        }
+                    }
+                }
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(30), answered)
+            .await
+            .expect("query timed out")
+    }
+
+    #[test]
+    fn test_auto_mode() {
+        let mut kad = kademlia(KadMode::Auto, true);
+        assert!(kad.is_server());
+        assert_eq!(kad.protocol_name(), b"/private/kad/1.0.0");
+
+        kad.on_nat_status(&NatStatus::Unknown);
+        assert!(kad.is_server());
+        kad.on_nat_status(&NatStatus::Private);
+        assert!(!kad.is_server());
+        kad.on_nat_status(&NatStatus::Unknown);
+        assert!(!kad.is_server());
+        kad.on_nat_status(&NatStatus::Public("/ip4/1.2.3.4/tcp/4001".parse().unwrap()));
+        assert!(kad.is_server());
+
+        assert!(kademlia(KadMode::Auto, false).is_server());
+    }
+
+    #[test]
+    fn test_fixed_mode() {
+        let public = NatStatus::Public("/ip4/1.2.3.4/tcp/4001".parse().unwrap());
+
+        let mut kad = kademlia(KadMode::Client, true);
+        kad.on_nat_status(&public);
+        assert!(!kad.is_server());
+
+        let mut kad = kademlia(KadMode::Server, true);
+        kad.on_nat_status(&NatStatus::Private);
+        assert!(kad.is_server());
+    }
+
+    #[tokio::test]
+    async fn test_switch_to_client_closes_connections() {
+        let mut node = swarm(KadMode::Auto, true);
+        node.listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+            .unwrap();
+        let addr = loop {
+            if let SwarmEvent::NewListenAddr { address, .. } = node.select_next_some().await {
+                break address;
+            }
+        };
+        let mut peer = swarm(KadMode::Server, false);
+        peer.dial(addr).unwrap();
+
+        let closed = async {
+            loop {
+                tokio::select! {
+                    event = node.select_next_some() => match event {
+                        SwarmEvent::ConnectionEstablished { .. } => {
+                            node.behaviour_mut().on_nat_status(&NatStatus::Private);
+                        }
+                        SwarmEvent::ConnectionClosed { .. } => return,
+                        _ => {}
+                    },
+                    _ = peer.select_next_some() => {}
+                }
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(30), closed)
+            .await
+            .expect("connection was not closed");
+        assert!(!node.behaviour().is_server());
+    }
+
+    #[tokio::test]
+    async fn test_swarm_modes() {
+        assert!(answers_queries(swarm(KadMode::Server, true)).await);
+        assert!(!answers_queries(swarm(KadMode::Client, true)).await);
+
+        // auto mode nodes answer until AutoNAT finds them unreachable
+        assert!(answers_queries(swarm(KadMode::Auto, true)).await);
+        let mut node = swarm(KadMode::Auto, true);
+        node.behaviour_mut().on_nat_status(&NatStatus::Private);
+        assert!(!answers_queries(node).await);
+    }
+}
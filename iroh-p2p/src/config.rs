@@ -8,12 +8,15 @@ use iroh_rpc_types::{
     p2p::{P2pClientAddr, P2pServerAddr},
     Addr,
 };
-use iroh_util::{insert_into_config_map, iroh_data_root};
+use iroh_util::{insert_into_config_map, iroh_data_path, iroh_data_root};
 use libp2p::Multiaddr;
 use serde::{Deserialize, Serialize};
 
 /// CONFIG_FILE_NAME is the name of the optional config file located in the iroh home directory
 pub const CONFIG_FILE_NAME: &str = "p2p.config.toml";
+/// KAD_STORE_DIR_NAME is the name of the directory of the Kademlia record store, located in
+/// the iroh data directory
+pub const KAD_STORE_DIR_NAME: &str = "kad";
 /// ENV_PREFIX should be used along side the config field name to set a config field using
 /// environment variables
 /// For example, `IROH_P2P_MDNS=true` would set the value of the `Libp2pConfig.mdns` field
@@ -28,16 +31,19 @@ pub const DEFAULT_BOOTSTRAP: &[&str] = &[
     "/dnsaddr/bootstrap.libp2p.io/p2p/QmbLHAnMoJPWSCR5Zhtx6BHJX9KiKNN6tpvbUcqanj75Nb",
     "/dnsaddr/bootstrap.libp2p.io/p2p/QmcZf59bWwK5XFi76CZX8cbJ4BhTzzA3gU1ZjYZcYW3dwt",
     "/ip4/104.131.131.82/tcp/4001/p2p/QmaCpDMGvV2BGHeYERUEnRQAwe3N8SzbUtfsmvsqQLuvuJ", // mars.i.ipfs.io
+    "/ip4/104.131.131.82/udp/4001/quic/p2p/QmaCpDMGvV2BGHeYERUEnRQAwe3N8SzbUtfsmvsqQLuvuJ", // mars.i.ipfs.io
 ];
-// no udp support yet
-
-// "/ip4/104.131.131.82/udp/4001/quic/p2p/QmaCpDMGvV2BGHeYERUEnRQAwe3N8SzbUtfsmvsqQLuvuJ", // mars.i.ipfs.io
 
 /// Libp2p config for the node.
 #[derive(PartialEq, Eq, Debug, Clone, Deserialize, Serialize)]
 pub struct Libp2pConfig {
-    /// Local address.
-    pub listening_multiaddr: Multiaddr,
+    /// Local addresses, `/tcp` and `/ws` addresses for TCP and WebSocket, `/udp/../quic`
+    /// addresses for QUIC.
+    pub listening_multiaddrs: Vec<Multiaddr>,
+    /// Deprecated single local address of earlier versions, replaces `listening_multiaddrs`
+    /// when set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub listening_multiaddr: Option<Multiaddr>,
     /// Bootstrap peer list.
     pub bootstrap_peers: Vec<Multiaddr>,
     /// Mdns discovery enabled.
@@ -54,6 +60,12 @@ pub struct Libp2pConfig {
     pub relay_client: bool,
     /// Gossipsub enabled.
     pub gossipsub: bool,
+    /// TCP transport enabled.
+    pub tcp: bool,
+    /// WebSocket transport enabled.
+    pub websocket: bool,
+    /// QUIC transport enabled.
+    pub quic: bool,
     pub max_conns_out: u32,
     pub max_conns_in: u32,
     pub max_conns_pending_out: u32,
@@ -62,6 +74,141 @@ pub struct Libp2pConfig {
     pub notify_handler_buffer_size: usize,
     pub connection_event_buffer_size: usize,
     pub dial_concurrency_factor: u8,
+    /// Timeout for establishing the raw TCP and WebSocket connections, in seconds, must not
+    /// be `0`.
+    pub transport_timeout_secs: u64,
+    /// Timeout for establishing a connection including its security and muxer upgrades,
+    /// in seconds, must not be `0`.
+    pub connection_timeout_secs: u64,
+    /// Stream multiplexers offered on TCP and WebSocket connections.
+    pub muxer: Muxer,
+    /// Maximum number of bytes buffered per yamux stream.
+    pub yamux_max_buffer_size: usize,
+    /// Receive window of yamux streams, in bytes, at least 256 KiB.
+    pub yamux_receive_window_size: u32,
+    /// Directory of the Kademlia record store, records are only kept in memory if unset.
+    pub kad_store_path: Option<PathBuf>,
+    /// Maximum number of Kademlia records stored.
+    pub kad_max_records: usize,
+    /// Maximum number of keys this node provides.
+    pub kad_max_provided_keys: usize,
+    /// Maximum number of providers stored per key.
+    pub kad_max_providers_per_key: usize,
+    /// Time after which stored Kademlia records expire, in seconds.
+    pub kad_record_ttl_secs: u64,
+    /// Time after which stored provider records expire, in seconds.
+    pub kad_provider_record_ttl_secs: u64,
+    /// Whether the node answers DHT queries of other peers.
+    pub kad_mode: KadMode,
+    /// Number of peers records and provider records are replicated to.
+    pub kad_replication_factor: usize,
+    /// Timeout of a single Kademlia query, in seconds, must not be `0`.
+    pub kad_query_timeout_secs: u64,
+    /// Number of peers queried in parallel during a Kademlia query.
+    pub kad_parallelism: usize,
+    /// Kademlia protocol name, change it to run a private DHT.
+    pub kad_protocol_name: String,
+    /// Keys that are periodically announced to the DHT.
+    pub reprovider_strategy: ReproviderStrategy,
+    /// Interval between two reprovider runs, in seconds, `0` disables reproviding.
+    ///
+    /// While the reprovider is enabled Kademlia does not republish provider records on its
+    /// own. Keys the strategy does not select, such as fetched blocks with the `roots`
+    /// strategies, are only announced once and disappear from the DHT when their provider
+    /// records expire.
+    pub reprovider_interval_secs: u64,
+    /// Number of keys announced at once.
+    pub reprovider_batch_size: usize,
+    /// Maximum number of keys announced per second, `0` for no limit.
+    pub reprovider_max_rate: u32,
+}
+
+/// Stream multiplexers to negotiate on connections that do not bring their own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Muxer {
+    /// Yamux, falling back to mplex for peers that do not support it.
+    Both,
+    Yamux,
+    Mplex,
+}
+
+impl Default for Muxer {
+    fn default() -> Self {
+        Muxer::Both
+    }
+}
+
+impl Muxer {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Muxer::Both => "both",
+            Muxer::Yamux => "yamux",
+            Muxer::Mplex => "mplex",
+        }
+    }
+}
+
+/// Role of the node in the DHT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KadMode {
+    /// Starts as a server and becomes a client once AutoNAT finds the node not publicly
+    /// reachable. Without AutoNAT the node stays a server.
+    Auto,
+    /// Only sends queries, peers can not add the node to their routing tables.
+    Client,
+    /// Sends and answers queries.
+    Server,
+}
+
+impl Default for KadMode {
+    fn default() -> Self {
+        KadMode::Auto
+    }
+}
+
+impl KadMode {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            KadMode::Auto => "auto",
+            KadMode::Client => "client",
+            KadMode::Server => "server",
+        }
+    }
+}
+
+/// Keys announced to the DHT by the reprovider.
+///
+/// Only keys that fit into the `kad_max_provided_keys` limit can be announced, the others
+/// are counted as failed in the reprovider status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReproviderStrategy {
+    /// Every block in the store.
+    All,
+    /// The blocks in the store that no other stored block links to.
+    Roots,
+    /// The keys this node was asked to provide through its RPC API, e.g. the roots of
+    /// locally added or imported content. They are only kept across restarts when
+    /// `kad_store_path` is set.
+    LocalRoots,
+}
+
+impl Default for ReproviderStrategy {
+    fn default() -> Self {
+        ReproviderStrategy::All
+    }
+}
+
+impl ReproviderStrategy {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            ReproviderStrategy::All => "all",
+            ReproviderStrategy::Roots => "roots",
+            ReproviderStrategy::LocalRoots => "local_roots",
+        }
+    }
 }
 
 /// Configuration for the node.
@@ -116,6 +263,91 @@ impl Source for Libp2pConfig {
             self.dial_concurrency_factor as i64,
         );
 
+        insert_into_config_map(
+            &mut map,
+            "transport_timeout_secs",
+            self.transport_timeout_secs as i64,
+        );
+        insert_into_config_map(
+            &mut map,
+            "connection_timeout_secs",
+            self.connection_timeout_secs as i64,
+        );
+        insert_into_config_map(&mut map, "muxer", self.muxer.as_str());
+        insert_into_config_map(
+            &mut map,
+            "yamux_max_buffer_size",
+            self.yamux_max_buffer_size as i64,
+        );
+        insert_into_config_map(
+            &mut map,
+            "yamux_receive_window_size",
+            self.yamux_receive_window_size as i64,
+        );
+
+        if let Some(path) = &self.kad_store_path {
+            insert_into_config_map(&mut map, "kad_store_path", path.to_str());
+        }
+        insert_into_config_map(&mut map, "kad_max_records", self.kad_max_records as i64);
+        insert_into_config_map(
+            &mut map,
+            "kad_max_provided_keys",
+            self.kad_max_provided_keys as i64,
+        );
+        insert_into_config_map(
+            &mut map,
+            "kad_max_providers_per_key",
+            self.kad_max_providers_per_key as i64,
+        );
+        insert_into_config_map(
+            &mut map,
+            "kad_record_ttl_secs",
+            self.kad_record_ttl_secs as i64,
+        );
+        insert_into_config_map(
+            &mut map,
+            "kad_provider_record_ttl_secs",
+            self.kad_provider_record_ttl_secs as i64,
+        );
+        insert_into_config_map(&mut map, "kad_mode", self.kad_mode.as_str());
+        insert_into_config_map(
+            &mut map,
+            "kad_replication_factor",
+            self.kad_replication_factor as i64,
+        );
+        insert_into_config_map(
+            &mut map,
+            "kad_query_timeout_secs",
+            self.kad_query_timeout_secs as i64,
+        );
+        insert_into_config_map(&mut map, "kad_parallelism", self.kad_parallelism as i64);
+        insert_into_config_map(
+            &mut map,
+            "kad_protocol_name",
+            self.kad_protocol_name.clone(),
+        );
+
+        insert_into_config_map(
+            &mut map,
+            "reprovider_strategy",
+            self.reprovider_strategy.as_str(),
+        );
+        insert_into_config_map(
+            &mut map,
+            "reprovider_interval_secs",
+            self.reprovider_interval_secs as i64,
+        );
+        insert_into_config_map(
+            &mut map,
+            "reprovider_batch_size",
+            self.reprovider_batch_size as i64,
+        );
+        insert_into_config_map(
+            &mut map,
+            "reprovider_max_rate",
+            self.reprovider_max_rate as i64,
+        );
+
         insert_into_config_map(&mut map, "kademlia", self.kademlia);
         insert_into_config_map(&mut map, "autonat", self.autonat);
         insert_into_config_map(&mut map, "bitswap", self.bitswap);
@@ -123,13 +355,20 @@ impl Source for Libp2pConfig {
         insert_into_config_map(&mut map, "relay_server", self.relay_server);
         insert_into_config_map(&mut map, "relay_client", self.relay_client);
         insert_into_config_map(&mut map, "gossipsub", self.gossipsub);
+        insert_into_config_map(&mut map, "tcp", self.tcp);
+        insert_into_config_map(&mut map, "websocket", self.websocket);
+        insert_into_config_map(&mut map, "quic", self.quic);
         let peers: Vec<String> = self.bootstrap_peers.iter().map(|b| b.to_string()).collect();
         insert_into_config_map(&mut map, "bootstrap_peers", peers);
-        insert_into_config_map(
-            &mut map,
-            "listening_multiaddr",
-            self.listening_multiaddr.to_string(),
-        );
+        let addrs: Vec<String> = self
+            .listening_multiaddrs
+            .iter()
+            .map(|addr| addr.to_string())
+            .collect();
+        insert_into_config_map(&mut map, "listening_multiaddrs", addrs);
+        if let Some(addr) = &self.listening_multiaddr {
+            insert_into_config_map(&mut map, "listening_multiaddr", addr.to_string());
+        }
         Ok(map)
     }
 }
@@ -158,7 +397,11 @@ impl Default for Libp2pConfig {
             .collect();
 
         Self {
-            listening_multiaddr: "/ip4/0.0.0.0/tcp/4444".parse().unwrap(),
+            listening_multiaddrs: vec![
+                "/ip4/0.0.0.0/tcp/4444".parse().unwrap(),
+                "/ip4/0.0.0.0/udp/4445/quic".parse().unwrap(),
+            ],
+            listening_multiaddr: None,
             bootstrap_peers,
             mdns: false,
             kademlia: true,
@@ -167,6 +410,9 @@ impl Default for Libp2pConfig {
             relay_client: true,
             gossipsub: true,
             bitswap: true,
+            tcp: true,
+            websocket: true,
+            quic: true,
             max_conns_pending_out: 256,
             max_conns_pending_in: 256,
             max_conns_in: 256,
@@ -175,6 +421,38 @@ impl Default for Libp2pConfig {
             notify_handler_buffer_size: 256,
             connection_event_buffer_size: 256,
             dial_concurrency_factor: 8,
+            transport_timeout_secs: 10,
+            connection_timeout_secs: 30,
+            muxer: Muxer::default(),
+            yamux_max_buffer_size: 16 * 1024 * 1024,
+            yamux_receive_window_size: 16 * 1024 * 1024,
+            kad_store_path: None,
+            // enough for >10gb of unixfs files at the default chunk size
+            kad_max_records: 1024 * 64,
+            kad_max_provided_keys: 1024 * 64,
+            kad_max_providers_per_key: 20,
+            kad_record_ttl_secs: 36 * 60 * 60,
+            kad_provider_record_ttl_secs: 24 * 60 * 60,
+            kad_mode: KadMode::default(),
+            kad_replication_factor: 20,
+            kad_query_timeout_secs: 60,
+            kad_parallelism: 16,
+            kad_protocol_name: "/ipfs/kad/1.0.0".to_string(),
+            reprovider_strategy: ReproviderStrategy::default(),
+            reprovider_interval_secs: 12 * 60 * 60,
+            reprovider_batch_size: 64,
+            reprovider_max_rate: 100,
+        }
+    }
+}
+
+impl Libp2pConfig {
+    /// Addresses to listen on, the deprecated `listening_multiaddr` takes precedence so that
+    /// existing configs keep working.
+    pub fn listen_addrs(&self) -> Vec<Multiaddr> {
+        match &self.listening_multiaddr {
+            Some(addr) => vec![addr.clone()],
+            None => self.listening_multiaddrs.clone(),
         }
     }
 }
@@ -196,7 +474,10 @@ impl Config {
         let rpc_client = RpcClientConfig::default_grpc();
 
         Self {
-            libp2p: Libp2pConfig::default(),
+            libp2p: Libp2pConfig {
+                kad_store_path: Some(iroh_data_path(KAD_STORE_DIR_NAME).unwrap()),
+                ..Default::default()
+            },
             rpc_client,
             metrics: MetricsConfig::default(),
             key_store_path: iroh_data_root().unwrap(),
@@ -304,6 +585,80 @@ mod tests {
             Value::new(None, default.dial_concurrency_factor as i64),
         );
 
+        expect.insert(
+            "transport_timeout_secs".to_string(),
+            Value::new(None, default.transport_timeout_secs as i64),
+        );
+        expect.insert(
+            "connection_timeout_secs".to_string(),
+            Value::new(None, default.connection_timeout_secs as i64),
+        );
+        expect.insert("muxer".to_string(), Value::new(None, "both"));
+        expect.insert(
+            "yamux_max_buffer_size".to_string(),
+            Value::new(None, default.yamux_max_buffer_size as i64),
+        );
+        expect.insert(
+            "yamux_receive_window_size".to_string(),
+            Value::new(None, default.yamux_receive_window_size as i64),
+        );
+
+        expect.insert(
+            "kad_store_path".to_string(),
+            Value::new(None, iroh_data_path(KAD_STORE_DIR_NAME).unwrap().to_str()),
+        );
+        expect.insert(
+            "kad_max_records".to_string(),
+            Value::new(None, default.kad_max_records as i64),
+        );
+        expect.insert(
+            "kad_max_provided_keys".to_string(),
+            Value::new(None, default.kad_max_provided_keys as i64),
+        );
+        expect.insert(
+            "kad_max_providers_per_key".to_string(),
+            Value::new(None, default.kad_max_providers_per_key as i64),
+        );
+        expect.insert(
+            "kad_record_ttl_secs".to_string(),
+            Value::new(None, default.kad_record_ttl_secs as i64),
+        );
+        expect.insert(
+            "kad_provider_record_ttl_secs".to_string(),
+            Value::new(None, default.kad_provider_record_ttl_secs as i64),
+        );
+        expect.insert("kad_mode".to_string(), Value::new(None, "auto"));
+        expect.insert(
+            "kad_replication_factor".to_string(),
+            Value::new(None, default.kad_replication_factor as i64),
+        );
+        expect.insert(
+            "kad_query_timeout_secs".to_string(),
+            Value::new(None, default.kad_query_timeout_secs as i64),
+        );
+        expect.insert(
+            "kad_parallelism".to_string(),
+            Value::new(None, default.kad_parallelism as i64),
+        );
+        expect.insert(
+            "kad_protocol_name".to_string(),
+            Value::new(None, default.kad_protocol_name.clone()),
+        );
+
+        expect.insert("reprovider_strategy".to_string(), Value::new(None, "all"));
+        expect.insert(
+            "reprovider_interval_secs".to_string(),
+            Value::new(None, default.reprovider_interval_secs as i64),
+        );
+        expect.insert(
+            "reprovider_batch_size".to_string(),
+            Value::new(None, default.reprovider_batch_size as i64),
+        );
+        expect.insert(
+            "reprovider_max_rate".to_string(),
+            Value::new(None, default.reprovider_max_rate as i64),
+        );
+
         expect.insert("kademlia".to_string(), Value::new(None, default.kademlia));
         expect.insert("autonat".to_string(), Value::new(None, default.autonat));
         expect.insert("mdns".to_string(), Value::new(None, default.mdns));
@@ -317,13 +672,21 @@ mod tests {
             Value::new(None, default.relay_client),
         );
         expect.insert("gossipsub".to_string(), Value::new(None, default.gossipsub));
+        expect.insert("tcp".to_string(), Value::new(None, default.tcp));
+        expect.insert("websocket".to_string(), Value::new(None, default.websocket));
+        expect.insert("quic".to_string(), Value::new(None, default.quic));
         expect.insert(
             "bootstrap_peers".to_string(),
             Value::new(None, bootstrap_peers),
         );
+        let listening_multiaddrs: Vec<String> = default
+            .listening_multiaddrs
+            .iter()
+            .map(|addr| addr.to_string())
+            .collect();
         expect.insert(
-            "listening_multiaddr".to_string(),
-            Value::new(None, default.listening_multiaddr.to_string()),
+            "listening_multiaddrs".to_string(),
+            Value::new(None, listening_multiaddrs),
         );
 
         let got = default.collect().unwrap();
@@ -347,4 +710,129 @@ mod tests {
 
         assert_eq!(expect, got);
     }
+
+    #[test]
+    fn test_transport_overrides() {
+        let got: Config = ConfigBuilder::builder()
+            .add_source(Config::default_grpc())
+            .set_override("libp2p.muxer", "yamux")
+            .unwrap()
+            .set_override("libp2p.connection_timeout_secs", 120)
+            .unwrap()
+            .set_override("libp2p.yamux_receive_window_size", 256 * 1024)
+            .unwrap()
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+
+        assert_eq!(got.libp2p.muxer, Muxer::Yamux);
+        assert_eq!(got.libp2p.connection_timeout_secs, 120);
+        assert_eq!(got.libp2p.yamux_receive_window_size, 256 * 1024);
+        assert_eq!(got.libp2p.transport_timeout_secs, 10);
+    }
+
+    #[test]
+    fn test_deprecated_listening_multiaddr() {
+        let got: Config = ConfigBuilder::builder()
+            .add_source(Config::default_grpc())
+            .set_override("libp2p.listening_multiaddr", "/ip4/127.0.0.1/tcp/4001")
+            .unwrap()
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+        let addr: Multiaddr = "/ip4/127.0.0.1/tcp/4001".parse().unwrap();
+        assert_eq!(got.libp2p.listening_multiaddr, Some(addr.clone()));
+        assert_eq!(got.libp2p.listen_addrs(), vec![addr]);
+
+        // the key survives a round trip through the config sources
+        let again: Config = ConfigBuilder::builder()
+            .add_source(got.clone())
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+        assert_eq!(again, got);
+
+        let default = Libp2pConfig::default();
+        assert_eq!(default.listen_addrs(), default.listening_multiaddrs);
+    }
+
+    #[test]
+    fn test_kad_store_overrides() {
+        let got: Config = ConfigBuilder::builder()
+            .add_source(Config::default_grpc())
+            .set_override("libp2p.kad_store_path", "/tmp/iroh-kad")
+            .unwrap()
+            .set_override("libp2p.kad_max_records", 1024)
+            .unwrap()
+            .set_override("libp2p.kad_provider_record_ttl_secs", 3600)
+            .unwrap()
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+
+        assert_eq!(
+            got.libp2p.kad_store_path,
+            Some(PathBuf::from("/tmp/iroh-kad"))
+        );
+        assert_eq!(got.libp2p.kad_max_records, 1024);
+        assert_eq!(got.libp2p.kad_provider_record_ttl_secs, 3600);
+        assert_eq!(got.libp2p.kad_max_provided_keys, 1024 * 64);
+
+        let got: Config = ConfigBuilder::builder()
+            .add_source(Config::default_with_rpc(
+                Config::default_grpc().rpc_client.p2p_addr.unwrap(),
+            ))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+        assert_eq!(got.libp2p.kad_store_path, None);
+    }
+
+    #[test]
+    fn test_reprovider_overrides() {
+        let got: Config = ConfigBuilder::builder()
+            .add_source(Config::default_grpc())
+            .set_override("libp2p.reprovider_strategy", "local_roots")
+            .unwrap()
+            .set_override("libp2p.reprovider_interval_secs", 0)
+            .unwrap()
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+
+        assert_eq!(
+            got.libp2p.reprovider_strategy,
+            ReproviderStrategy::LocalRoots
+        );
+        assert_eq!(got.libp2p.reprovider_interval_secs, 0);
+        assert_eq!(got.libp2p.reprovider_batch_size, 64);
+    }
+
+    #[test]
+    fn test_kad_overrides() {
+        let got: Config = ConfigBuilder::builder()
+            .add_source(Config::default_grpc())
+            .set_override("libp2p.kad_mode", "client")
+            .unwrap()
+            .set_override("libp2p.kad_replication_factor", 10)
+            .unwrap()
+            .set_override("libp2p.kad_protocol_name", "/private/kad/1.0.0")
+            .unwrap()
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+
+        assert_eq!(got.libp2p.kad_mode, KadMode::Client);
+        assert_eq!(got.libp2p.kad_replication_factor, 10);
+        assert_eq!(got.libp2p.kad_protocol_name, "/private/kad/1.0.0");
+        assert_eq!(got.libp2p.kad_query_timeout_secs, 60);
+        assert_eq!(got.libp2p.kad_parallelism, 16);
+    }
 }
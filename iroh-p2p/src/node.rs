@@ -15,17 +15,18 @@ use libp2p::identify::{Event as IdentifyEvent, Info as IdentifyInfo};
 use libp2p::identity::Keypair;
 use libp2p::kad::kbucket::{Distance, NodeStatus};
 use libp2p::kad::{
-    self, BootstrapOk, GetClosestPeersError, GetClosestPeersOk, GetProvidersOk, KademliaEvent,
-    QueryId, QueryResult,
+    BootstrapOk, GetClosestPeersError, GetClosestPeersOk, GetProvidersOk, KademliaEvent, QueryId,
+    QueryResult,
 };
 use libp2p::metrics::Recorder;
 use libp2p::multiaddr::Protocol;
 use libp2p::ping::Result as PingResult;
 use libp2p::swarm::dial_opts::{DialOpts, PeerCondition};
 use libp2p::swarm::{ConnectionHandler, IntoConnectionHandler, NetworkBehaviour, SwarmEvent};
-use libp2p::{PeerId, Swarm};
+use libp2p::{autonat, PeerId, Swarm};
 use tokio::sync::mpsc::{channel, Receiver, Sender};
 use tokio::sync::oneshot::{self, Sender as OneShotSender};
+use tokio::sync::watch;
 use tokio::task::JoinHandle;
 use tracing::{debug, error, info, trace, warn};
 
@@ -33,6 +34,7 @@ use iroh_bitswap::{BitswapEvent, Block};
 
 use crate::keys::{Keychain, Storage};
 use crate::providers::Providers;
+use crate::reprovider::{Reprovider, ReproviderStatus};
 use crate::rpc::ProviderRequestKey;
 use crate::swarm::build_swarm;
 use crate::{
@@ -80,6 +82,8 @@ pub struct Node<KeyStorage: Storage> {
     #[allow(dead_code)]
     kad_last_range: Option<(Distance, Distance)>,
     rpc_task: JoinHandle<()>,
+    reprovider_task: Option<JoinHandle<()>>,
+    reprovider_status: Option<watch::Receiver<ReproviderStatus>>,
     use_dht: bool,
     bitswap_sessions: BitswapSessions,
     providers: Providers,
@@ -94,10 +98,14 @@ pub(crate) const DEFAULT_PROVIDER_LIMIT: usize = 10;
 const NICE_INTERVAL: Duration = Duration::from_secs(6);
 const BOOTSTRAP_INTERVAL: Duration = Duration::from_secs(5 * 60);
 const EXPIRY_INTERVAL: Duration = Duration::from_secs(1);
+const RECORD_EXPIRY_INTERVAL: Duration = Duration::from_secs(60);
 
 impl<KeyStorage: Storage> Drop for Node<KeyStorage> {
     fn drop(&mut self) {
         self.rpc_task.abort();
+        if let Some(reprovider_task) = &self.reprovider_task {
+            reprovider_task.abort();
+        }
     }
 }
 
@@ -115,6 +123,7 @@ impl<KeyStorage: Storage> Node<KeyStorage> {
             ..
         } = config;
 
+        let reprovider_sender = network_sender_in.clone();
         let rpc_task = tokio::task::spawn(async move {
             // TODO: handle error
             rpc::new(rpc_addr, network_sender_in).await.unwrap()
@@ -124,11 +133,29 @@ impl<KeyStorage: Storage> Node<KeyStorage> {
             .await
             .context("failed to create rpc client")?;
 
+        let (reprovider_task, reprovider_status) =
+            if libp2p_config.kademlia && libp2p_config.reprovider_interval_secs > 0 {
+                let (reprovider, status) =
+                    Reprovider::new(&libp2p_config, rpc_client.clone(), reprovider_sender);
+                (Some(tokio::task::spawn(reprovider.run())), Some(status))
+            } else {
+                (None, None)
+            };
+
         let keypair = load_identity(&mut keychain).await?;
         let mut swarm = build_swarm(&libp2p_config, &keypair, rpc_client.clone()).await?;
 
-        Swarm::listen_on(&mut swarm, libp2p_config.listening_multiaddr.clone()).unwrap();
-        println!("{}", libp2p_config.listening_multiaddr);
+        if let Some(addr) = &libp2p_config.listening_multiaddr {
+            warn!(
+                "`listening_multiaddr` is deprecated, use `listening_multiaddrs = [\"{}\"]`",
+                addr
+            );
+        }
+        for addr in libp2p_config.listen_addrs() {
+            Swarm::listen_on(&mut swarm, addr.clone())
+                .with_context(|| format!("failed to listen on {}", addr))?;
+            println!("{}", addr);
+        }
 
         Ok(Node {
             swarm,
@@ -142,6 +169,8 @@ impl<KeyStorage: Storage> Node<KeyStorage> {
             _keychain: keychain,
             kad_last_range: None,
             rpc_task,
+            reprovider_task,
+            reprovider_status,
             use_dht: libp2p_config.kademlia,
             bitswap_sessions: Default::default(),
             providers: Providers::new(4),
@@ -159,6 +188,7 @@ impl<KeyStorage: Storage> Node<KeyStorage> {
         };
         let mut bootstrap_interval = tokio::time::interval(BOOTSTRAP_INTERVAL);
         let mut expiry_interval = tokio::time::interval(EXPIRY_INTERVAL);
+        let mut record_expiry_interval = tokio::time::interval(RECORD_EXPIRY_INTERVAL);
 
         loop {
             inc!(P2PMetrics::LoopCounter);
@@ -216,6 +246,11 @@ impl<KeyStorage: Storage> Node<KeyStorage> {
                         warn!("expiry error {:?}", err);
                     }
                 }
+                _ = record_expiry_interval.tick() => {
+                    if let Some(kad) = self.swarm.behaviour_mut().kad.as_mut() {
+                        kad.store_mut().remove_expired();
+                    }
+                }
             }
         }
     }
@@ -585,13 +620,15 @@ impl<KeyStorage: Storage> Node<KeyStorage> {
                     for protocol in &info.protocols {
                         let p = protocol.as_bytes();
 
-                        if p == kad::protocol::DEFAULT_PROTO_NAME {
-                            for addr in &info.listen_addrs {
-                                if let Some(kad) = self.swarm.behaviour_mut().kad.as_mut() {
+                        if let Some(kad) = self.swarm.behaviour_mut().kad.as_mut() {
+                            if p == kad.protocol_name() {
+                                for addr in &info.listen_addrs {
                                     kad.add_address(&peer_id, addr.clone());
                                 }
+                                continue;
                             }
-                        } else if p == b"/libp2p/autonat/1.0.0" {
+                        }
+                        if p == b"/libp2p/autonat/1.0.0" {
                             // TODO: expose protocol name on `libp2p::autonat`.
                             // TODO: should we remove them at some point?
                             for addr in &info.listen_addrs {
@@ -637,6 +674,12 @@ impl<KeyStorage: Storage> Node<KeyStorage> {
                         .inject_ping(e.peer, ping);
                 }
             }
+            Event::Autonat(autonat::Event::StatusChanged { old, new }) => {
+                debug!("nat status changed from {:?} to {:?}", old, new);
+                if let Some(kad) = self.swarm.behaviour_mut().kad.as_mut() {
+                    kad.on_nat_status(&new);
+                }
+            }
             Event::Relay(e) => {
                 libp2p_metrics().record(&e);
             }
@@ -747,7 +790,12 @@ impl<KeyStorage: Storage> Node<KeyStorage> {
             },
             RpcMessage::StartProviding(response_channel, key) => {
                 if let Some(kad) = self.swarm.behaviour_mut().kad.as_mut() {
-                    let res: Result<QueryId> = kad.start_providing(key).map_err(|e| e.into());
+                    let res: Result<QueryId> =
+                        kad.start_providing(key.clone()).map_err(|e| e.into());
+                    if res.is_ok() {
+                        // only keys provided on request are local roots
+                        kad.store_mut().add_local_root(key);
+                    }
                     // TODO: wait for kad to process the query request before returning
                     response_channel.send(res).ok();
                 } else {
@@ -756,9 +804,40 @@ impl<KeyStorage: Storage> Node<KeyStorage> {
                         .ok();
                 }
             }
+            RpcMessage::StartProvidingMany(response_channel, keys) => {
+                if let Some(kad) = self.swarm.behaviour_mut().kad.as_mut() {
+                    let mut provided = 0;
+                    for key in keys {
+                        match kad.start_providing(key) {
+                            Ok(_) => provided += 1,
+                            Err(err) => debug!("failed to provide key: {:?}", err),
+                        }
+                    }
+                    response_channel.send(Ok(provided)).ok();
+                } else {
+                    response_channel
+                        .send(Err(anyhow!("kademlia is not available")))
+                        .ok();
+                }
+            }
+            RpcMessage::LocalRoots(response_channel) => {
+                let keys = match self.swarm.behaviour_mut().kad.as_mut() {
+                    Some(kad) => kad.store_mut().local_roots().cloned().collect(),
+                    None => Vec::new(),
+                };
+                response_channel.send(keys).ok();
+            }
+            RpcMessage::ReproviderStatus(response_channel) => {
+                let status = self
+                    .reprovider_status
+                    .as_ref()
+                    .map(|status| status.borrow().clone());
+                response_channel.send(status).ok();
+            }
             RpcMessage::StopProviding(response_channel, key) => {
                 if let Some(kad) = self.swarm.behaviour_mut().kad.as_mut() {
                     kad.stop_providing(&key);
+                    kad.store_mut().remove_local_root(&key);
                     response_channel.send(Ok(())).ok();
                 } else {
                     response_channel
@@ -986,11 +1065,14 @@ mod tests {
     use crate::keys::MemoryStorage;
 
     use super::*;
+    use crate::config::ReproviderStrategy;
     use anyhow::Result;
     use iroh_rpc_types::{
         p2p::{P2pClientAddr, P2pServerAddr},
         Addr,
     };
+    use libp2p::kad::record::Key;
+    use multihash::{Code, MultihashDigest};
     use tracing_subscriber::{fmt, prelude::*, EnvFilter};
 
     #[cfg(feature = "rpc-grpc")]
@@ -1042,13 +1124,56 @@ mod tests {
         Ok(())
     }
 
+    #[cfg(feature = "rpc-mem")]
+    #[tokio::test]
+    async fn test_reprovider_status_and_local_roots() -> Result<()> {
+        let (server_addr, client_addr) = Addr::new_mem();
+        let mut network_config = Config::default_with_rpc(client_addr.clone());
+        network_config.libp2p.listening_multiaddrs = vec!["/ip4/127.0.0.1/tcp/0".parse()?];
+        network_config.libp2p.bootstrap_peers = Vec::new();
+        network_config.libp2p.reprovider_strategy = ReproviderStrategy::LocalRoots;
+
+        let kc = Keychain::<MemoryStorage>::new();
+        let mut p2p = Node::new(network_config, server_addr, kc).await?;
+
+        // only keys provided on request are local roots
+        let root = Key::new(&"root");
+        let (s, r) = oneshot::channel();
+        p2p.handle_rpc_message(RpcMessage::StartProviding(s, root.clone()))?;
+        r.await??;
+        p2p.handle_node_event(Event::Bitswap(BitswapEvent::Provide {
+            key: Cid::new_v1(0x55, Code::Sha2_256.digest(b"fetched")),
+        }))?;
+        let (s, r) = oneshot::channel();
+        p2p.handle_rpc_message(RpcMessage::LocalRoots(s))?;
+        assert_eq!(r.await?, vec![root]);
+
+        let p2p_task = tokio::task::spawn(async move {
+            p2p.run().await.unwrap();
+        });
+        let cfg = iroh_rpc_client::Config {
+            p2p_addr: Some(client_addr),
+            channels: Some(1),
+            ..Default::default()
+        };
+        let client = RpcClient::new(cfg).await?;
+        let status = client.try_p2p()?.reprovider_status().await?;
+        assert_eq!(status.strategy, "local_roots");
+        assert!(!status.running);
+        assert_eq!(status.provided, 0);
+        assert_eq!(status.last_run, None);
+
+        p2p_task.abort();
+        Ok(())
+    }
+
     async fn fetch_providers(
         addr: Multiaddr,
         rpc_server_addr: P2pServerAddr,
         rpc_client_addr: P2pClientAddr,
     ) -> Result<()> {
         let mut network_config = Config::default_with_rpc(rpc_client_addr.clone());
-        network_config.libp2p.listening_multiaddr = addr;
+        network_config.libp2p.listening_multiaddrs = vec![addr];
 
         let kc = Keychain::<MemoryStorage>::new();
         let mut p2p = Node::new(network_config, rpc_server_addr, kc).await?;
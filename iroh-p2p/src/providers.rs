@@ -2,11 +2,13 @@ use std::collections::{HashSet, VecDeque};
 
 use ahash::AHashMap;
 use libp2p::{
-    kad::{record::Key, store::MemoryStore, GetProvidersError, Kademlia, QueryId},
+    kad::{record::Key, GetProvidersError, Kademlia, QueryId},
     PeerId,
 };
 use tokio::sync::mpsc;
 
+use crate::record_store::PersistentStore;
+
 type ResponseChannel = mpsc::Sender<Result<HashSet<PeerId>, String>>;
 
 const OUTSTANDING_LIMIT: usize = 2048;
@@ -98,7 +100,7 @@ impl Providers {
         is_last: bool,
         key: Key,
         providers: HashSet<PeerId>,
-        kad: &mut Kademlia<MemoryStore>,
+        kad: &mut Kademlia<PersistentStore>,
     ) {
         if let Some(query) = self.current_queries.get_mut(&key) {
             // Ignore queries we didn't start.
@@ -167,7 +169,7 @@ impl Providers {
         &mut self,
         id: QueryId,
         error: GetProvidersError,
-        kad: &mut Kademlia<MemoryStore>,
+        kad: &mut Kademlia<PersistentStore>,
     ) {
         let key = match error {
             GetProvidersError::Timeout { key, .. } => key,
@@ -192,7 +194,7 @@ impl Providers {
         }
     }
 
-    pub fn poll(&mut self, kad: &mut Kademlia<MemoryStore>) {
+    pub fn poll(&mut self, kad: &mut Kademlia<PersistentStore>) {
         // Start a new query if not enough and have an outstanding one.
         if self.current_queries.len() < self.max_running_queries {
             if let Some(Query { key, queries }) = self.outstanding_queries.pop_front() {
@@ -0,0 +1,747 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use libp2p::kad::record::{Key, ProviderRecord, Record};
+use libp2p::kad::store::{self, MemoryStore, MemoryStoreConfig, RecordStore};
+use libp2p::{Multiaddr, PeerId};
+use rocksdb::{ColumnFamilyDescriptor, Options, WriteBatch, DB};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+const CF_RECORDS: &str = "records-v0";
+const CF_PROVIDERS: &str = "providers-v0";
+const CF_LOCAL_ROOTS: &str = "local-roots-v0";
+/// Changes queued for the writer thread before changes are dropped.
+const WRITE_QUEUE_LEN: usize = 64 * 1024;
+/// Dropped changes are reported once, then every this many changes.
+const DROPPED_WRITES_REPORT: usize = 1000;
+
+/// Kademlia record store that keeps its records and provider records on disk, so they
+/// survive restarts.
+///
+/// Reads are served from an in memory store, which also enforces the limits. Changes are
+/// handed to a writer thread that applies them to RocksDB in batches, so the swarm does not
+/// wait on the disk. Without a path records are only kept in memory.
+///
+/// Next to the records the store keeps the local roots, the keys this node was explicitly
+/// asked to provide, as opposed to keys provided because the node fetched the content or
+/// reprovides it.
+pub(crate) struct PersistentStore {
+    memory: MemoryStore,
+    /// Keys with provider records, the memory store only lists the local provider records.
+    provider_keys: HashSet<Key>,
+    local_roots: HashSet<Key>,
+    writer: Option<Writer>,
+}
+
+/// On disk form of a [`Record`], stored under its key.
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredRecord {
+    value: Vec<u8>,
+    publisher: Option<PeerId>,
+    /// Expiry as seconds since the unix epoch, instants do not survive restarts.
+    expires: Option<u64>,
+}
+
+/// On disk form of a [`ProviderRecord`], stored under [`provider_entry`].
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredProvider {
+    addresses: Vec<Multiaddr>,
+    expires: Option<u64>,
+}
+
+impl PersistentStore {
+    /// Opens the store at `path`, creating it if needed, and loads the records that have
+    /// not expired.
+    pub(crate) fn open(
+        local_id: PeerId,
+        config: MemoryStoreConfig,
+        path: Option<&Path>,
+    ) -> Result<Self> {
+        let mut store = PersistentStore {
+            memory: MemoryStore::with_config(local_id, config),
+            provider_keys: HashSet::new(),
+            local_roots: HashSet::new(),
+            writer: None,
+        };
+        if let Some(path) = path {
+            let mut options = Options::default();
+            options.create_if_missing(true);
+            options.create_missing_column_families(true);
+            let cfs = [CF_RECORDS, CF_PROVIDERS, CF_LOCAL_ROOTS]
+                .into_iter()
+                .map(|name| ColumnFamilyDescriptor::new(name, Options::default()));
+            let db = DB::open_cf_descriptors(&options, path, cfs)
+                .with_context(|| format!("failed to open kademlia store at {:?}", path))?;
+            store.load(&db)?;
+            store.writer = Some(Writer::spawn(db)?);
+        }
+        Ok(store)
+    }
+
+    /// Fills the memory store from `db`, deleting expired entries and the entries that are
+    /// over the limits.
+    fn load(&mut self, db: &DB) -> Result<()> {
+        let now = Instant::now();
+        let mut stale = Vec::new();
+
+        let cf = db
+            .cf_handle(CF_RECORDS)
+            .context("missing records column family")?;
+        for item in db.iterator_cf(cf, rocksdb::IteratorMode::Start) {
+            let (key, value) = item?;
+            let record = match bincode::deserialize::<StoredRecord>(&value) {
+                Ok(stored) => Record {
+                    key: Key::from(key.to_vec()),
+                    value: stored.value,
+                    publisher: stored.publisher,
+                    expires: stored.expires.map(to_instant),
+                },
+                Err(err) => {
+                    warn!("dropping undecodable kademlia record: {:?}", err);
+                    stale.push(key);
+                    continue;
+                }
+            };
+            if record.is_expired(now) || self.memory.put(record).is_err() {
+                stale.push(key);
+            }
+        }
+        for key in stale.drain(..) {
+            db.delete_cf(cf, key)?;
+        }
+
+        let cf = db
+            .cf_handle(CF_PROVIDERS)
+            .context("missing providers column family")?;
+        let mut loaded = Vec::new();
+        for item in db.iterator_cf(cf, rocksdb::IteratorMode::Start) {
+            let (entry, value) = item?;
+            let stored = parse_provider_entry(&entry)
+                .context("invalid provider entry")
+                .and_then(|(key, provider)| {
+                    let stored = bincode::deserialize::<StoredProvider>(&value)?;
+                    Ok(ProviderRecord {
+                        key,
+                        provider,
+                        expires: stored.expires.map(to_instant),
+                        addresses: stored.addresses,
+                    })
+                });
+            match stored {
+                Ok(record) if !record.is_expired(now) => {
+                    // records over the limits are dropped, or replaced by later ones
+                    let _ = self.memory.add_provider(record.clone());
+                    loaded.push((entry, record));
+                }
+                Ok(_) => stale.push(entry),
+                Err(err) => {
+                    warn!("dropping undecodable kademlia provider record: {:?}", err);
+                    stale.push(entry);
+                }
+            }
+        }
+        for (entry, record) in loaded {
+            let providers = self.memory.providers(&record.key);
+            if providers.iter().any(|r| r.provider == record.provider) {
+                self.provider_keys.insert(record.key);
+            } else {
+                stale.push(entry);
+            }
+        }
+        for entry in stale {
+            db.delete_cf(cf, entry)?;
+        }
+
+        let cf = db
+            .cf_handle(CF_LOCAL_ROOTS)
+            .context("missing local roots column family")?;
+        for item in db.iterator_cf(cf, rocksdb::IteratorMode::Start) {
+            let (key, _) = item?;
+            self.local_roots.insert(Key::from(key.to_vec()));
+        }
+
+        debug!(
+            "loaded {} kademlia records, {} provided keys and {} local roots",
+            self.memory.records().count(),
+            self.memory.provided().count(),
+            self.local_roots.len()
+        );
+        Ok(())
+    }
+
+    /// Keys this node was asked to provide.
+    pub(crate) fn local_roots(&self) -> impl Iterator<Item = &Key> {
+        self.local_roots.iter()
+    }
+
+    pub(crate) fn add_local_root(&mut self, key: Key) {
+        self.write(WriteOp::Put {
+            cf: CF_LOCAL_ROOTS,
+            key: key.to_vec(),
+            value: Vec::new(),
+        });
+        self.local_roots.insert(key);
+    }
+
+    pub(crate) fn remove_local_root(&mut self, key: &Key) {
+        if self.local_roots.remove(key) {
+            self.write(WriteOp::Delete {
+                cf: CF_LOCAL_ROOTS,
+                key: key.to_vec(),
+            });
+        }
+    }
+
+    /// Removes the records and provider records that have expired.
+    ///
+    /// The memory store keeps expired records until they are removed, this needs to be
+    /// called periodically.
+    pub(crate) fn remove_expired(&mut self) {
+        let now = Instant::now();
+        let records: Vec<_> = self
+            .memory
+            .records()
+            .filter(|record| record.is_expired(now))
+            .map(|record| record.key.clone())
+            .collect();
+        let providers: Vec<_> = self
+            .provider_keys
+            .iter()
+            .flat_map(|key| self.memory.providers(key))
+            .filter(|record| record.is_expired(now))
+            .collect();
+        if !records.is_empty() || !providers.is_empty() {
+            debug!(
+                "removing {} expired kademlia records and {} provider records",
+                records.len(),
+                providers.len()
+            );
+        }
+        for key in records {
+            self.remove(&key);
+        }
+        for record in providers {
+            self.remove_provider(&record.key, &record.provider);
+        }
+    }
+
+    fn write(&self, op: WriteOp) {
+        if let Some(writer) = &self.writer {
+            writer.send(op);
+        }
+    }
+
+    fn write_record(&self, record: &Record) {
+        if self.writer.is_none() {
+            return;
+        }
+        let stored = StoredRecord {
+            value: record.value.clone(),
+            publisher: record.publisher,
+            expires: record.expires.map(to_unix_secs),
+        };
+        match bincode::serialize(&stored) {
+            Ok(value) => self.write(WriteOp::Put {
+                cf: CF_RECORDS,
+                key: record.key.to_vec(),
+                value,
+            }),
+            Err(err) => warn!("failed to persist kademlia record: {:?}", err),
+        }
+    }
+
+    fn write_provider(&self, record: &ProviderRecord) {
+        if self.writer.is_none() {
+            return;
+        }
+        let stored = StoredProvider {
+            addresses: record.addresses.clone(),
+            expires: record.expires.map(to_unix_secs),
+        };
+        match bincode::serialize(&stored) {
+            Ok(value) => self.write(WriteOp::Put {
+                cf: CF_PROVIDERS,
+                key: provider_entry(&record.key, &record.provider),
+                value,
+            }),
+            Err(err) => warn!("failed to persist kademlia provider record: {:?}", err),
+        }
+    }
+}
+
+impl<'a> RecordStore<'a> for PersistentStore {
+    type RecordsIter = <MemoryStore as RecordStore<'a>>::RecordsIter;
+    type ProvidedIter = <MemoryStore as RecordStore<'a>>::ProvidedIter;
+
+    fn get(&'a self, k: &Key) -> Option<Cow<'_, Record>> {
+        self.memory.get(k)
+    }
+
+    fn put(&'a mut self, r: Record) -> store::Result<()> {
+        self.memory.put(r.clone())?;
+        self.write_record(&r);
+        Ok(())
+    }
+
+    fn remove(&'a mut self, k: &Key) {
+        self.memory.remove(k);
+        self.write(WriteOp::Delete {
+            cf: CF_RECORDS,
+            key: k.to_vec(),
+        });
+    }
+
+    fn records(&'a self) -> Self::RecordsIter {
+        self.memory.records()
+    }
+
+    fn add_provider(&'a mut self, record: ProviderRecord) -> store::Result<()> {
+        let key = record.key.clone();
+        let provider = record.provider;
+        let before = self.memory.providers(&key);
+        self.memory.add_provider(record)?;
+
+        // the memory store may replace a provider, or ignore the record, to stay within
+        // its limits
+        let after = self.memory.providers(&key);
+        for old in &before {
+            if !after.iter().any(|r| r.provider == old.provider) {
+                self.write(WriteOp::Delete {
+                    cf: CF_PROVIDERS,
+                    key: provider_entry(&key, &old.provider),
+                });
+            }
+        }
+        if let Some(record) = after.iter().find(|r| r.provider == provider) {
+            self.write_provider(record);
+            self.provider_keys.insert(key);
+        }
+        Ok(())
+    }
+
+    fn providers(&'a self, key: &Key) -> Vec<ProviderRecord> {
+        self.memory.providers(key)
+    }
+
+    fn provided(&'a self) -> Self::ProvidedIter {
+        self.memory.provided()
+    }
+
+    fn remove_provider(&'a mut self, k: &Key, p: &PeerId) {
+        self.memory.remove_provider(k, p);
+        self.write(WriteOp::Delete {
+            cf: CF_PROVIDERS,
+            key: provider_entry(k, p),
+        });
+        if self.memory.providers(k).is_empty() {
+            self.provider_keys.remove(k);
+        }
+    }
+}
+
+/// A change to the store on disk.
+#[derive(Debug)]
+enum WriteOp {
+    Put {
+        cf: &'static str,
+        key: Vec<u8>,
+        value: Vec<u8>,
+    },
+    Delete {
+        cf: &'static str,
+        key: Vec<u8>,
+    },
+}
+
+/// Applies the changes to RocksDB on a dedicated thread, batching the changes that queue
+/// up while a batch is written.
+///
+/// The swarm must not wait on the disk, so changes are dropped and the drops reported when
+/// the thread falls behind by more than the queue. Records on disk that miss a change are
+/// still dropped once they expire.
+///
+/// Dropping the writer waits for the queued changes to be written.
+struct Writer {
+    sender: Option<mpsc::SyncSender<WriteOp>>,
+    thread: Option<thread::JoinHandle<()>>,
+    dropped: AtomicUsize,
+}
+
+impl Writer {
+    fn spawn(db: DB) -> Result<Self> {
+        let (sender, receiver) = mpsc::sync_channel(WRITE_QUEUE_LEN);
+        let thread = thread::Builder::new()
+            .name("kad-store-writer".to_string())
+            .spawn(move || {
+                while let Ok(op) = receiver.recv() {
+                    let mut batch = WriteBatch::default();
+                    for op in std::iter::once(op).chain(receiver.try_iter()) {
+                        let (name, cf) = match &op {
+                            WriteOp::Put { cf, .. } | WriteOp::Delete { cf, .. } => {
+                                (*cf, db.cf_handle(cf))
+                            }
+                        };
+                        let cf = match cf {
+                            Some(cf) => cf,
+                            None => {
+                                warn!("missing column family {}", name);
+                                continue;
+                            }
+                        };
+                        match op {
+                            WriteOp::Put { key, value, .. } => batch.put_cf(cf, key, value),
+                            WriteOp::Delete { key, .. } => batch.delete_cf(cf, key),
+                        }
+                    }
+                    if let Err(err) = db.write(batch) {
+                        warn!("failed to persist kademlia records: {:?}", err);
+                    }
+                }
+            })
+            .context("failed to spawn the kademlia store writer")?;
+        Ok(Writer {
+            sender: Some(sender),
+            thread: Some(thread),
+            dropped: AtomicUsize::new(0),
+        })
+    }
+
+    fn send(&self, op: WriteOp) {
+        let sender = match &self.sender {
+            Some(sender) => sender,
+            None => return,
+        };
+        match sender.try_send(op) {
+            Ok(()) => {}
+            Err(mpsc::TrySendError::Full(_)) => {
+                let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+                if dropped == 1 || dropped % DROPPED_WRITES_REPORT == 0 {
+                    warn!(
+                        "the kademlia store writer is behind, {} changes dropped",
+                        dropped
+                    );
+                }
+            }
+            Err(mpsc::TrySendError::Disconnected(_)) => {
+                warn!("the kademlia store writer is gone, dropping a change");
+            }
+        }
+    }
+}
+
+impl Drop for Writer {
+    fn drop(&mut self) {
+        // closing the channel ends the thread once the queue is written
+        self.sender.take();
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                warn!("the kademlia store writer panicked");
+            }
+        }
+    }
+}
+
+/// Key of the entry of a provider record, the length prefixed record key followed by the
+/// provider, so that every provider of a key is stored on its own.
+fn provider_entry(key: &Key, provider: &PeerId) -> Vec<u8> {
+    let key = key.as_ref();
+    let provider = provider.to_bytes();
+    let mut entry = Vec::with_capacity(4 + key.len() + provider.len());
+    entry.extend_from_slice(&(key.len() as u32).to_be_bytes());
+    entry.extend_from_slice(key);
+    entry.extend_from_slice(&provider);
+    entry
+}
+
+fn parse_provider_entry(entry: &[u8]) -> Option<(Key, PeerId)> {
+    if entry.len() < 4 {
+        return None;
+    }
+    let (len, rest) = entry.split_at(4);
+    let len = u32::from_be_bytes(len.try_into().ok()?) as usize;
+    if rest.len() < len {
+        return None;
+    }
+    let (key, provider) = rest.split_at(len);
+    let provider = PeerId::from_bytes(provider).ok()?;
+    Some((Key::from(key.to_vec()), provider))
+}
+
+fn to_unix_secs(instant: Instant) -> u64 {
+    let now = Instant::now();
+    let unix_now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let unix = if instant >= now {
+        unix_now + (instant - now)
+    } else {
+        unix_now.saturating_sub(now - instant)
+    };
+    // round up, so records do not expire early
+    unix.as_secs() + u64::from(unix.subsec_nanos() > 0)
+}
+
+fn to_instant(unix_secs: u64) -> Instant {
+    let now = Instant::now();
+    let unix = Duration::from_secs(unix_secs);
+    let unix_now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    if unix >= unix_now {
+        now + (unix - unix_now)
+    } else {
+        now.checked_sub(unix_now - unix).unwrap_or(now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open(path: &Path, config: MemoryStoreConfig, peer_id: PeerId) -> PersistentStore {
+        PersistentStore::open(peer_id, config, Some(path)).unwrap()
+    }
+
+    /// Number of entries in the column family `name` of the store at `path`.
+    fn count_entries(path: &Path, name: &str) -> usize {
+        let db = DB::open_cf(
+            &Options::default(),
+            path,
+            [CF_RECORDS, CF_PROVIDERS, CF_LOCAL_ROOTS],
+        )
+        .unwrap();
+        let cf = db.cf_handle(name).unwrap();
+        db.iterator_cf(cf, rocksdb::IteratorMode::Start).count()
+    }
+
+    #[test]
+    fn records_survive_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let local_id = PeerId::random();
+        let remote_id = PeerId::random();
+        let key = Key::new(&"hello");
+        let expires = Instant::now() + Duration::from_secs(3600);
+
+        {
+            let mut store = open(dir.path(), Default::default(), local_id);
+            let mut record = Record::new(key.clone(), b"world".to_vec());
+            record.expires = Some(expires);
+            store.put(record).unwrap();
+            store
+                .add_provider(ProviderRecord::new(key.clone(), local_id, Vec::new()))
+                .unwrap();
+            let mut remote = ProviderRecord::new(
+                key.clone(),
+                remote_id,
+                vec!["/ip4/127.0.0.1/tcp/4444".parse().unwrap()],
+            );
+            remote.expires = Some(expires);
+            store.add_provider(remote).unwrap();
+        }
+
+        let store = open(dir.path(), Default::default(), local_id);
+        let record = store.get(&key).unwrap();
+        assert_eq!(record.value, b"world");
+        let restored = record.expires.unwrap();
+        assert!(restored + Duration::from_secs(1) >= expires);
+        assert!(restored <= expires + Duration::from_secs(2));
+
+        let mut providers: Vec<_> = store
+            .providers(&key)
+            .into_iter()
+            .map(|record| record.provider)
+            .collect();
+        providers.sort();
+        let mut expected = vec![local_id, remote_id];
+        expected.sort();
+        assert_eq!(providers, expected);
+        assert_eq!(store.provided().count(), 1);
+    }
+
+    #[test]
+    fn removals_and_expiry_are_persisted() {
+        let dir = tempfile::tempdir().unwrap();
+        let local_id = PeerId::random();
+        let remote_id = PeerId::random();
+        let kept = Key::new(&"kept");
+        let removed = Key::new(&"removed");
+        let expired = Key::new(&"expired");
+
+        {
+            let mut store = open(dir.path(), Default::default(), local_id);
+            store
+                .put(Record::new(kept.clone(), b"kept".to_vec()))
+                .unwrap();
+            store
+                .put(Record::new(removed.clone(), b"removed".to_vec()))
+                .unwrap();
+            let mut record = Record::new(expired.clone(), b"expired".to_vec());
+            record.expires = Instant::now().checked_sub(Duration::from_secs(10));
+            store.put(record).unwrap();
+
+            store
+                .add_provider(ProviderRecord::new(kept.clone(), remote_id, Vec::new()))
+                .unwrap();
+            store
+                .add_provider(ProviderRecord::new(removed.clone(), local_id, Vec::new()))
+                .unwrap();
+            store.remove(&removed);
+            store.remove_provider(&removed, &local_id);
+        }
+
+        let store = open(dir.path(), Default::default(), local_id);
+        assert!(store.get(&kept).is_some());
+        assert!(store.get(&removed).is_none());
+        assert!(store.get(&expired).is_none());
+        assert_eq!(store.providers(&kept).len(), 1);
+        assert!(store.providers(&removed).is_empty());
+        assert_eq!(store.provided().count(), 0);
+    }
+
+    #[test]
+    fn limits_apply_on_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let local_id = PeerId::random();
+
+        {
+            let mut store = open(dir.path(), Default::default(), local_id);
+            for i in 0..4u8 {
+                store
+                    .put(Record::new(Key::new(&i.to_string()), vec![i]))
+                    .unwrap();
+            }
+        }
+
+        let config = MemoryStoreConfig {
+            max_records: 2,
+            ..Default::default()
+        };
+        let mut store = open(dir.path(), config, local_id);
+        assert_eq!(store.records().count(), 2);
+        assert!(store
+            .put(Record::new(Key::new(&"more"), b"more".to_vec()))
+            .is_err());
+    }
+
+    #[test]
+    fn local_roots_survive_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let local_id = PeerId::random();
+        let root = Key::new(&"root");
+        let removed = Key::new(&"removed");
+
+        {
+            let mut store = open(dir.path(), Default::default(), local_id);
+            store.add_local_root(root.clone());
+            store.add_local_root(removed.clone());
+            store.remove_local_root(&removed);
+            // provided keys are not local roots
+            store
+                .add_provider(ProviderRecord::new(
+                    Key::new(&"fetched"),
+                    local_id,
+                    Vec::new(),
+                ))
+                .unwrap();
+        }
+
+        let store = open(dir.path(), Default::default(), local_id);
+        assert_eq!(store.local_roots().collect::<Vec<_>>(), vec![&root]);
+        assert_eq!(store.provided().count(), 1);
+    }
+
+    #[test]
+    fn providers_are_stored_per_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        let local_id = PeerId::random();
+        let key = Key::new(&"hello");
+        let config = MemoryStoreConfig {
+            max_providers_per_key: 2,
+            ..Default::default()
+        };
+
+        let provider = PeerId::random();
+        assert_eq!(
+            parse_provider_entry(&provider_entry(&key, &provider)),
+            Some((key.clone(), provider))
+        );
+
+        {
+            let mut store = open(dir.path(), config.clone(), local_id);
+            for _ in 0..4 {
+                store
+                    .add_provider(ProviderRecord::new(
+                        key.clone(),
+                        PeerId::random(),
+                        Vec::new(),
+                    ))
+                    .unwrap();
+            }
+            assert_eq!(store.providers(&key).len(), 2);
+        }
+        // providers replaced to stay within the limits are deleted
+        assert_eq!(count_entries(dir.path(), CF_PROVIDERS), 2);
+
+        let mut store = open(dir.path(), config, local_id);
+        let providers = store.providers(&key);
+        assert_eq!(providers.len(), 2);
+        store.remove_provider(&key, &providers[0].provider);
+        drop(store);
+        assert_eq!(count_entries(dir.path(), CF_PROVIDERS), 1);
+    }
+
+    #[test]
+    fn expired_records_are_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let local_id = PeerId::random();
+        let key = Key::new(&"hello");
+        let past = Instant::now().checked_sub(Duration::from_secs(10));
+
+        {
+            let mut store = open(dir.path(), Default::default(), local_id);
+            let mut record = Record::new(key.clone(), b"world".to_vec());
+            record.expires = past;
+            store.put(record).unwrap();
+            store
+                .put(Record::new(Key::new(&"kept"), b"kept".to_vec()))
+                .unwrap();
+            let mut expired = ProviderRecord::new(key.clone(), PeerId::random(), Vec::new());
+            expired.expires = past;
+            store.add_provider(expired).unwrap();
+            store
+                .add_provider(ProviderRecord::new(
+                    key.clone(),
+                    PeerId::random(),
+                    Vec::new(),
+                ))
+                .unwrap();
+            assert_eq!(store.providers(&key).len(), 2);
+
+            store.remove_expired();
+            assert!(store.get(&key).is_none());
+            assert_eq!(store.records().count(), 1);
+            assert_eq!(store.providers(&key).len(), 1);
+        }
+
+        assert_eq!(count_entries(dir.path(), CF_RECORDS), 1);
+        assert_eq!(count_entries(dir.path(), CF_PROVIDERS), 1);
+    }
+
+    #[test]
+    fn memory_only() {
+        let local_id = PeerId::random();
+        let mut store = PersistentStore::open(local_id, Default::default(), None).unwrap();
+        let key = Key::new(&"hello");
+        store
+            .put(Record::new(key.clone(), b"world".to_vec()))
+            .unwrap();
+        assert_eq!(store.get(&key).unwrap().value, b"world");
+    }
+}
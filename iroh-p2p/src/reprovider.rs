@@ -0,0 +1,297 @@
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use futures::{Stream, StreamExt};
+use iroh_metrics::{core::MRecorder, inc, p2p::P2PMetrics, record};
+use iroh_rpc_client::Client as RpcClient;
+use libp2p::kad::record::Key;
+use tokio::sync::mpsc::Sender;
+use tokio::sync::{oneshot, watch};
+use tokio::time::MissedTickBehavior;
+use tracing::{debug, info, warn};
+
+use crate::config::{Libp2pConfig, ReproviderStrategy};
+use crate::rpc::RpcMessage;
+
+/// Delay before the first run, so the node can join the DHT first.
+const INITIAL_DELAY: Duration = Duration::from_secs(60);
+
+/// Progress of the reprovider.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReproviderStatus {
+    pub(crate) strategy: ReproviderStrategy,
+    pub(crate) running: bool,
+    /// Keys announced in the current run, or in the last run if none is in progress.
+    pub(crate) provided: u64,
+    /// Keys that could not be announced in the current or last run.
+    pub(crate) failed: u64,
+    /// Start of the last complete run.
+    pub(crate) last_run: Option<SystemTime>,
+    /// Duration of the last complete run.
+    pub(crate) last_run_duration: Option<Duration>,
+}
+
+/// Periodically announces the keys selected by its strategy to the DHT.
+///
+/// Keys are announced through the node in batches, and the batches are spaced out to stay
+/// below the configured rate.
+#[derive(Debug)]
+pub(crate) struct Reprovider {
+    strategy: ReproviderStrategy,
+    interval: Duration,
+    batch_size: usize,
+    max_rate: u32,
+    rpc_client: RpcClient,
+    sender: Sender<RpcMessage>,
+    status: watch::Sender<ReproviderStatus>,
+}
+
+impl Reprovider {
+    pub(crate) fn new(
+        config: &Libp2pConfig,
+        rpc_client: RpcClient,
+        sender: Sender<RpcMessage>,
+    ) -> (Self, watch::Receiver<ReproviderStatus>) {
+        let (status, status_receiver) = watch::channel(ReproviderStatus {
+            strategy: config.reprovider_strategy,
+            ..Default::default()
+        });
+        let reprovider = Reprovider {
+            strategy: config.reprovider_strategy,
+            interval: Duration::from_secs(config.reprovider_interval_secs),
+            batch_size: config.reprovider_batch_size.max(1),
+            max_rate: config.reprovider_max_rate,
+            rpc_client,
+            sender,
+            status,
+        };
+        (reprovider, status_receiver)
+    }
+
+    /// Reprovides on the configured interval, until the node goes away.
+    pub(crate) async fn run(self) {
+        let start = tokio::time::Instant::now() + INITIAL_DELAY.min(self.interval);
+        let mut interval = tokio::time::interval_at(start, self.interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            if let Err(err) = self.reprovide().await {
+                self.status.send_modify(|status| status.running = false);
+                if self.sender.is_closed() {
+                    return;
+                }
+                warn!("reprovide failed: {:?}", err);
+            }
+        }
+    }
+
+    /// Announces all keys selected by the strategy once.
+    pub(crate) async fn reprovide(&self) -> Result<()> {
+        info!("reproviding ({})", self.strategy.as_str());
+        let started = Instant::now();
+        let started_at = SystemTime::now();
+        self.status.send_modify(|status| {
+            status.running = true;
+            status.provided = 0;
+            status.failed = 0;
+        });
+        record!(P2PMetrics::ReproviderProgress, 0);
+
+        match self.strategy {
+            ReproviderStrategy::LocalRoots => {
+                let (s, r) = oneshot::channel();
+                self.sender.send(RpcMessage::LocalRoots(s)).await?;
+                let keys = r.await?;
+                self.provide(futures::stream::once(async { Ok(keys) }), started)
+                    .await?;
+            }
+            ReproviderStrategy::All | ReproviderStrategy::Roots => {
+                let roots_only = self.strategy == ReproviderStrategy::Roots;
+                let cids = self.rpc_client.try_store()?.get_blocks(roots_only).await?;
+                let keys = cids.map(|cids| {
+                    let keys = cids?
+                        .iter()
+                        .map(|cid| Key::new(&cid.hash().to_bytes()))
+                        .collect();
+                    Ok(keys)
+                });
+                self.provide(keys, started).await?;
+            }
+        }
+
+        let duration = started.elapsed();
+        self.status.send_modify(|status| {
+            status.running = false;
+            status.last_run = Some(started_at);
+            status.last_run_duration = Some(duration);
+        });
+        let status = self.status.borrow().clone();
+        info!(
+            "reprovided {} keys in {:?}, {} failed",
+            status.provided, duration, status.failed
+        );
+        inc!(P2PMetrics::ReproviderRuns);
+        let last_run = started_at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        record!(P2PMetrics::ReproviderLastRun, last_run);
+        record!(
+            P2PMetrics::ReproviderLastRunDuration,
+            duration.as_millis() as u64
+        );
+        Ok(())
+    }
+
+    /// Announces the keys of `keys` in batches of `batch_size`.
+    async fn provide<S>(&self, keys: S, started: Instant) -> Result<()>
+    where
+        S: Stream<Item = Result<Vec<Key>>>,
+    {
+        tokio::pin!(keys);
+        let mut batch = Vec::with_capacity(self.batch_size);
+        while let Some(next) = keys.next().await {
+            for key in next? {
+                batch.push(key);
+                if batch.len() >= self.batch_size {
+                    self.provide_batch(std::mem::take(&mut batch), started)
+                        .await?;
+                }
+            }
+        }
+        if !batch.is_empty() {
+            self.provide_batch(batch, started).await?;
+        }
+        Ok(())
+    }
+
+    async fn provide_batch(&self, batch: Vec<Key>, started: Instant) -> Result<()> {
+        let count = batch.len() as u64;
+        let (s, r) = oneshot::channel();
+        self.sender
+            .send(RpcMessage::StartProvidingMany(s, batch))
+            .await?;
+        let provided = r.await??;
+        let failed = count - provided;
+        if failed > 0 {
+            debug!("failed to reprovide {} keys", failed);
+        }
+        self.status.send_modify(|status| {
+            status.provided += provided;
+            status.failed += failed;
+        });
+        record!(P2PMetrics::ReprovidedKeys, provided);
+        record!(P2PMetrics::ReproviderFailures, failed);
+
+        let announced = {
+            let status = self.status.borrow();
+            record!(P2PMetrics::ReproviderProgress, status.provided);
+            status.provided + status.failed
+        };
+        if self.max_rate > 0 {
+            let target = Duration::from_secs_f64(announced as f64 / self.max_rate as f64);
+            let elapsed = started.elapsed();
+            if target > elapsed {
+                tokio::time::sleep(target - elapsed).await;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc::{channel, Receiver};
+
+    use super::*;
+
+    /// Answers the node messages of the reprovider, failing to provide `fail`, and returns
+    /// the announced keys.
+    async fn node(mut receiver: Receiver<RpcMessage>, keys: Vec<Key>, fail: Key) -> Vec<Key> {
+        let mut provided = Vec::new();
+        while let Some(message) = receiver.recv().await {
+            match message {
+                RpcMessage::LocalRoots(response_channel) => {
+                    response_channel.send(keys.clone()).ok();
+                }
+                RpcMessage::StartProvidingMany(response_channel, keys) => {
+                    let count = provided.len();
+                    provided.extend(keys.into_iter().filter(|key| key != &fail));
+                    response_channel
+                        .send(Ok((provided.len() - count) as u64))
+                        .ok();
+                }
+                message => panic!("unexpected message: {:?}", message),
+            }
+        }
+        provided
+    }
+
+    #[tokio::test]
+    async fn reprovide_local_roots() {
+        let keys: Vec<_> = (0..10u8).map(|i| Key::new(&[i])).collect();
+        let (sender, receiver) = channel(64);
+        let node = tokio::task::spawn(node(receiver, keys.clone(), keys[3].clone()));
+
+        let config = Libp2pConfig {
+            reprovider_strategy: ReproviderStrategy::LocalRoots,
+            reprovider_batch_size: 4,
+            reprovider_max_rate: 0,
+            ..Default::default()
+        };
+        let rpc_client = RpcClient::new(Default::default()).await.unwrap();
+        let (reprovider, status) = Reprovider::new(&config, rpc_client, sender);
+
+        reprovider.reprovide().await.unwrap();
+        let got = status.borrow().clone();
+        assert!(!got.running);
+        assert_eq!(got.provided, 9);
+        assert_eq!(got.failed, 1);
+        assert!(got.last_run.is_some());
+        assert!(got.last_run_duration.is_some());
+
+        drop(reprovider);
+        let mut expected = keys;
+        expected.remove(3);
+        assert_eq!(node.await.unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn reprovide_rate_limit() {
+        let keys: Vec<_> = (0..6u8).map(|i| Key::new(&[i])).collect();
+        let (sender, receiver) = channel(64);
+        let node = tokio::task::spawn(node(receiver, keys.clone(), Key::new(b"none")));
+
+        let config = Libp2pConfig {
+            reprovider_strategy: ReproviderStrategy::LocalRoots,
+            reprovider_batch_size: 2,
+            reprovider_max_rate: 20,
+            ..Default::default()
+        };
+        let rpc_client = RpcClient::new(Default::default()).await.unwrap();
+        let (reprovider, status) = Reprovider::new(&config, rpc_client, sender);
+
+        let started = Instant::now();
+        reprovider.reprovide().await.unwrap();
+        // 6 keys at 20 keys per second
+        assert!(started.elapsed() >= Duration::from_millis(300));
+        assert_eq!(status.borrow().provided, 6);
+
+        drop(reprovider);
+        assert_eq!(node.await.unwrap().len(), 6);
+    }
+
+    #[tokio::test]
+    async fn reprovide_without_store() {
+        let (sender, _receiver) = channel(64);
+        let config = Libp2pConfig {
+            reprovider_strategy: ReproviderStrategy::Roots,
+            ..Default::default()
+        };
+        let rpc_client = RpcClient::new(Default::default()).await.unwrap();
+        let (reprovider, status) = Reprovider::new(&config, rpc_client, sender);
+
+        assert!(reprovider.reprovide().await.is_err());
+        assert_eq!(status.borrow().last_run, None);
+    }
+}
@@ -1,6 +1,7 @@
 use std::collections::{HashMap, HashSet};
 use std::io;
 use std::pin::Pin;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use anyhow::{anyhow, ensure, Context, Result};
 use bytes::Bytes;
@@ -26,10 +27,12 @@ use iroh_rpc_types::p2p::{
     GossipsubPeerIdMsg, GossipsubPeersResponse, GossipsubPublishRequest, GossipsubPublishResponse,
     GossipsubSubscribeResponse, GossipsubTopicHashMsg, GossipsubTopicsResponse, Key as ProviderKey,
     LookupRequest, Multiaddrs, NotifyNewBlocksBitswapRequest, P2p as RpcP2p, P2pServerAddr,
-    PeerIdResponse, PeerInfo, Providers, StopSessionBitswapRequest, VersionResponse,
+    PeerIdResponse, PeerInfo, Providers, ReproviderStatusResponse, StopSessionBitswapRequest,
+    VersionResponse,
 };
 
 use super::node::DEFAULT_PROVIDER_LIMIT;
+use crate::reprovider::ReproviderStatus;
 
 struct P2p {
     sender: Sender<RpcMessage>,
@@ -210,6 +213,30 @@ impl RpcP2p for P2p {
         Ok(())
     }
 
+    #[tracing::instrument(skip(self))]
+    async fn reprovider_status(&self, _: ()) -> Result<ReproviderStatusResponse> {
+        trace!("received ReproviderStatus request");
+        let (s, r) = oneshot::channel();
+        self.sender.send(RpcMessage::ReproviderStatus(s)).await?;
+
+        let status = r.await?.ok_or_else(|| anyhow!("reprovider is disabled"))?;
+        let since_epoch = |time: SystemTime| {
+            time.duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs()
+        };
+        Ok(ReproviderStatusResponse {
+            strategy: status.strategy.as_str().to_string(),
+            running: status.running,
+            provided: status.provided,
+            failed: status.failed,
+            last_run: status.last_run.map(since_epoch),
+            last_run_duration_ms: status
+                .last_run_duration
+                .map(|duration| duration.as_millis() as u64),
+        })
+    }
+
     #[tracing::instrument(skip(self, req))]
     async fn stop_providing(&self, req: ProviderKey) -> Result<()> {
         trace!("received StopProviding request: {:?}", req.key);
@@ -554,6 +581,11 @@ pub enum RpcMessage {
         limit: usize,
     },
     StartProviding(oneshot::Sender<Result<libp2p::kad::QueryId>>, Key),
+    /// Starts providing all keys, responding with the number of keys that are provided.
+    StartProvidingMany(oneshot::Sender<Result<u64>>, Vec<Key>),
+    /// Lists the keys this node was asked to provide, e.g. the roots of added content.
+    LocalRoots(oneshot::Sender<Vec<Key>>),
+    ReproviderStatus(oneshot::Sender<Option<ReproviderStatus>>),
     StopProviding(oneshot::Sender<Result<()>>, Key),
     NetListeningAddrs(oneshot::Sender<(PeerId, Vec<Multiaddr>)>),
     NetPeers(oneshot::Sender<HashMap<PeerId, Vec<Multiaddr>>>),
@@ -1,44 +1,77 @@
 use std::time::Duration;
 
-use anyhow::Result;
+use anyhow::{ensure, Result};
 use iroh_rpc_client::Client;
 use libp2p::{
     core::{
         self,
+        either::EitherOutput,
         muxing::StreamMuxerBox,
-        transport::{timeout::TransportTimeout, Boxed, OrTransport},
+        transport::{timeout::TransportTimeout, Boxed, OptionalTransport, OrTransport},
+        upgrade::OptionalUpgrade,
     },
     dns,
     identity::Keypair,
-    mplex, noise,
+    mplex, noise, quic,
     swarm::{ConnectionLimits, SwarmBuilder},
     yamux::{self, WindowUpdateMode},
     PeerId, Swarm, Transport,
 };
 
-use crate::{behaviour::NodeBehaviour, Libp2pConfig};
+use crate::{behaviour::NodeBehaviour, config::Muxer, Libp2pConfig};
+
+/// Smallest yamux receive window, yamux panics on smaller windows.
+const MIN_YAMUX_RECEIVE_WINDOW_SIZE: u32 = 256 * 1024;
 
 /// Builds the transport stack that LibP2P will communicate over.
+///
+/// TCP, WebSocket and QUIC can each be turned off in the config, the relay transport is
+/// added when the relay client is enabled. Fails if the timeouts or the yamux receive
+/// window in the config are invalid.
 async fn build_transport(
     keypair: &Keypair,
     config: &Libp2pConfig,
-) -> (
+) -> Result<(
     Boxed<(PeerId, StreamMuxerBox)>,
     Option<libp2p::relay::v2::client::Client>,
-) {
-    // TODO: make transports configurable
+)> {
+    ensure!(
+        config.transport_timeout_secs > 0,
+        "transport_timeout_secs must be greater than 0"
+    );
+    ensure!(
+        config.connection_timeout_secs > 0,
+        "connection_timeout_secs must be greater than 0"
+    );
+    ensure!(
+        config.yamux_receive_window_size >= MIN_YAMUX_RECEIVE_WINDOW_SIZE,
+        "yamux_receive_window_size must be at least {} bytes",
+        MIN_YAMUX_RECEIVE_WINDOW_SIZE
+    );
 
     let tcp_config = libp2p::tcp::GenTcpConfig::default().port_reuse(true);
-    let transport = libp2p::tcp::TokioTcpTransport::new(tcp_config.clone());
-    let transport =
-        libp2p::websocket::WsConfig::new(libp2p::tcp::TokioTcpTransport::new(tcp_config))
-            .or_transport(transport);
+    let tcp = if config.tcp {
+        OptionalTransport::some(libp2p::tcp::TokioTcpTransport::new(tcp_config.clone()))
+    } else {
+        OptionalTransport::none()
+    };
+    let websocket = if config.websocket {
+        OptionalTransport::some(libp2p::websocket::WsConfig::new(
+            libp2p::tcp::TokioTcpTransport::new(tcp_config),
+        ))
+    } else {
+        OptionalTransport::none()
+    };
+    let transport = websocket.or_transport(tcp);
 
-    // TODO: configurable
-    let transport = TransportTimeout::new(transport, Duration::from_secs(10));
+    let transport = TransportTimeout::new(
+        transport,
+        Duration::from_secs(config.transport_timeout_secs),
+    );
     let dns_cfg = dns::ResolverConfig::cloudflare();
     let dns_opts = dns::ResolverOpts::default();
-    let transport = dns::TokioDnsConfig::custom(transport, dns_cfg, dns_opts).unwrap();
+    let transport =
+        dns::TokioDnsConfig::custom(transport, dns_cfg.clone(), dns_opts.clone()).unwrap();
 
     let auth_config = {
         let dh_keys = noise::Keypair::<noise::X25519Spec>::new()
@@ -53,39 +86,56 @@ async fn build_transport(
         mplex_config.set_max_buffer_size(usize::MAX);
 
         let mut yamux_config = yamux::YamuxConfig::default();
-        yamux_config.set_max_buffer_size(16 * 1024 * 1024); // TODO: configurable
-        yamux_config.set_receive_window_size(16 * 1024 * 1024); // TODO: configurable
+        yamux_config.set_max_buffer_size(config.yamux_max_buffer_size);
+        yamux_config.set_receive_window_size(config.yamux_receive_window_size);
         yamux_config.set_window_update_mode(WindowUpdateMode::on_receive());
+
+        // only the selected muxers are offered during negotiation
+        let (yamux_config, mplex_config) = match config.muxer {
+            Muxer::Both => (
+                OptionalUpgrade::some(yamux_config),
+                OptionalUpgrade::some(mplex_config),
+            ),
+            Muxer::Yamux => (OptionalUpgrade::some(yamux_config), OptionalUpgrade::none()),
+            Muxer::Mplex => (OptionalUpgrade::none(), OptionalUpgrade::some(mplex_config)),
+        };
         core::upgrade::SelectUpgrade::new(yamux_config, mplex_config)
     };
 
-    // TODO: configurable
-    let connection_timeout = Duration::from_secs(30);
-    if config.relay_client {
+    let (relay_transport, relay_client) = if config.relay_client {
         let (relay_transport, relay_client) =
             libp2p::relay::v2::client::Client::new_transport_and_behaviour(
                 keypair.public().to_peer_id(),
             );
+        (OptionalTransport::some(relay_transport), Some(relay_client))
+    } else {
+        (OptionalTransport::none(), None)
+    };
 
-        let transport = OrTransport::new(relay_transport, transport);
-        let transport = transport
-            .upgrade(core::upgrade::Version::V1Lazy)
-            .authenticate(auth_config)
-            .multiplex(muxer_config)
-            .timeout(connection_timeout)
-            .boxed();
-
-        (transport, Some(relay_client))
+    let connection_timeout = Duration::from_secs(config.connection_timeout_secs);
+    let transport = OrTransport::new(relay_transport, transport)
+        .upgrade(core::upgrade::Version::V1Lazy)
+        .authenticate(auth_config)
+        .multiplex(muxer_config)
+        .timeout(connection_timeout);
+
+    // QUIC brings its own encryption and multiplexing
+    let quic = if config.quic {
+        let quic = quic::tokio::Transport::new(quic::Config::new(keypair))
+            .map(|(peer_id, muxer), _| (peer_id, StreamMuxerBox::new(muxer)));
+        OptionalTransport::some(dns::TokioDnsConfig::custom(quic, dns_cfg, dns_opts).unwrap())
     } else {
-        let transport = transport
-            .upgrade(core::upgrade::Version::V1Lazy)
-            .authenticate(auth_config)
-            .multiplex(muxer_config)
-            .timeout(connection_timeout)
-            .boxed();
-
-        (transport, None)
-    }
+        OptionalTransport::none()
+    };
+
+    let transport = OrTransport::new(quic, transport)
+        .map(|output, _| match output {
+            EitherOutput::First((peer_id, muxer)) => (peer_id, muxer),
+            EitherOutput::Second((peer_id, muxer)) => (peer_id, StreamMuxerBox::new(muxer)),
+        })
+        .boxed();
+
+    Ok((transport, relay_client))
 }
 
 pub(crate) async fn build_swarm(
@@ -95,7 +145,7 @@ pub(crate) async fn build_swarm(
 ) -> Result<Swarm<NodeBehaviour>> {
     let peer_id = keypair.public().to_peer_id();
 
-    let (transport, relay_client) = build_transport(keypair, config).await;
+    let (transport, relay_client) = build_transport(keypair, config).await?;
     let behaviour = NodeBehaviour::new(keypair, config, relay_client, rpc_client).await?;
 
     let limits = ConnectionLimits::default()
@@ -116,3 +166,67 @@ pub(crate) async fn build_swarm(
 
     Ok(swarm)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_transport_selection() {
+        let keypair = Keypair::generate_ed25519();
+        let quic_addr: libp2p::Multiaddr = "/ip4/127.0.0.1/udp/0/quic".parse().unwrap();
+        let tcp_addr: libp2p::Multiaddr = "/ip4/127.0.0.1/tcp/0".parse().unwrap();
+
+        let config = Libp2pConfig::default();
+        let (mut transport, _) = build_transport(&keypair, &config).await.unwrap();
+        assert!(transport.listen_on(quic_addr.clone()).is_ok());
+        assert!(transport.listen_on(tcp_addr.clone()).is_ok());
+
+        let config = Libp2pConfig {
+            tcp: false,
+            websocket: false,
+            relay_client: false,
+            ..Default::default()
+        };
+        let (mut transport, _) = build_transport(&keypair, &config).await.unwrap();
+        assert!(transport.listen_on(quic_addr.clone()).is_ok());
+        assert!(transport.listen_on(tcp_addr.clone()).is_err());
+
+        let config = Libp2pConfig {
+            quic: false,
+            ..Default::default()
+        };
+        let (mut transport, _) = build_transport(&keypair, &config).await.unwrap();
+        assert!(transport.listen_on(quic_addr).is_err());
+        assert!(transport.listen_on(tcp_addr).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_transport_config_validation() {
+        let keypair = Keypair::generate_ed25519();
+
+        let config = Libp2pConfig {
+            yamux_receive_window_size: MIN_YAMUX_RECEIVE_WINDOW_SIZE,
+            ..Default::default()
+        };
+        assert!(build_transport(&keypair, &config).await.is_ok());
+
+        let config = Libp2pConfig {
+            yamux_receive_window_size: MIN_YAMUX_RECEIVE_WINDOW_SIZE - 1,
+            ..Default::default()
+        };
+        assert!(build_transport(&keypair, &config).await.is_err());
+
+        let config = Libp2pConfig {
+            transport_timeout_secs: 0,
+            ..Default::default()
+        };
+        assert!(build_transport(&keypair, &config).await.is_err());
+
+        let config = Libp2pConfig {
+            connection_timeout_secs: 0,
+            ..Default::default()
+        };
+        assert!(build_transport(&keypair, &config).await.is_err());
+    }
+}
@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use bytes::Bytes;
+use cid::multihash::{Code, MultihashDigest};
+use cid::Cid;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use futures::TryStreamExt;
+use iroh_resolver::car_export::{export_blocks, ExportOptions};
+use iroh_resolver::resolver::{
+    ContentLoader, ContextId, LoadedCid, LoaderContext, Path, Resolver, Source,
+};
+use libipld::codec::Encode;
+use libipld::{Ipld, IpldCodec};
+use tokio::runtime::Runtime;
+
+const DAG_CBOR: u64 = 0x71;
+
+/// Serves blocks from memory, delaying every load like a network fetch.
+#[derive(Debug, Clone)]
+struct LatencyLoader {
+    blocks: HashMap<Cid, Bytes>,
+    latency: Duration,
+}
+
+#[async_trait]
+impl ContentLoader for LatencyLoader {
+    async fn load_cid(&self, cid: &Cid, _ctx: &LoaderContext) -> Result<LoadedCid> {
+        tokio::time::sleep(self.latency).await;
+        let data = self.blocks.get(cid).cloned().context("not found")?;
+        Ok(LoadedCid {
+            data,
+            source: Source::Bitswap,
+        })
+    }
+
+    async fn stop_session(&self, _ctx: ContextId) -> Result<()> {
+        Ok(())
+    }
+
+    async fn has_cid(&self, cid: &Cid) -> Result<bool> {
+        Ok(self.blocks.contains_key(cid))
+    }
+}
+
+/// A tree of dag-cbor nodes with `width` children each, `depth` levels deep.
+fn tree(width: usize, depth: usize) -> (HashMap<Cid, Bytes>, Cid) {
+    fn node(blocks: &mut HashMap<Cid, Bytes>, width: usize, depth: usize, id: &mut u64) -> Cid {
+        *id += 1;
+        let ipld = if depth == 0 {
+            Ipld::Integer(*id as i128)
+        } else {
+            Ipld::List(
+                (0..width)
+                    .map(|_| Ipld::Link(node(blocks, width, depth - 1, id)))
+                    .collect(),
+            )
+        };
+        let mut data = Vec::new();
+        ipld.encode(IpldCodec::DagCbor, &mut data).unwrap();
+        let cid = Cid::new_v1(DAG_CBOR, Code::Sha2_256.digest(&data));
+        blocks.insert(cid, Bytes::from(data));
+        cid
+    }
+
+    let mut blocks = HashMap::new();
+    let root = node(&mut blocks, width, depth, &mut 0);
+    (blocks, root)
+}
+
+pub fn export_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("car_export");
+    group.sample_size(10);
+    for (width, depth) in [(4, 3), (16, 2)] {
+        let (blocks, root) = tree(width, depth);
+        let loader = LatencyLoader {
+            blocks,
+            latency: Duration::from_millis(5),
+        };
+        let executor = Runtime::new().unwrap();
+        let resolver = executor.block_on(async { Resolver::new(loader) });
+        let id = format!("{}x{}", width, depth);
+
+        group.bench_with_input(BenchmarkId::new("export_blocks", &id), &root, |b, root| {
+            b.to_async(&executor).iter(|| async {
+                let blocks: Vec<_> =
+                    export_blocks(&resolver, Path::from_cid(*root), ExportOptions::default())
+                        .try_collect()
+                        .await
+                        .unwrap();
+                black_box(blocks)
+            });
+        });
+        group.bench_with_input(
+            BenchmarkId::new("resolve_recursive_raw", &id),
+            &root,
+            |b, root| {
+                b.to_async(&executor).iter(|| async {
+                    let blocks: Vec<_> = resolver
+                        .resolve_recursive_raw(Path::from_cid(*root), None)
+                        .try_collect()
+                        .await
+                        .unwrap();
+                    black_box(blocks)
+                });
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, export_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,530 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use anyhow::{Context as _, Result};
+use cid::Cid;
+use futures::{future::RemoteHandle, FutureExt, Stream, StreamExt};
+use iroh_car::{CarHeader, CarWriter};
+use libipld::prelude::Codec as _;
+use libipld::{Ipld, IpldCodec};
+use tokio::io::AsyncWrite;
+
+use crate::codecs::Codec;
+use crate::resolver::{ContentLoader, OutRaw, Path, Resolver};
+
+/// Multihash code of identity hashes, whose content is inlined into the cid.
+const IDENTITY_HASH: u64 = 0x00;
+/// Max number of upcoming blocks that are loaded ahead of the export.
+const PREFETCH_WINDOW: usize = 16;
+
+/// Order in which the blocks of a DAG are exported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Traversal {
+    /// Every block is followed by the blocks below it, in the order they are linked.
+    #[default]
+    DepthFirst,
+    /// Blocks are exported level by level, each level in the order the blocks are linked.
+    BreadthFirst,
+}
+
+/// Options for exporting a DAG with [`export_blocks`] or [`export_car`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExportOptions {
+    pub traversal: Traversal,
+    /// Maximum number of links followed from the root, `Some(0)` only exports the root.
+    pub max_depth: Option<usize>,
+    /// Stops the export before the block that would take the total size of the exported
+    /// blocks over this many bytes.
+    pub max_bytes: Option<u64>,
+    /// Leaves out the blocks with the raw codec below the root, without loading them.
+    pub skip_raw_leaves: bool,
+}
+
+/// Streams the blocks of the DAG below `root` in a deterministic order.
+///
+/// Every block is exported once, the first time it is reached, so exporting the same DAG
+/// with the same options always yields the same blocks in the same order. Blocks with
+/// identity hashes are not exported, their content is part of the cid. `max_depth` applies
+/// to the shortest path to a block: the links of a block that is reached again closer to
+/// the root are followed again.
+///
+/// Up to 16 of the blocks that are exported next are loaded concurrently. Blocks are only
+/// loaded ahead while the export is within `max_bytes`, loads that are still running when
+/// the stream is dropped are cancelled.
+pub fn export_blocks<T: ContentLoader>(
+    resolver: &Resolver<T>,
+    root: Path,
+    options: ExportOptions,
+) -> impl Stream<Item = Result<OutRaw>> {
+    let resolver = resolver.clone();
+    async_stream::try_stream! {
+        let mut ctx = resolver.new_context(root.clone());
+        let root = resolver.resolve_path_to_cid(&root, &mut ctx).await?;
+
+        let mut queue = VecDeque::from([(root, 0)]);
+        // the smallest depth each block was reached at
+        let mut seen: HashMap<Cid, usize> = HashMap::new();
+        let mut prefetched: HashMap<Cid, RemoteHandle<Result<OutRaw>>> = HashMap::new();
+        let mut bytes = 0u64;
+        loop {
+            let next = match options.traversal {
+                Traversal::DepthFirst => queue.pop_back(),
+                Traversal::BreadthFirst => queue.pop_front(),
+            };
+            let (cid, depth) = match next {
+                Some(next) => next,
+                None => break,
+            };
+            if !needs_visit(&seen, &cid, depth, options.max_depth) {
+                continue;
+            }
+            // blocks that are reached again only have their links followed
+            let exported = seen.insert(cid, depth).is_some();
+
+            // load the blocks that come next while this one is loaded, in export order
+            let within_budget = options.max_bytes.map_or(true, |max_bytes| bytes < max_bytes);
+            if within_budget && prefetched.len() < PREFETCH_WINDOW {
+                let upcoming: Vec<Cid> = match options.traversal {
+                    Traversal::DepthFirst => queue.iter().rev().map(|(cid, _)| *cid).collect(),
+                    Traversal::BreadthFirst => queue.iter().map(|(cid, _)| *cid).collect(),
+                };
+                for next in upcoming {
+                    if prefetched.len() >= PREFETCH_WINDOW {
+                        break;
+                    }
+                    if next == cid || seen.contains_key(&next) || prefetched.contains_key(&next) {
+                        continue;
+                    }
+                    let resolver = resolver.clone();
+                    let ctx = ctx.clone();
+                    let (load, handle) = async move { resolver.load_raw(next, &ctx).await }
+                        .remote_handle();
+                    tokio::spawn(load);
+                    prefetched.insert(next, handle);
+                }
+            }
+
+            let block = match prefetched.remove(&cid) {
+                Some(handle) => handle.await?,
+                None => resolver.load_raw(cid, &ctx).await?,
+            };
+            let size = block.content().len() as u64;
+            if !exported {
+                if let Some(max_bytes) = options.max_bytes {
+                    if bytes + size > max_bytes {
+                        break;
+                    }
+                }
+                bytes += size;
+            }
+
+            let mut links = Vec::new();
+            if options.max_depth.map_or(true, |max_depth| depth < max_depth) {
+                let mut linked = HashSet::new();
+                for link in ordered_links(&cid, block.content())? {
+                    let skip = link.hash().code() == IDENTITY_HASH
+                        || options.skip_raw_leaves && link.codec() == Codec::Raw as u64;
+                    if !skip
+                        && needs_visit(&seen, &link, depth + 1, options.max_depth)
+                        && linked.insert(link)
+                    {
+                        links.push(link);
+                    }
+                }
+            }
+
+            match options.traversal {
+                Traversal::DepthFirst => {
+                    queue.extend(links.into_iter().rev().map(|link| (link, depth + 1)))
+                }
+                Traversal::BreadthFirst => {
+                    queue.extend(links.into_iter().map(|link| (link, depth + 1)))
+                }
+            }
+
+            if !exported {
+                yield block;
+            }
+        }
+    }
+}
+
+/// Whether `cid` has to be visited at `depth`: it was not reached yet or, if the depth is
+/// limited, it was only reached further from the root.
+fn needs_visit(
+    seen: &HashMap<Cid, usize>,
+    cid: &Cid,
+    depth: usize,
+    max_depth: Option<usize>,
+) -> bool {
+    match seen.get(cid) {
+        Some(seen_depth) => max_depth.is_some() && depth < *seen_depth,
+        None => true,
+    }
+}
+
+/// Writes the DAG below `root` as a CARv1 file with the root as its only root, returning
+/// the number of blocks written.
+///
+/// The blocks are written in the order of [`export_blocks`], so exports of the same DAG with
+/// the same options are byte-identical.
+pub async fn export_car<T, W>(
+    resolver: &Resolver<T>,
+    root: Path,
+    options: ExportOptions,
+    writer: W,
+) -> Result<u64>
+where
+    T: ContentLoader,
+    W: AsyncWrite + Send + Unpin,
+{
+    let blocks = export_blocks(resolver, root, options);
+    tokio::pin!(blocks);
+
+    let root = blocks.next().await.context("root block not found")??;
+    let mut writer = CarWriter::new(CarHeader::new_v1(vec![*root.cid()]), writer);
+    writer.write(*root.cid(), root.content()).await?;
+    let mut count = 1;
+    while let Some(block) = blocks.next().await {
+        let block = block?;
+        writer.write(*block.cid(), block.content()).await?;
+        count += 1;
+    }
+    writer.finish().await?;
+    Ok(count)
+}
+
+/// Extracts the links of a block in the order they appear in it, duplicates included.
+fn ordered_links(cid: &Cid, data: &[u8]) -> Result<Vec<Cid>> {
+    if cid.codec() == Codec::Raw as u64 {
+        return Ok(Vec::new());
+    }
+    let codec = IpldCodec::try_from(cid.codec())?;
+    let ipld: Ipld = codec.decode(data)?;
+    let mut links = Vec::new();
+    collect_links(&ipld, &mut links);
+    Ok(links)
+}
+
+fn collect_links(ipld: &Ipld, links: &mut Vec<Cid>) {
+    match ipld {
+        Ipld::Link(cid) => links.push(*cid),
+        Ipld::List(list) => list.iter().for_each(|ipld| collect_links(ipld, links)),
+        Ipld::Map(map) => map.values().for_each(|ipld| collect_links(ipld, links)),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
+    use bytes::Bytes;
+    use cid::multihash::{Code, MultihashDigest};
+    use futures::TryStreamExt;
+    use iroh_car::{CarBlockstore, CarReader};
+    use libipld::codec::Encode;
+
+    use super::*;
+    use crate::resolver::{ContextId, LoadedCid, LoaderContext, Source};
+
+    const RAW: u64 = Codec::Raw as u64;
+    const DAG_CBOR: u64 = Codec::DagCbor as u64;
+
+    fn raw_block(data: &[u8]) -> (Cid, Bytes) {
+        let cid = Cid::new_v1(RAW, Code::Sha2_256.digest(data));
+        (cid, Bytes::copy_from_slice(data))
+    }
+
+    fn cbor_block(links: &[Cid]) -> (Cid, Bytes) {
+        let ipld = Ipld::List(links.iter().copied().map(Ipld::Link).collect());
+        let mut data = Vec::new();
+        ipld.encode(IpldCodec::DagCbor, &mut data).unwrap();
+        let cid = Cid::new_v1(DAG_CBOR, Code::Sha2_256.digest(&data));
+        (cid, Bytes::from(data))
+    }
+
+    /// root -> [mid, a], mid -> [b, a]
+    async fn dag() -> (Resolver<CarBlockstore<Cursor<Arc<[u8]>>>>, Vec<Cid>) {
+        let a = raw_block(b"a");
+        let b = raw_block(b"b");
+        let mid = cbor_block(&[b.0, a.0]);
+        let root = cbor_block(&[mid.0, a.0]);
+
+        // the blocks are stored in an order that does not match any traversal
+        let mut buffer = Vec::new();
+        let mut writer = CarWriter::new(CarHeader::new_v1(vec![root.0]), &mut buffer);
+        for (cid, data) in [&b, &root, &a, &mid] {
+            writer.write(*cid, data).await.unwrap();
+        }
+        writer.finish().await.unwrap();
+
+        let buffer: Arc<[u8]> = buffer.into();
+        let store = CarBlockstore::open(move || {
+            let buffer = buffer.clone();
+            async move { Ok(Cursor::new(buffer)) }
+        })
+        .await
+        .unwrap();
+        (Resolver::new(store), vec![root.0, mid.0, a.0, b.0])
+    }
+
+    /// Serves blocks from memory, counting the loads and the loads running at the same time.
+    #[derive(Debug, Clone, Default)]
+    struct CountingLoader {
+        blocks: Arc<HashMap<Cid, Bytes>>,
+        loads: Arc<AtomicUsize>,
+        running: Arc<AtomicUsize>,
+        max_running: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl ContentLoader for CountingLoader {
+        async fn load_cid(&self, cid: &Cid, _ctx: &LoaderContext) -> Result<LoadedCid> {
+            self.loads.fetch_add(1, Ordering::SeqCst);
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_running.fetch_max(running, Ordering::SeqCst);
+            for _ in 0..4 {
+                tokio::task::yield_now().await;
+            }
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            let data = self
+                .blocks
+                .get(cid)
+                .cloned()
+                .with_context(|| format!("{} not found", cid))?;
+            Ok(LoadedCid {
+                data,
+                source: Source::Bitswap,
+            })
+        }
+
+        async fn stop_session(&self, _ctx: ContextId) -> Result<()> {
+            Ok(())
+        }
+
+        async fn has_cid(&self, cid: &Cid) -> Result<bool> {
+            Ok(self.blocks.contains_key(cid))
+        }
+    }
+
+    /// root -> 40 raw leaves
+    fn wide_dag() -> (HashMap<Cid, Bytes>, Cid) {
+        let leaves: Vec<_> = (0..40u8).map(|i| raw_block(&[i])).collect();
+        let root = cbor_block(&leaves.iter().map(|(cid, _)| *cid).collect::<Vec<_>>());
+        let blocks = leaves.into_iter().chain([root.clone()]).collect();
+        (blocks, root.0)
+    }
+
+    async fn exported_cids<T: ContentLoader>(
+        resolver: &Resolver<T>,
+        root: Cid,
+        options: ExportOptions,
+    ) -> Vec<Cid> {
+        export_blocks(resolver, Path::from_cid(root), options)
+            .map_ok(|block| *block.cid())
+            .try_collect()
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn export_traversal_order() {
+        let (resolver, cids) = dag().await;
+        let [root, mid, a, b] = <[Cid; 4]>::try_from(cids).unwrap();
+
+        let dfs = exported_cids(&resolver, root, ExportOptions::default()).await;
+        assert_eq!(dfs, vec![root, mid, b, a]);
+
+        let options = ExportOptions {
+            traversal: Traversal::BreadthFirst,
+            ..Default::default()
+        };
+        let bfs = exported_cids(&resolver, root, options).await;
+        assert_eq!(bfs, vec![root, mid, a, b]);
+    }
+
+    #[tokio::test]
+    async fn export_limits() {
+        let (resolver, cids) = dag().await;
+        let [root, mid, a, _b] = <[Cid; 4]>::try_from(cids).unwrap();
+
+        let options = ExportOptions {
+            max_depth: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(exported_cids(&resolver, root, options).await, vec![root]);
+
+        let options = ExportOptions {
+            skip_raw_leaves: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            exported_cids(&resolver, root, options).await,
+            vec![root, mid]
+        );
+
+        let options = ExportOptions {
+            traversal: Traversal::BreadthFirst,
+            max_depth: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(
+            exported_cids(&resolver, root, options).await,
+            vec![root, mid, a]
+        );
+
+        let root_size = cbor_block(&[mid, a]).1.len() as u64;
+        let options = ExportOptions {
+            max_bytes: Some(root_size),
+            ..Default::default()
+        };
+        assert_eq!(exported_cids(&resolver, root, options).await, vec![root]);
+    }
+
+    #[tokio::test]
+    async fn export_depth_of_shared_blocks() {
+        // root -> [x, y], x -> [y], y -> [z]
+        let z = raw_block(b"z");
+        let y = cbor_block(&[z.0]);
+        let x = cbor_block(&[y.0]);
+        let root = cbor_block(&[x.0, y.0]);
+        let blocks = [&root, &x, &y, &z]
+            .into_iter()
+            .cloned()
+            .collect::<HashMap<_, _>>();
+        let resolver = Resolver::new(CountingLoader {
+            blocks: Arc::new(blocks),
+            ..Default::default()
+        });
+
+        // y is reached at depth 2 through x first, z is within the limit through root -> y
+        for traversal in [Traversal::DepthFirst, Traversal::BreadthFirst] {
+            let options = ExportOptions {
+                traversal,
+                max_depth: Some(2),
+                ..Default::default()
+            };
+            assert_eq!(
+                exported_cids(&resolver, root.0, options).await,
+                vec![root.0, x.0, y.0, z.0]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn export_prefetch_is_bounded() {
+        let (blocks, root) = wide_dag();
+        let root_size = blocks[&root].len() as u64;
+        let loader = CountingLoader {
+            blocks: Arc::new(blocks),
+            ..Default::default()
+        };
+        let resolver = Resolver::new(loader.clone());
+
+        let cids = exported_cids(&resolver, root, ExportOptions::default()).await;
+        assert_eq!(cids.len(), 41);
+        assert_eq!(loader.loads.load(Ordering::SeqCst), 41);
+        assert!(loader.max_running.load(Ordering::SeqCst) > 1);
+        assert!(loader.max_running.load(Ordering::SeqCst) <= PREFETCH_WINDOW + 1);
+
+        // nothing below the depth limit is loaded
+        loader.loads.store(0, Ordering::SeqCst);
+        let options = ExportOptions {
+            max_depth: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(exported_cids(&resolver, root, options).await, vec![root]);
+        assert_eq!(loader.loads.load(Ordering::SeqCst), 1);
+
+        // once the budget is used up only the block that exceeds it is loaded
+        loader.loads.store(0, Ordering::SeqCst);
+        let options = ExportOptions {
+            max_bytes: Some(root_size),
+            ..Default::default()
+        };
+        assert_eq!(exported_cids(&resolver, root, options).await, vec![root]);
+        assert_eq!(loader.loads.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn export_missing_block() {
+        let (mut blocks, root) = wide_dag();
+        let missing = *blocks.keys().find(|cid| **cid != root).unwrap();
+        blocks.remove(&missing);
+        let resolver = Resolver::new(CountingLoader {
+            blocks: Arc::new(blocks),
+            ..Default::default()
+        });
+
+        let result: Result<Vec<_>> =
+            export_blocks(&resolver, Path::from_cid(root), ExportOptions::default())
+                .try_collect()
+                .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn export_car_is_deterministic() {
+        let (resolver, cids) = dag().await;
+        let root = cids[0];
+
+        let mut first = Vec::new();
+        let count = export_car(
+            &resolver,
+            Path::from_cid(root),
+            ExportOptions::default(),
+            &mut first,
+        )
+        .await
+        .unwrap();
+        assert_eq!(count, 4);
+
+        let mut second = Vec::new();
+        export_car(
+            &resolver,
+            Path::from_cid(root),
+            ExportOptions::default(),
+            &mut second,
+        )
+        .await
+        .unwrap();
+        assert_eq!(first, second);
+
+        let reader = CarReader::new(Cursor::new(first)).await.unwrap();
+        assert_eq!(reader.header().roots(), &[root][..]);
+        let report = reader.verify_dag().await.unwrap();
+        assert!(report.is_exact());
+    }
+
+    #[tokio::test]
+    async fn export_fixture_car() {
+        let store = CarBlockstore::open(|| async {
+            let file = tokio::fs::File::open("./fixtures/big-foo.car").await?;
+            Ok(tokio::io::BufReader::new(file))
+        })
+        .await
+        .unwrap();
+        let root = store.header().roots()[0];
+        let resolver = Resolver::new(store);
+
+        let mut exported = Vec::new();
+        export_car(
+            &resolver,
+            Path::from_cid(root),
+            ExportOptions::default(),
+            &mut exported,
+        )
+        .await
+        .unwrap();
+        let report = CarReader::new(Cursor::new(exported))
+            .await
+            .unwrap()
+            .verify_dag()
+            .await
+            .unwrap();
+        assert!(report.is_complete());
+    }
+}
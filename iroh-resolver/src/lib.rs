@@ -1,4 +1,5 @@
 pub mod balanced_tree;
+pub mod car_export;
 pub mod chunker;
 pub mod codecs;
 pub mod hamt;
@@ -82,6 +82,9 @@ impl ContentLoader for RacingLoader {
                 warn!("failed to fetch data from store {}: {:?}", cid, err);
             }
         }
+        if ctx.local_only() {
+            return Err(anyhow!("{} is not available locally", cid));
+        }
 
         let p2p_fut = self.fetch_p2p(ctx.id(), &cid).fuse();
         let http_fut = self.fetch_http(&cid).fuse();
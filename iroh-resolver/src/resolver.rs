@@ -2,7 +2,7 @@ use std::collections::{BTreeSet, VecDeque};
 use std::fmt::{self, Debug, Display, Formatter};
 use std::pin::Pin;
 use std::str::FromStr;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
 use std::task::{Context, Poll};
 use std::time::Instant;
@@ -13,6 +13,7 @@ use bytes::Bytes;
 use cid::multihash::{Code, MultihashDigest};
 use cid::Cid;
 use futures::{Future, Stream, TryStreamExt};
+use iroh_car::CarBlockstore;
 use iroh_metrics::inc;
 use iroh_rpc_client::Client;
 use libipld::codec::Encode;
@@ -37,6 +38,8 @@ use crate::unixfs::{
 };
 
 pub const IROH_STORE: &str = "iroh-store";
+/// Name of the store for content loaded from a CAR file.
+pub const CAR_STORE: &str = "car";
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Block {
@@ -318,6 +321,11 @@ impl Out {
         &self.metadata
     }
 
+    /// The loader context this output was resolved with.
+    pub fn context(&self) -> &LoaderContext {
+        &self.context
+    }
+
     /// Is this content mutable?
     ///
     /// Returns `true` if the underlying root is an IPNS entry.
@@ -336,6 +344,24 @@ impl Out {
         self.metadata.unixfs_type == Some(UnixfsType::Symlink)
     }
 
+    /// Unix permission bits of the unixfs node, if present.
+    pub fn unixfs_mode(&self) -> Option<u32> {
+        match &self.content {
+            OutContent::Unixfs(node) => node.mode(),
+            _ => None,
+        }
+    }
+
+    /// Modification time of the unixfs node, if present.
+    ///
+    /// Returned as seconds and nanoseconds since the unix epoch.
+    pub fn unixfs_mtime(&self) -> Option<(i64, u32)> {
+        match &self.content {
+            OutContent::Unixfs(node) => node.mtime(),
+            _ => None,
+        }
+    }
+
     /// What kind of content this is this.
     pub fn typ(&self) -> OutType {
         self.content.typ()
@@ -345,6 +371,18 @@ impl Out {
         self.content.links()
     }
 
+    /// Returns the decoded IPLD data model of this content.
+    /// Only if this is not `unixfs`.
+    pub fn ipld(&self) -> Option<&Ipld> {
+        match &self.content {
+            OutContent::DagPb(ipld, _)
+            | OutContent::DagCbor(ipld, _)
+            | OutContent::DagJson(ipld, _)
+            | OutContent::Raw(ipld, _) => Some(ipld),
+            OutContent::Unixfs(_) => None,
+        }
+    }
+
     /// Returns links with an associated file or directory name if the content
     /// is unixfs
     pub fn named_links(&self) -> Result<Vec<(Option<&str>, Cid)>> {
@@ -658,6 +696,8 @@ pub enum Source {
 #[derive(Debug, Clone)]
 pub struct Resolver<T: ContentLoader> {
     loader: T,
+    /// Only load blocks that are available locally.
+    local_only: bool,
     next_id: Arc<AtomicU64>,
     _worker: Arc<JoinHandle<()>>,
     session_closer: async_channel::Sender<ContextId>,
@@ -666,6 +706,9 @@ pub struct Resolver<T: ContentLoader> {
 #[derive(Debug, Clone)]
 pub struct LoaderContext {
     id: ContextId,
+    local_only: bool,
+    /// Whether loaded blocks are recorded, checked before locking `inner` on every load.
+    recording: Arc<AtomicBool>,
     inner: Arc<Mutex<InnerLoaderContext>>,
 }
 
@@ -674,13 +717,40 @@ impl LoaderContext {
         trace!("new loader context: {:?}", id);
         LoaderContext {
             id,
-            inner: Arc::new(Mutex::new(InnerLoaderContext { path, closer })),
+            local_only: false,
+            recording: Arc::new(AtomicBool::new(false)),
+            inner: Arc::new(Mutex::new(InnerLoaderContext {
+                path,
+                closer,
+                recorded: None,
+            })),
         }
     }
 
     pub fn id(&self) -> ContextId {
         self.id
     }
+
+    /// Whether blocks must only be loaded from local storage, never from the network.
+    pub fn local_only(&self) -> bool {
+        self.local_only
+    }
+
+    /// Starts recording all blocks that are loaded through this context and its clones.
+    async fn start_recording(&self) {
+        self.inner.lock().await.recorded = Some(Vec::new());
+        self.recording.store(true, Ordering::Release);
+    }
+
+    fn is_recording(&self) -> bool {
+        self.recording.load(Ordering::Acquire)
+    }
+
+    /// Stops recording and returns the blocks loaded since recording started, in load order.
+    async fn stop_recording(&self) -> Vec<OutRaw> {
+        self.recording.store(false, Ordering::Release);
+        self.inner.lock().await.recorded.take().unwrap_or_default()
+    }
 }
 
 impl Drop for LoaderContext {
@@ -730,6 +800,8 @@ struct InnerLoaderContext {
     #[allow(dead_code)]
     path: Path,
     closer: async_channel::Sender<ContextId>,
+    /// Blocks loaded through this context, if recording is enabled.
+    recorded: Option<Vec<OutRaw>>,
 }
 
 #[async_trait]
@@ -757,6 +829,34 @@ impl<T: ContentLoader> ContentLoader for Arc<T> {
     }
 }
 
+/// Serves content straight out of a CAR file, without importing it into the store.
+#[async_trait]
+impl<R> ContentLoader for CarBlockstore<R>
+where
+    R: AsyncRead + AsyncSeek + Send + Unpin + 'static,
+{
+    async fn load_cid(&self, cid: &Cid, ctx: &LoaderContext) -> Result<LoadedCid> {
+        trace!("{:?} loading {} from car file", ctx.id(), cid);
+        let data = self
+            .get(cid)
+            .await?
+            .ok_or_else(|| anyhow!("{} not found in car file", cid))?;
+        Ok(LoadedCid {
+            data: data.into(),
+            source: Source::Store(CAR_STORE),
+        })
+    }
+
+    async fn stop_session(&self, _ctx: ContextId) -> Result<()> {
+        // no session tracking
+        Ok(())
+    }
+
+    async fn has_cid(&self, cid: &Cid) -> Result<bool> {
+        Ok(self.has(cid))
+    }
+}
+
 #[async_trait]
 impl ContentLoader for Client {
     async fn stop_session(&self, ctx: ContextId) -> Result<()> {
@@ -787,6 +887,9 @@ impl ContentLoader for Client {
                 );
             }
         }
+        if ctx.local_only() {
+            bail!("{} is not available locally", cid);
+        }
 
         // launch fetching using the initial set of cached providers
         let bytes = self
@@ -861,6 +964,7 @@ impl<T: ContentLoader> Resolver<T> {
 
         Resolver {
             loader,
+            local_only: false,
             next_id: Arc::new(AtomicU64::new(0)),
             _worker: Arc::new(worker),
             session_closer: session_closer_s,
@@ -876,6 +980,22 @@ impl<T: ContentLoader> Resolver<T> {
         &self.loader
     }
 
+    /// Returns a resolver sharing the loader and sessions of this one, which never fetches
+    /// blocks from the network for anything it resolves.
+    pub fn local_only(&self) -> Self {
+        Resolver {
+            local_only: true,
+            ..self.clone()
+        }
+    }
+
+    /// Creates a new context to load the blocks of `path` in.
+    pub(crate) fn new_context(&self, path: Path) -> LoaderContext {
+        let mut ctx = LoaderContext::from_path(self.next_id(), self.session_closer.clone(), path);
+        ctx.local_only = self.local_only;
+        ctx
+    }
+
     #[tracing::instrument(skip(self))]
     pub fn resolve_recursive_with_paths(
         &self,
@@ -971,8 +1091,7 @@ impl<T: ContentLoader> Resolver<T> {
         M: Fn(Cid, LoaderContext) -> F + Clone,
         F: Future<Output = Result<O>> + Send + 'static,
     {
-        let mut ctx =
-            LoaderContext::from_path(self.next_id(), self.session_closer.clone(), root.clone());
+        let mut ctx = self.new_context(root.clone());
 
         let mut cids = VecDeque::new();
         let this = self.clone();
@@ -1021,12 +1140,33 @@ impl<T: ContentLoader> Resolver<T> {
     /// Resolves through a given path, returning the [`Cid`] and raw bytes of the final leaf.
     #[tracing::instrument(skip(self))]
     pub async fn resolve(&self, path: Path) -> Result<Out> {
-        let ctx =
-            LoaderContext::from_path(self.next_id(), self.session_closer.clone(), path.clone());
+        let ctx = self.new_context(path.clone());
 
         self.resolve_with_ctx(ctx, path).await
     }
 
+    /// Resolves through a given path, like [`Resolver::resolve`], additionally returning
+    /// every block that had to be loaded to traverse the path, in the order they were loaded.
+    ///
+    /// The last block is always the root block of the resolved output.
+    #[tracing::instrument(skip(self))]
+    pub async fn resolve_with_path_blocks(&self, path: Path) -> Result<(Out, Vec<OutRaw>)> {
+        let ctx = self.new_context(path.clone());
+        ctx.start_recording().await;
+        let out = self.resolve_with_ctx(ctx.clone(), path).await;
+        let blocks = ctx.stop_recording().await;
+        Ok((out?, blocks))
+    }
+
+    /// Loads the raw bytes of a single block, reusing the session of the given context.
+    #[tracing::instrument(skip(self))]
+    pub async fn load_raw(&self, cid: Cid, ctx: &LoaderContext) -> Result<OutRaw> {
+        let mut ctx = ctx.clone();
+        self.load_cid(&cid, &mut ctx)
+            .await
+            .map(|loaded| OutRaw::from_loaded(cid, loaded))
+    }
+
     pub async fn resolve_with_ctx(&self, mut ctx: LoaderContext, path: Path) -> Result<Out> {
         // Resolve the root block.
         let (root_cid, loaded_cid) = self.resolve_root(&path, &mut ctx).await?;
@@ -1276,7 +1416,11 @@ impl<T: ContentLoader> Resolver<T> {
     }
 
     #[tracing::instrument(skip(self))]
-    async fn resolve_path_to_cid(&self, root: &Path, ctx: &mut LoaderContext) -> Result<Cid> {
+    pub(crate) async fn resolve_path_to_cid(
+        &self,
+        root: &Path,
+        ctx: &mut LoaderContext,
+    ) -> Result<Cid> {
         let mut current = root.clone();
 
         // maximum cursion of ipns lookups
@@ -1318,7 +1462,17 @@ impl<T: ContentLoader> Resolver<T> {
 
     #[tracing::instrument(skip(self))]
     async fn load_cid(&self, cid: &Cid, ctx: &mut LoaderContext) -> Result<LoadedCid> {
-        self.loader.load_cid(cid, ctx).await
+        let loaded = self.loader.load_cid(cid, ctx).await?;
+        if ctx.is_recording() {
+            if let Some(recorded) = ctx.inner.lock().await.recorded.as_mut() {
+                recorded.push(OutRaw {
+                    source: loaded.source.clone(),
+                    content: loaded.data.clone(),
+                    cid: *cid,
+                });
+            }
+        }
+        Ok(loaded)
     }
 
     #[tracing::instrument(skip(self))]
@@ -2642,6 +2796,37 @@ mod tests {
         assert_eq!(result[0].typ(), PathType::Ipfs);
     }
 
+    #[tokio::test]
+    async fn test_resolve_from_car_blockstore() {
+        // QmUu8pzQ5yjhDrg4GiHYLeko2oT76vcmYX5bw6sjiEJ82k foo
+        let root_cid_str = "QmUu8pzQ5yjhDrg4GiHYLeko2oT76vcmYX5bw6sjiEJ82k";
+
+        let blockstore = CarBlockstore::open(|| async {
+            let file = tokio::fs::File::open("./fixtures/big-foo.car").await?;
+            Ok(tokio::io::BufReader::new(file))
+        })
+        .await
+        .unwrap();
+        let resolver = Resolver::new(blockstore);
+
+        let path = format!("/ipfs/{root_cid_str}/bar/bar.txt");
+        let out = resolver.resolve(path.parse().unwrap()).await.unwrap();
+        assert_eq!(out.metadata().unixfs_type, Some(UnixfsType::File));
+        assert_eq!(out.metadata().source, Source::Store(CAR_STORE));
+
+        let reader = out
+            .pretty(
+                resolver.clone(),
+                OutMetrics::default(),
+                ResponseClip::NoClip,
+            )
+            .unwrap();
+        assert_eq!(read_to_string(reader).await, "world\n");
+
+        let missing = format!("/ipfs/{root_cid_str}/missing.txt");
+        assert!(resolver.resolve(missing.parse().unwrap()).await.is_err());
+    }
+
     #[tokio::test]
     async fn test_unixfs_hamt_dir() {
         // Test content
@@ -2847,4 +3032,85 @@ mod tests {
             format!("/ipfs/{root_cid_str}/bar/bar.txt")
         );
     }
+
+    #[tokio::test]
+    async fn test_resolve_with_path_blocks() {
+        // Test content
+        // ------------
+        // QmaRGe7bVmVaLmxbrMiVNXqW4pRNNp3xq7hFtyRKA3mtJL foo/bar/bar.txt
+        //   contains: "world"
+        // QmZULkCELmmk5XNfCgTnCyFgAVxBRBXyDHGGMVoLFLiXEN foo/hello.txt
+        //   contains: "hello"
+        // QmcHTZfwWWYG2Gbv9wR6bWZBvAgpFV5BcDoLrC2XMCkggn foo/bar
+        // QmdkGfDx42RNdAZFALHn5hjHqUq7L9o6Ef4zLnFEu3Y4Go foo
+
+        let bar_txt_cid: Cid = "QmaRGe7bVmVaLmxbrMiVNXqW4pRNNp3xq7hFtyRKA3mtJL"
+            .parse()
+            .unwrap();
+        let bar_cid: Cid = "QmcHTZfwWWYG2Gbv9wR6bWZBvAgpFV5BcDoLrC2XMCkggn"
+            .parse()
+            .unwrap();
+        let hello_txt_cid: Cid = "QmZULkCELmmk5XNfCgTnCyFgAVxBRBXyDHGGMVoLFLiXEN"
+            .parse()
+            .unwrap();
+        let root_cid: Cid = "QmdkGfDx42RNdAZFALHn5hjHqUq7L9o6Ef4zLnFEu3Y4Go"
+            .parse()
+            .unwrap();
+
+        let mut loader: HashMap<Cid, Bytes> = HashMap::new();
+        for cid in [bar_txt_cid, bar_cid, hello_txt_cid, root_cid] {
+            loader.insert(cid, load_fixture(&cid.to_string()).await);
+        }
+        let resolver = Resolver::new(Arc::new(loader));
+
+        let path = format!("/ipfs/{root_cid}/bar/bar.txt");
+        let (out, blocks) = resolver
+            .resolve_with_path_blocks(path.parse().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(out.metadata().resolved_path.last(), Some(&bar_txt_cid));
+        assert_eq!(
+            blocks.iter().map(|b| *b.cid()).collect::<Vec<_>>(),
+            vec![root_cid, bar_cid, bar_txt_cid]
+        );
+
+        // loads after resolving are not recorded anymore
+        let raw = resolver
+            .load_raw(hello_txt_cid, out.context())
+            .await
+            .unwrap();
+        assert_eq!(raw.cid(), &hello_txt_cid);
+        assert!(!out.context().is_recording());
+        assert!(out.context().stop_recording().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_local_only() {
+        let bar_txt_cid: Cid = "QmaRGe7bVmVaLmxbrMiVNXqW4pRNNp3xq7hFtyRKA3mtJL"
+            .parse()
+            .unwrap();
+        let bar_cid: Cid = "QmcHTZfwWWYG2Gbv9wR6bWZBvAgpFV5BcDoLrC2XMCkggn"
+            .parse()
+            .unwrap();
+        let root_cid: Cid = "QmdkGfDx42RNdAZFALHn5hjHqUq7L9o6Ef4zLnFEu3Y4Go"
+            .parse()
+            .unwrap();
+
+        let mut loader: HashMap<Cid, Bytes> = HashMap::new();
+        for cid in [bar_txt_cid, bar_cid, root_cid] {
+            loader.insert(cid, load_fixture(&cid.to_string()).await);
+        }
+        let resolver = Resolver::new(Arc::new(loader));
+        let path: Path = format!("/ipfs/{root_cid}/bar/bar.txt").parse().unwrap();
+
+        let out = resolver.resolve(path.clone()).await.unwrap();
+        assert!(!out.context().local_only());
+
+        let local = resolver.local_only();
+        let out = local.resolve(path.clone()).await.unwrap();
+        assert_eq!(out.metadata().resolved_path.last(), Some(&bar_txt_cid));
+        assert!(out.context().local_only());
+        // the original resolver is not affected
+        assert!(!resolver.new_context(path).local_only());
+    }
 }
@@ -179,6 +179,19 @@ impl Node {
     pub fn fanout(&self) -> Option<u32> {
         self.inner.fanout.and_then(|f| u32::try_from(f).ok())
     }
+
+    /// Returns the unix permission bits, if set.
+    pub fn mode(&self) -> Option<u32> {
+        self.inner.mode
+    }
+
+    /// Returns the modification time as seconds and nanoseconds since the unix epoch, if set.
+    pub fn mtime(&self) -> Option<(i64, u32)> {
+        self.inner
+            .mtime
+            .as_ref()
+            .map(|t| (t.seconds, t.fractional_nanoseconds.unwrap_or_default()))
+    }
 }
 
 impl UnixfsNode {
@@ -297,6 +310,32 @@ impl UnixfsNode {
         }
     }
 
+    /// Returns the unix permission bits, if set.
+    /// Raw leaves never carry a mode.
+    pub fn mode(&self) -> Option<u32> {
+        match self {
+            UnixfsNode::Raw(_) => None,
+            UnixfsNode::Directory(node)
+            | UnixfsNode::RawNode(node)
+            | UnixfsNode::File(node)
+            | UnixfsNode::Symlink(node)
+            | UnixfsNode::HamtShard(node, _) => node.mode(),
+        }
+    }
+
+    /// Returns the modification time as seconds and nanoseconds since the unix epoch, if set.
+    /// Raw leaves never carry an mtime.
+    pub fn mtime(&self) -> Option<(i64, u32)> {
+        match self {
+            UnixfsNode::Raw(_) => None,
+            UnixfsNode::Directory(node)
+            | UnixfsNode::RawNode(node)
+            | UnixfsNode::File(node)
+            | UnixfsNode::Symlink(node)
+            | UnixfsNode::HamtShard(node, _) => node.mtime(),
+        }
+    }
+
     pub fn links(&self) -> Links<'_> {
         match self {
             UnixfsNode::Raw(_) => Links::Raw,
@@ -11,7 +11,7 @@ use async_trait::async_trait;
 use bytes::Bytes;
 use cid::Cid;
 use futures::stream::TryStreamExt;
-use futures::{future, stream::LocalBoxStream, Stream, StreamExt};
+use futures::{future, stream::BoxStream, Stream, StreamExt};
 use iroh_rpc_client::Client;
 use prost::Message;
 use tokio::io::AsyncRead;
@@ -74,7 +74,7 @@ impl Directory {
         current.expect("must not be empty")
     }
 
-    pub fn encode<'a>(self) -> LocalBoxStream<'a, Result<Block>> {
+    pub fn encode<'a>(self) -> BoxStream<'a, Result<Block>> {
         async_stream::try_stream! {
             let mut links = Vec::new();
             for entry in self.entries {
@@ -110,6 +110,7 @@ impl Directory {
                         yield block;
                         (name, root)
                     }
+                    Entry::Encoded { name, root } => (name, Some(root)),
                 };
                 let root_block = root.expect("file must not be empty");
                 links.push(dag_pb::PbLink {
@@ -130,19 +131,19 @@ impl Directory {
             let node = UnixfsNode::Directory(Node { outer, inner });
             yield node.encode()?;
         }
-        .boxed_local()
+        .boxed()
     }
 }
 
 enum Content {
-    Reader(Pin<Box<dyn AsyncRead>>),
+    Reader(Pin<Box<dyn AsyncRead + Send>>),
     Path(PathBuf),
 }
 
 impl Debug for Content {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Content::Reader(_) => write!(f, "Content::Reader(Pin<Box<dyn AsyncRead>>)"),
+            Content::Reader(_) => write!(f, "Content::Reader(Pin<Box<dyn AsyncRead + Send>>)"),
             Content::Path(p) => write!(f, "Content::Path({})", p.display()),
         }
     }
@@ -269,7 +270,7 @@ impl Symlink {
 pub struct FileBuilder {
     name: Option<String>,
     path: Option<PathBuf>,
-    reader: Option<Pin<Box<dyn AsyncRead>>>,
+    reader: Option<Pin<Box<dyn AsyncRead + Send>>>,
     chunk_size: Option<usize>,
     degree: Option<usize>,
 }
@@ -323,7 +324,14 @@ impl FileBuilder {
         self
     }
 
-    pub fn content_reader<T: tokio::io::AsyncRead + 'static>(&mut self, content: T) -> &mut Self {
+    /// Reads the content of the file from `content`.
+    ///
+    /// The reader has to be `Send`, so that encoding the file can run on any thread, e.g.
+    /// inside a request handler.
+    pub fn content_reader<T: tokio::io::AsyncRead + Send + 'static>(
+        &mut self,
+        content: T,
+    ) -> &mut Self {
         self.reader = Some(Box::pin(content));
         self
     }
@@ -373,6 +381,11 @@ enum Entry {
     File(File),
     Directory(Directory),
     Symlink(Symlink),
+    /// An entry that was already encoded and stored, only linked by the directory.
+    Encoded {
+        name: String,
+        root: Block,
+    },
 }
 
 /// Construct a UnixFS directory.
@@ -420,6 +433,16 @@ impl DirectoryBuilder {
         self.entry(Entry::Symlink(symlink))
     }
 
+    /// Adds an entry that was encoded and stored separately, given its root block.
+    ///
+    /// Encoding the directory links to the entry, without yielding its blocks again.
+    pub fn add_encoded<N: Into<String>>(&mut self, name: N, root: Block) -> &mut Self {
+        self.entry(Entry::Encoded {
+            name: name.into(),
+            root,
+        })
+    }
+
     fn entry(&mut self, entry: Entry) -> &mut Self {
         if self.typ == DirectoryType::Basic && self.entries.len() >= DIRECTORY_LINK_LIMIT {
             self.typ = DirectoryType::Hamt
@@ -573,7 +596,30 @@ pub async fn add_file<S: Store>(
     ensure!(path.is_file(), "provided path was not a file");
 
     let file = FileBuilder::new().path(path).build().await?;
+    add_built_file(store, file, wrap).await
+}
+
+/// Adds a single file, read from `reader` until it is exhausted.
+/// - storing the content using `rpc.store`
+/// - returns a stream of AddEvent
+/// - optionally wraps into a UnixFs directory to preserve the `name`
+pub async fn add_reader<S: Store, R: AsyncRead + Send + 'static>(
+    store: Option<S>,
+    name: &str,
+    reader: R,
+    wrap: bool,
+) -> Result<impl Stream<Item = Result<AddEvent>>> {
+    let mut file = FileBuilder::new();
+    file.name(name).content_reader(reader);
+    let file = file.build().await?;
+    add_built_file(store, file, wrap).await
+}
 
+async fn add_built_file<S: Store>(
+    store: Option<S>,
+    file: File,
+    wrap: bool,
+) -> Result<impl Stream<Item = Result<AddEvent>>> {
     let blocks = {
         if wrap {
             // wrap file in dir to preserve file name
@@ -597,7 +643,19 @@ pub async fn add_dir<S: Store>(
     ensure!(path.is_dir(), "provided path was not a directory");
 
     let dir = make_dir_from_path(path).await?;
+    add_directory(store, dir, wrap).await
+}
 
+/// Adds a directory constructed with a `DirectoryBuilder`, which does not need to
+/// exist on disk.
+/// - storing the content using `rpc.store`
+/// - returns a stream of AddEvent
+/// - optionally wraps into a UnixFs directory to preserve the directory name
+pub async fn add_directory<S: Store>(
+    store: Option<S>,
+    dir: Directory,
+    wrap: bool,
+) -> Result<impl Stream<Item = Result<AddEvent>>> {
     // encode and store
     let blocks = {
         if wrap {
@@ -644,7 +702,7 @@ use async_stream::stream;
 
 fn add_blocks_to_store_chunked<S: Store>(
     store: S,
-    mut blocks: Pin<Box<dyn Stream<Item = Result<Block>>>>,
+    mut blocks: Pin<Box<dyn Stream<Item = Result<Block>> + Send>>,
 ) -> impl Stream<Item = Result<AddEvent>> {
     let mut chunk = Vec::new();
     let mut chunk_size = 0u64;
@@ -674,7 +732,7 @@ fn add_blocks_to_store_chunked<S: Store>(
 
 fn _add_blocks_to_store_single<S: Store>(
     store: Option<S>,
-    blocks: Pin<Box<dyn Stream<Item = Result<Block>>>>,
+    blocks: Pin<Box<dyn Stream<Item = Result<Block>> + Send>>,
 ) -> impl Stream<Item = Result<AddEvent>> {
     blocks
         .and_then(|x| future::ok(vec![x]))
@@ -701,12 +759,12 @@ fn _add_blocks_to_store_single<S: Store>(
 
 pub async fn add_blocks_to_store<S: Store>(
     store: Option<S>,
-    blocks: Pin<Box<dyn Stream<Item = Result<Block>>>>,
+    blocks: Pin<Box<dyn Stream<Item = Result<Block>> + Send>>,
 ) -> impl Stream<Item = Result<AddEvent>> {
     add_blocks_to_store_chunked(store.unwrap(), blocks)
 }
 
-#[async_recursion(?Send)]
+#[async_recursion]
 async fn make_dir_from_path<P: Into<PathBuf>>(path: P) -> Result<Directory> {
     let path = path.into();
     let mut dir = DirectoryBuilder::new();
@@ -1185,6 +1243,79 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_add_encoded() -> Result<()> {
+        let mut file = FileBuilder::new();
+        file.name("hello.txt").content_bytes(b"hello".to_vec());
+        let file_root = file.build().await?.encode_root().await?;
+
+        let mut file = FileBuilder::new();
+        file.name("hello.txt").content_bytes(b"hello".to_vec());
+        let mut dir = DirectoryBuilder::new();
+        dir.name("dir").add_file(file.build().await?);
+        let expected: Vec<_> = dir.build()?.encode().try_collect().await?;
+
+        let mut dir = DirectoryBuilder::new();
+        dir.name("dir").add_encoded("hello.txt", file_root);
+        let blocks: Vec<_> = dir.build()?.encode().try_collect().await?;
+
+        // only the directory node is yielded, it matches the one linking the file itself
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(&blocks[0], expected.last().unwrap());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_add_reader() -> Result<()> {
+        let store: Arc<tokio::sync::Mutex<std::collections::HashMap<Cid, Bytes>>> =
+            Default::default();
+        let reader = std::io::Cursor::new(b"hello from a reader".to_vec());
+        let events: Vec<_> = add_reader(Some(store.clone()), "hello.txt", reader, true)
+            .await?
+            .try_collect()
+            .await?;
+        let root = match events.last().context("no events")? {
+            AddEvent::ProgressDelta { cid, .. } => *cid,
+        };
+
+        let mut file = FileBuilder::new();
+        file.name("hello.txt")
+            .content_bytes(b"hello from a reader".to_vec());
+        let expected = file.build().await?.wrap().encode_root().await?;
+        assert_eq!(root, *expected.cid());
+        assert!(store.lock().await.contains_key(&root));
+
+        let decoded = UnixfsNode::decode(&root, store.lock().await[&root].clone())?;
+        let links = decoded.links().collect::<Result<Vec<_>>>()?;
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0].name.unwrap(), "hello.txt");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_add_directory() -> Result<()> {
+        let store: Arc<tokio::sync::Mutex<std::collections::HashMap<Cid, Bytes>>> =
+            Default::default();
+        let mut file = FileBuilder::new();
+        file.name("bar.txt").content_bytes(b"bar".to_vec());
+        let mut dir = DirectoryBuilder::new();
+        dir.name("foo").add_file(file.build().await?);
+
+        let events: Vec<_> = add_directory(Some(store.clone()), dir.build()?, false)
+            .await?
+            .try_collect()
+            .await?;
+        assert_eq!(events.len(), 2);
+        let root = match events.last().context("no events")? {
+            AddEvent::ProgressDelta { cid, .. } => *cid,
+        };
+        let decoded = UnixfsNode::decode(&root, store.lock().await[&root].clone())?;
+        let links = decoded.links().collect::<Result<Vec<_>>>()?;
+        assert_eq!(links[0].name.unwrap(), "bar.txt");
+        assert_eq!(store.lock().await.len(), 2);
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_hamt_detection() -> Result<()> {
         // allow hamt override
@@ -1274,6 +1405,7 @@ mod tests {
             Entry::Directory(dir) => dir.name.clone(),
             Entry::File(file) => file.name.clone(),
             Entry::Symlink(sym) => sym.name().to_string(),
+            Entry::Encoded { name, .. } => name.clone(),
         });
 
         assert_eq!(expected, got);
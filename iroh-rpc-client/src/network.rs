@@ -1,4 +1,5 @@
 use std::collections::{HashMap, HashSet};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use anyhow::{Context, Result};
 use bytes::Bytes;
@@ -10,7 +11,7 @@ use iroh_rpc_types::p2p::{
     BitswapBlock, BitswapRequest, ConnectByPeerIdRequest, ConnectRequest, DisconnectRequest,
     GossipsubPeerAndTopics, GossipsubPeerIdMsg, GossipsubPublishRequest, GossipsubTopicHashMsg,
     Key, LookupRequest, NotifyNewBlocksBitswapRequest, P2p, P2pClientAddr, P2pClientBackend,
-    PeerInfo, Providers, StopSessionBitswapRequest,
+    PeerInfo, Providers, ReproviderStatusResponse, StopSessionBitswapRequest,
 };
 use iroh_rpc_types::Addr;
 use libp2p::gossipsub::{MessageId, TopicHash};
@@ -180,6 +181,14 @@ impl P2pClient {
         Lookup::from_peer_info(peer_info)
     }
 
+    /// Reports the progress of the reprovider, which periodically announces the content of
+    /// the node to the DHT.
+    #[tracing::instrument(skip(self))]
+    pub async fn reprovider_status(&self) -> Result<ReproviderStatus> {
+        let res = self.backend.reprovider_status(()).await?;
+        Ok(ReproviderStatus::from(res))
+    }
+
     #[tracing::instrument(skip(self))]
     pub async fn disconnect(&self, peer_id: PeerId) -> Result<()> {
         warn!("NetDisconnect not yet implemented on p2p node");
@@ -275,6 +284,38 @@ impl P2pClient {
     }
 }
 
+/// Progress of the reprovider of a p2p node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReproviderStatus {
+    /// Strategy used to select the reprovided keys.
+    pub strategy: String,
+    /// Whether a run is in progress.
+    pub running: bool,
+    /// Keys announced in the current run, or in the last run if none is in progress.
+    pub provided: u64,
+    /// Keys that could not be announced in the current or last run.
+    pub failed: u64,
+    /// Start of the last complete run.
+    pub last_run: Option<SystemTime>,
+    /// Duration of the last complete run.
+    pub last_run_duration: Option<Duration>,
+}
+
+impl From<ReproviderStatusResponse> for ReproviderStatus {
+    fn from(res: ReproviderStatusResponse) -> Self {
+        Self {
+            strategy: res.strategy,
+            running: res.running,
+            provided: res.provided,
+            failed: res.failed,
+            last_run: res
+                .last_run
+                .map(|secs| UNIX_EPOCH + Duration::from_secs(secs)),
+            last_run_duration: res.last_run_duration_ms.map(Duration::from_millis),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Lookup {
     pub peer_id: PeerId,
@@ -338,7 +379,7 @@ mod tests {
         p2p_server, BitswapResponse, GetListeningAddrsResponse, GetPeersResponse,
         GossipsubAllPeersResponse, GossipsubPeersResponse, GossipsubPublishResponse,
         GossipsubSubscribeResponse, GossipsubTopicsResponse, Multiaddrs, PeerIdResponse,
-        VersionResponse,
+        ReproviderStatusResponse, VersionResponse,
     };
     use libp2p::gossipsub::IdentTopic;
     use tokio::net::TcpListener;
@@ -509,6 +550,20 @@ mod tests {
             todo!()
         }
 
+        async fn reprovider_status(
+            &self,
+            _request: Request<()>,
+        ) -> Result<tonic::Response<ReproviderStatusResponse>, tonic::Status> {
+            Ok(Response::new(ReproviderStatusResponse {
+                strategy: "roots".to_string(),
+                running: false,
+                provided: 42,
+                failed: 1,
+                last_run: Some(1_600_000_000),
+                last_run_duration_ms: Some(1500),
+            }))
+        }
+
         async fn gossipsub_add_explicit_peer(
             &self,
             _request: Request<GossipsubPeerIdMsg>,
@@ -652,4 +707,26 @@ mod tests {
         server_task.abort();
         server_task.await.unwrap_err();
     }
+
+    #[tokio::test]
+    async fn test_reprovider_status_rpc() {
+        let listener = TcpListener::bind("0.0.0.0:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server_task = tokio::spawn(async move { TestRpcServer::serve(listener).await });
+        let client = P2pClient::new(Addr::GrpcHttp2(addr)).await.unwrap();
+
+        let got = client.reprovider_status().await.unwrap();
+        let expect = ReproviderStatus {
+            strategy: "roots".to_string(),
+            running: false,
+            provided: 42,
+            failed: 1,
+            last_run: Some(UNIX_EPOCH + Duration::from_secs(1_600_000_000)),
+            last_run_duration: Some(Duration::from_millis(1500)),
+        };
+        assert_eq!(expect, got);
+
+        server_task.abort();
+        server_task.await.unwrap_err();
+    }
 }
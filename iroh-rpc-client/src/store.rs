@@ -3,13 +3,12 @@ use std::io::Cursor;
 use anyhow::{Context, Result};
 use bytes::Bytes;
 use cid::Cid;
-#[cfg(feature = "grpc")]
-use futures::Stream;
+use futures::{Stream, StreamExt};
 #[cfg(feature = "grpc")]
 use iroh_rpc_types::store::store_client::StoreClient as GrpcStoreClient;
 use iroh_rpc_types::store::{
-    GetLinksRequest, GetRequest, GetSizeRequest, HasRequest, PutManyRequest, PutRequest, Store,
-    StoreClientAddr, StoreClientBackend,
+    DeleteRequest, GetBlocksRequest, GetLinksRequest, GetRequest, GetSizeRequest, HasRequest,
+    PutManyRequest, PutRequest, Store, StoreClientAddr, StoreClientBackend,
 };
 use iroh_rpc_types::Addr;
 #[cfg(feature = "grpc")]
@@ -97,4 +96,32 @@ impl StoreClient {
         let size = self.backend.get_size(req).await?.size;
         Ok(size)
     }
+
+    /// Removes the block for `cid` from the store, returning whether it was present.
+    #[tracing::instrument(skip(self))]
+    pub async fn delete(&self, cid: Cid) -> Result<bool> {
+        let req = DeleteRequest {
+            cid: cid.to_bytes(),
+        };
+        let res = self.backend.delete(req).await?;
+        Ok(res.deleted)
+    }
+
+    /// Streams the cids of the blocks in the store in batches. With `roots_only` only the
+    /// blocks that no other stored block links to are listed.
+    #[tracing::instrument(skip(self))]
+    pub async fn get_blocks(
+        &self,
+        roots_only: bool,
+    ) -> Result<impl Stream<Item = Result<Vec<Cid>>>> {
+        let req = GetBlocksRequest { roots_only };
+        let res = self.backend.get_blocks(req).await?;
+        Ok(res.map(|batch| {
+            batch?
+                .cids
+                .iter()
+                .map(|c| Cid::read_bytes(Cursor::new(c)).context(format!("invalid cid: {:?}", c)))
+                .collect()
+        }))
+    }
 }
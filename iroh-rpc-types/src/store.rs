@@ -9,5 +9,9 @@ proxy!(
     get: GetRequest => GetResponse => GetResponse,
     has: HasRequest => HasResponse => HasResponse,
     get_links: GetLinksRequest => GetLinksResponse => GetLinksResponse,
-    get_size: GetSizeRequest => GetSizeResponse => GetSizeResponse
+    get_size: GetSizeRequest => GetSizeResponse => GetSizeResponse,
+    delete: DeleteRequest => DeleteResponse => DeleteResponse,
+    get_blocks: GetBlocksRequest =>
+        std::pin::Pin<Box<dyn futures::Stream<Item = Result<GetBlocksResponse, tonic::Status>> + Send>> =>
+        std::pin::Pin<Box<dyn futures::Stream<Item = anyhow::Result<GetBlocksResponse>> + Send>> [GetBlocksStream]
 );
@@ -63,7 +63,7 @@ impl Loader {
 
 #[async_trait]
 impl ContentLoader for Loader {
-    async fn load_cid(&self, cid: &Cid, _ctx: &LoaderContext) -> Result<LoadedCid> {
+    async fn load_cid(&self, cid: &Cid, ctx: &LoaderContext) -> Result<LoadedCid> {
         let cid = *cid;
         let providers = self.providers.lock().await.clone();
 
@@ -80,6 +80,7 @@ impl ContentLoader for Loader {
             }
         }
 
+        ensure!(!ctx.local_only(), "{} is not available locally", cid);
         ensure!(!providers.is_empty(), "no providers supplied");
 
         // TODO: track context id
@@ -146,7 +147,7 @@ impl P2pNode {
         };
         let config = config::Config {
             libp2p: config::Libp2pConfig {
-                listening_multiaddr: format!("/ip4/0.0.0.0/tcp/{port}").parse().unwrap(),
+                listening_multiaddrs: vec![format!("/ip4/0.0.0.0/tcp/{port}").parse().unwrap()],
                 mdns: false,
                 kademlia: true,
                 autonat: true,
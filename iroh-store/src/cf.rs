@@ -14,6 +14,10 @@ pub const CF_GRAPH_V0: &str = "graph-v0";
 ///
 /// By storing multihash first we can search for ids either by cid = (multihash, code) or by multihash.
 pub const CF_ID_V0: &str = "id-v0";
+/// Column family that counts the stored blocks linking to a blob, blobs without a count
+/// are roots.
+/// - indexed by id (u64), the count is a be encoded i64
+pub const CF_PARENTS_V0: &str = "parents-v0";
 
 // This wrapper type serializes the contained value out-of-line so that newer
 // versions can be viewed as the older version.
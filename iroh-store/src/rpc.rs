@@ -1,18 +1,24 @@
 use std::io::Cursor;
+use std::pin::Pin;
 
 use anyhow::{Context, Result};
 use async_trait::async_trait;
 use bytes::BytesMut;
 use cid::Cid;
+use futures::Stream;
 use iroh_rpc_types::store::{
-    GetLinksRequest, GetLinksResponse, GetRequest, GetResponse, GetSizeRequest, GetSizeResponse,
-    HasRequest, HasResponse, PutManyRequest, PutRequest, Store as RpcStore, StoreServerAddr,
-    VersionResponse,
+    DeleteRequest, DeleteResponse, GetBlocksRequest, GetBlocksResponse, GetLinksRequest,
+    GetLinksResponse, GetRequest, GetResponse, GetSizeRequest, GetSizeResponse, HasRequest,
+    HasResponse, PutManyRequest, PutRequest, Store as RpcStore, StoreServerAddr, VersionResponse,
 };
+use tokio::sync::mpsc;
 use tracing::info;
 
 use crate::store::Store;
 
+/// Number of cids sent per message of a `get_blocks` stream.
+const GET_BLOCKS_BATCH_SIZE: usize = 1024;
+
 #[cfg(feature = "rpc-grpc")]
 impl iroh_rpc_types::NamedService for Store {
     const NAME: &'static str = "store";
@@ -92,6 +98,38 @@ impl RpcStore for Store {
             Ok(GetSizeResponse { size: None })
         }
     }
+
+    #[tracing::instrument(skip(self))]
+    async fn delete(&self, req: DeleteRequest) -> Result<DeleteResponse> {
+        let cid = cid_from_bytes(req.cid)?;
+        let deleted = self.delete(&cid)?;
+
+        info!("store rpc call: delete cid {}", cid);
+        Ok(DeleteResponse { deleted })
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_blocks(
+        &self,
+        req: GetBlocksRequest,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<GetBlocksResponse>> + Send>>> {
+        let (s, r) = mpsc::channel(4);
+        let store = self.clone();
+        tokio::task::spawn_blocking(move || {
+            let res = store.for_each_block_batch(req.roots_only, GET_BLOCKS_BATCH_SIZE, |cids| {
+                let cids = cids.iter().map(|cid| cid.to_bytes()).collect();
+                // stop listing once the receiver is gone
+                s.blocking_send(Ok(GetBlocksResponse { cids })).is_ok()
+            });
+            if let Err(err) = res {
+                s.blocking_send(Err(err)).ok();
+            }
+        });
+
+        Ok(Box::pin(futures::stream::unfold(r, |mut r| async move {
+            r.recv().await.map(|batch| (batch, r))
+        })))
+    }
 }
 
 #[tracing::instrument(skip(store))]
@@ -1,4 +1,5 @@
 use std::{
+    collections::HashSet,
     sync::{
         atomic::{AtomicU64, Ordering},
         Arc,
@@ -17,13 +18,15 @@ use iroh_metrics::{
 use iroh_rpc_client::Client as RpcClient;
 use multihash::Multihash;
 use rocksdb::{
-    BlockBasedOptions, Cache, ColumnFamily, DBPinnableSlice, Direction, IteratorMode, Options,
-    WriteBatch, DB as RocksDb,
+    BlockBasedOptions, Cache, ColumnFamily, ColumnFamilyDescriptor, DBPinnableSlice, Direction,
+    IteratorMode, MergeOperands, Options, WriteBatch, DB as RocksDb,
 };
 use smallvec::SmallVec;
 use tokio::task;
 
-use crate::cf::{GraphV0, MetadataV0, CF_BLOBS_V0, CF_GRAPH_V0, CF_ID_V0, CF_METADATA_V0};
+use crate::cf::{
+    GraphV0, MetadataV0, CF_BLOBS_V0, CF_GRAPH_V0, CF_ID_V0, CF_METADATA_V0, CF_PARENTS_V0,
+};
 use crate::Config;
 
 #[derive(Clone)]
@@ -72,6 +75,27 @@ fn default_blob_opts() -> Options {
     opts
 }
 
+/// Options of CF_PARENTS_V0, whose counts are updated by merging in increments.
+fn default_parents_opts() -> Options {
+    let mut opts = Options::default();
+    opts.set_merge_operator_associative("parents_count", merge_parents_count);
+
+    opts
+}
+
+fn merge_parents_count(
+    _key: &[u8],
+    existing: Option<&[u8]>,
+    operands: &MergeOperands,
+) -> Option<Vec<u8>> {
+    let count = existing
+        .into_iter()
+        .chain(operands.iter())
+        .filter_map(|value| value.try_into().ok().map(i64::from_be_bytes))
+        .sum::<i64>();
+    Some(count.to_be_bytes().to_vec())
+}
+
 /// The key used in CF_ID_V0
 ///
 /// The multihash followed by the be encoded code. This allows both looking up an id by multihash and code (aka Cid),
@@ -119,6 +143,10 @@ impl Store {
                 let opts = Options::default();
                 db.create_cf(CF_ID_V0, &opts)?;
             }
+            {
+                let opts = default_parents_opts();
+                db.create_cf(CF_PARENTS_V0, &opts)?;
+            }
 
             Ok(db)
         })
@@ -147,11 +175,22 @@ impl Store {
 
         let path = config.path.clone();
         let (db, next_id) = task::spawn_blocking(move || -> Result<_> {
-            let db = RocksDb::open_cf(
-                &options,
-                path,
-                [CF_BLOBS_V0, CF_METADATA_V0, CF_GRAPH_V0, CF_ID_V0],
-            )?;
+            // stores created before the parents index get it built once
+            let has_parents = RocksDb::list_cf(&options, &path)?
+                .iter()
+                .any(|name| name == CF_PARENTS_V0);
+            options.create_missing_column_families(true);
+            let cfs = [CF_BLOBS_V0, CF_METADATA_V0, CF_GRAPH_V0, CF_ID_V0]
+                .into_iter()
+                .map(|name| ColumnFamilyDescriptor::new(name, Options::default()))
+                .chain([ColumnFamilyDescriptor::new(
+                    CF_PARENTS_V0,
+                    default_parents_opts(),
+                )]);
+            let db = RocksDb::open_cf_descriptors(&options, path, cfs)?;
+            if !has_parents {
+                build_parents_index(&db)?;
+            }
 
             // read last inserted id
             let next_id = {
@@ -233,6 +272,26 @@ impl Store {
         self.local_store()?.get_links(cid)
     }
 
+    /// Removes the block stored for `cid`, returning whether it was present.
+    ///
+    /// The cid itself stays known to the store, so links to it from other blocks keep resolving.
+    #[tracing::instrument(skip(self))]
+    pub fn delete(&self, cid: &Cid) -> Result<bool> {
+        self.local_store()?.delete(cid)
+    }
+
+    /// Calls `f` with the cids of the blocks in the store, in batches of at most `batch_size`,
+    /// until it returns `false`.
+    ///
+    /// With `roots_only` only the blocks that no other block in the store links to are listed.
+    pub fn for_each_block_batch<F>(&self, roots_only: bool, batch_size: usize, f: F) -> Result<()>
+    where
+        F: FnMut(Vec<Cid>) -> bool,
+    {
+        self.local_store()?
+            .for_each_block_batch(roots_only, batch_size, f)
+    }
+
     #[cfg(test)]
     fn get_ids_for_hash(
         &self,
@@ -257,11 +316,39 @@ impl Store {
             blobs: db
                 .cf_handle(CF_BLOBS_V0)
                 .context("missing column family: blobs")?,
+            parents: db
+                .cf_handle(CF_PARENTS_V0)
+                .context("missing column family: parents")?,
             next_id: &self.inner.next_id,
         })
     }
 }
 
+/// Counts the parents of every block from the graph.
+fn build_parents_index(db: &RocksDb) -> Result<()> {
+    const BATCH_SIZE: usize = 1024;
+
+    let graph = db
+        .cf_handle(CF_GRAPH_V0)
+        .ok_or_else(|| anyhow!("missing column family: graph"))?;
+    let parents = db
+        .cf_handle(CF_PARENTS_V0)
+        .ok_or_else(|| anyhow!("missing column family: parents"))?;
+    let mut batch = WriteBatch::default();
+    for item in db.iterator_cf(graph, IteratorMode::Start) {
+        let (_, value) = item?;
+        let graph = rkyv::check_archived_root::<GraphV0>(&value).map_err(|e| anyhow!("{:?}", e))?;
+        for child in graph.children.iter() {
+            batch.merge_cf(parents, child.to_be_bytes(), 1i64.to_be_bytes());
+        }
+        if batch.len() >= BATCH_SIZE {
+            db.write(std::mem::take(&mut batch))?;
+        }
+    }
+    db.write(batch)?;
+    Ok(())
+}
+
 /// The local store is fully synchronous and is not Send.
 ///
 /// Due to this, it can store column family handles.
@@ -273,6 +360,7 @@ struct LocalStore<'a> {
     metadata: &'a ColumnFamily,
     graph: &'a ColumnFamily,
     blobs: &'a ColumnFamily,
+    parents: &'a ColumnFamily,
     next_id: &'a AtomicU64,
 }
 
@@ -288,31 +376,24 @@ impl<'a> LocalStore<'a> {
         }
         let cf = self;
 
-        let id = self.next_id();
-
         let start = std::time::Instant::now();
 
+        // a cid that is already linked to keeps its id, so its parents stay accounted to it
+        let id = self.ensure_id_many(std::iter::once(cid), cf)?[0];
         let id_bytes = id.to_be_bytes();
 
-        // guranteed that the key does not exists, so we want to store it
-
-        let metadata = MetadataV0 {
-            codec: cid.codec(),
-            multihash: cid.hash().to_bytes(),
-        };
-        let metadata_bytes = rkyv::to_bytes::<_, 1024>(&metadata)?; // TODO: is this the right amount of scratch space?
-        let id_key = id_key(&cid);
-
         let children = self.ensure_id_many(links.into_iter(), cf)?;
 
+        let mut batch = WriteBatch::default();
+        for child in &children {
+            batch.merge_cf(cf.parents, child.to_be_bytes(), 1i64.to_be_bytes());
+        }
+
         let graph = GraphV0 { children };
         let graph_bytes = rkyv::to_bytes::<_, 1024>(&graph)?; // TODO: is this the right amount of scratch space?
         let blob_size = blob.as_ref().len();
 
-        let mut batch = WriteBatch::default();
-        batch.put_cf(cf.id, id_key, id_bytes);
         batch.put_cf(cf.blobs, id_bytes, blob);
-        batch.put_cf(cf.metadata, id_bytes, metadata_bytes);
         batch.put_cf(cf.graph, id_bytes, graph_bytes);
         self.db.write(batch)?;
         observe!(StoreHistograms::PutRequests, start.elapsed().as_secs_f64());
@@ -328,25 +409,22 @@ impl<'a> LocalStore<'a> {
         let cf = self;
 
         let mut batch = WriteBatch::default();
+        let mut batch_cids = HashSet::new();
         for (cid, blob, links) in blocks.into_iter() {
-            if self.has(&cid)? {
-                return Ok(());
+            // skip blocks that are already stored or appear earlier in the batch
+            if !batch_cids.insert(cid) || self.has(&cid)? {
+                continue;
             }
 
-            let id = self.next_id();
-
+            // the id is written right away, so later blocks of the batch linking to this one
+            // and cids that are already linked to share it
+            let id = self.ensure_id_many(std::iter::once(cid), cf)?[0];
             let id_bytes = id.to_be_bytes();
 
-            // guranteed that the key does not exists, so we want to store it
-
-            let metadata = MetadataV0 {
-                codec: cid.codec(),
-                multihash: cid.hash().to_bytes(),
-            };
-            let metadata_bytes = rkyv::to_bytes::<_, 1024>(&metadata)?; // TODO: is this the right amount of scratch space?
-            let id_key = id_key(&cid);
-
             let children = self.ensure_id_many(links.into_iter(), cf)?;
+            for child in &children {
+                batch.merge_cf(cf.parents, child.to_be_bytes(), 1i64.to_be_bytes());
+            }
 
             let graph = GraphV0 { children };
             let graph_bytes = rkyv::to_bytes::<_, 1024>(&graph)?; // TODO: is this the right amount of scratch space?
@@ -354,9 +432,7 @@ impl<'a> LocalStore<'a> {
             let blob_size = blob.as_ref().len();
             total_blob_size += blob_size as u64;
 
-            batch.put_cf(cf.id, id_key, id_bytes);
             batch.put_cf(cf.blobs, id_bytes, blob);
-            batch.put_cf(cf.metadata, id_bytes, metadata_bytes);
             batch.put_cf(cf.graph, id_bytes, graph_bytes);
         }
 
@@ -437,6 +513,86 @@ impl<'a> LocalStore<'a> {
         res
     }
 
+    fn delete(&self, cid: &Cid) -> Result<bool> {
+        let id = match self.get_id(cid)? {
+            Some(id) => id,
+            None => return Ok(false),
+        };
+        let id_bytes = id.to_be_bytes();
+        if self.db.get_pinned_cf(self.blobs, id_bytes)?.is_none() {
+            return Ok(false);
+        }
+
+        // the id and metadata are kept, as they might be referenced by the graph of other blocks
+        let mut batch = WriteBatch::default();
+        if let Some(graph) = self.db.get_pinned_cf(self.graph, id_bytes)? {
+            let graph =
+                rkyv::check_archived_root::<GraphV0>(&graph).map_err(|e| anyhow!("{:?}", e))?;
+            for child in graph.children.iter() {
+                batch.merge_cf(self.parents, child.to_be_bytes(), (-1i64).to_be_bytes());
+            }
+        }
+        batch.delete_cf(self.blobs, id_bytes);
+        batch.delete_cf(self.graph, id_bytes);
+        self.db.write(batch)?;
+
+        Ok(true)
+    }
+
+    fn for_each_block_batch<F>(&self, roots_only: bool, batch_size: usize, mut f: F) -> Result<()>
+    where
+        F: FnMut(Vec<Cid>) -> bool,
+    {
+        // every stored block has an entry in the graph, cids that are only linked to do not
+        let mut ids = Vec::with_capacity(batch_size);
+        for item in self.db.iterator_cf(self.graph, IteratorMode::Start) {
+            let (key, _) = item?;
+            let id = u64::from_be_bytes(key.as_ref().try_into()?);
+            if !roots_only || self.parents_count(id)? <= 0 {
+                ids.push(id);
+            }
+            if ids.len() >= batch_size {
+                if !f(self.get_cids_by_id(&ids)?) {
+                    return Ok(());
+                }
+                ids.clear();
+            }
+        }
+        if !ids.is_empty() {
+            f(self.get_cids_by_id(&ids)?);
+        }
+        Ok(())
+    }
+
+    /// Number of stored blocks that link to the block with `id`.
+    fn parents_count(&self, id: u64) -> Result<i64> {
+        match self.db.get_pinned_cf(self.parents, id.to_be_bytes())? {
+            Some(count) => Ok(i64::from_be_bytes(count.as_ref().try_into()?)),
+            None => Ok(0),
+        }
+    }
+
+    #[tracing::instrument(skip(self))]
+    fn get_cids_by_id(&self, ids: &[u64]) -> Result<Vec<Cid>> {
+        let keys = ids.iter().map(|id| (&self.metadata, id.to_be_bytes()));
+        let meta = self.db.multi_get_cf(keys);
+        let mut cids = Vec::with_capacity(meta.len());
+        for (id, meta) in ids.iter().zip(meta) {
+            match meta? {
+                Some(meta) => {
+                    let meta = rkyv::check_archived_root::<MetadataV0>(&meta)
+                        .map_err(|e| anyhow!("{:?}", e))?;
+                    let multihash = cid::multihash::Multihash::from_bytes(&meta.multihash)?;
+                    cids.push(cid::Cid::new_v1(meta.codec, multihash));
+                }
+                None => {
+                    bail!("missing metadata: {}", id);
+                }
+            }
+        }
+        Ok(cids)
+    }
+
     #[tracing::instrument(skip(self))]
     fn get_id(&self, cid: &Cid) -> Result<Option<u64>> {
         let id_key = id_key(cid);
@@ -751,6 +907,160 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_delete() -> anyhow::Result<()> {
+        let (store, _dir) = test_store().await?;
+        let child_data = b"child".to_vec();
+        let child = Cid::new_v1(RAW, Code::Sha2_256.digest(&child_data));
+        let parent_data = b"parent".to_vec();
+        let parent = Cid::new_v1(RAW, Code::Sha2_256.digest(&parent_data));
+
+        store.put(child, &child_data, vec![])?;
+        store.put(parent, &parent_data, vec![child])?;
+        assert!(!store.delete(&Cid::new_v1(RAW, Code::Sha2_256.digest(b"missing")))?);
+
+        assert!(store.delete(&child)?);
+        assert!(!store.has(&child)?);
+        assert!(store.get(&child)?.is_none());
+        assert!(store.get_links(&child)?.is_none());
+        assert!(!store.delete(&child)?);
+        // links to the deleted block still resolve
+        assert_eq!(store.get_links(&parent)?, Some(vec![child]));
+
+        store.put(child, &child_data, vec![])?;
+        assert_eq!(store.get(&child)?.unwrap().to_vec(), child_data);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_for_each_block_batch() -> anyhow::Result<()> {
+        let (store, _dir) = test_store().await?;
+        let block = |data: &[u8]| Cid::new_v1(RAW, Code::Sha2_256.digest(data));
+        let leaf = block(b"leaf");
+        let shared = block(b"shared");
+        let missing = block(b"missing");
+        let root_a = block(b"root a");
+        let root_b = block(b"root b");
+
+        store.put(leaf, b"leaf", vec![])?;
+        store.put(shared, b"shared", vec![leaf])?;
+        store.put(root_a, b"root a", vec![shared, missing])?;
+        store.put(root_b, b"root b", vec![shared])?;
+
+        let list = |roots_only, batch_size| -> anyhow::Result<Vec<Vec<Cid>>> {
+            let mut batches = Vec::new();
+            store.for_each_block_batch(roots_only, batch_size, |cids| {
+                batches.push(cids);
+                true
+            })?;
+            Ok(batches)
+        };
+
+        // blocks that are only linked to are not listed
+        assert_eq!(
+            list(false, 3)?,
+            vec![vec![leaf, shared, root_a], vec![root_b]]
+        );
+        assert_eq!(list(true, 10)?, vec![vec![root_a, root_b]]);
+
+        let mut calls = 0;
+        store.for_each_block_batch(false, 1, |_| {
+            calls += 1;
+            false
+        })?;
+        assert_eq!(calls, 1);
+
+        // a deleted block no longer links its children
+        store.delete(&root_a)?;
+        store.delete(&root_b)?;
+        assert_eq!(list(true, 10)?, vec![vec![shared]]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_put_many_parents() -> anyhow::Result<()> {
+        let (store, _dir) = test_store().await?;
+        let block = |data: &'static [u8]| {
+            let cid = Cid::new_v1(RAW, Code::Sha2_256.digest(data));
+            (cid, Bytes::from_static(data))
+        };
+        let leaf = block(b"leaf");
+        let mid = block(b"mid");
+        let root = block(b"root");
+        let other = block(b"other");
+
+        // parents come before their children, the leaf is repeated and mid is linked again
+        // after it was stored earlier in the batch
+        store.put_many(vec![
+            (root.0, root.1.clone(), vec![mid.0]),
+            (mid.0, mid.1.clone(), vec![leaf.0]),
+            (leaf.0, leaf.1.clone(), vec![]),
+            (leaf.0, leaf.1.clone(), vec![]),
+            (other.0, other.1.clone(), vec![mid.0]),
+        ])?;
+        assert_eq!(store.get(&leaf.0)?.unwrap().to_vec(), leaf.1.to_vec());
+
+        let roots = || -> anyhow::Result<Vec<Cid>> {
+            let mut roots = Vec::new();
+            store.for_each_block_batch(true, 10, |cids| {
+                roots.extend(cids);
+                true
+            })?;
+            Ok(roots)
+        };
+        assert_eq!(roots()?, vec![root.0, other.0]);
+
+        // mid has two parents and the leaf a single one
+        store.delete(&root.0)?;
+        assert_eq!(roots()?, vec![other.0]);
+        store.delete(&other.0)?;
+        assert_eq!(roots()?, vec![mid.0]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_parents_index_is_built_on_open() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let config = Config {
+            path: dir.path().into(),
+            rpc_client: RpcClientConfig::default(),
+            metrics: MetricsConfig::default(),
+        };
+        let block = |data: &[u8]| Cid::new_v1(RAW, Code::Sha2_256.digest(data));
+        let leaf = block(b"leaf");
+        let root = block(b"root");
+
+        let store = Store::create(config.clone()).await?;
+        store.put(leaf, b"leaf", vec![])?;
+        store.put(root, b"root", vec![leaf])?;
+        drop(store);
+
+        // a store from before the index
+        {
+            let mut db = RocksDb::open_cf(
+                &Options::default(),
+                dir.path(),
+                [
+                    CF_BLOBS_V0,
+                    CF_METADATA_V0,
+                    CF_GRAPH_V0,
+                    CF_ID_V0,
+                    CF_PARENTS_V0,
+                ],
+            )?;
+            db.drop_cf(CF_PARENTS_V0)?;
+        }
+
+        let store = Store::open(config).await?;
+        let mut roots = Vec::new();
+        store.for_each_block_batch(true, 10, |cids| {
+            roots.extend(cids);
+            true
+        })?;
+        assert_eq!(roots, vec![root]);
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_blob_by_hash() -> anyhow::Result<()> {
         let link1 = Cid::from_str("bafybeib4tddkl4oalrhe7q66rrz5dcpz4qwv5lmpstuqrls3djikw566y4")?;
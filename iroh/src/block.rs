@@ -0,0 +1,84 @@
+use anyhow::Result;
+use clap::{Args, Subcommand};
+use iroh_api::{codec_from_name, multihash_from_name, Api, BlockStat, Bytes, Cid, MultihashCode};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::doc;
+
+#[derive(Args, Debug, Clone)]
+#[clap(about = "Manipulate raw blocks in the store")]
+#[clap(after_help = doc::BLOCK_LONG_DESCRIPTION)]
+pub struct Block {
+    #[clap(subcommand)]
+    command: BlockCommands,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum BlockCommands {
+    #[clap(about = "Write the raw bytes of a block to stdout")]
+    Get {
+        /// CID of the block
+        cid: Cid,
+    },
+    #[clap(about = "Store the data read from stdin as a block")]
+    Put {
+        /// Codec of the data: raw, dag-pb, dag-cbor or dag-json
+        #[clap(long, default_value = "raw", value_parser = codec_from_name)]
+        codec: u64,
+        /// Multihash function: sha2-256, sha2-512, sha3-256, sha3-512, blake2b-256,
+        /// blake2b-512 or blake3
+        #[clap(long, default_value = "sha2-256", value_parser = multihash_from_name)]
+        mhtype: MultihashCode,
+    },
+    #[clap(about = "Print the size and links of a block")]
+    Stat {
+        /// CID of the block
+        cid: Cid,
+    },
+    #[clap(about = "Remove blocks from the store")]
+    Rm {
+        /// CIDs of the blocks to remove
+        #[clap(required = true)]
+        cids: Vec<Cid>,
+    },
+}
+
+pub async fn run_command(api: &Api, cmd: &Block) -> Result<()> {
+    match &cmd.command {
+        BlockCommands::Get { cid } => {
+            let data = api.block_get(*cid).await?;
+            let mut stdout = tokio::io::stdout();
+            stdout.write_all(&data).await?;
+            stdout.flush().await?;
+        }
+        BlockCommands::Put { codec, mhtype } => {
+            let mut data = Vec::new();
+            tokio::io::stdin().read_to_end(&mut data).await?;
+            let cid = api.block_put(Bytes::from(data), *codec, *mhtype).await?;
+            println!("{}", cid);
+        }
+        BlockCommands::Stat { cid } => {
+            let stat = api.block_stat(*cid).await?;
+            print_block_stat(&stat);
+        }
+        BlockCommands::Rm { cids } => {
+            for cid in cids {
+                if api.block_rm(*cid).await? {
+                    println!("removed {}", cid);
+                } else {
+                    println!("{} not found", cid);
+                }
+            }
+        }
+    };
+    Ok(())
+}
+
+fn print_block_stat(stat: &BlockStat) {
+    println!("CID:   {}", stat.cid);
+    println!("Size:  {}", stat.size);
+    println!("Links: {}", stat.links.len());
+    for link in &stat.links {
+        println!("  {}", link);
+    }
+}
@@ -0,0 +1,85 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use clap::{Args, Subcommand};
+use iroh_api::{Api, Bytes, Cid, DagCodec, IpfsPath};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::doc;
+
+#[derive(Args, Debug, Clone)]
+#[clap(about = "Work with IPLD data: dag-cbor, dag-json and CAR files")]
+pub struct Dag {
+    #[clap(subcommand)]
+    command: DagCommands,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum DagCommands {
+    #[clap(about = "Store a JSON document as an IPLD block")]
+    #[clap(after_help = doc::DAG_PUT_LONG_DESCRIPTION)]
+    Put {
+        /// File containing the JSON document, defaults to stdin
+        file: Option<PathBuf>,
+        /// Codec to store the block with, dag-cbor or dag-json
+        #[clap(long, default_value_t = DagCodec::DagCbor)]
+        codec: DagCodec,
+    },
+    #[clap(about = "Print the IPLD value at a path as dag-json")]
+    #[clap(after_help = doc::DAG_GET_LONG_DESCRIPTION)]
+    Get {
+        /// CID or CID/with/path/qualifier to resolve
+        ipfs_path: IpfsPath,
+    },
+    #[clap(about = "Write a DAG to stdout as a CAR file")]
+    #[clap(after_help = doc::DAG_EXPORT_LONG_DESCRIPTION)]
+    Export {
+        /// Root of the DAG to export
+        cid: Cid,
+    },
+    #[clap(about = "Import the blocks of a CAR file")]
+    #[clap(after_help = doc::DAG_IMPORT_LONG_DESCRIPTION)]
+    Import {
+        /// Path of the CAR file, or `-` to read it from stdin
+        path: PathBuf,
+    },
+}
+
+pub async fn run_command(api: &Api, cmd: &Dag) -> Result<()> {
+    match &cmd.command {
+        DagCommands::Put { file, codec } => {
+            let json = match file {
+                Some(file) => tokio::fs::read(file).await?,
+                None => {
+                    let mut json = Vec::new();
+                    tokio::io::stdin().read_to_end(&mut json).await?;
+                    json
+                }
+            };
+            let cid = api.dag_put(Bytes::from(json), *codec).await?;
+            println!("{}", cid);
+        }
+        DagCommands::Get { ipfs_path } => {
+            let json = api.dag_get(ipfs_path).await?;
+            let mut stdout = tokio::io::stdout();
+            stdout.write_all(&json).await?;
+            stdout.write_all(b"\n").await?;
+            stdout.flush().await?;
+        }
+        DagCommands::Export { cid } => {
+            api.dag_export(*cid, tokio::io::stdout()).await?;
+        }
+        DagCommands::Import { path } => {
+            let roots = if path.as_os_str() == "-" {
+                api.dag_import(tokio::io::stdin()).await?
+            } else {
+                let file = tokio::fs::File::open(path).await?;
+                api.dag_import(tokio::io::BufReader::new(file)).await?
+            };
+            for root in roots {
+                println!("{}", root);
+            }
+        }
+    };
+    Ok(())
+}
@@ -36,6 +36,12 @@ little nicer for humans by getting just the cat picture:
 
   > iroh get /ipfs/bafybeihjgu5w6wbbxqevdgccj5xm453dbzpkwmkyoepvs3vh6wft4uvf2q/cat.jpg cat.jpg
 
+Content can also be piped into add by passing '-' as the path. It is added as a
+single file, which is only wrapped in a directory if it is named with
+--stdin-name:
+
+  > cat cat.jpg | iroh add - --stdin-name cat.jpg
+
 The stored result of add is a 'MerkleDAG'. Merkle proofs (hashes) are a fast
 method of proving and checking data inclusion, and the tree formed by chunking
 the input into blocks is always a directed acyclic graph (DAG). These MerkleDAGs
@@ -199,3 +205,69 @@ for additional details about the peer. For example:
 
 For more info on multiaddrs see https://iroh.computer/docs/concepts#multiaddr.
 ";
+
+pub const DAG_PUT_LONG_DESCRIPTION: &str = "
+Reads a JSON document from <FILE>, or from stdin if no file is given, and stores
+it as a single IPLD block. Links to other blocks are written in the dag-json
+form {\"/\": \"<cid>\"}. The block is encoded as dag-cbor unless another codec is
+chosen with --codec:
+
+  > echo '{\"name\": \"cat\", \"picture\": {\"/\": \"bafkreicajtoxxqijyqzprtbeio2fxt7jlgapkedscxdeki3ok54stlb6ki\"}}' | iroh dag put
+  bafyreicwlvhnzacluj5ocd2yrjsijdxlxwj6rfntout7m6jhko2lspcidm
+
+The printed CID can be passed to 'dag get' or used as a link in other blocks.";
+
+pub const DAG_GET_LONG_DESCRIPTION: &str = "
+Resolves <IPFS_PATH> by following the fields and IPLD links named by each path
+segment, and prints the value it points to as dag-json. Unixfs content is
+printed as the dag-pb node it is stored in:
+
+  > iroh dag get bafyreicwlvhnzacluj5ocd2yrjsijdxlxwj6rfntout7m6jhko2lspcidm/name
+  \"cat\"";
+
+pub const DAG_EXPORT_LONG_DESCRIPTION: &str = "
+Writes every block of the DAG below <CID> to stdout as a CAR (content
+addressed archive) file, fetching missing blocks from the network:
+
+  > iroh dag export bafyreicwlvhnzacluj5ocd2yrjsijdxlxwj6rfntout7m6jhko2lspcidm > cat.car
+
+The CAR file can be loaded into another node with 'dag import'.";
+
+pub const DAG_IMPORT_LONG_DESCRIPTION: &str = "
+Reads all blocks of the CAR file at <PATH>, or from stdin if <PATH> is '-',
+into the iroh store and prints the roots listed in the CAR header:
+
+  > iroh dag import cat.car
+  bafyreicwlvhnzacluj5ocd2yrjsijdxlxwj6rfntout7m6jhko2lspcidm
+
+Imported content is not provided to the network.";
+
+pub const IMPORT_CAR_LONG_DESCRIPTION: &str = "
+Reads all blocks of the CAR file at <PATH>, or from stdin if <PATH> is '-', into
+the iroh store. Every block is checked against its CID. Once all blocks are
+stored the roots listed in the CAR header are provided to the network, unless
+--offline is given, and printed:
+
+  > iroh import car cat.car
+  Imported 2 blocks
+  /ipfs/bafyreicwlvhnzacluj5ocd2yrjsijdxlxwj6rfntout7m6jhko2lspcidm
+
+Use 'iroh dag import' to import blocks without providing them.";
+
+pub const BLOCK_LONG_DESCRIPTION: &str = "
+block commands read and write single blocks directly in the iroh store, without
+resolving paths or fetching anything from the network. They are meant for
+debugging the store and bitswap:
+
+  > echo -n 'hello' | iroh block put
+  bafkreibm6jg3ux5qumhcn2b3flc3tyu6dmlb4xa7u5bf44yegnrjhc4yeq
+  > iroh block stat bafkreibm6jg3ux5qumhcn2b3flc3tyu6dmlb4xa7u5bf44yegnrjhc4yeq
+  CID:   bafkreibm6jg3ux5qumhcn2b3flc3tyu6dmlb4xa7u5bf44yegnrjhc4yeq
+  Size:  5
+  Links: 0
+  > iroh block rm bafkreibm6jg3ux5qumhcn2b3flc3tyu6dmlb4xa7u5bf44yegnrjhc4yeq
+  removed bafkreibm6jg3ux5qumhcn2b3flc3tyu6dmlb4xa7u5bf44yegnrjhc4yeq
+
+Blocks are stored as raw data hashed with sha2-256 unless --codec or --mhtype
+are given. Data stored with an IPLD codec must be valid for that codec, so the
+links of the block can be recorded.";
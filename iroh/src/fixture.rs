@@ -1,10 +1,13 @@
 use std::collections::HashMap;
 use std::env;
+use std::io::Write;
 use std::path::PathBuf;
 use std::str::FromStr;
 
 use futures::StreamExt;
-use iroh_api::{AddEvent, Cid, Lookup, OutType, PeerId};
+use iroh_api::{
+    AddEvent, BlockStat, Bytes, Cid, EntryType, ImportEvent, Lookup, LsEntry, OutType, PeerId, Stat,
+};
 use iroh_api::{Api, P2pApi};
 use iroh_api::{ServiceStatus, StatusRow, StatusTable};
 use relative_path::RelativePathBuf;
@@ -98,6 +101,167 @@ fn fixture_add_directory() -> Api {
     api
 }
 
+fn fixture_add_stdin() -> Api {
+    let mut api = Api::default();
+    api.expect_check().returning(|| {
+        StatusTable::new(
+            Some(StatusRow::new("gateway", 1, ServiceStatus::Serving)),
+            Some(StatusRow::new("p2p", 1, ServiceStatus::Serving)),
+            Some(StatusRow::new("store", 1, ServiceStatus::Serving)),
+        )
+    });
+    api.expect_add_reader::<tokio::io::Stdin>()
+        .returning(|_reader, _name, _wrap| {
+            let cid = Cid::from_str("QmYbcW4tXLXHWw753boCK8Y7uxLu5abXjyYizhLznq9PUR").unwrap();
+            let add_event = AddEvent::ProgressDelta { cid, size: Some(0) };
+
+            Ok(Box::pin(futures::stream::iter(vec![Ok(add_event)])))
+        });
+    api.expect_provide().returning(|_| Ok(()));
+    api
+}
+
+fn fixture_cat() -> Api {
+    let mut api = Api::default();
+    api.expect_cat().returning(|_ipfs_path, _offset, _length| {
+        Ok(Box::new(std::io::Cursor::new("hello world\n")))
+    });
+    api
+}
+
+fn fixture_ls() -> Api {
+    let mut api = Api::default();
+    api.expect_ls().returning(|_ipfs_path| {
+        Ok(futures::stream::iter(vec![
+            Ok(LsEntry {
+                name: "docs".to_string(),
+                cid: Cid::from_str("QmP8jTG1m9GSDJLCbeWhVSVgEzCPPwXRdCRuJtQ5Tz9Kc9").unwrap(),
+                size: None,
+                typ: EntryType::Dir,
+            }),
+            Ok(LsEntry {
+                name: "hello.txt".to_string(),
+                cid: Cid::from_str("QmYbcW4tXLXHWw753boCK8Y7uxLu5abXjyYizhLznq9PUR").unwrap(),
+                size: Some(12),
+                typ: EntryType::File,
+            }),
+        ])
+        .boxed_local())
+    });
+    api
+}
+
+fn fixture_stat() -> Api {
+    let mut api = Api::default();
+    api.expect_stat().returning(|_ipfs_path| {
+        Ok(Stat {
+            cid: Cid::from_str("QmYbcW4tXLXHWw753boCK8Y7uxLu5abXjyYizhLznq9PUR").unwrap(),
+            codec: "dag-pb".to_string(),
+            typ: EntryType::File,
+            size: Some(512),
+            blocks: 3,
+            missing_blocks: 1,
+        })
+    });
+    api
+}
+
+fn fixture_dag_put() -> Api {
+    let mut api = Api::default();
+    api.expect_dag_put().returning(|_json, _codec| {
+        Ok(Cid::from_str("bafyreihakpd7te5nbmlhdk5ntvcvhf2hmfgrvcwna2sddq5zz5342mcbli").unwrap())
+    });
+    api
+}
+
+fn fixture_dag_get() -> Api {
+    let mut api = Api::default();
+    api.expect_dag_get()
+        .returning(|_ipfs_path| Ok(Bytes::from_static(br#""hello""#)));
+    api
+}
+
+fn fixture_dag_export() -> Api {
+    let mut api = Api::default();
+    api.expect_dag_export::<tokio::io::Stdout>()
+        .returning(|_cid, _writer| {
+            let mut stdout = std::io::stdout();
+            stdout.write_all(include_bytes!("../tests/cmd/dag_import.in/hello.car"))?;
+            stdout.flush()?;
+            Ok(1)
+        });
+    api
+}
+
+fn fixture_dag_import() -> Api {
+    let mut api = Api::default();
+    api.expect_dag_import::<tokio::io::BufReader<tokio::fs::File>>()
+        .returning(|_reader| {
+            Ok(vec![Cid::from_str(
+                "bafyreihakpd7te5nbmlhdk5ntvcvhf2hmfgrvcwna2sddq5zz5342mcbli",
+            )
+            .unwrap()])
+        });
+    api
+}
+
+fn fixture_import_car() -> Api {
+    let mut api = Api::default();
+    api.expect_check().returning(|| {
+        StatusTable::new(
+            Some(StatusRow::new("gateway", 1, ServiceStatus::Serving)),
+            Some(StatusRow::new("p2p", 1, ServiceStatus::Serving)),
+            Some(StatusRow::new("store", 1, ServiceStatus::Serving)),
+        )
+    });
+    api.expect_import_car::<tokio::io::BufReader<tokio::fs::File>>()
+        .returning(|_reader, provide| {
+            let cid = Cid::from_str("bafyreihakpd7te5nbmlhdk5ntvcvhf2hmfgrvcwna2sddq5zz5342mcbli")
+                .unwrap();
+            let events = vec![
+                Ok(ImportEvent::ProgressDelta { cid, size: 12 }),
+                Ok(ImportEvent::Root {
+                    cid,
+                    provided: provide,
+                }),
+            ];
+            Ok(Box::pin(futures::stream::iter(events)))
+        });
+    api
+}
+
+fn fixture_block_put() -> Api {
+    let mut api = Api::default();
+    api.expect_block_put().returning(|_data, _codec, _mhtype| {
+        Ok(Cid::from_str("bafkreibm6jg3ux5qumhcn2b3flc3tyu6dmlb4xa7u5bf44yegnrjhc4yeq").unwrap())
+    });
+    api
+}
+
+fn fixture_block_stat() -> Api {
+    let mut api = Api::default();
+    api.expect_block_stat().returning(|cid| {
+        Ok(BlockStat {
+            cid,
+            size: 58,
+            links: vec![Cid::from_str(
+                "bafkreibm6jg3ux5qumhcn2b3flc3tyu6dmlb4xa7u5bf44yegnrjhc4yeq",
+            )
+            .unwrap()],
+        })
+    });
+    api
+}
+
+fn fixture_block_rm() -> Api {
+    let mut api = Api::default();
+    let present =
+        Cid::from_str("bafkreibm6jg3ux5qumhcn2b3flc3tyu6dmlb4xa7u5bf44yegnrjhc4yeq").unwrap();
+    api.expect_block_rm()
+        .returning(move |cid| Ok(cid == present));
+    api
+}
+
 fn fixture_get_wrapped_file() -> Api {
     let mut api = Api::default();
     api.expect_get().returning(|_ipfs_path| {
@@ -169,6 +333,18 @@ fn register_fixtures() -> FixtureRegistry {
             "add_directory".to_string(),
             fixture_add_directory as GetFixture,
         ),
+        ("add_stdin".to_string(), fixture_add_stdin as GetFixture),
+        ("cat".to_string(), fixture_cat as GetFixture),
+        ("ls".to_string(), fixture_ls as GetFixture),
+        ("stat".to_string(), fixture_stat as GetFixture),
+        ("dag_put".to_string(), fixture_dag_put as GetFixture),
+        ("block_put".to_string(), fixture_block_put as GetFixture),
+        ("block_stat".to_string(), fixture_block_stat as GetFixture),
+        ("block_rm".to_string(), fixture_block_rm as GetFixture),
+        ("dag_get".to_string(), fixture_dag_get as GetFixture),
+        ("dag_export".to_string(), fixture_dag_export as GetFixture),
+        ("dag_import".to_string(), fixture_dag_import as GetFixture),
+        ("import_car".to_string(), fixture_import_car as GetFixture),
         (
             "get_wrapped_symlink".to_string(),
             fixture_get_wrapped_symlink as GetFixture,
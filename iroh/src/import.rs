@@ -0,0 +1,88 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use clap::{Args, Subcommand};
+use futures::stream::LocalBoxStream;
+use futures::StreamExt;
+use indicatif::{ProgressBar, ProgressStyle};
+use iroh_api::{Api, Cid, ImportEvent};
+
+use crate::doc;
+use crate::run::check_services;
+
+#[derive(Args, Debug, Clone)]
+#[clap(about = "Import content in other formats into iroh")]
+pub struct Import {
+    #[clap(subcommand)]
+    command: ImportCommands,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum ImportCommands {
+    #[clap(about = "Import the blocks of a CAR file & make its roots available on IPFS")]
+    #[clap(after_help = doc::IMPORT_CAR_LONG_DESCRIPTION)]
+    Car {
+        /// Path of the CAR file, or `-` to read it from stdin
+        path: PathBuf,
+        /// Don't provide the roots of the CAR file to the network
+        #[clap(long)]
+        offline: bool,
+    },
+}
+
+pub async fn run_command(api: &Api, cmd: &Import) -> Result<()> {
+    match &cmd.command {
+        ImportCommands::Car { path, offline } => {
+            let provide = !*offline;
+            check_services(api, provide).await?;
+
+            let pb = ProgressBar::new_spinner();
+            pb.set_style(ProgressStyle::with_template(
+                "[{elapsed_precise}] {spinner} {pos} blocks {msg}",
+            )?);
+            pb.inc(0);
+
+            let progress = if path.as_os_str() == "-" {
+                api.import_car(tokio::io::stdin(), provide).await?
+            } else {
+                let file = tokio::fs::File::open(path).await?;
+                api.import_car(tokio::io::BufReader::new(file), provide)
+                    .await?
+            };
+            let (blocks, roots) = import_car(progress, &pb).await?;
+            pb.finish_and_clear();
+
+            let blocks_str = if blocks == 1 { "block" } else { "blocks" };
+            println!("Imported {} {}", blocks, blocks_str);
+            for root in roots {
+                println!("/ipfs/{}", root);
+            }
+        }
+    };
+    Ok(())
+}
+
+/// Drives the import stream, reporting the progress on `pb`, and returns the number of
+/// imported blocks and the roots of the CAR file.
+async fn import_car(
+    mut progress: LocalBoxStream<'static, Result<ImportEvent>>,
+    pb: &ProgressBar,
+) -> Result<(u64, Vec<Cid>)> {
+    let mut blocks = 0;
+    let mut roots = Vec::new();
+    while let Some(event) = progress.next().await {
+        match event? {
+            ImportEvent::ProgressDelta { .. } => {
+                blocks += 1;
+                pb.inc(1);
+            }
+            ImportEvent::Root { cid, provided } => {
+                if provided {
+                    pb.set_message(format!("provided {}", cid));
+                }
+                roots.push(cid);
+            }
+        }
+    }
+    Ok((blocks, roots))
+}
@@ -1,7 +1,10 @@
+pub mod block;
 mod config;
+pub mod dag;
 pub mod doc;
 #[cfg(feature = "testing")]
 mod fixture;
+pub mod import;
 pub mod metrics;
 pub mod p2p;
 pub mod run;
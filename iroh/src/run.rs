@@ -5,16 +5,21 @@ use anyhow::{Context, Result};
 use clap::{Parser, Subcommand};
 use console::style;
 use crossterm::style::Stylize;
+use futures::stream::LocalBoxStream;
 use futures::StreamExt;
 use indicatif::{ProgressBar, ProgressStyle};
-use iroh_api::{AddEvent, Api, IpfsPath, ServiceStatus};
+use iroh_api::{AddEvent, Api, Cid, EntryType, IpfsPath, LsEntry, ServiceStatus, Stat};
 use iroh_metrics::config::Config as MetricsConfig;
 use iroh_util::{human, iroh_config_path, make_config};
+use tokio::io::AsyncWriteExt;
 
+use crate::block::{run_command as run_block_command, Block};
 use crate::config::{Config, CONFIG_FILE_NAME, ENV_PREFIX};
+use crate::dag::{run_command as run_dag_command, Dag};
 use crate::doc;
 #[cfg(feature = "testing")]
 use crate::fixture::get_fixture_api;
+use crate::import::{run_command as run_import_command, Import};
 use crate::p2p::{run_command as run_p2p_command, P2p};
 use crate::services::require_services;
 use crate::size::size_stream;
@@ -36,9 +41,12 @@ pub struct Cli {
 #[derive(Subcommand, Debug, Clone)]
 enum Commands {
     P2p(P2p),
+    Dag(Dag),
+    Block(Block),
+    Import(Import),
     #[clap(about = "Add a file or directory to iroh & make it available on IPFS")]
     Add {
-        /// The path to a file or directory to be added
+        /// The path to a file or directory to be added, or `-` to read a file from stdin
         path: PathBuf,
         /// Required to add a directory
         #[clap(long, short)]
@@ -49,6 +57,10 @@ enum Commands {
         /// Don't provide added content to the network
         #[clap(long)]
         offline: bool,
+        /// Name of the file read from stdin. Content from stdin is only wrapped in
+        /// a directory if a name is given
+        #[clap(long)]
+        stdin_name: Option<String>,
     },
     #[clap(about = "Fetch IPFS content and write it to disk")]
     #[clap(after_help = doc::GET_LONG_DESCRIPTION )]
@@ -58,6 +70,27 @@ enum Commands {
         /// filesystem path to write to. Optional and defaults to $CID
         output: Option<PathBuf>,
     },
+    #[clap(about = "Write the content of a file to stdout")]
+    Cat {
+        /// CID or CID/with/path/qualifier of the file
+        ipfs_path: IpfsPath,
+        /// Byte offset to start reading at
+        #[clap(long, short, default_value_t = 0)]
+        offset: u64,
+        /// Maximum number of bytes to read
+        #[clap(long, short)]
+        length: Option<u64>,
+    },
+    #[clap(about = "List the entries of a directory")]
+    Ls {
+        /// CID or CID/with/path/qualifier of the directory
+        ipfs_path: IpfsPath,
+    },
+    #[clap(about = "Show the CID, codec, size and local availability of content")]
+    Stat {
+        /// CID or CID/with/path/qualifier to inspect
+        ipfs_path: IpfsPath,
+    },
     #[clap(about = "Start local iroh services")]
     #[clap(after_help = doc::START_LONG_DESCRIPTION )]
     Start {
@@ -129,8 +162,15 @@ impl Cli {
                 recursive,
                 no_wrap,
                 offline,
+                stdin_name,
             } => {
-                add(api, path, *no_wrap, *recursive, !*offline).await?;
+                if path.as_os_str() == "-" {
+                    let wrap = !*no_wrap && stdin_name.is_some();
+                    let name = stdin_name.as_deref().unwrap_or_default();
+                    add_stdin(api, name, wrap, !*offline).await?;
+                } else {
+                    add(api, path, *no_wrap, *recursive, !*offline).await?;
+                }
             }
             Commands::Get {
                 ipfs_path: path,
@@ -141,7 +181,30 @@ impl Cli {
                     iroh_api::fs::write_get_stream(path, blocks, output.as_deref()).await?;
                 println!("Saving file(s) to {}", root_path.to_str().unwrap());
             }
+            Commands::Cat {
+                ipfs_path,
+                offset,
+                length,
+            } => {
+                let mut reader = api.cat(ipfs_path, *offset, *length).await?;
+                let mut stdout = tokio::io::stdout();
+                tokio::io::copy(&mut reader, &mut stdout).await?;
+                stdout.flush().await?;
+            }
+            Commands::Ls { ipfs_path } => {
+                let mut entries = api.ls(ipfs_path).await?;
+                while let Some(entry) = entries.next().await {
+                    println!("{}", format_ls_entry(&entry?));
+                }
+            }
+            Commands::Stat { ipfs_path } => {
+                let stat = api.stat(ipfs_path).await?;
+                print_stat(&stat);
+            }
             Commands::P2p(p2p) => run_p2p_command(&api.p2p()?, p2p).await?,
+            Commands::Dag(dag) => run_dag_command(api, dag).await?,
+            Commands::Block(block) => run_block_command(api, block).await?,
+            Commands::Import(import) => run_import_command(api, import).await?,
             Commands::Start { service, all } => {
                 let svc = match *all {
                     true => vec![
@@ -168,6 +231,34 @@ impl Cli {
     }
 }
 
+fn format_ls_entry(entry: &LsEntry) -> String {
+    let size = entry
+        .size
+        .map(|size| size.to_string())
+        .unwrap_or_else(|| "-".to_string());
+    let suffix = if entry.typ == EntryType::Dir { "/" } else { "" };
+    format!(
+        "{} {:<9} {:>12} {}{}",
+        entry.cid, entry.typ, size, entry.name, suffix
+    )
+}
+
+fn print_stat(stat: &Stat) {
+    println!("CID:     {}", stat.cid);
+    println!("Codec:   {}", stat.codec);
+    println!("Type:    {}", stat.typ);
+    match stat.size {
+        Some(size) => println!("Size:    {} ({} bytes)", human::format_bytes(size), size),
+        None => println!("Size:    unknown"),
+    }
+    println!("Blocks:  {}", stat.blocks);
+    match stat.missing_blocks {
+        0 => println!("Local:   yes"),
+        1 => println!("Local:   no, 1 block missing"),
+        missing => println!("Local:   no, {} blocks missing", missing),
+    }
+}
+
 async fn add(api: &Api, path: &Path, no_wrap: bool, recursive: bool, provide: bool) -> Result<()> {
     if !path.exists() {
         anyhow::bail!("Path does not exist");
@@ -182,30 +273,8 @@ async fn add(api: &Api, path: &Path, no_wrap: bool, recursive: bool, provide: bo
         );
     }
 
-    let mut steps = 3;
-    // we require p2p for adding right now because we don't have a mechanism for
-    // hydrating only the root CID to the p2p node for providing if a CID were
-    // ingested offline. Offline adding should happen, but this is the current
-    // path of least confusion
-    let svc_status = require_services(api, HashSet::from(["store"])).await?;
-    match (provide, svc_status.p2p.status()) {
-        (true, ServiceStatus::Down(_status)) => {
-            anyhow::bail!("Add provides content to the IPFS network by default, but the p2p service is not running.\n{}",
-            "hint: try using the --offline flag, or run 'iroh start p2p'".yellow()
-            )
-        }
-        (true, ServiceStatus::Unknown)
-        | (true, ServiceStatus::NotServing)
-        | (true, ServiceStatus::ServiceUnknown) => {
-            anyhow::bail!("Add provides content to the IPFS network by default, but the p2p service is not running.\n{}",
-            "hint: try using the --offline flag, or run 'iroh start p2p'".yellow()
-            )
-        }
-        (true, ServiceStatus::Serving) => {}
-        (false, _) => {
-            steps -= 1;
-        }
-    }
+    let steps = if provide { 3 } else { 2 };
+    check_services(api, provide).await?;
 
     println!(
         "{} Calculating size...",
@@ -244,7 +313,63 @@ async fn add(api: &Api, path: &Path, no_wrap: bool, recursive: bool, provide: bo
     // a while before it starts ending progress reports
     pb.inc(0);
 
-    let mut progress = api.add_stream(path, !no_wrap).await?;
+    let progress = api.add_stream(path, !no_wrap).await?;
+    let cids = import(progress, &pb).await?;
+    pb.finish_and_clear();
+
+    finish_add(api, cids, provide, steps).await
+}
+
+/// Adds a single file read from stdin, its size is not known upfront.
+async fn add_stdin(api: &Api, name: &str, wrap: bool, provide: bool) -> Result<()> {
+    let steps = if provide { 2 } else { 1 };
+    check_services(api, provide).await?;
+
+    println!(
+        "{} Importing content from stdin...",
+        style(format!("[1/{}]", steps)).bold().dim(),
+    );
+    let pb = ProgressBar::new_spinner();
+    pb.set_style(ProgressStyle::with_template(
+        "[{elapsed_precise}] {spinner} {bytes} ({bytes_per_sec}) {msg}",
+    )?);
+    pb.inc(0);
+
+    let progress = api.add_reader(tokio::io::stdin(), name, wrap).await?;
+    let cids = import(progress, &pb).await?;
+    pb.finish_and_clear();
+
+    finish_add(api, cids, provide, steps).await
+}
+
+pub(crate) async fn check_services(api: &Api, provide: bool) -> Result<()> {
+    // we require p2p for adding right now because we don't have a mechanism for
+    // hydrating only the root CID to the p2p node for providing if a CID were
+    // ingested offline. Offline adding should happen, but this is the current
+    // path of least confusion
+    let svc_status = require_services(api, HashSet::from(["store"])).await?;
+    match (provide, svc_status.p2p.status()) {
+        (true, ServiceStatus::Down(_status)) => {
+            anyhow::bail!("Add provides content to the IPFS network by default, but the p2p service is not running.\n{}",
+            "hint: try using the --offline flag, or run 'iroh start p2p'".yellow()
+            )
+        }
+        (true, ServiceStatus::Unknown)
+        | (true, ServiceStatus::NotServing)
+        | (true, ServiceStatus::ServiceUnknown) => {
+            anyhow::bail!("Add provides content to the IPFS network by default, but the p2p service is not running.\n{}",
+            "hint: try using the --offline flag, or run 'iroh start p2p'".yellow()
+            )
+        }
+        (true, ServiceStatus::Serving) | (false, _) => Ok(()),
+    }
+}
+
+/// Drives the add stream, reporting the progress on `pb`, and returns the added cids.
+async fn import(
+    mut progress: LocalBoxStream<'static, Result<AddEvent>>,
+    pb: &ProgressBar,
+) -> Result<Vec<Cid>> {
     let mut cids = Vec::new();
     while let Some(add_event) = progress.next().await {
         match add_event? {
@@ -256,8 +381,11 @@ async fn add(api: &Api, path: &Path, no_wrap: bool, recursive: bool, provide: bo
             }
         }
     }
-    pb.finish_and_clear();
+    Ok(cids)
+}
 
+/// Provides the root of the added content, if requested, and prints it.
+async fn finish_add(api: &Api, mut cids: Vec<Cid>, provide: bool, steps: usize) -> Result<()> {
     let root = *cids.last().context("File processing failed")?;
 
     if provide {
@@ -267,7 +395,7 @@ async fn add(api: &Api, path: &Path, no_wrap: bool, recursive: bool, provide: bo
         let rec_str = if cids.len() == 1 { "record" } else { "records" };
         println!(
             "{} Providing {} {} to the distributed hash table ...",
-            style(format!("[3/{}]", steps)).bold().dim(),
+            style(format!("[{}/{}]", steps, steps)).bold().dim(),
             cids.len(),
             rec_str,
         );
@@ -33,6 +33,78 @@ fn add_file_test() {
         .run();
 }
 
+#[test]
+fn add_stdin_test() {
+    trycmd::TestCases::new()
+        .env("IROH_CTL_FIXTURE", "add_stdin")
+        .case("tests/cmd/add_stdin.toml")
+        .run();
+}
+
+#[test]
+fn block_put_test() {
+    trycmd::TestCases::new()
+        .env("IROH_CTL_FIXTURE", "block_put")
+        .case("tests/cmd/block_put.toml")
+        .run();
+}
+
+#[test]
+fn block_rm_test() {
+    trycmd::TestCases::new()
+        .env("IROH_CTL_FIXTURE", "block_rm")
+        .case("tests/cmd/block_rm.trycmd")
+        .run();
+}
+
+#[test]
+fn block_stat_test() {
+    trycmd::TestCases::new()
+        .env("IROH_CTL_FIXTURE", "block_stat")
+        .case("tests/cmd/block_stat.trycmd")
+        .run();
+}
+
+#[test]
+fn cat_test() {
+    trycmd::TestCases::new()
+        .env("IROH_CTL_FIXTURE", "cat")
+        .case("tests/cmd/cat.trycmd")
+        .run();
+}
+
+#[test]
+fn dag_get_test() {
+    trycmd::TestCases::new()
+        .env("IROH_CTL_FIXTURE", "dag_get")
+        .case("tests/cmd/dag_get.trycmd")
+        .run();
+}
+
+#[test]
+fn dag_export_test() {
+    trycmd::TestCases::new()
+        .env("IROH_CTL_FIXTURE", "dag_export")
+        .case("tests/cmd/dag_export.toml")
+        .run();
+}
+
+#[test]
+fn dag_import_test() {
+    trycmd::TestCases::new()
+        .env("IROH_CTL_FIXTURE", "dag_import")
+        .case("tests/cmd/dag_import.trycmd")
+        .run();
+}
+
+#[test]
+fn dag_put_test() {
+    trycmd::TestCases::new()
+        .env("IROH_CTL_FIXTURE", "dag_put")
+        .case("tests/cmd/dag_put.toml")
+        .run();
+}
+
 #[test]
 fn get_cid_directory_overwrite_explicit_failure_test() {
     trycmd::TestCases::new()
@@ -129,6 +201,22 @@ async fn get_wrapped_symlink_test() {
         .run();
 }
 
+#[test]
+fn import_car_test() {
+    trycmd::TestCases::new()
+        .env("IROH_CTL_FIXTURE", "import_car")
+        .case("tests/cmd/import_car.trycmd")
+        .run();
+}
+
+#[test]
+fn ls_test() {
+    trycmd::TestCases::new()
+        .env("IROH_CTL_FIXTURE", "ls")
+        .case("tests/cmd/ls.trycmd")
+        .run();
+}
+
 #[test]
 fn lookup_test() {
     trycmd::TestCases::new()
@@ -137,6 +225,14 @@ fn lookup_test() {
         .run();
 }
 
+#[test]
+fn stat_test() {
+    trycmd::TestCases::new()
+        .env("IROH_CTL_FIXTURE", "stat")
+        .case("tests/cmd/stat.trycmd")
+        .run();
+}
+
 #[test]
 fn version_test() {
     trycmd::TestCases::new()